@@ -0,0 +1,26 @@
+#![no_main]
+
+//! Opens arbitrary bytes as an archive and reads every entry through the
+//! index, then verifies it. Every failure has to be an `ArchiveError`.
+
+use std::io::{Cursor, Read};
+
+use libfuzzer_sys::fuzz_target;
+use sulfur::reader::ArchiveReader;
+
+/// Bytes read from one entry, so a claimed size can't exhaust memory.
+const MAX_ENTRY_READ: u64 = 1 << 24;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(mut archive) = ArchiveReader::new(Cursor::new(data)) else {
+        return;
+    };
+    for index in 0..archive.entries().len() {
+        if let Ok(entry) = archive.open_entry_at(index) {
+            let _ = entry.take(MAX_ENTRY_READ).read_to_end(&mut Vec::new());
+        }
+    }
+    let _ = archive.verify_archive_checksum();
+    let _ = archive.verify();
+    let _ = archive.verify_deep();
+});
@@ -0,0 +1,63 @@
+#![no_main]
+
+//! Packs arbitrary contents with `ArchiveBuilder` and checks that reading and
+//! extracting the archive gives them back.
+
+use std::{
+    io::{Cursor, Read},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use libfuzzer_sys::fuzz_target;
+use sulfur::{
+    builder::ArchiveBuilder,
+    compressor::Method,
+    filesystem::MemFs,
+    log::Verbosity,
+    reader::ArchiveReader,
+    unpack::{UnpackOptions, unpack_stream},
+};
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&selector, data)) = data.split_first() else {
+        return;
+    };
+    let method = [Method::Store, Method::Gzip, Method::Deflate][selector as usize % 3];
+
+    // contents of the entries are separated by NUL bytes
+    let contents: Vec<&[u8]> = data.split(|&byte| byte == 0).take(16).collect();
+    let mut builder = ArchiveBuilder::new();
+    builder.method(method);
+    for (i, bytes) in contents.iter().enumerate() {
+        builder
+            .add_bytes(format!("dir/{}.bin", i), bytes)
+            .expect("failed to add an entry");
+    }
+    let archive = builder.finish(Vec::new()).expect("failed to finish the archive");
+
+    let mut reader = ArchiveReader::new(Cursor::new(&archive)).expect("failed to open the archive");
+    reader.verify().expect("archive doesn't verify");
+    for (i, bytes) in contents.iter().enumerate() {
+        let mut read = Vec::new();
+        reader
+            .open_entry(format!("dir/{}.bin", i))
+            .expect("entry is missing")
+            .read_to_end(&mut read)
+            .expect("failed to read an entry");
+        assert_eq!(&read, bytes);
+    }
+
+    let file_system = Arc::new(MemFs::new());
+    let options = UnpackOptions {
+        file_system: file_system.clone(),
+        verbosity: Verbosity::Quiet,
+        ..UnpackOptions::default()
+    };
+    unpack_stream(Cursor::new(&archive), PathBuf::from("/out"), &options)
+        .expect("failed to extract the archive");
+    for (i, bytes) in contents.iter().enumerate() {
+        let path = Path::new("/out/dir").join(format!("{}.bin", i));
+        assert_eq!(file_system.read(&path).as_deref(), Some(*bytes));
+    }
+});
@@ -0,0 +1,32 @@
+#![no_main]
+
+//! Extracts arbitrary bytes as an archive into a `MemFs`, both as a stream
+//! and from a file, which walks the index with `best_effort`.
+
+use std::{env, fs, io::Cursor, path::PathBuf, process, sync::Arc};
+
+use libfuzzer_sys::fuzz_target;
+use sulfur::{
+    filesystem::MemFs,
+    log::Verbosity,
+    unpack::{UnpackOptions, unpack, unpack_stream},
+};
+
+fn options(best_effort: bool) -> UnpackOptions {
+    UnpackOptions {
+        file_system: Arc::new(MemFs::new()),
+        verbosity: Verbosity::Quiet,
+        max_entry_size: Some(1 << 24),
+        best_effort,
+        ..UnpackOptions::default()
+    }
+}
+
+fuzz_target!(|data: &[u8]| {
+    let _ = unpack_stream(Cursor::new(data), PathBuf::from("/out"), &options(false));
+
+    let path = env::temp_dir().join(format!("sulfur-fuzz-{}.slf", process::id()));
+    fs::write(&path, data).expect("failed to write the input");
+    let _ = unpack(path.clone(), Some(PathBuf::from("/out")), &options(true));
+    let _ = fs::remove_file(path);
+});
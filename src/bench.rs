@@ -0,0 +1,402 @@
+use std::{
+    env, fmt,
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    process,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use flate2::Compression;
+use log::debug;
+
+use crate::{
+    compressor::Method,
+    error::Result,
+    filesystem::MemFs,
+    list::{format_size, ratio},
+    log::Verbosity,
+    pack::{PackOptions, pack},
+    reader::ArchiveReader,
+    unpack::{UnpackOptions, unpack},
+};
+
+/// Size of every data set generated by `bench` unless configured otherwise.
+pub const DEFAULT_BENCH_SIZE: u64 = 16 << 20;
+
+/// Size of every file of `DataSet::SmallFiles`.
+const SMALL_FILE_SIZE: u64 = 4 * 1024;
+
+/// Entries of the archive `LookupResult` extracts named files from.
+pub const LOOKUP_ENTRIES: u64 = 10_000;
+
+/// Named files `LookupResult` extracts.
+pub const LOOKUPS: usize = 100;
+
+/// Size of every entry of the lookup archive, small so lookups dominate.
+const LOOKUP_FILE_SIZE: u64 = 64;
+
+/// Settings measured on every data set, stored entries ignore the level.
+const SETTINGS: [(Method, u32); 4] = [
+    (Method::Store, 0),
+    (Method::Gzip, 1),
+    (Method::Gzip, 6),
+    (Method::Gzip, 9),
+];
+
+/// Words of `DataSet::Text`, repeated the way natural text repeats them.
+const WORDS: &str = "the archive of a file is packed into entries and every entry has its \
+    name size checksum method contents which are compressed stored once";
+
+/// Synthetic data generated by `bench`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DataSet {
+    /// A single file of text made of a small vocabulary, compressing well.
+    Text,
+    /// A single file of random bytes, which don't compress at all.
+    Random,
+    /// Many files of `SMALL_FILE_SIZE` text spread over directories, where
+    /// the cost per entry dominates.
+    SmallFiles,
+}
+
+impl DataSet {
+    pub const ALL: [Self; 3] = [Self::Text, Self::Random, Self::SmallFiles];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Text => "text",
+            Self::Random => "random",
+            Self::SmallFiles => "small-files",
+        }
+    }
+}
+
+/// Measurements of one data set packed with one setting, see `bench`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchResult {
+    pub data_set: DataSet,
+    pub method: Method,
+    /// Compression level, meaningless for `Method::Store`.
+    pub level: u32,
+    pub files: u64,
+    pub original_size: u64,
+    pub archive_size: u64,
+    pub pack_time: Duration,
+    pub unpack_time: Duration,
+}
+
+impl BenchResult {
+    /// Original bytes packed per second, in MB/s.
+    pub fn pack_throughput(&self) -> f64 {
+        throughput(self.original_size, self.pack_time)
+    }
+
+    /// Original bytes extracted per second, in MB/s.
+    pub fn unpack_throughput(&self) -> f64 {
+        throughput(self.original_size, self.unpack_time)
+    }
+}
+
+fn throughput(bytes: u64, time: Duration) -> f64 {
+    bytes as f64 / time.as_secs_f64().max(f64::EPSILON) / 1_000_000.0
+}
+
+/// Extraction of a few named entries from an archive of many, see `bench`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LookupResult {
+    pub entries: u64,
+    pub lookups: usize,
+    /// Opening the archive, which reads the index once.
+    pub open_time: Duration,
+    /// Looking up and extracting all named entries.
+    pub extract_time: Duration,
+}
+
+/// Results of `bench` in the order they were measured.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct BenchReport {
+    pub results: Vec<BenchResult>,
+    pub lookup: Option<LookupResult>,
+}
+
+impl fmt::Display for BenchReport {
+    /// One line per result with its ratio and throughputs, every line ends
+    /// with a newline.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<12}  {:>10}  {:<6}  {:>5}  {:>7}  {:>10}  {:>12}",
+            "Data", "Size", "Method", "Level", "Ratio", "Pack MB/s", "Unpack MB/s"
+        )?;
+        for result in &self.results {
+            let level = if result.method == Method::Store {
+                "—".to_string()
+            } else {
+                result.level.to_string()
+            };
+            let ratio = ratio(result.archive_size, result.original_size)
+                .map_or("—".to_string(), |ratio| format!("{:.1}%", ratio * 100.0));
+            writeln!(
+                f,
+                "{:<12}  {:>10}  {:<6}  {:>5}  {:>7}  {:>10.1}  {:>12.1}",
+                result.data_set.name(),
+                format_size(result.original_size),
+                result.method.name(),
+                level,
+                ratio,
+                result.pack_throughput(),
+                result.unpack_throughput()
+            )?;
+        }
+        if let Some(lookup) = &self.lookup {
+            writeln!(
+                f,
+                "Extracted {} of {} entries by name in {:.1?} ({:.1?} each), opened in {:.1?}",
+                lookup.lookups,
+                lookup.entries,
+                lookup.extract_time,
+                lookup.extract_time / lookup.lookups.max(1) as u32,
+                lookup.open_time
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Generates every `DataSet` of about `size` bytes in a temporary directory,
+/// then packs it with every setting and extracts the archive into a `MemFs`,
+/// so extraction is timed without the disk. Ratios include the metadata of
+/// the archive. Extracting `LOOKUPS` named entries from an archive of
+/// `LOOKUP_ENTRIES` is timed as well. The directory is removed afterwards.
+pub fn bench(size: u64) -> Result<BenchReport> {
+    let dir = env::temp_dir().join(format!("sulfur-bench-{}", process::id()));
+    fs::create_dir_all(&dir)?;
+    let result = run(&dir, size);
+    fs::remove_dir_all(&dir)?;
+    result
+}
+
+fn run(dir: &Path, size: u64) -> Result<BenchReport> {
+    let mut report = BenchReport::default();
+    for data_set in DataSet::ALL {
+        let source = dir.join(data_set.name());
+        let files = generate(data_set, &source, size)?;
+
+        for (method, level) in SETTINGS {
+            let name = format!("{}-{}-{}", data_set.name(), method.name(), level);
+            let pack_options = PackOptions {
+                method: Some(method),
+                level: Compression::new(level),
+                name: Some(name.clone().into()),
+                verbosity: Verbosity::Quiet,
+                ..PackOptions::default()
+            };
+            let start = Instant::now();
+            pack(source.clone(), Some(dir.to_path_buf()), &pack_options)?;
+            let pack_time = start.elapsed();
+
+            let archive_path = dir.join(name).with_extension("slf");
+            let unpack_options = UnpackOptions {
+                file_system: Arc::new(MemFs::new()),
+                verbosity: Verbosity::Quiet,
+                ..UnpackOptions::default()
+            };
+            let start = Instant::now();
+            unpack(
+                archive_path.clone(),
+                Some(PathBuf::from("bench")),
+                &unpack_options,
+            )?;
+            let unpack_time = start.elapsed();
+
+            report.results.push(BenchResult {
+                data_set,
+                method,
+                level,
+                files,
+                original_size: size,
+                archive_size: archive_path.metadata()?.len(),
+                pack_time,
+                unpack_time,
+            });
+            fs::remove_file(&archive_path)?;
+        }
+    }
+    report.lookup = Some(lookup(dir)?);
+    Ok(report)
+}
+
+/// Packs `LOOKUP_ENTRIES` small files and extracts `LOOKUPS` of them spread
+/// over the archive by name, into memory.
+fn lookup(dir: &Path) -> Result<LookupResult> {
+    let source = dir.join("lookup");
+    let mut random = XorShift(0x9e37_79b9_7f4a_7c15);
+    let words: Vec<&[u8]> = WORDS.split_whitespace().map(str::as_bytes).collect();
+    for i in 0..LOOKUP_ENTRIES {
+        let subdir = source.join(format!("{:02}", i % 16));
+        fs::create_dir_all(&subdir)?;
+        write_file(
+            &subdir.join(format!("{}.txt", i)),
+            LOOKUP_FILE_SIZE,
+            |buf| text(&mut random, &words, buf),
+        )?;
+    }
+    let pack_options = PackOptions {
+        verbosity: Verbosity::Quiet,
+        ..PackOptions::default()
+    };
+    pack(source, Some(dir.to_path_buf()), &pack_options)?;
+    let archive_path = dir.join("lookup.slf");
+
+    let start = Instant::now();
+    let mut archive = ArchiveReader::open(&archive_path)?;
+    let open_time = start.elapsed();
+
+    let step = archive.entries().len() / LOOKUPS;
+    let names: Vec<_> = (0..LOOKUPS)
+        .filter_map(|i| archive.entries().get(i * step))
+        .map(|entry| entry.name().to_os_string())
+        .collect();
+    let start = Instant::now();
+    for name in &names {
+        io::copy(&mut archive.open_entry(name)?, &mut Vec::new())?;
+    }
+    let extract_time = start.elapsed();
+
+    Ok(LookupResult {
+        entries: archive.entries().len() as u64,
+        lookups: names.len(),
+        open_time,
+        extract_time,
+    })
+}
+
+/// Writes `data_set` of `size` bytes under `dir`, returning the count of files.
+fn generate(data_set: DataSet, dir: &Path, size: u64) -> Result<u64> {
+    fs::create_dir_all(dir)?;
+    let mut random = XorShift(0x9e37_79b9_7f4a_7c15);
+    let words: Vec<&[u8]> = WORDS.split_whitespace().map(str::as_bytes).collect();
+    let files = match data_set {
+        DataSet::Text => {
+            write_file(&dir.join("text.txt"), size, |buf| {
+                text(&mut random, &words, buf)
+            })?;
+            1
+        }
+        DataSet::Random => {
+            write_file(&dir.join("random.bin"), size, |buf| random.fill(buf))?;
+            1
+        }
+        DataSet::SmallFiles => {
+            let count = size.div_ceil(SMALL_FILE_SIZE);
+            for i in 0..count {
+                let subdir = dir.join(format!("{:02}", i % 16));
+                fs::create_dir_all(&subdir)?;
+                let len = SMALL_FILE_SIZE.min(size - i * SMALL_FILE_SIZE);
+                write_file(&subdir.join(format!("{}.txt", i)), len, |buf| {
+                    text(&mut random, &words, buf)
+                })?;
+            }
+            count
+        }
+    };
+    debug!(
+        "Generated {} of {} in {} files",
+        data_set.name(),
+        size,
+        files
+    );
+    Ok(files)
+}
+
+/// Writes `size` bytes produced by `fill` in blocks.
+fn write_file(path: &Path, size: u64, mut fill: impl FnMut(&mut [u8])) -> Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    let mut block = vec![0u8; 64 * 1024];
+    let mut remaining = size;
+    while remaining > 0 {
+        let len = remaining.min(block.len() as u64) as usize;
+        fill(&mut block[..len]);
+        writer.write_all(&block[..len])?;
+        remaining -= len as u64;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Fills `buf` with `words` separated by spaces and newlines.
+fn text(random: &mut XorShift, words: &[&[u8]], buf: &mut [u8]) {
+    let mut i = 0;
+    while i < buf.len() {
+        let word = words[(random.next() % words.len() as u64) as usize];
+        // lines of about a dozen words
+        let separator = if random.next().is_multiple_of(12) {
+            b'\n'
+        } else {
+            b' '
+        };
+        for &b in word.iter().chain(&[separator]) {
+            if i == buf.len() {
+                break;
+            }
+            buf[i] = b;
+            i += 1;
+        }
+    }
+}
+
+/// Generator of the data sets, seeded the same way every time so runs
+/// compare.
+struct XorShift(u64);
+
+impl XorShift {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn fill(&mut self, buf: &mut [u8]) {
+        for chunk in buf.chunks_mut(8) {
+            let bytes = self.next().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn measures_every_setting_on_every_data_set() {
+        let size = 64 * 1024;
+        let report = bench(size).unwrap();
+        assert_eq!(report.results.len(), DataSet::ALL.len() * SETTINGS.len());
+        assert!(
+            !env::temp_dir()
+                .join(format!("sulfur-bench-{}", process::id()))
+                .exists()
+        );
+
+        for result in &report.results {
+            let ratio = ratio(result.archive_size, result.original_size).unwrap();
+            let expected = match (result.data_set, result.method) {
+                (DataSet::SmallFiles, _) => {
+                    assert_eq!(result.files, size / SMALL_FILE_SIZE);
+                    continue;
+                }
+                (_, Method::Store) | (DataSet::Random, _) => 1.0..1.1,
+                (DataSet::Text, _) => 0.0..0.5,
+            };
+            assert!(expected.contains(&ratio), "{:?}: {}", result, ratio);
+        }
+        let lookup = report.lookup.as_ref().unwrap();
+        assert_eq!(lookup.entries, LOOKUP_ENTRIES);
+        assert_eq!(lookup.lookups, LOOKUPS);
+        assert_eq!(report.to_string().lines().count(), report.results.len() + 2);
+    }
+}
@@ -0,0 +1,210 @@
+use std::{
+    collections::HashSet,
+    ffi::OsString,
+    io::{self, Read, Write},
+};
+
+use flate2::{Compression, CrcWriter};
+
+use crate::{
+    Footer, InnerFile, SIGNATURE, VERSION,
+    checksum::ChecksumAlgorithm,
+    compressor::{Compressor, Method},
+    error::{ArchiveError, Result},
+};
+
+/// Assembles an archive from in-memory data instead of a directory tree.
+/// Entries are compressed as they're added and kept in memory until `finish`.
+#[derive(Default)]
+pub struct ArchiveBuilder {
+    method: Method,
+    entries: Vec<(InnerFile, Vec<u8>)>,
+    names: HashSet<OsString>,
+}
+
+impl ArchiveBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Compression method of the entries added afterwards.
+    pub fn method(&mut self, method: Method) -> &mut Self {
+        self.method = method;
+        self
+    }
+
+    pub fn add_bytes<N: Into<OsString>>(&mut self, name: N, bytes: &[u8]) -> Result<&mut Self> {
+        self.add_reader(name, bytes)
+    }
+
+    pub fn add_reader<N: Into<OsString>, R: Read>(
+        &mut self,
+        name: N,
+        mut reader: R,
+    ) -> Result<&mut Self> {
+        let name = name.into();
+        if name.is_empty() {
+            return Err(ArchiveError::EmptyFilename);
+        }
+        if !self.names.insert(name.clone()) {
+            return Err(ArchiveError::DuplicateEntry(
+                name.to_string_lossy().into_owned(),
+            ));
+        }
+
+        let mut compressor =
+            Compressor::with_method(Vec::new(), self.method, Compression::default());
+        io::copy(&mut reader, &mut compressor)?;
+        let (data, summary) = compressor.finish()?;
+
+        let mut inner_file = InnerFile::create(
+            name,
+            summary.original_size,
+            summary.compressed_size,
+            summary.original_checksum,
+            summary.compressed_checksum,
+        );
+        inner_file.set_method(self.method);
+
+        self.entries.push((inner_file, data));
+        Ok(self)
+    }
+
+    /// Writes the archive into `writer`. Sizes are known upfront, so unlike
+    /// `pack` nothing has to be patched afterwards and `writer` needn't seek.
+    pub fn finish<W: Write>(mut self, writer: W) -> Result<W> {
+        let mut writer = CrcWriter::new(writer);
+
+        // signature, version, file count, index offset, flags and checksum algorithm
+        let header_size = 21;
+        let mut position = header_size;
+        for (inner_file, data) in &mut self.entries {
+            inner_file.position = position;
+            position += inner_file.metadata_size() + data.len() as u64;
+        }
+        let index_offset = position;
+
+        writer.write_all(SIGNATURE)?;
+        writer.write_all(&VERSION.to_bytes())?;
+        writer.write_all(&u32::try_from(self.entries.len())?.to_le_bytes())?;
+        writer.write_all(&index_offset.to_le_bytes())?;
+        writer.write_all(&0u16.to_le_bytes())?; //flags
+        writer.write_all(&[ChecksumAlgorithm::Crc32.id()])?;
+
+        for (inner_file, data) in &self.entries {
+            inner_file.write_fields(&mut writer)?;
+            writer.write_all(data)?;
+        }
+
+        for (inner_file, _) in &self.entries {
+            writer.write_all(&inner_file.position.to_le_bytes())?;
+        }
+
+        let total_length = index_offset + 8 * self.entries.len() as u64 + Footer::size(VERSION);
+        Footer {
+            total_length,
+            checksum: None,
+        }
+        .write(&mut writer)?;
+
+        let checksum = writer.crc().sum();
+        let mut writer = writer.into_inner();
+        writer.write_all(&checksum.to_le_bytes())?;
+        writer.flush()?;
+        Ok(writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Cursor, path::Path, sync::Arc};
+
+    use flate2::{Crc, write::GzEncoder};
+
+    use super::*;
+    use crate::{
+        filesystem::MemFs,
+        reader::ArchiveReader,
+        unpack::{UnpackOptions, unpack_stream},
+    };
+
+    #[test]
+    fn two_entries_round_trip() {
+        let generated = b"generated line\n".repeat(1000);
+        let mut builder = ArchiveBuilder::new();
+        builder.add_bytes("bytes.txt", b"from a slice").unwrap();
+        builder
+            .method(Method::Store)
+            .add_reader("dir/reader.txt", generated.as_slice())
+            .unwrap();
+        let archive = builder.finish(Vec::new()).unwrap();
+
+        let mut reader = ArchiveReader::new(Cursor::new(archive)).unwrap();
+        reader.verify().unwrap();
+        let methods: Vec<_> = reader.entries().iter().map(InnerFile::method).collect();
+        assert_eq!(methods, [Method::Gzip, Method::Store]);
+
+        for (name, expected) in [
+            ("bytes.txt", b"from a slice".as_slice()),
+            ("dir/reader.txt", &generated),
+        ] {
+            let mut contents = Vec::new();
+            reader
+                .open_entry(name)
+                .unwrap()
+                .read_to_end(&mut contents)
+                .unwrap();
+            assert!(contents == expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn multi_member_gzip_entries_decode() {
+        let (first, second) = (
+            b"first member\n".repeat(100),
+            b"second member\n".repeat(100),
+        );
+        // as written by tools which compress in independent blocks
+        let mut data = Vec::new();
+        for part in [&first, &second] {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(part).unwrap();
+            data.extend(encoder.finish().unwrap());
+        }
+        let contents = [first, second].concat();
+        let checksum = |bytes: &[u8]| {
+            let mut crc = Crc::new();
+            crc.update(bytes);
+            crc.sum()
+        };
+        let mut inner_file = InnerFile::create(
+            "members.txt".into(),
+            contents.len() as u64,
+            data.len() as u64,
+            checksum(&contents),
+            checksum(&data),
+        );
+        inner_file.set_method(Method::Gzip);
+        let mut builder = ArchiveBuilder::new();
+        builder.entries.push((inner_file, data));
+        let archive = builder.finish(Vec::new()).unwrap();
+
+        let mut reader = ArchiveReader::new(Cursor::new(&archive)).unwrap();
+        reader.verify().unwrap();
+        let mut read = Vec::new();
+        reader
+            .open_entry("members.txt")
+            .unwrap()
+            .read_to_end(&mut read)
+            .unwrap();
+        assert!(read == contents);
+
+        let fs = Arc::new(MemFs::new());
+        let options = UnpackOptions {
+            file_system: fs.clone(),
+            ..UnpackOptions::default()
+        };
+        unpack_stream(archive.as_slice(), "out".into(), &options).unwrap();
+        assert!(fs.read(Path::new("out/members.txt")).unwrap() == contents);
+    }
+}
@@ -0,0 +1,169 @@
+use std::io::{self, Read, Write};
+
+use flate2::Crc;
+
+use crate::error::{ArchiveError, Result};
+
+/// Algorithm of the checksums of original contents, stored in the header
+/// since 1.9. Compressed contents and the archive checksum always use
+/// CRC32, which gzip needs anyway.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ChecksumAlgorithm {
+    /// The CRC32 of gzip, the only one before 1.9.
+    #[default]
+    Crc32 = 0,
+    /// CRC32C (Castagnoli), computed with the SSE4.2 or ARMv8 CRC
+    /// instructions where available. Only faster than CRC32 when those are
+    /// enabled at build time, e.g. with `-C target-cpu=native`.
+    Crc32c = 1,
+}
+
+impl ChecksumAlgorithm {
+    pub fn from_id(id: u8) -> Result<Self> {
+        match id {
+            0 => Ok(Self::Crc32),
+            1 => Ok(Self::Crc32c),
+            _ => Err(ArchiveError::CorruptedArchive(format!(
+                "Unknown checksum algorithm id: {}",
+                id
+            ))),
+        }
+    }
+
+    pub fn from_name(name: &str) -> Result<Self> {
+        match name {
+            "crc32" => Ok(Self::Crc32),
+            "crc32c" => Ok(Self::Crc32c),
+            _ => Err(ArchiveError::Usage(format!(
+                "Unknown checksum algorithm '{}', expected one of: crc32, crc32c",
+                name
+            ))),
+        }
+    }
+
+    pub fn id(self) -> u8 {
+        self as u8
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Crc32 => "crc32",
+            Self::Crc32c => "crc32c",
+        }
+    }
+}
+
+/// Running checksum of original contents by a `ChecksumAlgorithm`.
+pub enum Checksum {
+    Crc32(Crc),
+    Crc32c { sum: u32, amount: u64 },
+}
+
+impl Checksum {
+    pub fn new(algorithm: ChecksumAlgorithm) -> Self {
+        match algorithm {
+            ChecksumAlgorithm::Crc32 => Self::Crc32(Crc::new()),
+            ChecksumAlgorithm::Crc32c => Self::Crc32c { sum: 0, amount: 0 },
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        match self {
+            Self::Crc32(crc) => crc.update(data),
+            Self::Crc32c { sum, amount } => {
+                *sum = crc32c::crc32c_append(*sum, data);
+                *amount += data.len() as u64;
+            }
+        }
+    }
+
+    pub fn sum(&self) -> u32 {
+        match self {
+            Self::Crc32(crc) => crc.sum(),
+            Self::Crc32c { sum, .. } => *sum,
+        }
+    }
+
+    /// Extends the checksum as if the bytes of `other` were appended.
+    /// Both have to use the same algorithm.
+    pub fn combine(&mut self, other: &Self) {
+        match (self, other) {
+            (Self::Crc32(crc), Self::Crc32(other)) => crc.combine(other),
+            (
+                Self::Crc32c { sum, amount },
+                Self::Crc32c {
+                    sum: other,
+                    amount: len,
+                },
+            ) => {
+                // only entries above 4 GiB on 32-bit targets would be cut
+                *sum = crc32c::crc32c_combine(*sum, *other, *len as usize);
+                *amount += len;
+            }
+            _ => unreachable!("checksums of different algorithms combined"),
+        }
+    }
+}
+
+/// Reader checksumming the bytes read through it, like `flate2::CrcReader`.
+pub struct ChecksumReader<R: Read> {
+    reader: R,
+    checksum: Checksum,
+}
+
+impl<R: Read> ChecksumReader<R> {
+    pub fn new(reader: R, algorithm: ChecksumAlgorithm) -> Self {
+        Self {
+            reader,
+            checksum: Checksum::new(algorithm),
+        }
+    }
+
+    pub fn checksum(&self) -> &Checksum {
+        &self.checksum
+    }
+}
+
+impl<R: Read> Read for ChecksumReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let bytes = self.reader.read(buf)?;
+        self.checksum.update(&buf[..bytes]);
+        Ok(bytes)
+    }
+}
+
+/// Writer checksumming the bytes accepted by the inner writer, like
+/// `flate2::CrcWriter`.
+pub struct ChecksumWriter<W: Write> {
+    writer: W,
+    checksum: Checksum,
+}
+
+impl<W: Write> ChecksumWriter<W> {
+    pub fn new(writer: W, algorithm: ChecksumAlgorithm) -> Self {
+        Self {
+            writer,
+            checksum: Checksum::new(algorithm),
+        }
+    }
+
+    pub fn checksum(&self) -> &Checksum {
+        &self.checksum
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write> Write for ChecksumWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let bytes = self.writer.write(buf)?;
+        self.checksum.update(&buf[..bytes]);
+        Ok(bytes)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
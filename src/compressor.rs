@@ -0,0 +1,600 @@
+use std::{
+    io::{self, Chain, Cursor, Read, Write},
+    ops::RangeInclusive,
+    path::Path,
+};
+
+use flate2::{
+    Compression,
+    read::DeflateDecoder as DeflateReadDecoder,
+    write::{DeflateDecoder, DeflateEncoder, GzEncoder, MultiGzDecoder},
+};
+#[cfg(feature = "xz")]
+use xz2::write::{XzDecoder, XzEncoder};
+
+use crate::{
+    HasherWriter,
+    checksum::{Checksum, ChecksumAlgorithm},
+    error::{ArchiveError, Result},
+};
+
+/// Extensions of formats which don't get smaller when compressed again.
+const COMPRESSED_EXTENSIONS: &[&str] = &[
+    "zip", "gz", "tgz", "bz2", "xz", "zst", "7z", "rar", "slf", "png", "jpg", "jpeg", "gif",
+    "webp", "mp3", "mp4", "mkv", "avi", "mov", "webm", "ogg", "flac", "pdf", "docx", "xlsx", "jar",
+    "apk",
+];
+
+/// Largest dictionary a deflate stream can refer to, the size of its window.
+pub const MAX_DICTIONARY_SIZE: usize = 32 * 1024;
+
+/// Compression method of an entry, stored as a single byte id.
+/// Id `2` is reserved for zstd, `3` is xz and needs the `xz` feature.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Method {
+    Store = 0,
+    #[default]
+    Gzip = 1,
+    /// Slower than gzip, but smaller, for archives where the ratio matters.
+    #[cfg(feature = "xz")]
+    Xz = 3,
+    /// Raw deflate primed with the dictionary of the archive, an empty one
+    /// if it has none. Saves the gzip framing as well.
+    Deflate = 4,
+}
+
+impl Method {
+    pub fn from_id(id: u8) -> Result<Self> {
+        match id {
+            0 => Ok(Self::Store),
+            1 => Ok(Self::Gzip),
+            4 => Ok(Self::Deflate),
+            #[cfg(feature = "xz")]
+            3 => Ok(Self::Xz),
+            #[cfg(not(feature = "xz"))]
+            3 => Err(Self::unavailable("xz")),
+            2 => Err(Self::unavailable("zstd")),
+            _ => Err(ArchiveError::CorruptedArchive(format!(
+                "Unknown compression method id: {}",
+                id
+            ))),
+        }
+    }
+
+    pub fn from_name(name: &str) -> Result<Self> {
+        match name {
+            "store" => Ok(Self::Store),
+            "gzip" => Ok(Self::Gzip),
+            "deflate" => Ok(Self::Deflate),
+            "zstd" => Self::from_id(2),
+            "xz" => Self::from_id(3),
+            _ => Err(ArchiveError::Usage(format!(
+                "Unknown compression method '{}', expected one of: store, gzip, deflate, xz",
+                name
+            ))),
+        }
+    }
+
+    pub fn id(self) -> u8 {
+        self as u8
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Store => "store",
+            Self::Gzip => "gzip",
+            Self::Deflate => "deflate",
+            #[cfg(feature = "xz")]
+            Self::Xz => "xz",
+        }
+    }
+
+    /// Picks the method for a file by its extension: formats which are
+    /// compressed already are stored, everything else is gzipped.
+    pub fn for_path(path: &Path) -> Self {
+        let Some(extension) = path.extension().and_then(|ex| ex.to_str()) else {
+            return Self::Gzip;
+        };
+
+        if COMPRESSED_EXTENSIONS
+            .iter()
+            .any(|known| known.eq_ignore_ascii_case(extension))
+        {
+            Self::Store
+        } else {
+            Self::Gzip
+        }
+    }
+
+    /// Levels the method accepts, `None` if it ignores the level.
+    pub fn level_range(self) -> Option<RangeInclusive<u32>> {
+        match self {
+            Self::Store => None,
+            Self::Gzip | Self::Deflate => Some(0..=9),
+            #[cfg(feature = "xz")]
+            Self::Xz => Some(0..=9),
+        }
+    }
+
+    /// Rejects a `level` outside of `level_range` as a usage error.
+    pub fn check_level(self, level: Compression) -> Result<()> {
+        match self.level_range() {
+            Some(range) if !range.contains(&level.level()) => Err(ArchiveError::Usage(format!(
+                "Level {} is out of range for {}, expected {} to {}",
+                level.level(),
+                self.name(),
+                range.start(),
+                range.end()
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    fn unavailable(name: &str) -> ArchiveError {
+        ArchiveError::Unsupported(format!(
+            "Compression method '{}' isn't available in this build",
+            name
+        ))
+    }
+}
+
+/// Named trade-off between speed and size, an alternative to a level which
+/// means something else for every method. `Max` picks xz when the build has
+/// it, the others stay with gzip.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Preset {
+    Fast,
+    #[default]
+    Balanced,
+    Max,
+}
+
+impl Preset {
+    pub fn from_name(name: &str) -> Result<Self> {
+        match name {
+            "fast" => Ok(Self::Fast),
+            "balanced" => Ok(Self::Balanced),
+            "max" => Ok(Self::Max),
+            _ => Err(ArchiveError::Usage(format!(
+                "Unknown preset '{}', expected one of: fast, balanced, max",
+                name
+            ))),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Fast => "fast",
+            Self::Balanced => "balanced",
+            Self::Max => "max",
+        }
+    }
+
+    /// Method and level of the preset, the level within the range of the
+    /// method.
+    pub fn settings(self) -> (Method, Compression) {
+        match self {
+            Self::Fast => (Method::Gzip, Compression::fast()),
+            Self::Balanced => (Method::Gzip, Compression::default()),
+            #[cfg(feature = "xz")]
+            Self::Max => (Method::Xz, Compression::new(9)),
+            #[cfg(not(feature = "xz"))]
+            Self::Max => (Method::Gzip, Compression::best()),
+        }
+    }
+}
+
+/// Sizes and checksums of a finished compressed stream.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CompressionSummary {
+    pub original_size: u64,
+    pub compressed_size: u64,
+    pub original_checksum: u32,
+    /// Covers exactly the bytes which reached the inner writer, the gzip
+    /// trailer written by `finish` included, since every encoder writes
+    /// through the `HasherWriter` and nothing else writes past it.
+    pub compressed_checksum: u32,
+}
+
+enum Encoder<W: Write> {
+    Store(HasherWriter<W>),
+    Gzip(GzEncoder<HasherWriter<W>>),
+    Deflate(DeflateEncoder<Skip<HasherWriter<W>>>),
+    #[cfg(feature = "xz")]
+    Xz(XzEncoder<HasherWriter<W>>),
+}
+
+/// Compressor that wraps any writer and keeps track of the original and
+/// compressed sizes and checksums, without the `.slf` container.
+pub struct Compressor<W: Write> {
+    encoder: Encoder<W>,
+    checksum: Checksum,
+    original_size: u64,
+}
+
+impl<W: Write> Compressor<W> {
+    pub fn new(writer: W) -> Self {
+        Self::with_level(writer, Compression::default())
+    }
+
+    pub fn with_level(writer: W, level: Compression) -> Self {
+        Self::with_method(writer, Method::Gzip, level)
+    }
+
+    pub fn with_method(writer: W, method: Method, level: Compression) -> Self {
+        let hasher_writer = HasherWriter::new(writer, Checksum::new(ChecksumAlgorithm::Crc32));
+        let encoder = match method {
+            Method::Store => Encoder::Store(hasher_writer),
+            Method::Gzip => Encoder::Gzip(GzEncoder::new(hasher_writer, level)),
+            Method::Deflate => {
+                Encoder::Deflate(DeflateEncoder::new(Skip::new(hasher_writer, 0), level))
+            }
+            #[cfg(feature = "xz")]
+            Method::Xz => Encoder::Xz(XzEncoder::new(hasher_writer, level.level())),
+        };
+        Self {
+            encoder,
+            checksum: Checksum::new(ChecksumAlgorithm::Crc32),
+            original_size: 0,
+        }
+    }
+
+    /// Same as `with_method`, priming `Method::Deflate` with `dictionary`.
+    /// The dictionary is compressed and flushed first, but none of its output
+    /// reaches `writer`, so later matches refer to it without it being stored.
+    /// Other methods ignore it.
+    pub fn with_dictionary(
+        writer: W,
+        method: Method,
+        level: Compression,
+        dictionary: &[u8],
+    ) -> Result<Self> {
+        let mut compressor = Self::with_method(writer, method, level);
+        if let Encoder::Deflate(encoder) = &mut compressor.encoder
+            && !dictionary.is_empty()
+        {
+            encoder.get_mut().skip = u64::MAX;
+            encoder.write_all(dictionary)?;
+            // a sync flush ends the block on a byte boundary
+            encoder.flush()?;
+            encoder.get_mut().skip = 0;
+        }
+        Ok(compressor)
+    }
+
+    /// Checksums the original contents by `algorithm` instead of CRC32,
+    /// the compressed ones stay CRC32.
+    pub fn with_checksum(mut self, algorithm: ChecksumAlgorithm) -> Self {
+        self.checksum = Checksum::new(algorithm);
+        self
+    }
+
+    /// Writes the trailer and returns the inner writer with the summary.
+    pub fn finish(self) -> Result<(W, CompressionSummary)> {
+        let mut hasher_writer = match self.encoder {
+            Encoder::Store(hasher_writer) => hasher_writer,
+            Encoder::Gzip(encoder) => encoder.finish()?,
+            Encoder::Deflate(encoder) => encoder.finish()?.inner,
+            #[cfg(feature = "xz")]
+            Encoder::Xz(encoder) => encoder.finish()?,
+        };
+
+        let summary = CompressionSummary {
+            original_size: self.original_size,
+            compressed_size: hasher_writer.take_written_bytes(),
+            original_checksum: self.checksum.sum(),
+            compressed_checksum: hasher_writer.sum(),
+        };
+
+        Ok((hasher_writer.into_inner(), summary))
+    }
+}
+
+impl<W: Write> Write for Compressor<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let bytes = match &mut self.encoder {
+            Encoder::Store(writer) => writer.write(buf)?,
+            Encoder::Gzip(encoder) => encoder.write(buf)?,
+            Encoder::Deflate(encoder) => encoder.write(buf)?,
+            #[cfg(feature = "xz")]
+            Encoder::Xz(encoder) => encoder.write(buf)?,
+        };
+        self.checksum.update(&buf[..bytes]);
+        self.original_size += bytes as u64;
+        Ok(bytes)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.encoder {
+            Encoder::Store(writer) => writer.flush(),
+            Encoder::Gzip(encoder) => encoder.flush(),
+            Encoder::Deflate(encoder) => encoder.flush(),
+            #[cfg(feature = "xz")]
+            Encoder::Xz(encoder) => encoder.flush(),
+        }
+    }
+}
+
+/// Writer that decompresses data of the given method into the inner writer.
+pub enum Decompressor<W: Write> {
+    Store(W),
+    /// Decodes every gzip member, other tools may write several.
+    Gzip(MultiGzDecoder<W>),
+    Deflate(DeflateDecoder<Skip<W>>),
+    /// Decodes every xz stream, concatenated ones as well.
+    #[cfg(feature = "xz")]
+    Xz(XzDecoder<W>),
+}
+
+impl<W: Write> Decompressor<W> {
+    pub fn new(writer: W, method: Method) -> Self {
+        match method {
+            Method::Store => Self::Store(writer),
+            Method::Gzip => Self::Gzip(MultiGzDecoder::new(writer)),
+            Method::Deflate => Self::Deflate(DeflateDecoder::new(Skip::new(writer, 0))),
+            #[cfg(feature = "xz")]
+            Method::Xz => Self::Xz(XzDecoder::new_multi_decoder(writer)),
+        }
+    }
+
+    /// Same as `new`, priming `Method::Deflate` with the dictionary its data
+    /// was compressed against. Other methods ignore it.
+    pub fn with_dictionary(writer: W, method: Method, dictionary: &[u8]) -> Result<Self> {
+        let mut decompressor = Self::new(writer, method);
+        if let Self::Deflate(decoder) = &mut decompressor {
+            decoder.get_mut().skip = dictionary.len() as u64;
+            decoder.write_all(&stored_blocks(dictionary))?;
+        }
+        Ok(decompressor)
+    }
+
+    pub fn finish(self) -> Result<W> {
+        Ok(match self {
+            Self::Store(writer) => writer,
+            Self::Gzip(decoder) => decoder.finish()?,
+            Self::Deflate(decoder) => decoder.finish()?.inner,
+            #[cfg(feature = "xz")]
+            Self::Xz(mut decoder) => decoder.finish()?,
+        })
+    }
+}
+
+impl<W: Write> Write for Decompressor<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Store(writer) => writer.write(buf),
+            Self::Gzip(decoder) => decoder.write(buf),
+            Self::Deflate(decoder) => decoder.write(buf),
+            #[cfg(feature = "xz")]
+            Self::Xz(decoder) => decoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Store(writer) => writer.flush(),
+            Self::Gzip(decoder) => decoder.flush(),
+            Self::Deflate(decoder) => decoder.flush(),
+            #[cfg(feature = "xz")]
+            Self::Xz(decoder) => decoder.flush(),
+        }
+    }
+}
+
+/// Reader decompressing `Method::Deflate` data primed with a dictionary.
+pub struct DeflateReader<R: Read> {
+    decoder: DeflateReadDecoder<Chain<Cursor<Vec<u8>>, R>>,
+    skip: u64,
+}
+
+impl<R: Read> DeflateReader<R> {
+    pub fn new(reader: R, dictionary: &[u8]) -> Self {
+        let primed = Cursor::new(stored_blocks(dictionary)).chain(reader);
+        Self {
+            decoder: DeflateReadDecoder::new(primed),
+            skip: dictionary.len() as u64,
+        }
+    }
+
+    /// The reader of the compressed data, without the dictionary before it.
+    pub fn get_mut(&mut self) -> &mut R {
+        self.decoder.get_mut().get_mut().1
+    }
+}
+
+impl<R: Read> Read for DeflateReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.skip > 0 {
+            let skipped = io::copy(&mut (&mut self.decoder).take(self.skip), &mut io::sink())?;
+            if skipped < self.skip {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            self.skip = 0;
+        }
+        self.decoder.read(buf)
+    }
+}
+
+/// Encodes `data` as uncompressed deflate blocks, which leave it in the
+/// window of the decoder like a dictionary would.
+fn stored_blocks(data: &[u8]) -> Vec<u8> {
+    let mut blocks = Vec::with_capacity(data.len() + data.len() / 0xffff * 5 + 5);
+    for block in data.chunks(0xffff) {
+        let len = block.len() as u16;
+        // not final, stored, padded to the byte boundary
+        blocks.push(0);
+        blocks.extend_from_slice(&len.to_le_bytes());
+        blocks.extend_from_slice(&(!len).to_le_bytes());
+        blocks.extend_from_slice(block);
+    }
+    blocks
+}
+
+/// Writer dropping the first `skip` bytes written into it, which hides the
+/// dictionary a deflate stream is primed with.
+pub struct Skip<W: Write> {
+    inner: W,
+    skip: u64,
+}
+
+impl<W: Write> Skip<W> {
+    fn new(inner: W, skip: u64) -> Self {
+        Self { inner, skip }
+    }
+}
+
+impl<W: Write> Write for Skip<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.skip > 0 {
+            let skipped = buf
+                .len()
+                .min(usize::try_from(self.skip).unwrap_or(usize::MAX));
+            self.skip -= skipped as u64;
+            return Ok(skipped);
+        }
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use flate2::Crc;
+
+    use super::*;
+
+    #[test]
+    fn compressor_summarizes_and_round_trips() {
+        let input = b"line of text\n".repeat(1000);
+        for method in [
+            Method::Store,
+            Method::Gzip,
+            Method::Deflate,
+            #[cfg(feature = "xz")]
+            Method::Xz,
+        ] {
+            let mut compressor =
+                Compressor::with_method(Vec::new(), method, Compression::default());
+            // in pieces, as a caller streaming its input would
+            for chunk in input.chunks(1000) {
+                compressor.write_all(chunk).unwrap();
+            }
+            let (output, summary) = compressor.finish().unwrap();
+
+            let crc = |bytes: &[u8]| {
+                let mut crc = Crc::new();
+                crc.update(bytes);
+                crc.sum()
+            };
+            assert_eq!(
+                summary.original_size,
+                input.len() as u64,
+                "{}",
+                method.name()
+            );
+            assert_eq!(
+                summary.compressed_size,
+                output.len() as u64,
+                "{}",
+                method.name()
+            );
+            assert_eq!(summary.original_checksum, crc(&input), "{}", method.name());
+            assert_eq!(
+                summary.compressed_checksum,
+                crc(&output),
+                "{}",
+                method.name()
+            );
+            if method != Method::Store {
+                assert!(output.len() < input.len() / 10, "{}", method.name());
+            }
+
+            let mut decompressor = Decompressor::new(Vec::new(), method);
+            decompressor.write_all(&output).unwrap();
+            assert!(decompressor.finish().unwrap() == input, "{}", method.name());
+        }
+    }
+
+    #[test]
+    fn presets_select_levels() {
+        #[cfg(feature = "xz")]
+        let max = Method::Xz;
+        #[cfg(not(feature = "xz"))]
+        let max = Method::Gzip;
+        for (name, method, level) in [
+            ("fast", Method::Gzip, 1),
+            ("balanced", Method::Gzip, 6),
+            ("max", max, 9),
+        ] {
+            let preset = Preset::from_name(name).unwrap();
+            assert_eq!(preset.name(), name);
+            let (selected, selected_level) = preset.settings();
+            assert_eq!(selected, method, "{}", name);
+            assert_eq!(selected_level.level(), level, "{}", name);
+            selected.check_level(selected_level).unwrap();
+        }
+        assert!(matches!(
+            Preset::from_name("slow"),
+            Err(ArchiveError::Usage(_))
+        ));
+    }
+
+    #[test]
+    fn levels_are_checked_per_method() {
+        for method in [
+            Method::Gzip,
+            Method::Deflate,
+            #[cfg(feature = "xz")]
+            Method::Xz,
+        ] {
+            assert_eq!(method.level_range(), Some(0..=9));
+            method.check_level(Compression::new(0)).unwrap();
+            match method.check_level(Compression::new(10)) {
+                Err(ArchiveError::Usage(message)) => assert_eq!(
+                    message,
+                    format!(
+                        "Level 10 is out of range for {}, expected 0 to 9",
+                        method.name()
+                    )
+                ),
+                result => panic!("{:?}", result),
+            }
+        }
+        // stored contents ignore the level
+        assert_eq!(Method::Store.level_range(), None);
+        Method::Store.check_level(Compression::new(22)).unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "xz")]
+    fn xz_round_trip() {
+        let data = b"line of text\n".repeat(10_000);
+        let mut compressor =
+            Compressor::with_method(Vec::new(), Method::Xz, Compression::default());
+        compressor.write_all(&data).unwrap();
+        let (compressed, summary) = compressor.finish().unwrap();
+        assert!(compressed.len() < data.len() / 10);
+        assert_eq!(summary.original_size, data.len() as u64);
+
+        let mut decompressor = Decompressor::new(Vec::new(), Method::Xz);
+        decompressor.write_all(&compressed).unwrap();
+        assert!(decompressor.finish().unwrap() == data);
+    }
+
+    #[test]
+    #[cfg(not(feature = "xz"))]
+    fn xz_is_unavailable_without_the_feature() {
+        for result in [Method::from_name("xz"), Method::from_id(3)] {
+            match result {
+                Err(ArchiveError::Unsupported(message)) => assert_eq!(
+                    message,
+                    "Compression method 'xz' isn't available in this build"
+                ),
+                result => panic!("{:?}", result),
+            }
+        }
+    }
+}
@@ -0,0 +1,295 @@
+use std::{
+    collections::HashMap,
+    io::{self, Read, Seek, SeekFrom, Take, Write},
+    path::Path,
+    sync::atomic::AtomicBool,
+};
+
+use flate2::{
+    Compression, Crc, CrcReader,
+    read::{DeflateDecoder, MultiGzDecoder},
+};
+#[cfg(feature = "xz")]
+use xz2::read::XzDecoder;
+
+use crate::{
+    HasherWriter, check_cancelled,
+    checksum::{Checksum, ChecksumAlgorithm},
+    compressor::{CompressionSummary, Compressor, Method},
+    error::{ArchiveError, Result},
+    limiter::RateLimiter,
+    volume::VolumeReader,
+};
+
+/// Size of the chunks files are cut into by `PackOptions::dedup`.
+pub const CHUNK_SIZE: usize = 64 * 1024;
+/// Largest original size of a chunk accepted when reading.
+pub const MAX_CHUNK_SIZE: usize = 1 << 20;
+
+/// Record holding a chunk: method (1 byte), original size (4 bytes),
+/// compressed size (4 bytes) and the compressed chunk.
+const LITERAL: u8 = 0;
+/// Record pointing at a literal record earlier in the archive: offset (8 bytes).
+const REFERENCE: u8 = 1;
+
+/// Chunks written so far by their checksum and length, along with a handle
+/// of the archive being written, so a chunk is compared byte by byte before
+/// being referenced.
+pub(crate) struct ChunkStore {
+    chunks: HashMap<(u32, usize), u64>,
+    archive: VolumeReader,
+    chunk: Vec<u8>,
+    stored: Vec<u8>,
+    compressed: Vec<u8>,
+    checksum: ChecksumAlgorithm,
+}
+
+impl ChunkStore {
+    pub(crate) fn new(archive_path: &Path, checksum: ChecksumAlgorithm) -> Result<Self> {
+        Ok(Self {
+            chunks: HashMap::new(),
+            archive: VolumeReader::open(archive_path)?,
+            chunk: vec![0u8; CHUNK_SIZE],
+            stored: Vec::with_capacity(CHUNK_SIZE),
+            compressed: Vec::with_capacity(CHUNK_SIZE),
+            checksum,
+        })
+    }
+
+    /// Writes contents of `reader` as chunk records, referencing chunks
+    /// written before instead of storing them again.
+    pub(crate) fn write_entry<R: Read, W: Write + Seek>(
+        &mut self,
+        reader: &mut R,
+        method: Method,
+        writer: &mut W,
+        limiter: &mut RateLimiter,
+        cancel: Option<&AtomicBool>,
+    ) -> Result<CompressionSummary> {
+        let mut position = writer.stream_position()?;
+        let mut records = HasherWriter::new(writer, Checksum::new(ChecksumAlgorithm::Crc32));
+        let mut original_checksum = Checksum::new(self.checksum);
+        let mut original_size = 0;
+
+        loop {
+            check_cancelled(cancel)?;
+
+            let len = fill_chunk(reader, &mut self.chunk)?;
+            if len == 0 {
+                break;
+            }
+            limiter.consume(len);
+
+            let chunk = &self.chunk[..len];
+            original_checksum.update(chunk);
+            original_size += len as u64;
+
+            let mut checksum = Crc::new();
+            checksum.update(chunk);
+            let key = (checksum.sum(), len);
+
+            if let Some(&offset) = self.chunks.get(&key) {
+                // the chunk has to be on disk to be read back
+                records.flush()?;
+                self.archive.refresh()?;
+                self.archive.seek(SeekFrom::Start(offset + 1))?;
+                read_literal(&mut self.archive, &mut self.compressed, &mut self.stored)?;
+
+                if self.stored == chunk {
+                    records.write_all(&[REFERENCE])?;
+                    records.write_all(&offset.to_le_bytes())?;
+                    position += 9;
+                    continue;
+                }
+            }
+
+            let mut compressor =
+                Compressor::with_method(Vec::new(), method, Compression::default());
+            compressor.write_all(chunk)?;
+            let (compressed, _) = compressor.finish()?;
+            // chunks which don't shrink are stored, which also bounds their size
+            let (method, compressed) = if compressed.len() < len {
+                (method, compressed.as_slice())
+            } else {
+                (Method::Store, chunk)
+            };
+
+            records.write_all(&[LITERAL, method.id()])?;
+            records.write_all(&u32::try_from(len)?.to_le_bytes())?;
+            records.write_all(&u32::try_from(compressed.len())?.to_le_bytes())?;
+            records.write_all(compressed)?;
+
+            self.chunks.entry(key).or_insert(position);
+            position += 10 + compressed.len() as u64;
+        }
+
+        Ok(CompressionSummary {
+            original_size,
+            compressed_size: records.take_written_bytes(),
+            original_checksum: original_checksum.sum(),
+            compressed_checksum: records.sum(),
+        })
+    }
+}
+
+/// Reads until `chunk` is full or the reader is exhausted.
+fn fill_chunk<R: Read>(reader: &mut R, chunk: &mut [u8]) -> io::Result<usize> {
+    let mut len = 0;
+    while len < chunk.len() {
+        match reader.read(&mut chunk[len..]) {
+            Ok(0) => break,
+            Ok(bytes) => len += bytes,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(len)
+}
+
+/// Reads a literal record following its kind byte and decodes the chunk
+/// into `chunk`.
+fn read_literal<R: Read>(
+    reader: &mut R,
+    compressed: &mut Vec<u8>,
+    chunk: &mut Vec<u8>,
+) -> Result<()> {
+    let mut fields = [0u8; 9];
+    reader.read_exact(&mut fields)?;
+    let method = Method::from_id(fields[0])?;
+    let original_size = u32::from_le_bytes(fields[1..5].try_into()?) as usize;
+    let compressed_size = u32::from_le_bytes(fields[5..9].try_into()?) as usize;
+
+    if original_size > MAX_CHUNK_SIZE || compressed_size > MAX_CHUNK_SIZE {
+        return Err(ArchiveError::CorruptedArchive(format!(
+            "Chunk of {} bytes ({} compressed) exceeds the limit of {}",
+            original_size, compressed_size, MAX_CHUNK_SIZE
+        )));
+    }
+
+    compressed.resize(compressed_size, 0);
+    reader.read_exact(compressed)?;
+
+    chunk.clear();
+    match method {
+        Method::Store => chunk.extend_from_slice(compressed),
+        Method::Gzip => {
+            MultiGzDecoder::new(compressed.as_slice())
+                .take(original_size as u64 + 1)
+                .read_to_end(chunk)?;
+        }
+        Method::Deflate => {
+            DeflateDecoder::new(compressed.as_slice())
+                .take(original_size as u64 + 1)
+                .read_to_end(chunk)?;
+        }
+        #[cfg(feature = "xz")]
+        Method::Xz => {
+            XzDecoder::new_multi_decoder(compressed.as_slice())
+                .take(original_size as u64 + 1)
+                .read_to_end(chunk)?;
+        }
+    }
+
+    if chunk.len() != original_size {
+        return Err(ArchiveError::CorruptedArchive(format!(
+            "Archive corrupted! Unpacked chunk has another size! {} isn't equal to {}",
+            original_size,
+            chunk.len()
+        )));
+    }
+    Ok(())
+}
+
+/// Decodes the chunk records of an entry at the current position of the
+/// reader, following references to chunks stored earlier in the archive.
+pub struct ChunkDecoder<'a, R: Read + Seek> {
+    records: CrcReader<Take<&'a mut R>>,
+    chunk: Vec<u8>,
+    compressed: Vec<u8>,
+    consumed: usize,
+}
+
+impl<'a, R: Read + Seek> ChunkDecoder<'a, R> {
+    pub fn new(reader: &'a mut R, compressed_size: u64) -> Self {
+        Self {
+            records: CrcReader::new(reader.take(compressed_size)),
+            chunk: Vec::new(),
+            compressed: Vec::new(),
+            consumed: 0,
+        }
+    }
+
+    /// Returns the next decoded chunk, `None` after the last record.
+    pub fn next_chunk(&mut self) -> Result<Option<&[u8]>> {
+        if self.records.get_ref().limit() == 0 {
+            return Ok(None);
+        }
+
+        let mut kind = [0u8];
+        self.records.read_exact(&mut kind)?;
+
+        match kind[0] {
+            LITERAL => read_literal(&mut self.records, &mut self.compressed, &mut self.chunk)?,
+            REFERENCE => {
+                let mut offset = [0u8; 8];
+                self.records.read_exact(&mut offset)?;
+                let offset = u64::from_le_bytes(offset);
+
+                // the referenced chunk isn't part of this entry's checksum
+                let archive = self.records.get_mut().get_mut();
+                let position = archive.stream_position()?;
+                if offset >= position {
+                    return Err(ArchiveError::CorruptedArchive(format!(
+                        "Chunk reference at {} points forward to {}",
+                        position, offset
+                    )));
+                }
+
+                archive.seek(SeekFrom::Start(offset))?;
+                archive.read_exact(&mut kind)?;
+                if kind[0] != LITERAL {
+                    return Err(ArchiveError::CorruptedArchive(format!(
+                        "Chunk reference points to offset {}, which isn't a chunk",
+                        offset
+                    )));
+                }
+                read_literal(archive, &mut self.compressed, &mut self.chunk)?;
+                archive.seek(SeekFrom::Start(position))?;
+            }
+            kind => {
+                return Err(ArchiveError::CorruptedArchive(format!(
+                    "Unknown chunk record kind: {}",
+                    kind
+                )));
+            }
+        }
+
+        Ok(Some(&self.chunk))
+    }
+
+    /// Checksum of all records of the entry, reading the rest of them.
+    pub fn compressed_checksum(&mut self) -> Result<u32> {
+        io::copy(&mut self.records, &mut io::sink())?;
+        Ok(self.records.crc().sum())
+    }
+}
+
+impl<R: Read + Seek> Read for ChunkDecoder<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.consumed == self.chunk.len() {
+            if self
+                .next_chunk()
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+                .is_none()
+            {
+                return Ok(0);
+            }
+            self.consumed = 0;
+        }
+
+        let bytes = buf.len().min(self.chunk.len() - self.consumed);
+        buf[..bytes].copy_from_slice(&self.chunk[self.consumed..self.consumed + bytes]);
+        self.consumed += bytes;
+        Ok(bytes)
+    }
+}
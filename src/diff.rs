@@ -0,0 +1,140 @@
+use std::{collections::BTreeMap, ffi::OsString, fmt, path::Path};
+
+use crate::{InnerFile, error::Result, reader::ArchiveReader};
+
+/// Entries differing between two archives, each list sorted by name.
+#[derive(Debug, Default)]
+pub struct ArchiveDiff {
+    /// Entries only in the second archive.
+    pub added: Vec<InnerFile>,
+    /// Entries only in the first archive.
+    pub removed: Vec<InnerFile>,
+    /// Entries in both with another original size or checksum, as `(old, new)`.
+    pub modified: Vec<(InnerFile, InnerFile)>,
+}
+
+impl ArchiveDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.modified.is_empty()
+    }
+}
+
+/// Compares entries of two archives by name, reading only their indexes.
+/// Of entries with the same name in one archive the first one is compared.
+pub fn diff<P: AsRef<Path>>(old: P, new: P) -> Result<ArchiveDiff> {
+    let old = entries_by_name(old.as_ref())?;
+    let mut new = entries_by_name(new.as_ref())?;
+
+    let mut diff = ArchiveDiff::default();
+    for (name, old_entry) in old {
+        match new.remove(name.as_os_str()) {
+            Some(new_entry)
+                if new_entry.original_size() != old_entry.original_size()
+                    || new_entry.original_checksum() != old_entry.original_checksum() =>
+            {
+                diff.modified.push((old_entry, new_entry))
+            }
+            Some(_) => {}
+            None => diff.removed.push(old_entry),
+        }
+    }
+    diff.added = new.into_values().collect();
+
+    Ok(diff)
+}
+
+fn entries_by_name(path: &Path) -> Result<BTreeMap<OsString, InnerFile>> {
+    let archive = ArchiveReader::open(path)?;
+
+    let mut entries = BTreeMap::new();
+    for entry in archive.entries() {
+        entries
+            .entry(entry.name().to_os_string())
+            .or_insert_with(|| entry.clone());
+    }
+    Ok(entries)
+}
+
+impl fmt::Display for ArchiveDiff {
+    /// Lists the entries in `added`, `removed` and `modified` sections,
+    /// every line ends with a newline.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "no differences");
+        }
+
+        if !self.added.is_empty() {
+            writeln!(f, "added:")?;
+            for entry in &self.added {
+                let name = entry.name().to_string_lossy();
+                writeln!(f, "  + {} ({} bytes)", name, entry.original_size())?;
+            }
+        }
+        if !self.removed.is_empty() {
+            writeln!(f, "removed:")?;
+            for entry in &self.removed {
+                let name = entry.name().to_string_lossy();
+                writeln!(f, "  - {} ({} bytes)", name, entry.original_size())?;
+            }
+        }
+        if !self.modified.is_empty() {
+            writeln!(f, "modified:")?;
+            for (old, new) in &self.modified {
+                writeln!(
+                    f,
+                    "  ~ {} ({} -> {} bytes)",
+                    new.name().to_string_lossy(),
+                    old.original_size(),
+                    new.original_size()
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::{builder::ArchiveBuilder, testing::TempDir};
+
+    fn write_archive(path: &Path, files: &[(&str, &[u8])]) {
+        let mut builder = ArchiveBuilder::new();
+        for (name, contents) in files {
+            builder.add_bytes(*name, contents).unwrap();
+        }
+        fs::write(path, builder.finish(Vec::new()).unwrap()).unwrap();
+    }
+
+    #[test]
+    fn reports_modified_and_added() {
+        let dir = TempDir::new("diff");
+        let (old, new) = (dir.join("old.slf"), dir.join("new.slf"));
+        write_archive(&old, &[("same.txt", b"same"), ("changed.txt", b"before")]);
+        write_archive(
+            &new,
+            &[
+                ("changed.txt", b"BEFORE"),
+                ("same.txt", b"same"),
+                ("new.txt", b"new"),
+            ],
+        );
+
+        let changes = diff(&old, &new).unwrap();
+        assert_eq!(changes.added.len(), 1);
+        assert_eq!(changes.added[0].name(), "new.txt");
+        assert!(changes.removed.is_empty());
+        assert_eq!(changes.modified.len(), 1);
+        assert_eq!(changes.modified[0].1.name(), "changed.txt");
+        assert_eq!(
+            changes.to_string(),
+            "added:\n  + new.txt (3 bytes)\nmodified:\n  ~ changed.txt (6 -> 6 bytes)\n"
+        );
+
+        let reversed = diff(&new, &old).unwrap();
+        assert_eq!(reversed.removed[0].name(), "new.txt");
+        assert!(diff(&old, &old).unwrap().is_empty());
+    }
+}
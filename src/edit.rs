@@ -0,0 +1,393 @@
+use std::{
+    ffi::OsStr,
+    fs::{File, remove_file},
+    io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use log::{debug, warn};
+
+use crate::{
+    BUFFER_SIZE, FLAG_SIGNED, Header, InnerFile, SIGNATURE, VERSION,
+    error::{ArchiveError, Result},
+    pack::{persist, temp_path, write_dictionary, write_footer, write_index_array},
+    reader::ArchiveReader,
+    stays_inside,
+    volume::is_first_volume,
+};
+
+/// Renames the entry `old` to `new`, along with the hard links to it. Names
+/// are length-prefixed, so the archive is rewritten into a temporary file
+/// next to it, which then replaces the original. Contents of the entries are
+/// copied as they are.
+pub fn rename<P: AsRef<Path>>(archive_path: P, old: &OsStr, new: &OsStr) -> Result<()> {
+    let archive_path = archive_path.as_ref();
+    if new.is_empty() {
+        return Err(ArchiveError::Usage("New entry name is empty".to_string()));
+    }
+    // unpacking would otherwise write outside of the target
+    if Path::new(new).file_name().is_none() || !stays_inside(Path::new(new)) {
+        return Err(ArchiveError::Path(format!(
+            "New entry name '{}' points outside of the archive",
+            new.to_string_lossy()
+        )));
+    }
+
+    let (header, mut entries) = read_entries(archive_path)?;
+    if entries.iter().any(|e| e.name() == new) {
+        return Err(ArchiveError::Path(format!(
+            "Entry '{}' already exists in the archive",
+            new.to_string_lossy()
+        )));
+    }
+
+    let entry = entries
+        .iter_mut()
+        .find(|e| e.name() == old)
+        .ok_or_else(|| ArchiveError::EntryNotFound(old.to_string_lossy().into_owned()))?;
+    entry.set_name(new.to_os_string());
+    for entry in entries.iter_mut() {
+        if entry.link_target() == Some(old) {
+            entry.set_link_target(Some(new.to_os_string()));
+        }
+    }
+
+    debug!(
+        "Renaming '{}' to '{}'",
+        old.to_string_lossy(),
+        new.to_string_lossy()
+    );
+    rewrite(archive_path, &header, &mut entries)
+}
+
+/// Removes the entry `name`, rewriting the archive like `rename`. Solid
+/// archives would need their block recompressed and are refused.
+pub fn remove<P: AsRef<Path>>(archive_path: P, name: &OsStr) -> Result<()> {
+    let archive_path = archive_path.as_ref();
+
+    let (header, mut entries) = read_entries(archive_path)?;
+    let index = entries
+        .iter()
+        .position(|e| e.name() == name)
+        .ok_or_else(|| ArchiveError::EntryNotFound(name.to_string_lossy().into_owned()))?;
+
+    if header.is_solid() {
+        return Err(ArchiveError::Unsupported(
+            "Entries can't be removed from solid archives".to_string(),
+        ));
+    }
+
+    if let Some(link) = entries.iter().find(|e| e.link_target() == Some(name)) {
+        return Err(ArchiveError::Usage(format!(
+            "Entry '{}' holds the contents of the hard link '{}', remove the link first",
+            name.to_string_lossy(),
+            link.name().to_string_lossy()
+        )));
+    }
+
+    entries.remove(index);
+    debug!("Removing '{}'", name.to_string_lossy());
+    rewrite(archive_path, &header, &mut entries)
+}
+
+/// Reads the header and entries of an archive about to be rewritten,
+/// checking its checksum first, so the new one doesn't cover damaged data.
+fn read_entries(archive_path: &Path) -> Result<(Header, Vec<InnerFile>)> {
+    if is_first_volume(archive_path) {
+        return Err(ArchiveError::Unsupported(
+            "Split archives can't be modified in place".to_string(),
+        ));
+    }
+
+    let mut archive = ArchiveReader::open(archive_path)?;
+    archive.verify_archive_checksum()?;
+
+    if archive.header().is_dedup() {
+        return Err(ArchiveError::Unsupported(
+            "Deduplicated archives can't be modified in place, chunks are referenced by offset"
+                .to_string(),
+        ));
+    }
+    check_rewritable(archive.header())?;
+
+    warn_signature_dropped(archive.header(), archive_path);
+    Ok((archive.header().clone(), archive.entries().to_vec()))
+}
+
+/// Fails for archives of a later minor version, as rewriting them at the
+/// current one would drop the metadata this build doesn't know.
+pub(crate) fn check_rewritable(header: &Header) -> Result<()> {
+    if header.version > VERSION {
+        return Err(ArchiveError::Unsupported(format!(
+            "Archive of format version {} can't be rewritten by this build of {}, metadata added since would be lost",
+            header.version, VERSION
+        )));
+    }
+    Ok(())
+}
+
+/// Warns that the rewritten archive won't be signed anymore, see `FLAG_SIGNED`.
+pub(crate) fn warn_signature_dropped(header: &Header, archive_path: &Path) {
+    if header.is_signed() {
+        warn!(
+            "'{}' is signed, the rewritten archive won't be, sign it again",
+            archive_path.display()
+        );
+    }
+}
+
+/// Writes `entries` into a new archive of the current version, copying the
+/// contents of each from its position in the original, which is replaced.
+fn rewrite(archive_path: &Path, header: &Header, entries: &mut [InnerFile]) -> Result<()> {
+    let temp_path = temp_path(archive_path);
+    let result = File::open(archive_path)
+        .and_then(|source| Ok((BufReader::new(source), File::create(&temp_path)?)))
+        .map_err(ArchiveError::from)
+        .and_then(|(mut reader, file)| {
+            write_entries(
+                &mut reader,
+                BufWriter::new(file),
+                &temp_path,
+                header,
+                entries,
+            )
+        });
+
+    if let Err(e) = result {
+        if temp_path.exists() {
+            remove_file(&temp_path)?;
+        }
+        return Err(e);
+    }
+
+    persist(&temp_path, archive_path)
+}
+
+fn write_entries(
+    reader: &mut BufReader<File>,
+    mut writer: BufWriter<File>,
+    temp_path: &Path,
+    header: &Header,
+    entries: &mut [InnerFile],
+) -> Result<()> {
+    writer.write_all(SIGNATURE)?;
+    writer.write_all(&VERSION.to_bytes())?;
+    writer.write_all(&u32::try_from(entries.len())?.to_le_bytes())?; //file count
+    writer.write_all(&u64::to_le_bytes(0))?; //index offset
+    // the signature can't be renewed without the private key
+    writer.write_all(&(header.flags & !FLAG_SIGNED).to_le_bytes())?;
+    writer.write_all(&[header.checksum.id()])?;
+    if header.has_dictionary() {
+        write_dictionary(&mut writer, &header.dictionary)?;
+    }
+
+    let mut buffer = vec![0u8; BUFFER_SIZE];
+    // the solid block follows the metadata of the last entry
+    let block_entry = entries.iter().map(InnerFile::position).max();
+
+    // kept in the stored order, which a solid block depends on
+    let mut order: Vec<usize> = (0..entries.len()).collect();
+    order.sort_by_key(|&i| entries[i].position());
+
+    for i in order {
+        let entry = &mut entries[i];
+        let position = entry.position();
+        entry.write_metadata(&mut writer)?;
+
+        if !header.is_solid() {
+            // skips the old metadata, which may be of an older version
+            reader.seek(SeekFrom::Start(position))?;
+            InnerFile::from_archive(reader, &mut buffer, header.version)?;
+            io::copy(&mut reader.take(entry.compressed_size()), &mut writer)?;
+        }
+    }
+
+    if header.is_solid()
+        && let Some(position) = block_entry
+    {
+        reader.seek(SeekFrom::Start(position))?;
+        InnerFile::from_archive(reader, &mut buffer, header.version)?;
+        copy_solid_block(reader, &mut writer, &mut buffer)?;
+    }
+
+    let index_offset = writer.stream_position()?;
+    write_index_array(&mut writer, entries)?;
+    writer.seek(SeekFrom::Start(10))?;
+    writer.write_all(&index_offset.to_le_bytes())?;
+    writer.seek(SeekFrom::End(0))?;
+
+    write_footer(&mut writer, temp_path)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Copies the solid block at the current position of `reader`, including
+/// its sizes and checksums.
+fn copy_solid_block<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    buffer: &mut [u8],
+) -> Result<()> {
+    reader.read_exact(&mut buffer[..16])?;
+    let compressed_size = u64::from_le_bytes(buffer[..8].try_into()?);
+    writer.write_all(&buffer[..16])?;
+
+    let copied = io::copy(&mut reader.take(compressed_size), writer)?;
+    if copied != compressed_size {
+        return Err(ArchiveError::CorruptedArchive(format!(
+            "Solid block claims {} compressed bytes, but only {} remain in the archive",
+            compressed_size, copied
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io::Read, path::PathBuf};
+
+    use flate2::Compression;
+
+    use super::*;
+    use crate::{
+        Version,
+        builder::ArchiveBuilder,
+        compressor::Method,
+        reader::ArchiveReader,
+        repack::repack,
+        testing::{self, TempDir},
+    };
+
+    #[test]
+    fn newer_archives_are_not_rewritten() {
+        let mut builder = ArchiveBuilder::new();
+        builder.add_bytes("a.txt", b"a").unwrap();
+        let mut archive = builder.finish(Vec::new()).unwrap();
+        testing::set_version(&mut archive, Version::new(VERSION.major, VERSION.minor + 1));
+
+        let dir = TempDir::new("newer-rewrite");
+        let path = dir.join("a.slf");
+        fs::write(&path, &archive).unwrap();
+
+        let results = [
+            rename(&path, OsStr::new("a.txt"), OsStr::new("b.txt")),
+            remove(&path, OsStr::new("a.txt")),
+            repack(
+                &path,
+                dir.join("b.slf"),
+                Method::Gzip,
+                Compression::default(),
+            ),
+        ];
+        for result in results {
+            assert!(matches!(result, Err(ArchiveError::Unsupported(_))));
+        }
+        assert_eq!(fs::read(&path).unwrap(), archive);
+        assert!(!dir.join("b.slf").exists());
+    }
+
+    /// Writes an archive of `files` into `dir` and returns its path.
+    fn write_archive(dir: &TempDir, files: &[(&str, &[u8])]) -> PathBuf {
+        let mut builder = ArchiveBuilder::new();
+        for (name, contents) in files {
+            builder.add_bytes(*name, contents).unwrap();
+        }
+        let path = dir.join("edited.slf");
+        fs::write(&path, builder.finish(Vec::new()).unwrap()).unwrap();
+        path
+    }
+
+    /// Names and contents of the entries of the archive at `path`, in order.
+    fn read_archive(path: &Path) -> Vec<(String, Vec<u8>)> {
+        let mut reader = ArchiveReader::open(path).unwrap();
+        reader.verify().unwrap();
+        (0..reader.entries().len())
+            .map(|i| {
+                let mut entry = reader.open_entry_at(i).unwrap();
+                let name = entry.entry().name().to_string_lossy().into_owned();
+                let mut contents = Vec::new();
+                entry.read_to_end(&mut contents).unwrap();
+                (name, contents)
+            })
+            .collect()
+    }
+
+    const FILES: [(&str, &[u8]); 3] = [
+        ("a.txt", b"first"),
+        ("b.txt", b"second"),
+        ("c.txt", b"third"),
+    ];
+
+    #[test]
+    fn renames_an_entry() {
+        let dir = TempDir::new("rename");
+        let path = write_archive(&dir, &FILES);
+        rename(&path, OsStr::new("b.txt"), OsStr::new("dir/renamed.txt")).unwrap();
+
+        let expected = [
+            ("a.txt", "first"),
+            ("dir/renamed.txt", "second"),
+            ("c.txt", "third"),
+        ];
+        let expected: Vec<_> = expected
+            .iter()
+            .map(|(name, contents)| (name.to_string(), contents.as_bytes().to_vec()))
+            .collect();
+        assert_eq!(read_archive(&path), expected);
+
+        let renamed = fs::read(&path).unwrap();
+        assert!(matches!(
+            rename(&path, OsStr::new("b.txt"), OsStr::new("d.txt")),
+            Err(ArchiveError::EntryNotFound(_))
+        ));
+        assert!(matches!(
+            rename(&path, OsStr::new("a.txt"), OsStr::new("c.txt")),
+            Err(ArchiveError::Path(_))
+        ));
+        assert_eq!(fs::read(&path).unwrap(), renamed);
+    }
+
+    #[test]
+    fn names_outside_the_archive_are_refused() {
+        let dir = TempDir::new("rename-outside");
+        let path = write_archive(&dir, &FILES);
+        let archive = fs::read(&path).unwrap();
+
+        for name in [
+            "../escaped.txt",
+            "dir/../../escaped.txt",
+            "/etc/escaped",
+            "..",
+        ] {
+            assert!(
+                matches!(
+                    rename(&path, OsStr::new("a.txt"), OsStr::new(name)),
+                    Err(ArchiveError::Path(_))
+                ),
+                "{}",
+                name
+            );
+        }
+        assert_eq!(fs::read(&path).unwrap(), archive);
+    }
+
+    #[test]
+    fn removes_the_middle_entry() {
+        let dir = TempDir::new("remove");
+        let path = write_archive(&dir, &FILES);
+        remove(&path, OsStr::new("b.txt")).unwrap();
+
+        let remaining = read_archive(&path);
+        let expected = [FILES[0], FILES[2]];
+        let expected: Vec<_> = expected
+            .iter()
+            .map(|(name, contents)| (name.to_string(), contents.to_vec()))
+            .collect();
+        assert_eq!(remaining, expected);
+
+        assert!(matches!(
+            remove(&path, OsStr::new("b.txt")),
+            Err(ArchiveError::EntryNotFound(_))
+        ));
+    }
+}
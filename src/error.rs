@@ -1,16 +1,27 @@
 use std::{array::TryFromSliceError, fmt, io, num::TryFromIntError, path::StripPrefixError};
 
-use crate::{BUFFER_SIZE, VERSION};
+use crate::{BUFFER_SIZE, VERSION, Version};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum ArchiveError {
     Io(String),
+    /// Writing failed because the device is full, kept apart from `Io` so the
+    /// partially written output can be named and removed.
+    OutOfSpace(String),
     Path(String),
+    Usage(String),
     IncorrectType(String),
-    UnsupportedVersion(usize),
+    UnsupportedVersion(Version),
     BufferOverflow(usize),
     CorruptedArchive(String),
     EmptyFilename,
+    DuplicateEntry(String),
+    EntryNotFound(String),
+    CaseCollision(String, String),
+    /// The archive isn't signed, or not by the expected key.
+    SignatureInvalid(String),
+    Cancelled,
+    Unsupported(String),
     TryFromSlice(String),
     TryFromInt(String),
 }
@@ -18,7 +29,9 @@ impl fmt::Display for ArchiveError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Self::Io(c) => write!(f, "{}", c),
+            Self::OutOfSpace(c) => write!(f, "Out of space: {}", c),
             Self::Path(c) => write!(f, "{}", c),
+            Self::Usage(c) => write!(f, "{}", c),
             Self::BufferOverflow(found) => write!(
                 f,
                 "Buffer overflow: {} bytes less, then {} bytes",
@@ -26,8 +39,8 @@ impl fmt::Display for ArchiveError {
             ),
             Self::UnsupportedVersion(v) => write!(
                 f,
-                "Archive file has unsupported version: Current version suppots only {:?}.x archives, when {} was supplied",
-                VERSION[0], v
+                "Archive file has unsupported version: Current version suppots only {}.x archives, when {} was supplied",
+                VERSION.major, v
             ),
             Self::IncorrectType(c) => write!(
                 f,
@@ -36,14 +49,57 @@ impl fmt::Display for ArchiveError {
             ),
             Self::CorruptedArchive(c) => write!(f, "{}", c),
             Self::EmptyFilename => write!(f, "Filename is empty"),
+            Self::DuplicateEntry(name) => write!(
+                f,
+                "Duplicate entry '{}', use `--allow-duplicates` to pack it anyway",
+                name
+            ),
+            Self::EntryNotFound(name) => write!(f, "Entry '{}' isn't found in the archive", name),
+            Self::CaseCollision(first, second) => write!(
+                f,
+                "Entries '{}' and '{}' differ only by case and would overwrite each other",
+                first, second
+            ),
+            Self::SignatureInvalid(c) => write!(f, "{}", c),
+            Self::Cancelled => write!(f, "Operation was cancelled"),
+            Self::Unsupported(c) => write!(f, "{}", c),
             Self::TryFromSlice(c) => write!(f, "{}", c),
             Self::TryFromInt(c) => write!(f, "{}", c),
         }
     }
 }
+impl std::error::Error for ArchiveError {}
+
 impl From<io::Error> for ArchiveError {
     fn from(value: io::Error) -> Self {
-        Self::Io(value.to_string())
+        // errors surfaced through `Read`/`Write` implementations
+        if let Some(error) = value.get_ref().and_then(|e| e.downcast_ref::<Self>()) {
+            return error.clone();
+        }
+        match value.kind() {
+            // a write of zero bytes is how some devices report being full
+            io::ErrorKind::StorageFull | io::ErrorKind::WriteZero => {
+                Self::OutOfSpace(value.to_string())
+            }
+            _ => Self::Io(value.to_string()),
+        }
+    }
+}
+#[cfg(feature = "zip")]
+impl From<zip::result::ZipError> for ArchiveError {
+    fn from(value: zip::result::ZipError) -> Self {
+        use zip::result::ZipError;
+        match value {
+            ZipError::Io(e) => e.into(),
+            ZipError::InvalidPassword => {
+                Self::Usage("Wrong password for the ZIP archive".to_string())
+            }
+            ZipError::UnsupportedArchive(ZipError::PASSWORD_REQUIRED) => {
+                Self::Usage("ZIP archive is encrypted, a password is required".to_string())
+            }
+            ZipError::InvalidArchive(_) => Self::CorruptedArchive(value.to_string()),
+            _ => Self::Unsupported(value.to_string()),
+        }
     }
 }
 impl From<StripPrefixError> for ArchiveError {
@@ -0,0 +1,275 @@
+use std::{
+    collections::HashMap,
+    ffi::OsString,
+    fmt,
+    fs::File,
+    hash::{BuildHasher, RandomState},
+    io,
+    path::Path,
+};
+
+use log::debug;
+
+use crate::{
+    InnerFile,
+    compressor::{Compressor, Method},
+    error::{ArchiveError, Result},
+    pack::{PackOptions, collect_files, dictionary_tail, entry_method, record_metadata},
+};
+
+/// Share of the files compressed by `estimate` unless configured otherwise.
+pub const DEFAULT_SAMPLE: f64 = 0.05;
+
+/// Header and footer of an archive without a dictionary.
+const FIXED_OVERHEAD: u64 = 21 + 16;
+
+/// Predicted size of the archive `pack` would write, see `estimate`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SizeEstimate {
+    pub files: u64,
+    pub original_size: u64,
+    /// Files actually compressed and their original bytes.
+    pub sampled_files: u64,
+    pub sampled_size: u64,
+    /// Bytes of the whole archive, metadata and index included.
+    pub estimated_size: u64,
+    /// Half-width of a rough 95% interval around `estimated_size`, derived
+    /// from the spread of the ratios sampled per extension. `None` when too
+    /// few files were sampled to judge it.
+    pub margin: Option<u64>,
+}
+
+/// Sampled files of one extension.
+#[derive(Default)]
+struct Ratio {
+    original: u64,
+    compressed: u64,
+    /// Ratios of the single non-empty files.
+    samples: Vec<f64>,
+    /// Original bytes left to extrapolate.
+    unsampled: u64,
+}
+
+impl Ratio {
+    fn get(&self) -> Option<f64> {
+        (self.original > 0).then(|| self.compressed as f64 / self.original as f64)
+    }
+}
+
+/// Standard error of the mean of `samples`, `None` for fewer than two.
+fn standard_error(samples: &[f64]) -> Option<f64> {
+    let [_, _, ..] = samples else {
+        return None;
+    };
+    let n = samples.len() as f64;
+    let mean = samples.iter().sum::<f64>() / n;
+    let variance = samples.iter().map(|r| (r - mean) * (r - mean)).sum::<f64>() / (n - 1.0);
+    Some((variance / n).sqrt())
+}
+
+/// Estimates the size of the archive of `source` packed with `options` by
+/// compressing a random `sample` share of its files, at least one. Files
+/// of every extension are assumed to compress like the sampled ones of the
+/// same extension, or like all sampled files when none of it was picked.
+/// Stored files need no sampling. Solid archives and deduplication aren't
+/// taken into account, so their estimate is an upper bound.
+pub fn estimate<P: AsRef<Path>>(
+    source: P,
+    sample: f64,
+    options: &PackOptions,
+) -> Result<SizeEstimate> {
+    if !(sample > 0.0 && sample <= 1.0) {
+        return Err(ArchiveError::Usage(format!(
+            "Sample of {}% is outside of (0%, 100%]",
+            sample * 100.0
+        )));
+    }
+
+    let root = source.as_ref();
+    let files = collect_files(root, options)?;
+    let dictionary = options
+        .dictionary
+        .as_deref()
+        .map_or(&[][..], dictionary_tail);
+
+    let mut ratios: HashMap<OsString, Ratio> = HashMap::new();
+    // stored files and the compressed sizes of the sampled ones
+    let mut known = 0;
+    let mut metadata = FIXED_OVERHEAD;
+    let mut estimate = SizeEstimate {
+        files: files.len() as u64,
+        original_size: 0,
+        sampled_files: 0,
+        sampled_size: 0,
+        estimated_size: 0,
+        margin: None,
+    };
+
+    let mut compressible = Vec::new();
+    for path in &files {
+        let file_metadata = path.metadata()?;
+        let size = file_metadata.len();
+        let name = if root.is_file() {
+            path.file_name().unwrap_or_default()
+        } else {
+            path.strip_prefix(root).unwrap_or(path).as_os_str()
+        };
+        let mut inner_file = InnerFile::create(name.to_os_string(), size, 0, 0, 0);
+        record_metadata(&mut inner_file, &file_metadata, options);
+        metadata += inner_file.metadata_size() + 8;
+        estimate.original_size += size;
+
+        match entry_method(path, size, options) {
+            Method::Store => known += size,
+            method => compressible.push((path, size, method)),
+        }
+    }
+
+    let picker = RandomState::new();
+    let mut picked: Vec<bool> = compressible
+        .iter()
+        .map(|(path, _, _)| (picker.hash_one(path) as f64 / u64::MAX as f64) < sample)
+        .collect();
+    if !picked.contains(&true)
+        && let Some(largest) = (0..compressible.len()).max_by_key(|&i| compressible[i].1)
+    {
+        picked[largest] = true;
+    }
+
+    for (&(path, size, method), picked) in compressible.iter().zip(picked) {
+        let extension = path
+            .extension()
+            .map(|ex| ex.to_ascii_lowercase())
+            .unwrap_or_default();
+        let ratio = ratios.entry(extension).or_default();
+        if !picked {
+            ratio.unsampled += size;
+            continue;
+        }
+
+        let mut compressor =
+            Compressor::with_dictionary(io::sink(), method, options.level, dictionary)?;
+        io::copy(&mut File::open(path)?, &mut compressor)?;
+        let (_, summary) = compressor.finish()?;
+        debug!(
+            "Sampled '{}' ({} -> {} bytes, {})",
+            path.display(),
+            summary.original_size,
+            summary.compressed_size,
+            method.name()
+        );
+
+        ratio.original += summary.original_size;
+        ratio.compressed += summary.compressed_size;
+        if summary.original_size > 0 {
+            let sample = summary.compressed_size as f64 / summary.original_size as f64;
+            ratio.samples.push(sample);
+        }
+        estimate.sampled_files += 1;
+        estimate.sampled_size += summary.original_size;
+        known += summary.compressed_size;
+    }
+
+    let overall = Ratio {
+        original: ratios.values().map(|r| r.original).sum(),
+        compressed: ratios.values().map(|r| r.compressed).sum(),
+        samples: ratios.values().flat_map(|r| r.samples.clone()).collect(),
+        unsampled: 0,
+    };
+    let overall_error = standard_error(&overall.samples);
+
+    let mut extrapolated = 0.0;
+    // errors of the extensions are independent, so their variances add up
+    let mut variance = Some(0.0);
+    for ratio in ratios.values().filter(|r| r.unsampled > 0) {
+        let unsampled = ratio.unsampled as f64;
+        extrapolated += unsampled * ratio.get().or(overall.get()).unwrap_or(1.0);
+
+        let error = match ratio.get() {
+            Some(_) => standard_error(&ratio.samples).or(overall_error),
+            None => overall_error,
+        };
+        variance = variance
+            .zip(error)
+            .map(|(sum, e): (f64, f64)| sum + (e * unsampled).powi(2));
+    }
+
+    estimate.estimated_size = metadata + known + extrapolated.round() as u64;
+    estimate.margin = variance.map(|v| (2.0 * v.sqrt()).round() as u64);
+    Ok(estimate)
+}
+
+impl fmt::Display for SizeEstimate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Files:          {} ({} bytes)",
+            self.files, self.original_size
+        )?;
+        writeln!(
+            f,
+            "Sampled:        {} ({} bytes)",
+            self.sampled_files, self.sampled_size
+        )?;
+        write!(f, "Estimated size: {} bytes", self.estimated_size)?;
+        match self.margin {
+            Some(margin) => write!(
+                f,
+                "\nConfidence:     roughly 95% within ±{} bytes, assuming the sample is representative",
+                margin
+            ),
+            None => write!(
+                f,
+                "\nConfidence:     unknown, too few files were sampled to judge the spread"
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::{pack::pack, testing::TempDir};
+
+    #[test]
+    fn estimate_is_close_to_the_packed_size() {
+        let dir = TempDir::new("estimate");
+        let contents: Vec<_> = (0..200)
+            .map(|i| {
+                let line = format!("line {} of file {}\n", i % 7, i);
+                (format!("file{:03}.txt", i), line.repeat(200 + i * 10))
+            })
+            .collect();
+        let files: Vec<_> = contents
+            .iter()
+            .map(|(name, text)| (name.as_str(), text.as_bytes()))
+            .collect();
+        let source = dir.write_files("src", &files);
+        let options = PackOptions::default();
+        pack(source.clone(), Some(dir.join("archives")), &options).unwrap();
+        let actual = fs::metadata(dir.join("archives/src.slf")).unwrap().len();
+
+        let full = estimate(&source, 1.0, &options).unwrap();
+        assert_eq!(full.estimated_size, actual);
+
+        let sampled = estimate(&source, 0.2, &options).unwrap();
+        assert_eq!(sampled.files, 200);
+        assert!(sampled.sampled_files < 200);
+        let error = sampled.estimated_size.abs_diff(actual) as f64 / actual as f64;
+        assert!(error < 0.1, "{:?} for {} bytes", sampled, actual);
+    }
+
+    #[test]
+    fn sample_outside_the_range_is_rejected() {
+        let dir = TempDir::new("estimate-range");
+        let source = dir.write_files("src", &[("a.txt", b"a")]);
+        for sample in [0.0, 1.5] {
+            assert!(matches!(
+                estimate(&source, sample, &PackOptions::default()),
+                Err(ArchiveError::Usage(_))
+            ));
+        }
+    }
+}
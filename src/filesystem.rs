@@ -0,0 +1,373 @@
+use std::{
+    collections::BTreeMap,
+    ffi::{OsStr, OsString},
+    fs::{self, File},
+    io::{self, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+/// File written by `unpack` through a `FileSystem`.
+pub trait OutputFile: Write + Seek + Send {
+    /// Truncates or extends the file to `len` bytes, extending with zeros.
+    fn set_len(&self, len: u64) -> io::Result<()>;
+}
+
+impl OutputFile for File {
+    fn set_len(&self, len: u64) -> io::Result<()> {
+        File::set_len(self, len)
+    }
+}
+
+/// Destination of extracted entries, see `UnpackOptions::file_system`.
+/// Paths are the ones `unpack` would use on disk, the target directory
+/// included. Failures which only mean the operation isn't available, such
+/// as restoring an owner without privileges, should be reported as
+/// `PermissionDenied` or `Unsupported`, so extraction continues without it.
+pub trait FileSystem: Send + Sync {
+    /// Creates or truncates the file at `path`, its parent exists.
+    fn create_file(&self, path: &Path) -> io::Result<Box<dyn OutputFile>>;
+    /// Creates the directory and all its missing parents.
+    fn create_dir_all(&self, path: &Path) -> io::Result<()>;
+    fn exists(&self, path: &Path) -> bool;
+    /// Whether `path` is a directory without entries.
+    fn is_empty_dir(&self, path: &Path) -> io::Result<bool>;
+    fn remove_file(&self, path: &Path) -> io::Result<()>;
+    /// Makes `link` another name of the file at `original`.
+    fn hard_link(&self, original: &Path, link: &Path) -> io::Result<()>;
+    /// Sets Unix permission bits of a file or directory.
+    fn set_permissions(&self, path: &Path, mode: u32) -> io::Result<()>;
+    /// Sets the modification time of a file or directory. A file gets it
+    /// before its permissions are restored, so it's still writable.
+    fn set_modified(&self, path: &Path, time: SystemTime) -> io::Result<()>;
+    /// Modification time of an existing file.
+    fn modified(&self, path: &Path) -> io::Result<SystemTime>;
+    fn set_owner(&self, path: &Path, uid: u32, gid: u32) -> io::Result<()>;
+    fn set_xattr(&self, path: &Path, name: &OsStr, value: &[u8]) -> io::Result<()>;
+}
+
+/// The file system of the operating system, used by default.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RealFs;
+
+impl FileSystem for RealFs {
+    fn create_file(&self, path: &Path) -> io::Result<Box<dyn OutputFile>> {
+        Ok(Box::new(File::create(path)?))
+    }
+
+    fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+        fs::create_dir_all(path)
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        path.exists()
+    }
+
+    fn is_empty_dir(&self, path: &Path) -> io::Result<bool> {
+        Ok(path.is_dir() && path.read_dir()?.next().is_none())
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        fs::remove_file(path)
+    }
+
+    fn hard_link(&self, original: &Path, link: &Path) -> io::Result<()> {
+        fs::hard_link(original, link)
+    }
+
+    #[cfg(unix)]
+    fn set_permissions(&self, path: &Path, mode: u32) -> io::Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(mode))
+    }
+
+    #[cfg(not(unix))]
+    fn set_permissions(&self, _path: &Path, _mode: u32) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn set_modified(&self, path: &Path, time: SystemTime) -> io::Result<()> {
+        // directories can't be opened for writing, the owner sets the time
+        // through any descriptor
+        #[cfg(unix)]
+        let file = File::open(path)?;
+        #[cfg(not(unix))]
+        let file = File::options().write(true).open(path)?;
+        file.set_modified(time)
+    }
+
+    fn modified(&self, path: &Path) -> io::Result<SystemTime> {
+        fs::metadata(path)?.modified()
+    }
+
+    #[cfg(unix)]
+    fn set_owner(&self, path: &Path, uid: u32, gid: u32) -> io::Result<()> {
+        std::os::unix::fs::chown(path, Some(uid), Some(gid))
+    }
+
+    #[cfg(not(unix))]
+    fn set_owner(&self, _path: &Path, _uid: u32, _gid: u32) -> io::Result<()> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+
+    #[cfg(all(unix, feature = "xattrs"))]
+    fn set_xattr(&self, path: &Path, name: &OsStr, value: &[u8]) -> io::Result<()> {
+        xattr::set(path, name, value)
+    }
+
+    #[cfg(not(all(unix, feature = "xattrs")))]
+    fn set_xattr(&self, _path: &Path, _name: &OsStr, _value: &[u8]) -> io::Result<()> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+}
+
+/// Metadata of a file or directory of a `MemFs`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MemMetadata {
+    pub is_dir: bool,
+    pub mode: Option<u32>,
+    pub modified: Option<SystemTime>,
+    pub owner: Option<(u32, u32)>,
+    pub xattrs: Vec<(OsString, Vec<u8>)>,
+}
+
+#[derive(Debug, Default)]
+struct MemNode {
+    metadata: MemMetadata,
+    /// Shared by hard links to the same file.
+    contents: Arc<Mutex<Vec<u8>>>,
+}
+
+/// File system kept in memory, e.g. to check what `unpack` would write
+/// without touching the disk. Hard links share their contents, but not
+/// the metadata.
+#[derive(Debug, Default)]
+pub struct MemFs {
+    nodes: Mutex<BTreeMap<PathBuf, MemNode>>,
+}
+
+impl MemFs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Paths of all files and directories, sorted.
+    pub fn paths(&self) -> Vec<PathBuf> {
+        self.nodes.lock().unwrap().keys().cloned().collect()
+    }
+
+    /// Contents of the file at `path`, `None` for directories and missing files.
+    pub fn read(&self, path: &Path) -> Option<Vec<u8>> {
+        let nodes = self.nodes.lock().unwrap();
+        let node = nodes.get(path).filter(|node| !node.metadata.is_dir)?;
+        let contents = node.contents.lock().unwrap().clone();
+        Some(contents)
+    }
+
+    pub fn metadata(&self, path: &Path) -> Option<MemMetadata> {
+        self.nodes
+            .lock()
+            .unwrap()
+            .get(path)
+            .map(|node| node.metadata.clone())
+    }
+
+    /// Whether both paths are hard links to the same file.
+    pub fn same_file(&self, a: &Path, b: &Path) -> bool {
+        let nodes = self.nodes.lock().unwrap();
+        match (nodes.get(a), nodes.get(b)) {
+            (Some(a), Some(b)) => !a.metadata.is_dir && Arc::ptr_eq(&a.contents, &b.contents),
+            _ => false,
+        }
+    }
+
+    fn update(&self, path: &Path, update: impl FnOnce(&mut MemMetadata)) -> io::Result<()> {
+        let mut nodes = self.nodes.lock().unwrap();
+        let node = nodes.get_mut(path).ok_or_else(|| not_found(path))?;
+        update(&mut node.metadata);
+        Ok(())
+    }
+}
+
+fn not_found(path: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("'{}' doesn't exist", path.display()),
+    )
+}
+
+impl FileSystem for MemFs {
+    fn create_file(&self, path: &Path) -> io::Result<Box<dyn OutputFile>> {
+        let mut nodes = self.nodes.lock().unwrap();
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty())
+            && !nodes.get(parent).is_some_and(|node| node.metadata.is_dir)
+        {
+            return Err(not_found(parent));
+        }
+
+        let node = nodes.entry(path.to_path_buf()).or_default();
+        if node.metadata.is_dir {
+            return Err(io::Error::new(
+                io::ErrorKind::IsADirectory,
+                format!("'{}' is a directory", path.display()),
+            ));
+        }
+        node.contents.lock().unwrap().clear();
+        Ok(Box::new(MemFile {
+            contents: node.contents.clone(),
+            position: 0,
+        }))
+    }
+
+    fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+        let mut nodes = self.nodes.lock().unwrap();
+        for dir in path.ancestors().filter(|p| !p.as_os_str().is_empty()) {
+            let node = nodes.entry(dir.to_path_buf()).or_insert_with(|| MemNode {
+                metadata: MemMetadata {
+                    is_dir: true,
+                    ..MemMetadata::default()
+                },
+                contents: Arc::default(),
+            });
+            if !node.metadata.is_dir {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotADirectory,
+                    format!("'{}' is a file", dir.display()),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        self.nodes.lock().unwrap().contains_key(path)
+    }
+
+    fn is_empty_dir(&self, path: &Path) -> io::Result<bool> {
+        let nodes = self.nodes.lock().unwrap();
+        if !nodes.get(path).is_some_and(|node| node.metadata.is_dir) {
+            return Ok(false);
+        }
+        // children sort right after their parent
+        let has_children = nodes
+            .range(path.to_path_buf()..)
+            .nth(1)
+            .is_some_and(|(child, _)| child.starts_with(path));
+        Ok(!has_children)
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        let mut nodes = self.nodes.lock().unwrap();
+        match nodes.get(path) {
+            Some(node) if !node.metadata.is_dir => {
+                nodes.remove(path);
+                Ok(())
+            }
+            Some(_) => Err(io::Error::new(
+                io::ErrorKind::IsADirectory,
+                format!("'{}' is a directory", path.display()),
+            )),
+            None => Err(not_found(path)),
+        }
+    }
+
+    fn hard_link(&self, original: &Path, link: &Path) -> io::Result<()> {
+        let mut nodes = self.nodes.lock().unwrap();
+        let node = nodes
+            .get(original)
+            .filter(|node| !node.metadata.is_dir)
+            .ok_or_else(|| not_found(original))?;
+        if nodes.contains_key(link) {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("'{}' already exists", link.display()),
+            ));
+        }
+
+        let linked = MemNode {
+            metadata: node.metadata.clone(),
+            contents: node.contents.clone(),
+        };
+        nodes.insert(link.to_path_buf(), linked);
+        Ok(())
+    }
+
+    fn set_permissions(&self, path: &Path, mode: u32) -> io::Result<()> {
+        self.update(path, |metadata| metadata.mode = Some(mode))
+    }
+
+    fn set_modified(&self, path: &Path, time: SystemTime) -> io::Result<()> {
+        self.update(path, |metadata| metadata.modified = Some(time))
+    }
+
+    /// Files only have a modification time once it's set.
+    fn modified(&self, path: &Path) -> io::Result<SystemTime> {
+        let nodes = self.nodes.lock().unwrap();
+        let node = nodes.get(path).ok_or_else(|| not_found(path))?;
+        node.metadata
+            .modified
+            .ok_or_else(|| io::ErrorKind::Unsupported.into())
+    }
+
+    fn set_owner(&self, path: &Path, uid: u32, gid: u32) -> io::Result<()> {
+        self.update(path, |metadata| metadata.owner = Some((uid, gid)))
+    }
+
+    fn set_xattr(&self, path: &Path, name: &OsStr, value: &[u8]) -> io::Result<()> {
+        self.update(path, |metadata| {
+            metadata.xattrs.retain(|(existing, _)| existing != name);
+            metadata.xattrs.push((name.to_os_string(), value.to_vec()));
+        })
+    }
+}
+
+/// File of a `MemFs`, writing straight into the shared contents.
+struct MemFile {
+    contents: Arc<Mutex<Vec<u8>>>,
+    position: u64,
+}
+
+impl Write for MemFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut contents = self.contents.lock().unwrap();
+        let start = usize::try_from(self.position).map_err(io::Error::other)?;
+        let end = start + buf.len();
+        if contents.len() < end {
+            contents.resize(end, 0);
+        }
+        contents[start..end].copy_from_slice(buf);
+        self.position = end as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Seek for MemFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let len = self.contents.lock().unwrap().len() as u64;
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => len.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+        self.position = position.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Seek before the start of the file",
+            )
+        })?;
+        Ok(self.position)
+    }
+}
+
+impl OutputFile for MemFile {
+    fn set_len(&self, len: u64) -> io::Result<()> {
+        let len = usize::try_from(len).map_err(io::Error::other)?;
+        self.contents.lock().unwrap().resize(len, 0);
+        Ok(())
+    }
+}
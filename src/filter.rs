@@ -0,0 +1,140 @@
+use std::{
+    ffi::OsStr,
+    io::{self, Read, Write},
+    sync::Arc,
+};
+
+use crate::{BUFFER_SIZE, error::Result};
+
+/// Transformation of the contents of a file before it's compressed, e.g.
+/// stripping debug info. Entries store the size and checksums of the output,
+/// so extraction gives back the filtered contents.
+pub trait ContentFilter: Send + Sync {
+    /// Reads the contents of the file packed as `name` from `input` and writes
+    /// the contents to store into `output`.
+    fn filter(&self, name: &OsStr, input: &mut dyn Read, output: &mut dyn Write) -> Result<()>;
+}
+
+/// Whether a filter applies to the entry of the given name.
+pub type FilterPredicate = Arc<dyn Fn(&OsStr) -> bool + Send + Sync>;
+
+/// A filter with the entries it applies to, see `PackOptions::filters`.
+#[derive(Clone)]
+pub struct FilterRule {
+    pub applies_to: FilterPredicate,
+    pub filter: Arc<dyn ContentFilter>,
+}
+
+impl FilterRule {
+    pub fn new(
+        applies_to: impl Fn(&OsStr) -> bool + Send + Sync + 'static,
+        filter: impl ContentFilter + 'static,
+    ) -> Self {
+        Self {
+            applies_to: Arc::new(applies_to),
+            filter: Arc::new(filter),
+        }
+    }
+}
+
+/// Replaces CRLF line endings by LF, e.g. for text files checked out on
+/// Windows. Lone CRs are kept.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NormalizeLineEndings;
+
+impl ContentFilter for NormalizeLineEndings {
+    fn filter(&self, _name: &OsStr, input: &mut dyn Read, output: &mut dyn Write) -> Result<()> {
+        let mut buffer = vec![0u8; BUFFER_SIZE];
+        let mut filtered = Vec::with_capacity(BUFFER_SIZE + 1);
+        // a CR at the end of a read may be followed by LF in the next one
+        let mut pending_cr = false;
+        loop {
+            let bytes = match input.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(bytes) => bytes,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            };
+
+            filtered.clear();
+            for &byte in &buffer[..bytes] {
+                if pending_cr && byte != b'\n' {
+                    filtered.push(b'\r');
+                }
+                pending_cr = byte == b'\r';
+                if !pending_cr {
+                    filtered.push(byte);
+                }
+            }
+            output.write_all(&filtered)?;
+        }
+
+        if pending_cr {
+            output.write_all(b"\r")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use super::*;
+    use crate::{
+        pack::{PackOptions, pack},
+        reader::ArchiveReader,
+        testing::TempDir,
+        unpack::{UnpackOptions, unpack},
+    };
+
+    fn normalized(input: &[u8]) -> Vec<u8> {
+        let mut output = Vec::new();
+        NormalizeLineEndings
+            .filter(OsStr::new("name"), &mut &input[..], &mut output)
+            .unwrap();
+        output
+    }
+
+    #[test]
+    fn line_endings_are_normalized() {
+        assert_eq!(normalized(b"a\r\nb\rc\r\n\r"), b"a\nb\rc\n\r");
+        // a CRLF split between two reads
+        let mut split = vec![b'x'; BUFFER_SIZE - 1];
+        split.extend_from_slice(b"\r\ny");
+        let mut expected = vec![b'x'; BUFFER_SIZE - 1];
+        expected.extend_from_slice(b"\ny");
+        assert_eq!(normalized(&split), expected);
+    }
+
+    #[test]
+    fn filtered_contents_round_trip() {
+        let dir = TempDir::new("content-filter");
+        let text = b"first line\r\nsecond line\r\n".repeat(100);
+        let binary = b"\r\n\x00\x01\r\n".to_vec();
+        let source = dir.write_files("src", &[("text.txt", &text), ("data.bin", &binary)]);
+        let options = PackOptions {
+            filters: vec![FilterRule::new(
+                |name| Path::new(name).extension().is_some_and(|ex| ex == "txt"),
+                NormalizeLineEndings,
+            )],
+            ..PackOptions::default()
+        };
+        pack(source, Some(dir.join("archives")), &options).unwrap();
+
+        let archive = dir.join("archives/src.slf");
+        let mut reader = ArchiveReader::open(&archive).unwrap();
+        reader.verify().unwrap();
+        let filtered = b"first line\nsecond line\n".repeat(100);
+        let size = |name: &str| {
+            let entry = reader.entries().iter().find(|e| e.name() == name).unwrap();
+            entry.original_size()
+        };
+        assert_eq!(size("text.txt"), filtered.len() as u64);
+        assert_eq!(size("data.bin"), binary.len() as u64);
+
+        unpack(archive, Some(dir.join("out")), &UnpackOptions::default()).unwrap();
+        assert!(fs::read(dir.join("out/src/text.txt")).unwrap() == filtered);
+        assert_eq!(fs::read(dir.join("out/src/data.bin")).unwrap(), binary);
+    }
+}
@@ -0,0 +1,214 @@
+use std::{fmt, io::Read, path::PathBuf};
+
+use crate::{
+    BUFFER_SIZE, DEFAULT_BUFFER_CAPACITY, FLAG_DICTIONARY, FLAG_SPLIT, Footer, Header, SIGNATURE,
+    Version, checksum::ChecksumAlgorithm, error::Result, source::ArchiveSource, supports_version,
+};
+
+/// Raw header fields of an archive, read without rejecting unexpected values.
+pub struct ArchiveInfo {
+    pub length: u64,
+    pub signature: Vec<u8>,
+    pub version: Option<Version>,
+    pub file_count: Option<u32>,
+    pub index_offset: Option<u64>,
+    pub flags: Option<u16>,
+    /// Id of the algorithm of original content checksums, since 1.9.
+    pub checksum: Option<u8>,
+    /// Index and count of volumes, with `FLAG_SPLIT` since 1.10.
+    pub volume: Option<(u16, u16)>,
+    /// Length of the dictionary following the header, if it has one.
+    pub dictionary_size: Option<u32>,
+    /// Result of reading the footer, `None` if the version has no footer.
+    pub footer: Option<std::result::Result<Footer, String>>,
+    /// Whether the archive checksum matches, `None` if it isn't stored.
+    pub checksum_valid: Option<bool>,
+}
+
+/// Reads only the header and the footer of the archive, entries aren't touched.
+pub fn info(source: PathBuf) -> Result<ArchiveInfo> {
+    let mut reader = ArchiveSource::open(&source, DEFAULT_BUFFER_CAPACITY)?;
+    let length = reader.len();
+
+    let mut raw = Vec::with_capacity(20);
+    (&mut reader).take(20).read_to_end(&mut raw)?;
+
+    let field = |range: std::ops::Range<usize>| raw.get(range);
+    let mut info = ArchiveInfo {
+        length,
+        signature: raw[..raw.len().min(4)].to_vec(),
+        version: field(4..6).map(|v| Version::from_bytes([v[0], v[1]])),
+        file_count: field(6..10)
+            .and_then(|v| v.try_into().ok())
+            .map(u32::from_le_bytes),
+        index_offset: field(10..18)
+            .and_then(|v| v.try_into().ok())
+            .map(u64::from_le_bytes),
+        flags: None,
+        checksum: None,
+        volume: None,
+        dictionary_size: None,
+        footer: None,
+        checksum_valid: None,
+    };
+
+    let Some(version) = info.version else {
+        return Ok(info);
+    };
+    if version >= Version::new(1, 2) {
+        info.flags = field(18..20)
+            .and_then(|v| v.try_into().ok())
+            .map(u16::from_le_bytes);
+    }
+    if version >= Version::new(1, 9) && raw.len() == 20 {
+        let mut id = [0u8; 1];
+        if reader.read_exact(&mut id).is_ok() {
+            info.checksum = Some(id[0]);
+        }
+    }
+    if version >= Version::new(1, 10) && info.flags.is_some_and(|flags| flags & FLAG_SPLIT != 0) {
+        let mut volume = [0u8; 4];
+        if reader.read_exact(&mut volume).is_ok() {
+            info.volume = Some((
+                u16::from_le_bytes([volume[0], volume[1]]),
+                u16::from_le_bytes([volume[2], volume[3]]),
+            ));
+        }
+    }
+    if version >= Version::new(1, 7) && info.flags.is_some_and(|flags| flags & FLAG_DICTIONARY != 0)
+    {
+        let mut size = [0u8; 4];
+        if reader.read_exact(&mut size).is_ok() {
+            info.dictionary_size = Some(u32::from_le_bytes(size));
+        }
+    }
+
+    if info.signature != SIGNATURE || !supports_version(version) || version < Version::new(1, 4) {
+        return Ok(info);
+    }
+
+    let mut buffer = vec![0u8; BUFFER_SIZE];
+    match Footer::from_archive(&mut reader, &mut buffer, version) {
+        Ok(footer) => {
+            if footer.checksum.is_some() {
+                let valid = footer.verify_checksum(&mut reader, &mut buffer).is_ok();
+                info.checksum_valid = Some(valid);
+            }
+            info.footer = Some(Ok(footer));
+        }
+        Err(e) => info.footer = Some(Err(e.to_string())),
+    }
+    Ok(info)
+}
+
+impl ArchiveInfo {
+    fn header(&self) -> Option<Header> {
+        Some(Header {
+            version: self.version?,
+            file_count: self.file_count?,
+            index_offset: self.index_offset?,
+            flags: self.flags.unwrap_or(0),
+            checksum: self
+                .checksum
+                .and_then(|id| ChecksumAlgorithm::from_id(id).ok())
+                .unwrap_or_default(),
+            volume_index: self.volume.map_or(1, |(index, _)| index),
+            volume_count: self.volume.map_or(1, |(_, count)| count),
+            // only its length is read, see `dictionary_size`
+            dictionary: Vec::new(),
+        })
+    }
+}
+
+impl fmt::Display for ArchiveInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let missing = "<truncated>";
+
+        writeln!(f, "length: {}", self.length)?;
+        write!(f, "signature: '{}'", self.signature.escape_ascii())?;
+        if self.signature != SIGNATURE {
+            write!(f, " (expected '{}')", SIGNATURE.escape_ascii())?;
+        }
+        writeln!(f)?;
+
+        match self.version {
+            Some(version) if supports_version(version) => writeln!(f, "version: {}", version)?,
+            Some(version) => writeln!(f, "version: {} (unsupported)", version)?,
+            None => writeln!(f, "version: {}", missing)?,
+        }
+
+        match self.file_count {
+            Some(count) => writeln!(f, "file count: {}", count)?,
+            None => writeln!(f, "file count: {}", missing)?,
+        }
+
+        match self.index_offset {
+            Some(0) => writeln!(f, "index offset: 0 (no index)")?,
+            Some(offset) => writeln!(f, "index offset: {}", offset)?,
+            None => writeln!(f, "index offset: {}", missing)?,
+        }
+
+        if let Some(header) = self.header() {
+            if header.version >= Version::new(1, 2) {
+                match self.flags {
+                    Some(flags) => {
+                        let names: Vec<&str> = [
+                            (header.is_solid(), "solid"),
+                            (header.is_dedup(), "dedup"),
+                            (header.has_dictionary(), "dictionary"),
+                            (header.is_split(), "split"),
+                        ]
+                        .into_iter()
+                        .filter_map(|(set, name)| set.then_some(name))
+                        .collect();
+
+                        write!(f, "flags: {:#06x}", flags)?;
+                        if !names.is_empty() {
+                            write!(f, " ({})", names.join(", "))?;
+                        }
+                        writeln!(f)?
+                    }
+                    None => writeln!(f, "flags: {}", missing)?,
+                }
+            }
+            if header.version >= Version::new(1, 9) {
+                match self.checksum.map(ChecksumAlgorithm::from_id) {
+                    Some(Ok(algorithm)) => writeln!(f, "checksum: {}", algorithm.name())?,
+                    Some(Err(_)) => {
+                        writeln!(f, "checksum: {} (unknown)", self.checksum.unwrap_or(0))?
+                    }
+                    None => writeln!(f, "checksum: {}", missing)?,
+                }
+            }
+            if header.is_split() {
+                match self.volume {
+                    Some((index, 0)) => writeln!(f, "volume: {} (count not written)", index)?,
+                    Some((index, count)) => writeln!(f, "volume: {} of {}", index, count)?,
+                    None => writeln!(f, "volume: {}", missing)?,
+                }
+            }
+            if header.has_dictionary() {
+                match self.dictionary_size {
+                    Some(size) => writeln!(f, "dictionary: {} bytes", size)?,
+                    None => writeln!(f, "dictionary: {}", missing)?,
+                }
+            }
+            let dictionary_size = self.dictionary_size.unwrap_or(0) as u64;
+            writeln!(f, "header size: {}", header.size() + dictionary_size)?;
+        }
+
+        match &self.footer {
+            Some(Ok(footer)) => {
+                writeln!(f, "footer: present, total length {}", footer.total_length)?
+            }
+            Some(Err(e)) => writeln!(f, "footer: invalid ({})", e)?,
+            None => writeln!(f, "footer: none")?,
+        }
+
+        match self.checksum_valid {
+            Some(true) => write!(f, "archive checksum: valid"),
+            Some(false) => write!(f, "archive checksum: invalid"),
+            None => write!(f, "archive checksum: none"),
+        }
+    }
+}
@@ -0,0 +1,1406 @@
+/*
+.slf File structure:
+Signature (4 bytes = '.slf'),
+version (2 bytes),
+count of files (4 bytes),
+index offset (8 bytes),
+flags (2 bytes, since 1.2),
+checksum algorithm of original contents (1 byte, since 1.9, 0 = CRC32,
+  1 = CRC32C, compressed contents and the archive are always CRC32),
+volume (since 1.10, only with `FLAG_SPLIT`):
+ | index of the volume (2 bytes, 1 for the first volume holding the header),
+ | count of volumes (2 bytes, 0 while the archive is being written),
+dictionary (since 1.7, only with `FLAG_DICTIONARY`):
+ | length (4 bytes),
+ | dictionary ('length' bytes, at most `MAX_DICTIONARY_SIZE`),
+ | length of file name(4 bytes),
+ | name ('length' bytes, raw bytes on Unix, WTF-8 on Windows),
+ | original size of file (8 bytes),
+ | compressed size (8 bytes),
+ | original checksum (4 bytes),
+ | compressed checksum (4 bytes),
+ | owner uid (4 bytes, since 1.1),
+ | owner gid (4 bytes, since 1.1),
+ | compression method (1 byte, since 1.3, the high bit marks chunked
+ |   contents since 1.6, entries of method 4 are compressed against the
+ |   dictionary since 1.7),
+ | count of extension records (2 bytes, since 1.8),
+ | | type (2 bytes, 1 = extended attribute, 2 = hard link, 3 = directory
+ | |   modification time, 4 = mode, 5 = modification time, unknown types,
+ | |   e.g. of a later minor version, are skipped),
+ | | length (4 bytes),
+ | | value ('length' bytes, for attributes their name, a NUL byte and the value,
+ | |   for hard links the name of the entry holding the contents, for modes
+ | |   the permission bits (4 bytes), for modification times the seconds
+ | |   since the Unix epoch (8 bytes, signed) and nanoseconds (4 bytes), for
+ | |   directory modification times the levels of the directory above the
+ | |   entry (2 bytes, 1 = its parent) followed by the time as before),
+ | ...
+ | compressed file ('compressed size' bytes),
+ ...
+Index array (8 bytes * File count),
+Signature (only with `FLAG_SIGNED`, 64 bytes, Ed25519ph over all preceding
+  bytes, see `signing`),
+Footer (since 1.4):
+ | signature (4 bytes = 'FEND'),
+ | total length of the archive (8 bytes),
+ | checksum of all preceding bytes of the archive (4 bytes, since 1.5).
+
+Split archives are stored as volumes 'name.slf.001', 'name.slf.002', ...
+which concatenated give the archive above. Since 1.10 the header in the first
+volume counts them, so volumes beyond the count aren't read.
+
+Solid archives (`FLAG_SOLID`) store metadata of all files first, with
+compressed size and compressed checksum set to 0, followed by a single block:
+ | compressed size (8 bytes),
+ | original checksum of the whole block (4 bytes),
+ | compressed checksum (4 bytes),
+ | compressed contents of all files in the metadata order,
+Index array and footer.
+
+Deduplicated archives (`FLAG_DEDUP`, since 1.6) store the contents of chunked
+entries as a sequence of records, each compressed and checked on its own:
+ | kind (1 byte, 0 = chunk, 1 = reference),
+ | chunk: method (1 byte), original size (4 bytes), compressed size (4 bytes)
+ |   and the compressed chunk,
+ | reference: offset of a chunk record earlier in the archive (8 bytes),
+ ...
+The compressed size and checksum of the entry cover its records only.
+*/
+
+pub mod bench;
+pub mod builder;
+pub mod checksum;
+pub mod compressor;
+pub mod dedup;
+pub mod diff;
+pub mod edit;
+pub mod error;
+pub mod estimate;
+pub mod filesystem;
+pub mod filter;
+pub mod info;
+pub mod limiter;
+pub mod list;
+pub mod log;
+pub mod manifest;
+pub mod pack;
+pub mod progress;
+pub mod reader;
+#[cfg(feature = "remote")]
+pub mod remote;
+pub mod repack;
+pub mod retry;
+#[cfg(feature = "signing")]
+pub mod signing;
+pub mod source;
+pub mod spill;
+#[cfg(test)]
+mod testing;
+pub mod transform;
+pub mod unpack;
+pub mod verify;
+pub mod volume;
+#[cfg(feature = "zip")]
+mod zipfile;
+
+use std::{
+    ffi::{OsStr, OsString},
+    fmt,
+    io::{self, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Component, Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, SystemTime},
+};
+
+#[cfg(unix)]
+use std::os::unix::ffi::OsStringExt;
+
+#[cfg(windows)]
+use std::os::windows::ffi::OsStringExt;
+
+#[cfg(not(any(unix, windows)))]
+compile_error!("Entry names can be decoded only on Unix and Windows");
+
+use ::log::{debug, warn};
+use flate2::Crc;
+
+use crate::{
+    checksum::{Checksum, ChecksumAlgorithm},
+    compressor::{MAX_DICTIONARY_SIZE, Method},
+    error::{ArchiveError, Result},
+};
+
+/// Magic bytes at the start of every archive.
+pub const SIGNATURE: &[u8] = b".slf";
+/// Format version written by this build.
+pub const VERSION: Version = Version::new(1, 10);
+pub const FOOTER_SIGNATURE: &[u8] = b"FEND";
+pub const BUFFER_SIZE: usize = 128 * 1024;
+/// Default capacity of the `BufReader`/`BufWriter` around the archive and
+/// extracted files. Reads and writes of `BUFFER_SIZE` bypass it, so it only
+/// gathers metadata fields and the chunks written by the compressor.
+pub const DEFAULT_BUFFER_CAPACITY: usize = 64 * 1024;
+
+/// Default ceiling of the original size of a single entry, 1 TiB.
+pub const MAX_ORIGINAL_SIZE: u64 = 1 << 40;
+
+/// Most entries space is reserved for up front, the count of the header
+/// isn't trusted with more before the entries are actually read.
+const MAX_PREALLOCATED_ENTRIES: usize = 1 << 16;
+
+/// Capacity of a list of the entries of an archive of `file_count` entries.
+pub(crate) fn entry_capacity(file_count: u32) -> usize {
+    (file_count as usize).min(MAX_PREALLOCATED_ENTRIES)
+}
+
+/// All files are compressed as one stream.
+pub const FLAG_SOLID: u16 = 1 << 0;
+/// Contents of entries are split into chunks stored once, see `dedup`.
+pub const FLAG_DEDUP: u16 = 1 << 1;
+/// The header is followed by a dictionary `Method::Deflate` entries are
+/// compressed against.
+pub const FLAG_DICTIONARY: u16 = 1 << 2;
+/// The index array is followed by a signature of the archive, see `signing`.
+/// Rewriting the archive drops it.
+pub const FLAG_SIGNED: u16 = 1 << 3;
+/// The archive is stored as volumes, counted in the header since 1.10, see
+/// `volume`.
+pub const FLAG_SPLIT: u16 = 1 << 4;
+
+/// Flags known to this build, see `supports_version`.
+const KNOWN_FLAGS: u16 = FLAG_SOLID | FLAG_DEDUP | FLAG_DICTIONARY | FLAG_SIGNED | FLAG_SPLIT;
+
+/// Bit of the method byte marking an entry stored as chunk records.
+const CHUNKED: u8 = 1 << 7;
+
+/// Extension record holding an extended attribute of the file.
+const EXTENSION_XATTR: u16 = 1;
+/// Extension record marking the entry as a hard link to another one, its
+/// own contents are empty.
+const EXTENSION_HARD_LINK: u16 = 2;
+/// Extension record holding the modification time of a directory above the
+/// file, as the count of levels up followed by the time as in
+/// `EXTENSION_MTIME`. Stored once per directory, with its first entry.
+const EXTENSION_DIR_MTIME: u16 = 3;
+/// Extension record holding the Unix permission bits of the file.
+const EXTENSION_MODE: u16 = 4;
+/// Extension record holding the modification time of the file, as signed
+/// seconds since the Unix epoch followed by nanoseconds.
+const EXTENSION_MTIME: u16 = 5;
+/// Largest value of an extension record accepted when reading.
+const MAX_EXTENSION_SIZE: usize = 64 * 1024;
+
+/// Returns the format version written by this build.
+pub fn format_version() -> Version {
+    VERSION
+}
+
+/// Whether archives of the given version can be read by this build, which
+/// reads every minor version of its major one. Later minor versions keep the
+/// fields of this one and add only extension records, which are skipped, and
+/// flags, which may come with header fields of their own. A later archive
+/// setting a flag unknown to this build is refused when its header is read.
+pub fn supports_version(version: Version) -> bool {
+    version.major == VERSION.major
+}
+
+/// Format version of an archive, stored as two bytes. Versions are ordered
+/// by their major and then minor number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Version {
+    pub major: u8,
+    pub minor: u8,
+}
+
+impl Version {
+    pub const fn new(major: u8, minor: u8) -> Self {
+        Self { major, minor }
+    }
+
+    pub const fn from_bytes(bytes: [u8; 2]) -> Self {
+        Self::new(bytes[0], bytes[1])
+    }
+
+    pub const fn to_bytes(self) -> [u8; 2] {
+        [self.major, self.minor]
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+/// Set from another thread to stop a running `pack` or `unpack`.
+pub type CancelFlag = Arc<AtomicBool>;
+
+/// Fails with `Unsupported` when extended attributes are requested, but the
+/// build can't handle them.
+fn check_xattrs_supported(enabled: bool) -> Result<()> {
+    if enabled && !cfg!(all(unix, feature = "xattrs")) {
+        return Err(ArchiveError::Unsupported(
+            "Extended attributes aren't supported by this build".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Fails with `Cancelled` once the flag is set.
+fn check_cancelled(cancel: Option<&AtomicBool>) -> Result<()> {
+    if cancel.is_some_and(|flag| flag.load(Ordering::Relaxed)) {
+        return Err(ArchiveError::Cancelled);
+    }
+    Ok(())
+}
+
+#[derive(Clone)]
+pub struct Header {
+    pub version: Version,
+    pub file_count: u32,
+    pub index_offset: u64,
+    pub flags: u16,
+    /// Algorithm of the original checksums, CRC32 before 1.9.
+    pub checksum: ChecksumAlgorithm,
+    /// Index and count of volumes stored with `FLAG_SPLIT`, 1 otherwise.
+    pub volume_index: u16,
+    pub volume_count: u16,
+    /// Dictionary stored with `FLAG_DICTIONARY`, empty otherwise.
+    pub dictionary: Vec<u8>,
+}
+
+impl Header {
+    pub fn from_archive<R: Read>(reader: &mut R, buffer: &mut [u8]) -> Result<Self> {
+        let signature = match reader.read_exact(&mut buffer[..4]) {
+            Ok(()) => &buffer[..4],
+            // too short to be an archive at all
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => &[],
+            Err(e) => return Err(e.into()),
+        };
+        if signature != SIGNATURE {
+            return Err(ArchiveError::CorruptedArchive(
+                "File is corrupted or has incorrect type".to_string(),
+            ));
+        }
+
+        reader.read_exact(&mut buffer[..2])?;
+        let version = Version::from_bytes([buffer[0], buffer[1]]);
+        if !supports_version(version) {
+            return Err(ArchiveError::UnsupportedVersion(version));
+        }
+        if version > VERSION {
+            warn!(
+                "Archive is of format version {}, newer than {} of this build, metadata added since is ignored",
+                version, VERSION
+            );
+        }
+
+        reader.read_exact(&mut buffer[..4])?;
+        let file_count = u32::from_le_bytes(buffer[..4].try_into()?);
+
+        reader.read_exact(&mut buffer[..8])?;
+        let index_offset = u64::from_le_bytes(buffer[..8].try_into()?);
+
+        let flags = if version >= Version::new(1, 2) {
+            reader.read_exact(&mut buffer[..2])?;
+            u16::from_le_bytes(buffer[..2].try_into()?)
+        } else {
+            0
+        };
+        // the header fields of an unknown flag can't be skipped, their size
+        // isn't known
+        if version > VERSION && flags & !KNOWN_FLAGS != 0 {
+            return Err(ArchiveError::Unsupported(format!(
+                "Archive of format version {} has flags {:#06x} unknown to this build of {}",
+                version,
+                flags & !KNOWN_FLAGS,
+                VERSION
+            )));
+        }
+
+        let checksum = if version >= Version::new(1, 9) {
+            reader.read_exact(&mut buffer[..1])?;
+            ChecksumAlgorithm::from_id(buffer[0])?
+        } else {
+            ChecksumAlgorithm::Crc32
+        };
+
+        let (volume_index, volume_count) =
+            if version >= Version::new(1, 10) && flags & FLAG_SPLIT != 0 {
+                reader.read_exact(&mut buffer[..4])?;
+                (
+                    u16::from_le_bytes(buffer[..2].try_into()?),
+                    u16::from_le_bytes(buffer[2..4].try_into()?),
+                )
+            } else {
+                (1, 1)
+            };
+
+        let mut dictionary = Vec::new();
+        if version >= Version::new(1, 7) && flags & FLAG_DICTIONARY != 0 {
+            reader.read_exact(&mut buffer[..4])?;
+            let len = u32::from_le_bytes(buffer[..4].try_into()?) as usize;
+            if len > MAX_DICTIONARY_SIZE {
+                return Err(ArchiveError::CorruptedArchive(format!(
+                    "Dictionary of {} bytes exceeds the limit of {}",
+                    len, MAX_DICTIONARY_SIZE
+                )));
+            }
+            dictionary.resize(len, 0);
+            reader.read_exact(&mut dictionary)?;
+        }
+
+        Ok(Self {
+            version,
+            file_count,
+            index_offset,
+            flags,
+            checksum,
+            volume_index,
+            volume_count,
+            dictionary,
+        })
+    }
+
+    /// Writers unable to seek back leave the index offset as 0.
+    pub fn has_index(&self) -> bool {
+        self.index_offset != 0
+    }
+
+    pub fn is_solid(&self) -> bool {
+        self.flags & FLAG_SOLID != 0
+    }
+
+    pub fn is_signed(&self) -> bool {
+        self.flags & FLAG_SIGNED != 0
+    }
+
+    pub fn is_dedup(&self) -> bool {
+        self.flags & FLAG_DEDUP != 0
+    }
+
+    /// Whether the header holds the volume fields.
+    pub fn is_split(&self) -> bool {
+        self.version >= Version::new(1, 10) && self.flags & FLAG_SPLIT != 0
+    }
+
+    pub fn has_dictionary(&self) -> bool {
+        self.version >= Version::new(1, 7) && self.flags & FLAG_DICTIONARY != 0
+    }
+
+    /// Length of the header in bytes, entries follow right after it.
+    pub fn size(&self) -> u64 {
+        let size = if self.version >= Version::new(1, 9) {
+            21
+        } else if self.version >= Version::new(1, 2) {
+            20
+        } else {
+            18
+        };
+        let size = if self.is_split() { size + 4 } else { size };
+        if self.has_dictionary() {
+            size + 4 + self.dictionary.len() as u64
+        } else {
+            size
+        }
+    }
+
+    pub fn has_footer(&self) -> bool {
+        self.version >= Version::new(1, 4)
+    }
+}
+
+/// Trailer marking a completely written archive.
+pub struct Footer {
+    pub total_length: u64,
+    pub checksum: Option<u32>,
+}
+
+impl Footer {
+    pub fn size(version: Version) -> u64 {
+        if version >= Version::new(1, 5) {
+            16
+        } else {
+            12
+        }
+    }
+
+    /// Reads the footer from the end of the archive, keeping the current position.
+    pub fn from_archive<R: Read + Seek>(
+        reader: &mut R,
+        buffer: &mut [u8],
+        version: Version,
+    ) -> Result<Self> {
+        let size = Self::size(version);
+        let current = reader.stream_position()?;
+        let length = reader.seek(SeekFrom::End(0))?;
+
+        if length < size {
+            return Err(Self::incomplete());
+        }
+
+        reader.seek(SeekFrom::Start(length - size))?;
+        reader.read_exact(&mut buffer[..size as usize])?;
+        if &buffer[..4] != FOOTER_SIGNATURE {
+            return Err(Self::incomplete());
+        }
+
+        let total_length = u64::from_le_bytes(buffer[4..12].try_into()?);
+        if total_length != length {
+            return Err(Self::incomplete());
+        }
+
+        let checksum = if size == 16 {
+            Some(u32::from_le_bytes(buffer[12..16].try_into()?))
+        } else {
+            None
+        };
+
+        reader.seek(SeekFrom::Start(current))?;
+        Ok(Self {
+            total_length,
+            checksum,
+        })
+    }
+
+    /// Writes the signature and the total length, the checksum is appended
+    /// separately once everything before it is on disk.
+    pub fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
+        writer.write_all(FOOTER_SIGNATURE)?;
+        writer.write_all(&self.total_length.to_le_bytes())?;
+        Ok(())
+    }
+
+    /// Compares the stored archive checksum with the actual one, keeping the
+    /// current position. Archives older than 1.5 have nothing to compare.
+    pub fn verify_checksum<R: Read + Seek>(&self, reader: &mut R, buffer: &mut [u8]) -> Result<()> {
+        let Some(expected) = self.checksum else {
+            return Ok(());
+        };
+
+        let current = reader.stream_position()?;
+        let length = self
+            .total_length
+            .checked_sub(4)
+            .ok_or_else(Self::incomplete)?;
+        let checksum = archive_checksum(reader, length, buffer)?;
+        reader.seek(SeekFrom::Start(current))?;
+
+        if checksum != expected {
+            return Err(ArchiveError::CorruptedArchive(format!(
+                "Archive corrupted! Archive checksum {} isn't equal to {}",
+                checksum, expected
+            )));
+        }
+        Ok(())
+    }
+
+    fn incomplete() -> ArchiveError {
+        ArchiveError::CorruptedArchive("Archive incomplete".to_string())
+    }
+}
+
+/// Computes the checksum of the first `length` bytes of the archive.
+pub fn archive_checksum<R: Read + Seek>(
+    reader: &mut R,
+    length: u64,
+    buffer: &mut [u8],
+) -> Result<u32> {
+    reader.seek(SeekFrom::Start(0))?;
+
+    let mut checksum = Crc::new();
+    let mut remaining = length;
+    while remaining > 0 {
+        let to_read = remaining.min(buffer.len() as u64) as usize;
+        reader.read_exact(&mut buffer[..to_read])?;
+        checksum.update(&buffer[..to_read]);
+        remaining -= to_read as u64;
+    }
+    Ok(checksum.sum())
+}
+
+/// Writer counting and checksumming the bytes accepted by the inner writer,
+/// a short write hashes only the part actually written.
+pub struct HasherWriter<W: Write> {
+    writer: W,
+    hasher: Option<Checksum>,
+    bytes: u64,
+}
+
+impl<W: Write> HasherWriter<W> {
+    pub fn new(writer: W, hasher: Checksum) -> Self {
+        Self {
+            writer,
+            hasher: Some(hasher),
+            bytes: 0,
+        }
+    }
+
+    /// Only counts the written bytes, `sum` is always 0.
+    pub fn counting(writer: W) -> Self {
+        Self {
+            writer,
+            hasher: None,
+            bytes: 0,
+        }
+    }
+
+    pub fn sum(&self) -> u32 {
+        self.hasher.as_ref().map_or(0, Checksum::sum)
+    }
+
+    pub fn take_written_bytes(&mut self) -> u64 {
+        let old = self.bytes;
+        self.bytes = 0;
+        old
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write + Seek> HasherWriter<W> {
+    pub fn stream_position(&mut self) -> error::Result<u64> {
+        let pos = self.writer.stream_position()?;
+        Ok(pos)
+    }
+}
+
+impl<W: Write> Write for HasherWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let bytes = self.writer.write(buf)?;
+        if let Some(hasher) = &mut self.hasher {
+            hasher.update(&buf[..bytes]);
+        }
+        self.bytes += bytes as u64;
+        Ok(bytes)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InnerFile {
+    name: OsString,
+    original_size: u64,
+    compressed_size: u64,
+    original_checksum: u32,
+    compressed_checksum: u32,
+    uid: u32,
+    gid: u32,
+    method: Method,
+    chunked: bool,
+    /// Extended attributes as (name, value), see `PackOptions::xattrs`.
+    xattrs: Vec<(OsString, Vec<u8>)>,
+    /// Entry holding the contents of this hard link, see `PackOptions::hard_links`.
+    link_target: Option<OsString>,
+    /// Unix permission bits of the file.
+    mode: Option<u32>,
+    /// Modification time of the file, not stored by `PackOptions::reproducible`.
+    mtime: Option<SystemTime>,
+    /// Modification times of the directories holding the file, as (levels
+    /// above it, time), see `EXTENSION_DIR_MTIME`.
+    dir_mtimes: Vec<(u16, SystemTime)>,
+    position: u64,
+}
+
+impl InnerFile {
+    pub fn new(name: OsString) -> Self {
+        Self {
+            name,
+            ..Default::default()
+        }
+    }
+
+    pub fn create(
+        name: OsString,
+        original_size: u64,
+        compressed_size: u64,
+        original_checksum: u32,
+        compressed_checksum: u32,
+    ) -> Self {
+        let mut file = Self::new(name);
+        file.set_original_size(original_size);
+        file.set_compressed_size(compressed_size);
+        file.set_original_checksum(original_checksum);
+        file.set_compressed_checksum(compressed_checksum);
+        file
+    }
+
+    pub fn from_archive<R: Read + Seek>(
+        reader: &mut R,
+        buffer: &mut [u8],
+        version: Version,
+    ) -> Result<Self> {
+        Self::from_archive_with_limit(reader, buffer, version, MAX_ORIGINAL_SIZE)
+    }
+
+    /// Same as `from_archive`, but rejects entries with an original size
+    /// above `max_original_size`.
+    pub fn from_archive_with_limit<R: Read + Seek>(
+        reader: &mut R,
+        buffer: &mut [u8],
+        version: Version,
+        max_original_size: u64,
+    ) -> Result<Self> {
+        let position = reader.stream_position()?;
+        let length = reader.seek(SeekFrom::End(0))?;
+        reader.seek(SeekFrom::Start(position))?;
+
+        // caught here, `read_metadata` would only fail with an `UnexpectedEof`
+        let mut name_len = [0u8; 4];
+        reader.read_exact(&mut name_len)?;
+        let name_len = u64::from(u32::from_le_bytes(name_len));
+        let remaining = length.saturating_sub(position + 4);
+        if name_len > remaining {
+            return Err(ArchiveError::CorruptedArchive(format!(
+                "Entry at offset {} claims a name of {} bytes, but only {} remain in the archive",
+                position, name_len, remaining
+            )));
+        }
+        reader.seek(SeekFrom::Start(position))?;
+
+        let mut file = Self::read_metadata(reader, buffer, version, max_original_size)?;
+
+        let current = reader.stream_position()?;
+        if file.compressed_size > length.saturating_sub(current) {
+            return Err(ArchiveError::CorruptedArchive(format!(
+                "Entry '{}' claims {} compressed bytes, but only {} remain in the archive",
+                file.name.to_string_lossy(),
+                file.compressed_size,
+                length.saturating_sub(current)
+            )));
+        }
+
+        file.position = position;
+        Ok(file)
+    }
+
+    /// Parses the metadata without seeking, so the position of the entry is
+    /// left unset and its compressed size isn't checked against the archive.
+    pub(crate) fn read_metadata<R: Read>(
+        reader: &mut R,
+        buffer: &mut [u8],
+        version: Version,
+        max_original_size: u64,
+    ) -> Result<Self> {
+        reader.read_exact(&mut buffer[..4])?;
+        let name_len = u32::from_le_bytes(buffer[..4].try_into()?) as usize;
+
+        if name_len == 0 {
+            return Err(ArchiveError::EmptyFilename);
+        }
+
+        if name_len > BUFFER_SIZE.min(buffer.len()) {
+            return Err(ArchiveError::BufferOverflow(name_len));
+        }
+
+        reader.read_exact(&mut buffer[..name_len])?;
+        let name = name_from_bytes(&buffer[..name_len])?;
+
+        reader.read_exact(&mut buffer[..8])?;
+        let original_size = u64::from_le_bytes(buffer[..8].try_into()?);
+
+        reader.read_exact(&mut buffer[..8])?;
+        let compressed_size = u64::from_le_bytes(buffer[..8].try_into()?);
+
+        reader.read_exact(&mut buffer[..4])?;
+        let original_checksum = u32::from_le_bytes(buffer[..4].try_into()?);
+
+        reader.read_exact(&mut buffer[..4])?;
+        let compressed_checksum = u32::from_le_bytes(buffer[..4].try_into()?);
+
+        let mut file = InnerFile::create(
+            name,
+            original_size,
+            compressed_size,
+            original_checksum,
+            compressed_checksum,
+        );
+
+        if version >= Version::new(1, 1) {
+            reader.read_exact(&mut buffer[..8])?;
+            let uid = u32::from_le_bytes(buffer[..4].try_into()?);
+            let gid = u32::from_le_bytes(buffer[4..8].try_into()?);
+            file.set_owner(uid, gid);
+        }
+
+        if version >= Version::new(1, 3) {
+            reader.read_exact(&mut buffer[..1])?;
+            let mut id = buffer[0];
+            if version >= Version::new(1, 6) {
+                file.set_chunked(id & CHUNKED != 0);
+                id &= !CHUNKED;
+            }
+            file.set_method(Method::from_id(id)?);
+        }
+
+        if version >= Version::new(1, 8) {
+            reader.read_exact(&mut buffer[..2])?;
+            let count = u16::from_le_bytes(buffer[..2].try_into()?);
+            for _ in 0..count {
+                reader.read_exact(&mut buffer[..6])?;
+                let kind = u16::from_le_bytes(buffer[..2].try_into()?);
+                let len = u32::from_le_bytes(buffer[2..6].try_into()?) as usize;
+                if len > MAX_EXTENSION_SIZE.min(buffer.len()) {
+                    return Err(ArchiveError::CorruptedArchive(format!(
+                        "Extension of entry '{}' has {} bytes, exceeding the limit of {}",
+                        file.name.to_string_lossy(),
+                        len,
+                        MAX_EXTENSION_SIZE
+                    )));
+                }
+
+                reader.read_exact(&mut buffer[..len])?;
+                if kind == EXTENSION_XATTR {
+                    let value = &buffer[..len];
+                    let separator = value.iter().position(|&b| b == 0).ok_or_else(|| {
+                        ArchiveError::CorruptedArchive(format!(
+                            "Extended attribute of entry '{}' has no name",
+                            file.name.to_string_lossy()
+                        ))
+                    })?;
+                    let name = name_from_bytes(&value[..separator])?;
+                    file.xattrs.push((name, value[separator + 1..].to_vec()));
+                } else if kind == EXTENSION_HARD_LINK {
+                    file.link_target = Some(name_from_bytes(&buffer[..len])?);
+                } else if kind == EXTENSION_MODE {
+                    let value = extension_value::<4>(&file, &buffer[..len], "mode")?;
+                    file.mode = Some(u32::from_le_bytes(value));
+                } else if kind == EXTENSION_MTIME {
+                    let value = extension_value::<12>(&file, &buffer[..len], "modification time")?;
+                    let seconds = i64::from_le_bytes(value[..8].try_into()?);
+                    let nanos = u32::from_le_bytes(value[8..].try_into()?);
+                    file.mtime = Some(time_from_parts(seconds, nanos).ok_or_else(|| {
+                        ArchiveError::CorruptedArchive(format!(
+                            "Modification time of entry '{}' is out of range",
+                            file.name.to_string_lossy()
+                        ))
+                    })?);
+                } else if kind == EXTENSION_DIR_MTIME {
+                    let value = extension_value::<14>(
+                        &file,
+                        &buffer[..len],
+                        "directory modification time",
+                    )?;
+                    let levels = u16::from_le_bytes(value[..2].try_into()?);
+                    let seconds = i64::from_le_bytes(value[2..10].try_into()?);
+                    let nanos = u32::from_le_bytes(value[10..].try_into()?);
+                    let mtime = time_from_parts(seconds, nanos).ok_or_else(|| {
+                        ArchiveError::CorruptedArchive(format!(
+                            "Directory modification time of entry '{}' is out of range",
+                            file.name.to_string_lossy()
+                        ))
+                    })?;
+                    file.dir_mtimes.push((levels, mtime));
+                } else {
+                    // records of later versions are skipped by their length
+                    debug!(
+                        "Ignored extension record of unknown type {} ({} bytes) of entry '{}'",
+                        kind,
+                        len,
+                        file.name.to_string_lossy()
+                    );
+                }
+            }
+        }
+
+        if original_size > max_original_size {
+            return Err(ArchiveError::CorruptedArchive(format!(
+                "Entry '{}' is too large: {} bytes exceeds the limit of {}",
+                file.name.to_string_lossy(),
+                original_size,
+                max_original_size
+            )));
+        }
+        Ok(file)
+    }
+
+    pub fn write_metadata<W: Write + ?Sized + Seek>(
+        &mut self,
+        writer: &mut BufWriter<W>,
+    ) -> Result<u64> {
+        self.position = writer.stream_position()?;
+        self.write_fields(writer)?;
+
+        // offset of the compressed size
+        Ok(self.position + 4 + self.name.as_encoded_bytes().len() as u64 + 8)
+    }
+
+    /// Count of bytes written by `write_metadata`.
+    fn metadata_size(&self) -> u64 {
+        let extensions: u64 = self
+            .xattrs
+            .iter()
+            .map(|(name, value)| 6 + name.as_encoded_bytes().len() as u64 + 1 + value.len() as u64)
+            .sum::<u64>()
+            + self
+                .link_target
+                .as_ref()
+                .map_or(0, |target| 6 + target.as_encoded_bytes().len() as u64)
+            + self.mode.map_or(0, |_| 6 + 4)
+            + self.mtime.map_or(0, |_| 6 + 12)
+            + self.dir_mtimes.len() as u64 * (6 + 14);
+        4 + self.name.as_encoded_bytes().len() as u64 + 8 + 8 + 4 + 4 + 4 + 4 + 1 + 2 + extensions
+    }
+
+    fn write_fields<W: Write + ?Sized>(&self, writer: &mut W) -> Result<()> {
+        let name_bytes = self.name.as_encoded_bytes();
+        writer.write_all(&(name_bytes.len() as u32).to_le_bytes())?;
+        writer.write_all(name_bytes)?;
+        writer.write_all(&self.original_size.to_le_bytes())?;
+        writer.write_all(&self.compressed_size.to_le_bytes())?;
+        writer.write_all(&self.original_checksum.to_le_bytes())?;
+        writer.write_all(&self.compressed_checksum.to_le_bytes())?;
+        writer.write_all(&self.uid.to_le_bytes())?;
+        writer.write_all(&self.gid.to_le_bytes())?;
+        let chunked = if self.chunked { CHUNKED } else { 0 };
+        writer.write_all(&[self.method.id() | chunked])?;
+
+        let count = self.xattrs.len()
+            + usize::from(self.link_target.is_some())
+            + usize::from(self.mode.is_some())
+            + usize::from(self.mtime.is_some())
+            + self.dir_mtimes.len();
+        writer.write_all(&u16::try_from(count)?.to_le_bytes())?;
+        for (name, value) in &self.xattrs {
+            let name = name.as_encoded_bytes();
+            writer.write_all(&EXTENSION_XATTR.to_le_bytes())?;
+            writer.write_all(&u32::try_from(name.len() + 1 + value.len())?.to_le_bytes())?;
+            writer.write_all(name)?;
+            writer.write_all(&[0])?;
+            writer.write_all(value)?;
+        }
+        if let Some(target) = &self.link_target {
+            let target = target.as_encoded_bytes();
+            writer.write_all(&EXTENSION_HARD_LINK.to_le_bytes())?;
+            writer.write_all(&u32::try_from(target.len())?.to_le_bytes())?;
+            writer.write_all(target)?;
+        }
+        if let Some(mode) = self.mode {
+            writer.write_all(&EXTENSION_MODE.to_le_bytes())?;
+            writer.write_all(&4u32.to_le_bytes())?;
+            writer.write_all(&mode.to_le_bytes())?;
+        }
+        if let Some(mtime) = self.mtime {
+            let (seconds, nanos) = time_to_parts(mtime);
+            writer.write_all(&EXTENSION_MTIME.to_le_bytes())?;
+            writer.write_all(&12u32.to_le_bytes())?;
+            writer.write_all(&seconds.to_le_bytes())?;
+            writer.write_all(&nanos.to_le_bytes())?;
+        }
+        for &(levels, mtime) in &self.dir_mtimes {
+            let (seconds, nanos) = time_to_parts(mtime);
+            writer.write_all(&EXTENSION_DIR_MTIME.to_le_bytes())?;
+            writer.write_all(&14u32.to_le_bytes())?;
+            writer.write_all(&levels.to_le_bytes())?;
+            writer.write_all(&seconds.to_le_bytes())?;
+            writer.write_all(&nanos.to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    pub fn name(&self) -> &OsStr {
+        &self.name
+    }
+
+    pub fn original_size(&self) -> u64 {
+        self.original_size
+    }
+
+    pub fn compressed_size(&self) -> u64 {
+        self.compressed_size
+    }
+
+    pub fn original_checksum(&self) -> u32 {
+        self.original_checksum
+    }
+
+    pub fn compressed_checksum(&self) -> u32 {
+        self.compressed_checksum
+    }
+
+    pub fn uid(&self) -> u32 {
+        self.uid
+    }
+
+    pub fn gid(&self) -> u32 {
+        self.gid
+    }
+
+    pub fn method(&self) -> Method {
+        self.method
+    }
+
+    /// Whether the contents are stored as chunk records, see `FLAG_DEDUP`.
+    pub fn is_chunked(&self) -> bool {
+        self.chunked
+    }
+
+    /// Extended attributes stored with `--xattrs` as (name, value).
+    pub fn xattrs(&self) -> &[(OsString, Vec<u8>)] {
+        &self.xattrs
+    }
+
+    /// Name of the entry this one is a hard link to, its own contents are
+    /// empty.
+    pub fn link_target(&self) -> Option<&OsStr> {
+        self.link_target.as_deref()
+    }
+
+    /// Unix permission bits of the file, unknown for entries of archives
+    /// before they were stored.
+    pub fn mode(&self) -> Option<u32> {
+        self.mode
+    }
+
+    /// Modification time of the file, unknown for entries of reproducible
+    /// archives and archives before it was stored.
+    pub fn mtime(&self) -> Option<SystemTime> {
+        self.mtime
+    }
+
+    /// Modification times of the directories above the file as (levels up,
+    /// time), each directory is recorded with its first entry only.
+    pub fn dir_mtimes(&self) -> &[(u16, SystemTime)] {
+        &self.dir_mtimes
+    }
+
+    /// Offset of the entry metadata in the archive.
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    fn set_name(&mut self, name: OsString) {
+        self.name = name
+    }
+
+    fn set_original_size(&mut self, size: u64) {
+        self.original_size = size
+    }
+
+    fn set_compressed_size(&mut self, size: u64) {
+        self.compressed_size = size
+    }
+
+    fn set_original_checksum(&mut self, checksum: u32) {
+        self.original_checksum = checksum
+    }
+
+    fn set_compressed_checksum(&mut self, checksum: u32) {
+        self.compressed_checksum = checksum
+    }
+
+    fn set_owner(&mut self, uid: u32, gid: u32) {
+        self.uid = uid;
+        self.gid = gid;
+    }
+
+    fn set_method(&mut self, method: Method) {
+        self.method = method
+    }
+
+    fn set_chunked(&mut self, chunked: bool) {
+        self.chunked = chunked
+    }
+
+    fn set_link_target(&mut self, target: Option<OsString>) {
+        self.link_target = target
+    }
+
+    fn set_mode(&mut self, mode: Option<u32>) {
+        self.mode = mode
+    }
+
+    fn set_mtime(&mut self, mtime: Option<SystemTime>) {
+        self.mtime = mtime
+    }
+
+    fn set_dir_mtimes(&mut self, mtimes: Vec<(u16, SystemTime)>) {
+        self.dir_mtimes = mtimes
+    }
+
+    #[cfg(all(unix, feature = "xattrs"))]
+    fn set_xattrs(&mut self, xattrs: Vec<(OsString, Vec<u8>)>) {
+        self.xattrs = xattrs
+    }
+}
+
+/// Value of a fixed size extension record of `file`, failing when the
+/// record has another length.
+fn extension_value<const N: usize>(file: &InnerFile, value: &[u8], what: &str) -> Result<[u8; N]> {
+    value.try_into().map_err(|_| {
+        ArchiveError::CorruptedArchive(format!(
+            "Record of the {} of entry '{}' has {} bytes instead of {}",
+            what,
+            file.name.to_string_lossy(),
+            value.len(),
+            N
+        ))
+    })
+}
+
+/// Splits `time` into signed seconds since the Unix epoch and nanoseconds,
+/// which always count forwards, so 0.25s before the epoch is (-1, 750000000).
+fn time_to_parts(time: SystemTime) -> (i64, u32) {
+    match time.duration_since(SystemTime::UNIX_EPOCH) {
+        Ok(after) => (
+            i64::try_from(after.as_secs()).unwrap_or(i64::MAX),
+            after.subsec_nanos(),
+        ),
+        Err(e) => {
+            let before = e.duration();
+            let seconds = i64::try_from(before.as_secs()).unwrap_or(i64::MAX);
+            match before.subsec_nanos() {
+                0 => (-seconds, 0),
+                nanos => (-seconds - 1, 1_000_000_000 - nanos),
+            }
+        }
+    }
+}
+
+/// Inverse of `time_to_parts`, `None` when the time can't be represented.
+fn time_from_parts(seconds: i64, nanos: u32) -> Option<SystemTime> {
+    if nanos >= 1_000_000_000 {
+        return None;
+    }
+    let epoch = SystemTime::UNIX_EPOCH;
+    if seconds >= 0 {
+        epoch.checked_add(Duration::new(seconds as u64, nanos))
+    } else {
+        epoch
+            .checked_sub(Duration::from_secs(seconds.unsigned_abs()))?
+            .checked_add(Duration::from_nanos(u64::from(nanos)))
+    }
+}
+
+/// Restores a name written by `as_encoded_bytes` on the same platform.
+#[cfg(unix)]
+fn name_from_bytes(bytes: &[u8]) -> Result<OsString> {
+    Ok(OsString::from_vec(bytes.to_vec()))
+}
+
+/// Restores a name written by `as_encoded_bytes` on the same platform,
+/// decoding WTF-8 so unpaired surrogates survive. Names which aren't WTF-8,
+/// e.g. non-UTF-8 names packed on Unix, are rejected.
+#[cfg(windows)]
+fn name_from_bytes(bytes: &[u8]) -> Result<OsString> {
+    let invalid = || {
+        ArchiveError::CorruptedArchive(format!(
+            "Entry name '{}' can't be represented on this platform",
+            String::from_utf8_lossy(bytes)
+        ))
+    };
+
+    let mut wide = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let first = bytes[i] as u32;
+        let (len, min, mut code) = match first {
+            0x00..=0x7f => (1, 0, first),
+            0xc0..=0xdf => (2, 0x80, first & 0x1f),
+            0xe0..=0xef => (3, 0x800, first & 0x0f),
+            0xf0..=0xf4 => (4, 0x10000, first & 0x07),
+            _ => return Err(invalid()),
+        };
+
+        let tail = bytes.get(i + 1..i + len).ok_or_else(invalid)?;
+        for &byte in tail {
+            if byte & 0xc0 != 0x80 {
+                return Err(invalid());
+            }
+            code = (code << 6) | (byte & 0x3f) as u32;
+        }
+        if code < min || code > 0x10ffff {
+            return Err(invalid());
+        }
+
+        if code >= 0x10000 {
+            let code = code - 0x10000;
+            wide.push(0xd800 | (code >> 10) as u16);
+            wide.push(0xdc00 | (code & 0x3ff) as u16);
+        } else {
+            // surrogates are kept as they are, that's what WTF-8 is for
+            wide.push(code as u16);
+        }
+        i += len;
+    }
+    Ok(OsString::from_wide(&wide))
+}
+
+/// Whether a relative `path` stays inside the directory it's joined to,
+/// i.e. it's not absolute and its `..` never climb above the start.
+fn stays_inside(path: &Path) -> bool {
+    let mut depth = 0usize;
+    for component in path.components() {
+        match component {
+            Component::Normal(_) => depth += 1,
+            Component::CurDir => {}
+            Component::ParentDir => match depth.checked_sub(1) {
+                Some(parent) => depth = parent,
+                None => return false,
+            },
+            Component::RootDir | Component::Prefix(_) => return false,
+        }
+    }
+    true
+}
+
+fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = Vec::new();
+
+    for component in path.components() {
+        match component {
+            Component::Prefix(p) => {
+                normalized.clear();
+                normalized.push(Component::Prefix(p));
+            }
+            Component::RootDir => {
+                normalized.clear();
+                normalized.push(Component::RootDir);
+            }
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if let Some(last) = normalized.last() {
+                    match last {
+                        Component::Normal(_) => {
+                            normalized.pop();
+                        }
+                        Component::ParentDir => normalized.push(component),
+                        Component::RootDir => {}
+                        _ => {}
+                    }
+                } else {
+                    normalized.push(component);
+                }
+            }
+            Component::Normal(_) => normalized.push(component),
+        }
+    }
+
+    if normalized.is_empty() {
+        return PathBuf::from(".");
+    }
+    normalized.iter().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io::Cursor};
+
+    use super::*;
+    use crate::{
+        builder::ArchiveBuilder,
+        reader::ArchiveReader,
+        testing::{self, TempDir},
+        unpack::{UnpackOptions, unpack},
+    };
+
+    fn is_incomplete<T>(result: Result<T>) -> bool {
+        matches!(result, Err(ArchiveError::CorruptedArchive(message)) if message == "Archive incomplete")
+    }
+
+    #[test]
+    fn truncated_footer_is_detected() {
+        let mut builder = ArchiveBuilder::new();
+        builder.add_bytes("a.txt", b"contents").unwrap();
+        let archive = builder.finish(Vec::new()).unwrap();
+        let mut buffer = vec![0; 64];
+        let footer =
+            Footer::from_archive(&mut Cursor::new(&archive), &mut buffer, VERSION).unwrap();
+        assert_eq!(footer.total_length, archive.len() as u64);
+
+        let dir = TempDir::new("truncated-footer");
+        for cut in [1, 4, Footer::size(VERSION) as usize] {
+            let truncated = &archive[..archive.len() - cut];
+            let mut reader = Cursor::new(truncated);
+            assert!(is_incomplete(Footer::from_archive(
+                &mut reader,
+                &mut buffer,
+                VERSION
+            )));
+            assert!(is_incomplete(
+                ArchiveReader::new(Cursor::new(truncated)).and_then(|mut reader| reader.verify())
+            ));
+
+            let source = dir.join("truncated.slf");
+            fs::write(&source, truncated).unwrap();
+            let result = unpack(source, Some(dir.join("out")), &UnpackOptions::default());
+            assert!(is_incomplete(result), "cut {}", cut);
+        }
+    }
+
+    #[test]
+    fn flipped_header_byte_fails_archive_checksum() {
+        let mut builder = ArchiveBuilder::new();
+        builder.add_bytes("a.txt", b"contents").unwrap();
+        let archive = builder.finish(Vec::new()).unwrap();
+        let mut buffer = vec![0; BUFFER_SIZE];
+        let footer =
+            Footer::from_archive(&mut Cursor::new(&archive), &mut buffer, VERSION).unwrap();
+        footer
+            .verify_checksum(&mut Cursor::new(&archive), &mut buffer)
+            .unwrap();
+
+        // everything after the signature and version up to the first entry
+        for at in 6..Header::from_archive(&mut Cursor::new(&archive), &mut buffer)
+            .unwrap()
+            .size() as usize
+        {
+            let mut damaged = archive.clone();
+            damaged[at] ^= 0x10;
+            let result = footer.verify_checksum(&mut Cursor::new(&damaged), &mut buffer);
+            assert!(
+                matches!(result, Err(ArchiveError::CorruptedArchive(ref message)) if message.contains("Archive checksum")),
+                "byte {}: {:?}",
+                at,
+                result
+            );
+        }
+    }
+
+    #[test]
+    fn oversized_name_length_names_the_offset() {
+        let mut builder = ArchiveBuilder::new();
+        builder.add_bytes("a.txt", b"contents").unwrap();
+        let mut archive = builder.finish(Vec::new()).unwrap();
+        let mut buffer = vec![0; BUFFER_SIZE];
+        let header = Header::from_archive(&mut Cursor::new(&archive), &mut buffer).unwrap();
+
+        // still fits into the buffer, but not into the archive
+        let offset = header.size() as usize;
+        archive[offset..offset + 4].copy_from_slice(&100_000u32.to_le_bytes());
+        let mut reader = Cursor::new(&archive);
+        reader.set_position(header.size());
+        match InnerFile::from_archive(&mut reader, &mut buffer, VERSION) {
+            Err(ArchiveError::CorruptedArchive(message)) => assert!(
+                message.starts_with(&format!(
+                    "Entry at offset {} claims a name of 100000 bytes",
+                    offset
+                )),
+                "{}",
+                message
+            ),
+            result => panic!("{:?}", result),
+        }
+    }
+
+    #[test]
+    fn unknown_extensions_are_skipped() {
+        let mut file = InnerFile::create("a.txt".into(), 8, 8, 1, 2);
+        file.mode = Some(0o640);
+        let mut entry = Vec::new();
+        file.write_fields(&mut entry).unwrap();
+
+        // a record of a later version, ahead of the known one
+        let count = 4 + 5 + 8 + 8 + 4 + 4 + 4 + 4 + 1;
+        assert_eq!(entry[count..count + 2], 1u16.to_le_bytes());
+        entry[count..count + 2].copy_from_slice(&2u16.to_le_bytes());
+        let unknown = [&0x7fffu16.to_le_bytes()[..], &5u32.to_le_bytes(), b"later"].concat();
+        entry.splice(count + 2..count + 2, unknown);
+        let contents_start = entry.len() as u64;
+        entry.extend_from_slice(b"contents");
+
+        let mut reader = Cursor::new(&entry);
+        let mut buffer = vec![0; BUFFER_SIZE];
+        let read = InnerFile::from_archive(&mut reader, &mut buffer, VERSION).unwrap();
+        assert_eq!(read.name(), "a.txt");
+        assert_eq!(read.mode(), Some(0o640));
+        assert_eq!(read.original_size(), 8);
+        assert_eq!(reader.position(), contents_start);
+    }
+
+    #[test]
+    fn newer_minor_versions_with_unknown_extensions_extract() {
+        let mut builder = ArchiveBuilder::new();
+        builder.add_bytes("a.txt", b"first").unwrap();
+        builder.add_bytes("b.txt", b"second").unwrap();
+        let mut archive = builder.finish(Vec::new()).unwrap();
+
+        // an unknown record in the first entry, moving everything after it
+        let header_size = 21;
+        let count = header_size + 4 + 5 + 8 + 8 + 4 + 4 + 4 + 4 + 1;
+        assert_eq!(archive[count..count + 2], 0u16.to_le_bytes());
+        archive[count..count + 2].copy_from_slice(&1u16.to_le_bytes());
+        let record = [&0x7fffu16.to_le_bytes()[..], &5u32.to_le_bytes(), b"later"].concat();
+        let shift = record.len() as u64;
+        archive.splice(count + 2..count + 2, record);
+
+        let read_u64 =
+            |archive: &[u8], at: usize| u64::from_le_bytes(archive[at..at + 8].try_into().unwrap());
+        let index_offset = read_u64(&archive, 10) + shift;
+        archive[10..18].copy_from_slice(&index_offset.to_le_bytes());
+        let second = index_offset as usize + 8;
+        let position = read_u64(&archive, second) + shift;
+        archive[second..second + 8].copy_from_slice(&position.to_le_bytes());
+        let total_at = archive.len() - 12;
+        let total_length = read_u64(&archive, total_at) + shift;
+        archive[total_at..total_at + 8].copy_from_slice(&total_length.to_le_bytes());
+        testing::set_version(&mut archive, Version::new(VERSION.major, VERSION.minor + 1));
+
+        let mut reader = ArchiveReader::new(Cursor::new(&archive)).unwrap();
+        reader.verify().unwrap();
+
+        let dir = TempDir::new("newer-minor");
+        let path = dir.join("newer.slf");
+        fs::write(&path, &archive).unwrap();
+        unpack(path, Some(dir.join("out")), &UnpackOptions::default()).unwrap();
+        assert_eq!(fs::read(dir.join("out/newer/a.txt")).unwrap(), b"first");
+        assert_eq!(fs::read(dir.join("out/newer/b.txt")).unwrap(), b"second");
+    }
+
+    #[test]
+    fn newer_minor_versions_with_unknown_flags_are_refused() {
+        let mut builder = ArchiveBuilder::new();
+        builder.add_bytes("a.txt", b"a").unwrap();
+        let mut archive = builder.finish(Vec::new()).unwrap();
+        let flags = u16::from_le_bytes([archive[18], archive[19]]) | 1 << 15;
+        archive[18..20].copy_from_slice(&flags.to_le_bytes());
+        testing::set_version(&mut archive, Version::new(VERSION.major, VERSION.minor + 1));
+
+        let mut buffer = vec![0; BUFFER_SIZE];
+        match Header::from_archive(&mut Cursor::new(&archive), &mut buffer) {
+            Err(ArchiveError::Unsupported(message)) => {
+                assert!(message.contains("0x8000"), "{}", message)
+            }
+            result => panic!("{:?}", result.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn versions_are_ordered_and_round_trip() {
+        let versions = [
+            Version::new(0, 9),
+            Version::new(1, 0),
+            Version::new(1, 2),
+            Version::new(1, 10),
+            Version::new(2, 0),
+        ];
+        for pair in versions.windows(2) {
+            assert!(pair[0] < pair[1], "{} < {}", pair[0], pair[1]);
+        }
+        for version in versions {
+            assert_eq!(Version::from_bytes(version.to_bytes()), version);
+        }
+        assert_eq!(Version::new(1, 10).to_bytes(), [1, 10]);
+        assert_eq!(Version::new(1, 10).to_string(), "1.10");
+
+        let mut builder = ArchiveBuilder::new();
+        builder.add_bytes("a.txt", b"a").unwrap();
+        let mut archive = builder.finish(Vec::new()).unwrap();
+        let newer = Version::new(VERSION.major + 1, 0);
+        testing::set_version(&mut archive, newer);
+        let mut buffer = vec![0; BUFFER_SIZE];
+        assert!(matches!(
+            Header::from_archive(&mut Cursor::new(&archive), &mut buffer),
+            Err(ArchiveError::UnsupportedVersion(version)) if version == newer
+        ));
+    }
+}
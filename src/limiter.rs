@@ -0,0 +1,57 @@
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Throttles a stream of chunks to the given average throughput.
+/// A limit of zero means unlimited.
+pub struct RateLimiter {
+    bytes_per_sec: u64,
+    start: Instant,
+    bytes: u64,
+}
+
+impl RateLimiter {
+    pub fn new(bytes_per_sec: u64) -> Self {
+        Self {
+            bytes_per_sec,
+            start: Instant::now(),
+            bytes: 0,
+        }
+    }
+
+    /// Accounts processed bytes, sleeping until they fit into the limit.
+    pub fn consume(&mut self, bytes: usize) {
+        if self.bytes_per_sec == 0 {
+            return;
+        }
+
+        self.bytes += bytes as u64;
+        let expected = Duration::from_secs_f64(self.bytes as f64 / self.bytes_per_sec as f64);
+        let elapsed = self.start.elapsed();
+        if expected > elapsed {
+            thread::sleep(expected - elapsed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn throttles_to_the_limit() {
+        let start = Instant::now();
+        let mut limiter = RateLimiter::new(256 * 1024);
+        for _ in 0..16 {
+            limiter.consume(4096);
+        }
+        // 64 KiB at 256 KiB per second
+        assert!(start.elapsed() >= Duration::from_millis(250));
+
+        let start = Instant::now();
+        let mut unlimited = RateLimiter::new(0);
+        unlimited.consume(1 << 30);
+        assert!(start.elapsed() < Duration::from_millis(250));
+    }
+}
@@ -0,0 +1,250 @@
+use std::{collections::HashMap, ffi::OsString, fmt, path::Path};
+
+use crate::{InnerFile, error::Result, reader::ArchiveReader};
+
+/// Entries of an archive in index order, see `list`.
+#[derive(Debug, Clone)]
+pub struct Listing {
+    pub entries: Vec<InnerFile>,
+    /// Contents of solid archives share one block, so entries have no
+    /// compressed size of their own.
+    pub solid: bool,
+}
+
+/// Lists the entries of the archive, reading only its index.
+pub fn list<P: AsRef<Path>>(path: P) -> Result<Listing> {
+    let archive = ArchiveReader::open(path)?;
+    Ok(Listing {
+        entries: archive.entries().to_vec(),
+        solid: archive.header().is_solid(),
+    })
+}
+
+/// `compressed` as a share of `original`, above 1 for contents which grew.
+/// `None` for empty contents, which have no meaningful ratio.
+pub fn ratio(compressed: u64, original: u64) -> Option<f64> {
+    (original > 0).then(|| compressed as f64 / original as f64)
+}
+
+/// Formats `bytes` with a binary unit, e.g. `512 B` or `1.5 MiB`.
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["KiB", "MiB", "GiB", "TiB", "PiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    // 1023.96 KiB would round up to "1024.0 KiB" otherwise
+    while size >= 1023.95 && unit + 1 < UNITS.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+/// Missing value in a column, such as the ratio of an empty file.
+const NONE: &str = "—";
+
+impl fmt::Display for Listing {
+    /// One line per entry with its sizes, ratio and method, followed by the
+    /// totals, every line ends with a newline.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:>10}  {:>10}  {:>7}  {:<7}  Name",
+            "Original", "Compressed", "Ratio", "Method"
+        )?;
+
+        let (mut original, mut compressed) = (0, 0);
+        for entry in &self.entries {
+            original += entry.original_size();
+            compressed += entry.compressed_size();
+
+            let method = if entry.link_target().is_some() {
+                "link"
+            } else {
+                entry.method().name()
+            };
+            writeln!(
+                f,
+                "{:>10}  {}  {}  {:<7}  {}",
+                format_size(entry.original_size()),
+                self.column(10, format_size(entry.compressed_size())),
+                self.column(7, percent(entry.compressed_size(), entry.original_size())),
+                method,
+                entry.name().to_string_lossy()
+            )?;
+        }
+
+        writeln!(
+            f,
+            "{:>10}  {}  {}  {:<7}  {} entries",
+            format_size(original),
+            self.column(10, format_size(compressed)),
+            self.column(7, percent(compressed, original)),
+            "",
+            self.entries.len()
+        )
+    }
+}
+
+impl Listing {
+    /// Totals of the entries grouped by file extension, see `CompressionStats`.
+    pub fn compression_stats(&self) -> CompressionStats {
+        let mut groups: HashMap<OsString, ExtensionStats> = HashMap::new();
+        for entry in &self.entries {
+            let extension = Path::new(entry.name())
+                .extension()
+                .map(|extension| extension.to_ascii_lowercase())
+                .unwrap_or_default();
+            let group = groups
+                .entry(extension.clone())
+                .or_insert_with(|| ExtensionStats {
+                    extension,
+                    ..ExtensionStats::default()
+                });
+            group.entries += 1;
+            group.original_size += entry.original_size();
+            group.compressed_size += entry.compressed_size();
+        }
+
+        let mut groups: Vec<ExtensionStats> = groups.into_values().collect();
+        groups.sort_by(|a, b| {
+            b.compressed_size
+                .cmp(&a.compressed_size)
+                .then(b.original_size.cmp(&a.original_size))
+                .then_with(|| a.extension.cmp(&b.extension))
+        });
+        CompressionStats {
+            groups,
+            solid: self.solid,
+        }
+    }
+
+    /// Right-aligns a compressed size or ratio, unknown in solid archives.
+    fn column(&self, width: usize, value: String) -> String {
+        let value = if self.solid { NONE.to_string() } else { value };
+        format!("{:>width$}", value, width = width)
+    }
+}
+
+fn percent(compressed: u64, original: u64) -> String {
+    ratio(compressed, original).map_or(NONE.to_string(), |ratio| format!("{:.1}%", ratio * 100.0))
+}
+
+/// Totals of the entries with one file extension, see `CompressionStats`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ExtensionStats {
+    /// Lowercase extension without the dot, empty for names without one.
+    pub extension: OsString,
+    pub entries: u64,
+    pub original_size: u64,
+    pub compressed_size: u64,
+}
+
+impl ExtensionStats {
+    /// Compressed size of the group as a share of its original size.
+    pub fn ratio(&self) -> Option<f64> {
+        ratio(self.compressed_size, self.original_size)
+    }
+}
+
+/// Entries of an archive grouped by file extension, largest compressed
+/// size first, so the kinds of files taking up the archive stand out.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompressionStats {
+    pub groups: Vec<ExtensionStats>,
+    /// Compressed sizes are unknown in solid archives, see `Listing::solid`.
+    pub solid: bool,
+}
+
+impl fmt::Display for CompressionStats {
+    /// One line per extension with its totals and ratio, every line ends
+    /// with a newline.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<10}  {:>7}  {:>10}  {:>10}  {:>7}",
+            "Extension", "Entries", "Original", "Compressed", "Ratio"
+        )?;
+        for group in &self.groups {
+            let extension = if group.extension.is_empty() {
+                "(none)".to_string()
+            } else {
+                format!(".{}", group.extension.to_string_lossy())
+            };
+            let (compressed, ratio) = if self.solid {
+                (NONE.to_string(), NONE.to_string())
+            } else {
+                (
+                    format_size(group.compressed_size),
+                    percent(group.compressed_size, group.original_size),
+                )
+            };
+            writeln!(
+                f,
+                "{:<10}  {:>7}  {:>10}  {:>10}  {:>7}",
+                extension,
+                group.entries,
+                format_size(group.original_size),
+                compressed,
+                ratio
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::{builder::ArchiveBuilder, compressor::Method, testing::noise};
+
+    #[test]
+    fn empty_and_grown_entries_have_ratios() {
+        assert_eq!(ratio(0, 0), None);
+        assert_eq!(ratio(10, 0), None);
+        assert_eq!(ratio(150, 100), Some(1.5));
+        assert_eq!(percent(0, 0), NONE);
+        assert_eq!(percent(150, 100), "150.0%");
+
+        // noise only grows by gzip framing
+        let noise = noise(100);
+        let mut builder = ArchiveBuilder::new();
+        builder.method(Method::Gzip);
+        builder.add_bytes("empty", b"").unwrap();
+        builder.add_bytes("noise.bin", &noise).unwrap();
+        let archive = builder.finish(Vec::new()).unwrap();
+        let listing = Listing {
+            entries: ArchiveReader::new(Cursor::new(archive))
+                .unwrap()
+                .entries()
+                .to_vec(),
+            solid: false,
+        };
+        let grown = &listing.entries[1];
+        assert!(grown.compressed_size() > grown.original_size());
+
+        let text = listing.to_string();
+        let lines: Vec<_> = text.lines().collect();
+        assert_eq!(lines.len(), 4, "{}", text);
+        assert!(lines[1].trim_start().starts_with("0 B"), "{}", text);
+        assert!(lines[1].contains(NONE), "{}", text);
+        let percent = percent(grown.compressed_size(), grown.original_size());
+        assert!(lines[2].contains(&percent), "{}", text);
+        assert!(lines[3].ends_with("2 entries"));
+    }
+
+    #[test]
+    fn sizes_use_binary_units() {
+        assert_eq!(format_size(0), "0 B");
+        assert_eq!(format_size(1023), "1023 B");
+        assert_eq!(format_size(1024), "1.0 KiB");
+        assert_eq!(format_size(1536 * 1024), "1.5 MiB");
+        assert_eq!(format_size(1024 * 1024 - 1), "1.0 MiB");
+        assert_eq!(format_size(3 << 30), "3.0 GiB");
+    }
+}
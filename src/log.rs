@@ -0,0 +1,37 @@
+use log::{Level, LevelFilter};
+
+/// How much `pack` and `unpack` report, set with `-q`, `-v` and `-vv` on
+/// the command line. Messages go through the `log` crate, so applications
+/// route them into their own logger. Errors are reported at every level.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    /// Errors only.
+    Quiet,
+    /// Warnings and notable events, the default.
+    Normal,
+    /// A line for every packed or extracted entry and a final summary.
+    Verbose,
+    /// Everything, including internals of the format.
+    Debug,
+}
+
+impl Verbosity {
+    /// Least severe level of the messages shown, for `log::set_max_level`.
+    pub fn max_level(self) -> LevelFilter {
+        match self {
+            Self::Quiet => LevelFilter::Error,
+            Self::Normal | Self::Verbose => LevelFilter::Info,
+            Self::Debug => LevelFilter::Debug,
+        }
+    }
+
+    /// Level of the lines about single entries and the summary, shown from
+    /// `Verbose` on.
+    pub fn entry_level(self) -> Level {
+        if self >= Self::Verbose {
+            Level::Info
+        } else {
+            Level::Debug
+        }
+    }
+}
@@ -1,262 +1,688 @@
-/*
-.slf File structure:
-Signature (4 bytes = '.slf'),
-version (2 bytes),
-count of files (4 bytes),
-index offset (8 bytes)
- | length of file name(4 bytes),
- | name ('length' bytes),
- | original size of file (8 bytes),
- | compressed size (8 bytes),
- | original checksum (4 bytes),
- | compressed checksum (4 bytes),
- | compressed file ('compressed size' bytes),
- ...
-Index array (8 bytes * File count).
-*/
-
-pub mod error;
-pub mod pack;
-pub mod unpack;
-
 use std::{
     env,
-    ffi::OsString,
-    fs::File,
-    io::{self, BufWriter, Read, Seek, Write},
-    path::{Component, PathBuf},
+    ffi::OsStr,
+    fs, io,
+    path::{Path, PathBuf},
+    process,
+    str::FromStr,
+    sync::{Arc, Mutex, atomic::Ordering},
+    thread,
+    time::{Duration, Instant},
 };
 
-#[cfg(unix)]
-use std::os::unix::ffi::OsStringExt;
-
-#[cfg(windows)]
-use std::os::windows::ffi::OsStringExt;
-
-use flate2::Crc;
-
-use crate::error::{ArchiveError, Result};
-
-pub const SIGNATURE: &[u8] = b".slf";
-pub const VERSION: [u8; 2] = [1, 0]; // 1.0
-pub const BUFFER_SIZE: usize = 128 * 1024;
-
-use pack::pack;
-use unpack::unpack;
+use flate2::Compression;
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use sulfur::{
+    CancelFlag,
+    bench::{DEFAULT_BENCH_SIZE, bench},
+    checksum::ChecksumAlgorithm,
+    compressor::{Method, Preset},
+    diff::diff,
+    edit::{remove, rename},
+    error::{ArchiveError, Result},
+    estimate::{DEFAULT_SAMPLE, estimate},
+    filter::{FilterRule, NormalizeLineEndings},
+    info::info,
+    list::list,
+    log::Verbosity,
+    manifest::{check, manifest, save_manifest, write_manifest},
+    pack::{Format, PackOptions, SortBy, pack, read_file_list},
+    progress::Progress,
+    reader::ArchiveReader,
+    repack::repack,
+    transform::Transform,
+    unpack::{EntryStatus, UnpackOptions, glob_set, is_url, unpack, unpack_stream, unpack_url},
+    verify::{verify, verify_deep, verify_signature},
+};
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
-    if args.len() < 3 {
-        eprintln!("Usage: {} <pack|unpack> <directory|file>", args[0]);
-        return;
+    // only fails if a logger was set already
+    let _ = log::set_logger(&StderrLogger);
+    log::set_max_level(LevelFilter::Info);
+    if let Err(e) = arguments().and_then(|args| run(&args)) {
+        eprintln!("[ERROR] {}", e);
+        process::exit(exit_code(&e));
     }
+}
 
-    let target = if let Some(target) = args.get(3) {
-        Some(PathBuf::from(target))
-    } else {
-        None
-    };
+/// Prints the messages of this crate to standard error, up to the level
+/// set from `-q`, `-v`, `-vv` or `RUST_LOG`. Messages of dependencies, e.g.
+/// the HTTP client, are left out.
+struct StderrLogger;
 
-    let result = match args[1].as_str() {
-        "pack" => pack(PathBuf::from(&args[2]), target),
-        "unpack" => unpack(PathBuf::from(&args[2]), target),
-        _ => Err(ArchiveError::Io(format!(
-            "Incorrect usage of '{}', see `--help` for more info",
-            &args[1]
-        ))),
-    };
+impl Log for StderrLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level() && metadata.target().starts_with("sulfur")
+    }
 
-    if let Err(e) = result {
-        eprintln!("[ERROR] {}", e)
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            let label = match record.level() {
+                Level::Error => "ERROR",
+                Level::Warn => "WARNING",
+                Level::Info => "INFO",
+                Level::Debug => "DEBUG",
+                Level::Trace => "TRACE",
+            };
+            eprintln!("[{}] {}", label, record.args());
+        }
     }
+
+    fn flush(&self) {}
 }
 
-pub struct HasherWriter<'a> {
-    writer: &'a mut BufWriter<File>,
-    hasher: Crc,
-    bytes: u64,
+/// Arguments of the process, an argument which isn't UTF-8 is a usage error
+/// where `env::args` would panic. The program name defaults to `sulfur` if
+/// the caller passed none.
+fn arguments() -> Result<Vec<String>> {
+    let mut args = env::args_os()
+        .map(|arg| {
+            arg.into_string().map_err(|arg| {
+                ArchiveError::Usage(format!(
+                    "Argument '{}' isn't valid UTF-8",
+                    arg.to_string_lossy()
+                ))
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    if args.is_empty() {
+        args.push("sulfur".to_string());
+    }
+    Ok(args)
 }
 
-impl<'a> HasherWriter<'a> {
-    pub fn new(writer: &'a mut BufWriter<File>, hasher: Crc) -> Self {
-        Self {
-            writer,
-            hasher,
-            bytes: 0,
+fn run(args: &[String]) -> Result<()> {
+    let mut positional = Vec::new();
+    let mut pack_options = PackOptions::default();
+    let mut unpack_options = UnpackOptions::default();
+    let mut verbosity = None;
+    let mut machine = false;
+    let mut compression_stats = false;
+    let mut globs = Vec::new();
+    let mut eol_globs = Vec::new();
+    let mut sample = None;
+    let mut output = None;
+    let mut deep = false;
+    let mut pubkey = None;
+    let mut level = None;
+    let mut preset = None;
+
+    let mut iter = args[1..].iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "-q" | "--quiet" => verbosity = Some(Verbosity::Quiet),
+            "-v" | "--verbose" => verbosity = Some(Verbosity::Verbose),
+            "-vv" => verbosity = Some(Verbosity::Debug),
+            "-h" | "--help" => {
+                print!("{}", usage(&args[0]));
+                return Ok(());
+            }
+            "--machine" | "--numeric-progress" => machine = true,
+            "--allow-duplicates" => pack_options.allow_duplicates = true,
+            "--allow-empty" => pack_options.allow_empty = true,
+            "--solid" => pack_options.solid = true,
+            "--reproducible" => pack_options.reproducible = true,
+            "--compression-stats" => compression_stats = true,
+            "--dedup" => pack_options.dedup = true,
+            "--resume" => pack_options.resume = true,
+            "-L" | "--dereference" => pack_options.dereference = true,
+            "--hard-links" => pack_options.hard_links = true,
+            "--no-recursion" => pack_options.recursive = false,
+            "--exclude-hidden" => pack_options.exclude_hidden = true,
+            "--include-hidden" => pack_options.exclude_hidden = false,
+            "--split" => pack_options.split = Some(parse_size(next_value(&mut iter, arg)?, arg)?),
+            "--exclude-smaller-than" => {
+                pack_options.exclude_smaller_than =
+                    Some(parse_size(next_value(&mut iter, arg)?, arg)?)
+            }
+            "--exclude-larger-than" => {
+                pack_options.exclude_larger_than =
+                    Some(parse_size(next_value(&mut iter, arg)?, arg)?)
+            }
+            "--compress-threshold" => {
+                pack_options.compress_threshold = parse_number(next_value(&mut iter, arg)?, arg)?
+            }
+            "--method" => {
+                pack_options.method = Some(Method::from_name(next_value(&mut iter, arg)?)?)
+            }
+            "--sort-by" => pack_options.sort_by = SortBy::from_name(next_value(&mut iter, arg)?)?,
+            "--format" => pack_options.format = Format::from_name(next_value(&mut iter, arg)?)?,
+            "--password" => {
+                let password = next_value(&mut iter, arg)?;
+                pack_options.password = Some(password.to_string());
+                unpack_options.password = Some(password.to_string());
+            }
+            "--checksum" => {
+                pack_options.checksum = ChecksumAlgorithm::from_name(next_value(&mut iter, arg)?)?
+            }
+            "--level" => {
+                // checked against the range of the method by `pack`
+                level = Some(Compression::new(parse_number(
+                    next_value(&mut iter, arg)?,
+                    arg,
+                )?));
+            }
+            "--preset" => preset = Some(Preset::from_name(next_value(&mut iter, arg)?)?),
+            "--dict" => {
+                let path = next_value(&mut iter, arg)?;
+                let dictionary = fs::read(path).map_err(|e| {
+                    ArchiveError::Path(format!("Failed to read dictionary '{}': {}", path, e))
+                })?;
+                pack_options.dictionary = Some(dictionary);
+            }
+            "--preserve-owner" => unpack_options.preserve_owner = true,
+            "--xattrs" => {
+                pack_options.xattrs = true;
+                unpack_options.xattrs = true;
+            }
+            "--best-effort" => unpack_options.best_effort = true,
+            "--sparse" => unpack_options.sparse = true,
+            "--pipeline" => unpack_options.pipeline = true,
+            "--no-verify" => unpack_options.verify = false,
+            "--deep" => deep = true,
+            "--sign" => pack_options.sign = Some(PathBuf::from(next_value(&mut iter, arg)?)),
+            "--pubkey" => pubkey = Some(PathBuf::from(next_value(&mut iter, arg)?)),
+            "--fresh" => unpack_options.fresh = true,
+            "--skip-newer" => unpack_options.skip_newer = true,
+            "--check-case" => unpack_options.check_case = true,
+            "--single-in-cwd" => unpack_options.single_in_cwd = true,
+            "--check-archive" => unpack_options.check_archive = true,
+            "--match" => globs.push(next_value(&mut iter, arg)?),
+            "--normalize-eol" => eol_globs.push(next_value(&mut iter, arg)?),
+            "--strip-components" => {
+                unpack_options.strip_components = parse_number(next_value(&mut iter, arg)?, arg)?
+            }
+            "--max-entry-size" => {
+                unpack_options.max_entry_size =
+                    Some(parse_number(next_value(&mut iter, arg)?, arg)?)
+            }
+            "--chmod" => {
+                unpack_options.file_mode = Some(parse_mode(next_value(&mut iter, arg)?, arg)?)
+            }
+            "--chmod-dirs" => {
+                unpack_options.dir_mode = Some(parse_mode(next_value(&mut iter, arg)?, arg)?)
+            }
+            "--transform" => {
+                let transform: Transform = next_value(&mut iter, arg)?.parse()?;
+                pack_options.transforms.push(transform.clone());
+                unpack_options.transforms.push(transform);
+            }
+            "--total-timeout" => {
+                let seconds = parse_number(next_value(&mut iter, arg)?, arg)?;
+                let cancel = cancel_after(Duration::from_secs(seconds));
+                pack_options.cancel = Some(cancel.clone());
+                unpack_options.cancel = Some(cancel);
+            }
+            "--io-retries" => {
+                let retries = parse_number(next_value(&mut iter, arg)?, arg)?;
+                pack_options.io_retries = retries;
+                unpack_options.io_retries = retries;
+            }
+            "--jobs" => {
+                let jobs = parse_number(next_value(&mut iter, arg)?, arg)?;
+                pack_options.jobs = jobs;
+                unpack_options.jobs = jobs;
+            }
+            "--spill-threshold" => {
+                let threshold = parse_size(next_value(&mut iter, arg)?, arg)?;
+                pack_options.spill_threshold = usize::try_from(threshold)?;
+            }
+            "--buffer-capacity" => {
+                let capacity = parse_size(next_value(&mut iter, arg)?, arg)?;
+                let capacity = usize::try_from(capacity)?;
+                pack_options.buffer_capacity = capacity;
+                unpack_options.buffer_capacity = capacity;
+            }
+            "--name" => pack_options.name = Some(next_value(&mut iter, arg)?.into()),
+            "--base" => pack_options.base = Some(PathBuf::from(next_value(&mut iter, arg)?)),
+            "--files-from" => {
+                let list = PathBuf::from(next_value(&mut iter, arg)?);
+                pack_options.files = Some(read_file_list(&list, b'\n')?);
+            }
+            "--files-from0" => {
+                let list = PathBuf::from(next_value(&mut iter, arg)?);
+                pack_options.files = Some(read_file_list(&list, 0)?);
+            }
+            "-o" | "--output" => output = Some(PathBuf::from(next_value(&mut iter, arg)?)),
+            "--sample" => sample = Some(parse_percent(next_value(&mut iter, arg)?, arg)?),
+            "--rate-limit" => {
+                let limit = parse_number(next_value(&mut iter, arg)?, arg)?;
+                pack_options.rate_limit = limit;
+                unpack_options.rate_limit = limit;
+            }
+            // `-` alone names stdin, and negative numbers are values
+            _ if arg.starts_with('-')
+                && arg.len() > 1
+                && !arg[1..].starts_with(|c: char| c.is_ascii_digit()) =>
+            {
+                return Err(ArchiveError::Usage(format!("Unknown option '{}'", arg)));
+            }
+            _ => positional.push(arg),
         }
     }
 
-    pub fn sum(&self) -> u32 {
-        self.hasher.sum()
+    // the flags take precedence over `RUST_LOG`
+    let env_level = env::var("RUST_LOG")
+        .ok()
+        .and_then(|v| v.trim().parse::<LevelFilter>().ok());
+    match (verbosity, env_level) {
+        (Some(verbosity), _) => log::set_max_level(verbosity.max_level()),
+        (None, Some(level)) => log::set_max_level(level),
+        (None, None) => {}
     }
-
-    pub fn stream_position(&mut self) -> error::Result<u64> {
-        let pos = self.writer.stream_position()?;
-        Ok(pos)
+    let verbosity = verbosity.unwrap_or(Verbosity::Normal);
+    // an explicit method and level win over those of the preset, which
+    // leaves picking gzip or store by extension alone
+    let (preset_method, preset_level) = preset.map(Preset::settings).unzip();
+    if pack_options.method.is_none() {
+        pack_options.method = preset_method.filter(|&method| method != Method::default());
     }
-
-    pub fn take_written_bytes(&mut self) -> u64 {
-        let old = self.bytes;
-        self.bytes = 0;
-        old
+    if let Some(level) = level.or(preset_level) {
+        pack_options.level = level;
     }
-}
-impl<'a> Write for HasherWriter<'a> {
-    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        self.hasher.update(buf);
-        let bytes = self.writer.write(buf)?;
-        self.bytes += bytes as u64;
-        Ok(bytes)
+    if !eol_globs.is_empty() {
+        let globs = glob_set(&eol_globs)?;
+        pack_options.filters.push(FilterRule::new(
+            move |name| globs.is_match(Path::new(name)),
+            NormalizeLineEndings,
+        ));
     }
-
-    fn flush(&mut self) -> io::Result<()> {
-        self.writer.flush()
+    if !globs.is_empty() {
+        unpack_options.matching = Some(glob_set(&globs)?);
     }
-}
-
-pub struct InnerFile {
-    name: OsString,
-    original_size: u64,
-    compressed_size: u64,
-    original_checksum: u32,
-    compressed_checksum: u32,
-    position: u64,
-}
-
-impl InnerFile {
-    pub fn new(name: OsString) -> Self {
-        Self {
-            name,
-            ..Default::default()
-        }
+    pack_options.verbosity = verbosity;
+    unpack_options.verbosity = verbosity;
+
+    // listed files need no source, their names start at `--base`
+    let listed = pack_options.files.is_some() && positional.first().is_some_and(|c| *c == "pack");
+    let benching = positional.first().is_some_and(|c| *c == "bench");
+    if positional.len() < 2 && !listed && !benching {
+        return Err(ArchiveError::Usage(usage(&args[0]).trim_end().to_string()));
     }
 
-    pub fn create(
-        name: OsString,
-        original_size: u64,
-        compressed_size: u64,
-        original_checksum: u32,
-        compressed_checksum: u32,
-    ) -> Self {
-        let mut file = Self::new(name);
-        file.set_original_size(original_size);
-        file.set_compressed_size(compressed_size);
-        file.set_original_checksum(original_checksum);
-        file.set_compressed_checksum(compressed_checksum);
-        file
+    let target = output.or_else(|| positional.get(2).map(PathBuf::from));
+
+    let start = Instant::now();
+    let last = Arc::new(Mutex::new(None));
+    if machine {
+        let callback = {
+            let last = last.clone();
+            Arc::new(move |progress: Progress| {
+                print_progress(&progress, start.elapsed());
+                *last.lock().unwrap() = Some(progress);
+            })
+        };
+        pack_options.progress = Some(callback.clone());
+        unpack_options.progress = Some(callback);
     }
 
-    pub fn from_archive<R: Read + Seek>(reader: &mut R, buffer: &mut [u8]) -> Result<Self> {
-        reader.read_exact(&mut buffer[..4])?;
-        let name_len = u32::from_le_bytes(buffer[..4].try_into()?) as usize;
-
-        if name_len == 0 {
-            return Err(ArchiveError::EmptyFilename);
+    match positional[0].as_str() {
+        "pack" => {
+            let source = if listed {
+                if positional.len() > 1 {
+                    return Err(ArchiveError::Usage(
+                        "Listed files are packed without a source, use `--base` to name them relative to a directory and `-o` for the target".to_string(),
+                    ));
+                }
+                pack_options
+                    .base
+                    .clone()
+                    .unwrap_or_else(|| PathBuf::from("."))
+            } else {
+                PathBuf::from(positional[1])
+            };
+            let stats = pack(source, target, &pack_options)?;
+            if compression_stats {
+                match stats {
+                    Some(stats) => eprint!("{}", stats),
+                    None => log::warn!("Compression statistics aren't available for ZIP archives"),
+                }
+            }
+            if machine {
+                let (entries, bytes) = last
+                    .lock()
+                    .unwrap()
+                    .as_ref()
+                    .map_or((0, 0), |progress: &Progress| {
+                        (progress.entries, progress.bytes)
+                    });
+                println!(
+                    "{{\"command\":\"pack\",\"entries\":{},\"bytes\":{},\"elapsed_ms\":{}}}",
+                    entries,
+                    bytes,
+                    start.elapsed().as_millis()
+                );
+            }
+            Ok(())
         }
-
-        if name_len as usize > BUFFER_SIZE {
-            return Err(ArchiveError::BufferOverflow(name_len as usize));
+        "unpack" => {
+            let extracted = if positional[1] == "-" {
+                let target = target.unwrap_or_else(|| PathBuf::from("."));
+                unpack_stream(io::stdin().lock(), target, &unpack_options)?
+            } else if is_url(positional[1]) {
+                let target = target.unwrap_or_else(|| PathBuf::from("."));
+                unpack_url(positional[1], target, &unpack_options)?
+            } else {
+                unpack(PathBuf::from(positional[1]), target, &unpack_options)?
+            };
+            if machine {
+                let skipped = extracted
+                    .iter()
+                    .filter(|e| e.status == EntryStatus::Skipped)
+                    .count();
+                let bytes: u64 = extracted
+                    .iter()
+                    .filter(|e| e.status != EntryStatus::Skipped)
+                    .map(|e| e.original_size)
+                    .sum();
+                println!(
+                    "{{\"command\":\"unpack\",\"entries\":{},\"skipped\":{},\"bytes\":{},\"elapsed_ms\":{}}}",
+                    extracted.len() - skipped,
+                    skipped,
+                    bytes,
+                    start.elapsed().as_millis()
+                );
+            }
+            Ok(())
         }
-
-        reader.read_exact(&mut buffer[..(name_len as usize)])?;
-        let name = OsString::from_vec(buffer[..(name_len as usize)].to_vec());
-
-        reader.read_exact(&mut buffer[..8])?;
-        let original_size = u64::from_le_bytes(buffer[..8].try_into()?);
-
-        reader.read_exact(&mut buffer[..8])?;
-        let compressed_size = u64::from_le_bytes(buffer[..8].try_into()?);
-
-        reader.read_exact(&mut buffer[..4])?;
-        let original_checksum = u32::from_le_bytes(buffer[..4].try_into()?);
-
-        reader.read_exact(&mut buffer[..4])?;
-        let compressed_checksum = u32::from_le_bytes(buffer[..4].try_into()?);
-
-        Ok(InnerFile::create(
-            name,
-            original_size,
-            compressed_size,
-            original_checksum,
-            compressed_checksum,
-        ))
+        "verify" => {
+            let source = PathBuf::from(positional[1]);
+            // checked first, a tampered archive is reported as such
+            if let Some(pubkey) = pubkey {
+                verify_signature(&source, &pubkey)?;
+            }
+            if deep {
+                verify_deep(source)
+            } else {
+                verify(source)
+            }
+        }
+        "bench" => {
+            let size = match positional.get(1) {
+                Some(size) => parse_size(size, "bench")?,
+                None => DEFAULT_BENCH_SIZE,
+            };
+            print!("{}", bench(size)?);
+            Ok(())
+        }
+        "list" | "ls" => {
+            print!("{}", list(positional[1])?);
+            Ok(())
+        }
+        "diff" => match &positional[1..] {
+            [old, new] => {
+                print!("{}", diff(old, new)?);
+                Ok(())
+            }
+            _ => Err(ArchiveError::Usage(format!(
+                "Usage: {} diff <old archive> <new archive>",
+                args[0]
+            ))),
+        },
+        "contains" => match &positional[2..] {
+            [name] => {
+                if !ArchiveReader::open(positional[1])?.contains(name) {
+                    return Err(ArchiveError::EntryNotFound(name.to_string()));
+                }
+                Ok(())
+            }
+            _ => Err(ArchiveError::Usage(format!(
+                "Usage: {} contains <archive> <name>",
+                args[0]
+            ))),
+        },
+        "rename" | "mv" => match &positional[2..] {
+            [old, new] => rename(positional[1], OsStr::new(old), OsStr::new(new)),
+            _ => Err(ArchiveError::Usage(format!(
+                "Usage: {} rename <archive> <old name> <new name>",
+                args[0]
+            ))),
+        },
+        "remove" | "rm" | "delete" => match &positional[2..] {
+            [name] => remove(positional[1], OsStr::new(name)),
+            _ => Err(ArchiveError::Usage(format!(
+                "Usage: {} remove <archive> <name>",
+                args[0]
+            ))),
+        },
+        "repack" => match &positional[1..] {
+            [source, target] => repack(
+                source,
+                target,
+                pack_options.method.unwrap_or(Method::Gzip),
+                pack_options.level,
+            ),
+            _ => Err(ArchiveError::Usage(format!(
+                "Usage: {} repack <archive> <new archive> [--method <method>] [--level <level>|--preset <preset>]",
+                args[0]
+            ))),
+        },
+        "estimate" => {
+            println!(
+                "{}",
+                estimate(
+                    positional[1],
+                    sample.unwrap_or(DEFAULT_SAMPLE),
+                    &pack_options
+                )?
+            );
+            Ok(())
+        }
+        "info" => {
+            println!("{}", info(PathBuf::from(positional[1]))?);
+            Ok(())
+        }
+        "manifest" => match target {
+            Some(path) => save_manifest(positional[1], &path),
+            None => write_manifest(&manifest(positional[1])?, io::stdout().lock()),
+        },
+        "check" => match &positional[2..] {
+            [path] => {
+                let check = check(positional[1], Path::new(path))?;
+                print!("{}", check);
+                if !check.is_empty() {
+                    return Err(ArchiveError::CorruptedArchive(
+                        "Archive doesn't match its manifest".to_string(),
+                    ));
+                }
+                Ok(())
+            }
+            _ => Err(ArchiveError::Usage(format!(
+                "Usage: {} check <archive> <manifest>",
+                args[0]
+            ))),
+        },
+        _ => Err(ArchiveError::Usage(format!(
+            "Unknown command '{}'\n\n{}",
+            positional[0],
+            usage(&args[0]).trim_end()
+        ))),
     }
+}
 
-    pub fn write_metadata<W: Write + ?Sized + Seek>(
-        &mut self,
-        writer: &mut BufWriter<W>,
-    ) -> Result<u64> {
-        self.position = writer.stream_position()?;
-        let name_bytes = self.name.as_encoded_bytes();
-        writer.write_all(&(name_bytes.len() as u32).to_le_bytes())?;
-        writer.write_all(name_bytes)?;
-        writer.write_all(&self.original_size.to_le_bytes())?;
-        let position = writer.stream_position()?;
-        writer.write_all(&self.compressed_size.to_le_bytes())?;
-        writer.write_all(&self.original_checksum.to_le_bytes())?;
-        writer.write_all(&self.compressed_checksum.to_le_bytes())?;
-        Ok(position)
+/// Lists the commands, shown on too few arguments, `--help` and unknown
+/// commands.
+fn usage(program: &str) -> String {
+    const COMMANDS: &[(&str, &str)] = &[
+        (
+            "pack <directory|file> [target]",
+            "Pack into '<target>/<name>.slf'",
+        ),
+        (
+            "pack --files-from <list|-> [-o <target>]",
+            "Pack the listed files, named relative to --base",
+        ),
+        (
+            "unpack <archive|-> [target]",
+            "Extract, '-' reads the archive from stdin",
+        ),
+        (
+            "unpack <url> [target] [--match <glob>]",
+            "Download and extract only the matching entries",
+        ),
+        (
+            "verify <archive> [--deep] [--pubkey <pem>]",
+            "Check every entry, --deep locates the damaged ones",
+        ),
+        (
+            "list <archive>",
+            "List the entries with their sizes and ratios",
+        ),
+        ("info <archive>", "Show the header and the footer"),
+        (
+            "manifest <archive> [-o <manifest>]",
+            "Write the metadata of every entry as JSON",
+        ),
+        (
+            "check <archive> <manifest>",
+            "Compare the index with a manifest",
+        ),
+        (
+            "bench [size]",
+            "Measure methods and levels on generated data, 16M each, and named lookups",
+        ),
+        (
+            "estimate <directory|file>",
+            "Predict the archive size from a sample",
+        ),
+        (
+            "diff <old archive> <new archive>",
+            "Compare the entries of two archives",
+        ),
+        (
+            "contains <archive> <name>",
+            "Exit with 0 if the entry exists, 1 if not",
+        ),
+        (
+            "rename <archive> <old name> <new name>",
+            "Rename an entry in place",
+        ),
+        ("remove <archive> <name>", "Remove an entry in place"),
+        (
+            "repack <archive> <new archive>",
+            "Recompress with another method or level",
+        ),
+    ];
+    let width = COMMANDS
+        .iter()
+        .map(|(command, _)| command.len())
+        .max()
+        .unwrap_or(0);
+
+    let mut text = format!("Usage: {} <command> [options]\n\nCommands:\n", program);
+    for (command, description) in COMMANDS {
+        text += &format!("  {:width$}  {}\n", command, description, width = width);
     }
+    text += "\nThe target defaults to the directory of the source, the current one for '-',\n";
+    text += "and can also be passed with `-o <target>`.\n";
+    text
+}
 
-    fn set_original_size(&mut self, size: u64) {
-        self.original_size = size
-    }
+/// Prints a `--machine` progress record to stderr, `total`,
+/// `total_compressed` and `eta_ms` are left out when unknown. `speed` is the
+/// average of original bytes per second since `elapsed` ago.
+fn print_progress(progress: &Progress, elapsed: Duration) {
+    let optional = |key: &str, value: Option<u128>| {
+        value
+            .map(|value| format!(" {}={}", key, value))
+            .unwrap_or_default()
+    };
+    let speed = (progress.bytes as f64 / elapsed.as_secs_f64().max(f64::EPSILON)) as u64;
+    eprintln!(
+        "progress entries={} total_entries={} bytes={}{} compressed={}{} speed={}{}",
+        progress.entries,
+        progress.total_entries,
+        progress.bytes,
+        optional("total", progress.total_bytes.map(u128::from)),
+        progress.compressed_bytes,
+        optional(
+            "total_compressed",
+            progress.total_compressed_bytes.map(u128::from)
+        ),
+        speed,
+        optional("eta_ms", progress.eta(elapsed).map(|eta| eta.as_millis()))
+    );
+}
 
-    fn set_compressed_size(&mut self, size: u64) {
-        self.compressed_size = size
-    }
+/// Returns a flag which is set once `timeout` elapses.
+fn cancel_after(timeout: Duration) -> CancelFlag {
+    let cancel = CancelFlag::default();
+    let flag = cancel.clone();
+    thread::spawn(move || {
+        thread::sleep(timeout);
+        flag.store(true, Ordering::Relaxed);
+    });
+    cancel
+}
 
-    fn set_original_checksum(&mut self, checksum: u32) {
-        self.original_checksum = checksum
-    }
+fn next_value<'a>(iter: &mut impl Iterator<Item = &'a String>, flag: &str) -> Result<&'a str> {
+    iter.next()
+        .map(String::as_str)
+        .ok_or_else(|| ArchiveError::Usage(format!("Missing value for '{}'", flag)))
+}
 
-    fn set_compressed_checksum(&mut self, checksum: u32) {
-        self.compressed_checksum = checksum
-    }
+fn parse_number<T: FromStr>(value: &str, flag: &str) -> Result<T> {
+    value
+        .parse()
+        .map_err(|_| ArchiveError::Usage(format!("Invalid value '{}' for '{}'", value, flag)))
 }
 
-impl Default for InnerFile {
-    fn default() -> Self {
-        Self {
-            name: OsString::default(),
-            original_size: u64::default(),
-            compressed_size: u64::default(),
-            original_checksum: u32::default(),
-            compressed_checksum: u32::default(),
-            position: u64::default(),
-        }
-    }
+/// Parses an octal permission mode such as `644` or `0755`.
+fn parse_mode(value: &str, flag: &str) -> Result<u32> {
+    u32::from_str_radix(value, 8)
+        .ok()
+        .filter(|&mode| mode <= 0o7777)
+        .ok_or_else(|| ArchiveError::Usage(format!("Invalid mode '{}' for '{}'", value, flag)))
 }
 
-fn normalize_path(path: &PathBuf) -> PathBuf {
-    let mut normalized = Vec::new();
+/// Parses a percentage such as `5%` or `12.5`, returned as a fraction.
+fn parse_percent(value: &str, flag: &str) -> Result<f64> {
+    let percent: f64 = parse_number(value.strip_suffix('%').unwrap_or(value), flag)?;
+    Ok(percent / 100.0)
+}
 
-    for component in path.components() {
-        match component {
-            Component::Prefix(p) => {
-                normalized.clear();
-                normalized.push(Component::Prefix(p));
-            }
-            Component::RootDir => {
-                normalized.clear();
-                normalized.push(Component::RootDir);
-            }
-            Component::CurDir => {}
-            Component::ParentDir => {
-                if let Some(last) = normalized.last() {
-                    match last {
-                        Component::Normal(_) => {
-                            normalized.pop();
-                        }
-                        Component::RootDir => {}
-                        _ => {}
-                    }
-                } else {
-                    normalized.push(component);
-                }
-            }
-            Component::Normal(_) => normalized.push(component),
-        }
+/// Parses a size in bytes with an optional `K`, `M` or `G` binary suffix.
+fn parse_size(value: &str, flag: &str) -> Result<u64> {
+    let (number, multiplier) = match value.char_indices().last() {
+        Some((i, 'K' | 'k')) => (&value[..i], 1 << 10),
+        Some((i, 'M' | 'm')) => (&value[..i], 1 << 20),
+        Some((i, 'G' | 'g')) => (&value[..i], 1 << 30),
+        _ => (value, 1),
+    };
+    parse_number::<u64>(number, flag)?
+        .checked_mul(multiplier)
+        .ok_or_else(|| ArchiveError::Usage(format!("Invalid value '{}' for '{}'", value, flag)))
+}
+
+const EXIT_GENERIC: i32 = 1;
+const EXIT_CORRUPTED: i32 = 2;
+const EXIT_PATH: i32 = 3;
+const EXIT_USAGE: i32 = 4;
+
+/// Maps an error to the process exit code:
+/// `1` - generic failure (I/O, conversions) or an entry which isn't found,
+/// which is how `contains` tells its absence,
+/// `2` - corrupted or unsupported archive,
+/// `3` - invalid source or target path,
+/// `4` - incorrect usage of the command line.
+fn exit_code(error: &ArchiveError) -> i32 {
+    match error {
+        ArchiveError::CorruptedArchive(_)
+        | ArchiveError::IncorrectType(_)
+        | ArchiveError::UnsupportedVersion(_)
+        | ArchiveError::BufferOverflow(_)
+        | ArchiveError::EmptyFilename
+        | ArchiveError::SignatureInvalid(_) => EXIT_CORRUPTED,
+        ArchiveError::Path(_)
+        | ArchiveError::DuplicateEntry(_)
+        | ArchiveError::CaseCollision(_, _) => EXIT_PATH,
+        ArchiveError::Usage(_) => EXIT_USAGE,
+        ArchiveError::Io(_)
+        | ArchiveError::EntryNotFound(_)
+        | ArchiveError::OutOfSpace(_)
+        | ArchiveError::Cancelled
+        | ArchiveError::Unsupported(_)
+        | ArchiveError::TryFromSlice(_)
+        | ArchiveError::TryFromInt(_) => EXIT_GENERIC,
     }
-    normalized.iter().collect()
 }
@@ -0,0 +1,279 @@
+use std::{
+    fmt,
+    fs::File,
+    io::{BufReader, BufWriter, Write},
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    InnerFile,
+    error::{ArchiveError, Result},
+    reader::ArchiveReader,
+};
+
+/// Metadata of an archive and its entries without their contents, stored as
+/// JSON next to the archive to check it later, see `check`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    pub version: String,
+    pub solid: bool,
+    /// Algorithm of the original checksums.
+    pub checksum_algorithm: String,
+    /// Length stored in the footer, `None` if the version has no footer.
+    pub total_length: Option<u64>,
+    /// Checksum of the whole archive, if it's stored.
+    pub checksum: Option<u32>,
+    /// Entries in the order of the index.
+    pub entries: Vec<ManifestEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    /// Name of the entry, non UTF-8 bytes are replaced.
+    pub name: String,
+    pub original_size: u64,
+    pub compressed_size: u64,
+    pub original_checksum: u32,
+    pub compressed_checksum: u32,
+    pub method: String,
+    /// Position of the entry's metadata, as stored in the index.
+    pub offset: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub link_target: Option<String>,
+}
+
+impl From<&InnerFile> for ManifestEntry {
+    fn from(entry: &InnerFile) -> Self {
+        Self {
+            name: entry.name().to_string_lossy().into_owned(),
+            original_size: entry.original_size(),
+            compressed_size: entry.compressed_size(),
+            original_checksum: entry.original_checksum(),
+            compressed_checksum: entry.compressed_checksum(),
+            method: entry.method().name().to_string(),
+            offset: entry.position(),
+            link_target: entry
+                .link_target()
+                .map(|target| target.to_string_lossy().into_owned()),
+        }
+    }
+}
+
+/// Reads the manifest of the archive at `path` from its header, footer and
+/// index, the contents aren't read.
+pub fn manifest<P: AsRef<Path>>(path: P) -> Result<Manifest> {
+    let archive = ArchiveReader::open(path)?;
+    let header = archive.header();
+    Ok(Manifest {
+        version: header.version.to_string(),
+        solid: header.is_solid(),
+        checksum_algorithm: header.checksum.name().to_string(),
+        total_length: archive.footer().map(|footer| footer.total_length),
+        checksum: archive.footer().and_then(|footer| footer.checksum),
+        entries: archive.entries().iter().map(ManifestEntry::from).collect(),
+    })
+}
+
+/// Writes `manifest` as indented JSON ending with a newline.
+pub fn write_manifest<W: Write>(manifest: &Manifest, mut writer: W) -> Result<()> {
+    serde_json::to_writer_pretty(&mut writer, manifest)
+        .map_err(|e| ArchiveError::Io(format!("Failed to write the manifest: {}", e)))?;
+    writeln!(writer)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Writes the manifest of the archive at `archive` to the file at `path`.
+pub fn save_manifest<P: AsRef<Path>>(archive: P, path: &Path) -> Result<()> {
+    let manifest = manifest(archive)?;
+    let file = File::create(path).map_err(|e| {
+        ArchiveError::Path(format!(
+            "Failed to create manifest '{}': {}",
+            path.display(),
+            e
+        ))
+    })?;
+    write_manifest(&manifest, BufWriter::new(file))
+}
+
+pub fn read_manifest(path: &Path) -> Result<Manifest> {
+    let file = File::open(path).map_err(|e| {
+        ArchiveError::Path(format!(
+            "Failed to read manifest '{}': {}",
+            path.display(),
+            e
+        ))
+    })?;
+    serde_json::from_reader(BufReader::new(file))
+        .map_err(|e| ArchiveError::Usage(format!("'{}' isn't a manifest: {}", path.display(), e)))
+}
+
+/// A field of the archive or of an entry differing from the manifest.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Drift {
+    /// Name of the entry in the manifest, `None` for fields of the archive.
+    pub entry: Option<String>,
+    pub field: &'static str,
+    /// Value in the manifest, as JSON.
+    pub expected: String,
+    /// Value read from the archive, as JSON.
+    pub found: String,
+}
+
+/// Differences between an archive and its manifest, see `check`.
+#[derive(Debug, Clone, Default)]
+pub struct ManifestCheck {
+    pub drift: Vec<Drift>,
+}
+
+impl ManifestCheck {
+    pub fn is_empty(&self) -> bool {
+        self.drift.is_empty()
+    }
+
+    fn compare<T: PartialEq + Serialize>(
+        &mut self,
+        entry: Option<&str>,
+        field: &'static str,
+        expected: T,
+        found: T,
+    ) {
+        if expected != found {
+            self.drift.push(Drift {
+                entry: entry.map(str::to_string),
+                field,
+                expected: json(&expected),
+                found: json(&found),
+            });
+        }
+    }
+}
+
+fn json<T: Serialize>(value: &T) -> String {
+    serde_json::to_string(value).unwrap_or_default()
+}
+
+/// Re-reads the header, footer and index of the archive at `archive` and
+/// compares them with the manifest at `manifest`. Entries are compared in the
+/// order of the index, so a reordered or renamed entry is reported as well.
+/// Contents aren't read, `verify` checks them against the index.
+pub fn check<P: AsRef<Path>>(archive: P, manifest: &Path) -> Result<ManifestCheck> {
+    let expected = read_manifest(manifest)?;
+    let found = self::manifest(archive)?;
+
+    let mut check = ManifestCheck::default();
+    check.compare(None, "version", &expected.version, &found.version);
+    check.compare(None, "solid", expected.solid, found.solid);
+    check.compare(
+        None,
+        "checksum algorithm",
+        &expected.checksum_algorithm,
+        &found.checksum_algorithm,
+    );
+    check.compare(
+        None,
+        "total length",
+        expected.total_length,
+        found.total_length,
+    );
+    check.compare(None, "checksum", expected.checksum, found.checksum);
+    check.compare(None, "entries", expected.entries.len(), found.entries.len());
+
+    for (expected, found) in expected.entries.iter().zip(&found.entries) {
+        let entry = Some(expected.name.as_str());
+        check.compare(entry, "name", &expected.name, &found.name);
+        check.compare(
+            entry,
+            "original size",
+            expected.original_size,
+            found.original_size,
+        );
+        check.compare(
+            entry,
+            "compressed size",
+            expected.compressed_size,
+            found.compressed_size,
+        );
+        check.compare(
+            entry,
+            "original checksum",
+            expected.original_checksum,
+            found.original_checksum,
+        );
+        check.compare(
+            entry,
+            "compressed checksum",
+            expected.compressed_checksum,
+            found.compressed_checksum,
+        );
+        check.compare(entry, "method", &expected.method, &found.method);
+        check.compare(entry, "offset", expected.offset, found.offset);
+        check.compare(
+            entry,
+            "link target",
+            &expected.link_target,
+            &found.link_target,
+        );
+    }
+    Ok(check)
+}
+
+impl fmt::Display for ManifestCheck {
+    /// One line per differing field, every line ends with a newline.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "no drift");
+        }
+
+        for drift in &self.drift {
+            match &drift.entry {
+                Some(entry) => write!(f, "  ~ {}: ", entry)?,
+                None => write!(f, "  ~ archive: ")?,
+            }
+            writeln!(f, "{} {} -> {}", drift.field, drift.expected, drift.found)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::{builder::ArchiveBuilder, testing::TempDir};
+
+    #[test]
+    fn check_reports_tampered_entries() {
+        let dir = TempDir::new("manifest");
+        let mut builder = ArchiveBuilder::new();
+        builder.add_bytes("a.txt", b"first").unwrap();
+        builder.add_bytes("b.txt", b"second").unwrap();
+        let archive = dir.join("archive.slf");
+        builder.finish(fs::File::create(&archive).unwrap()).unwrap();
+        let manifest_path = dir.join("manifest.json");
+        save_manifest(&archive, &manifest_path).unwrap();
+        assert!(check(&archive, &manifest_path).unwrap().is_empty());
+
+        // the original size follows the name length and the name
+        let b = &self::manifest(&archive).unwrap().entries[1];
+        let size_at = (b.offset + 4 + 5) as usize;
+        let mut bytes = fs::read(&archive).unwrap();
+        bytes[size_at..size_at + 8].copy_from_slice(&7u64.to_le_bytes());
+        fs::write(&archive, bytes).unwrap();
+
+        let result = check(&archive, &manifest_path).unwrap();
+        assert_eq!(
+            result.drift,
+            [Drift {
+                entry: Some("b.txt".to_string()),
+                field: "original size",
+                expected: "6".to_string(),
+                found: "7".to_string(),
+            }]
+        );
+        assert_eq!(result.to_string(), "  ~ b.txt: original size 6 -> 7\n");
+    }
+}
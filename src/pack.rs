@@ -1,106 +1,1076 @@
 use std::{
-    fs::{File, create_dir_all},
-    io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write},
-    path::{Path, PathBuf},
+    collections::{HashMap, HashSet},
+    ffi::{OsStr, OsString},
+    fs::{self, File, Metadata, OpenOptions, create_dir_all, remove_file},
+    io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{self, Path, PathBuf},
+    sync::{
+        Condvar, Mutex,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc::{self, Sender},
+    },
+    thread,
+    time::SystemTime,
 };
 
-use flate2::{Compression, Crc, write::GzEncoder};
+use flate2::Compression;
+use log::{debug, info, log, warn};
 use walkdir::WalkDir;
 
 use crate::{
-    VERSION,
+    FLAG_DEDUP, FLAG_DICTIONARY, FLAG_SIGNED, FLAG_SOLID, FLAG_SPLIT, Header, VERSION,
+    check_cancelled, check_xattrs_supported,
+    checksum::{ChecksumAlgorithm, ChecksumReader},
+    compressor::{CompressionSummary, Compressor, MAX_DICTIONARY_SIZE, Method},
+    dedup::ChunkStore,
     error::{ArchiveError, Result},
-    normalize_path,
+    filter::{ContentFilter, FilterRule},
+    limiter::RateLimiter,
+    list::{CompressionStats, Listing},
+    log::Verbosity,
+    name_from_bytes, normalize_path,
+    progress::{Phase, ProgressCallback, ProgressTracker},
+    reader::EntryReader,
+    retry::{DEFAULT_IO_RETRIES, Retrying},
+    spill::{DEFAULT_SPILL_THRESHOLD, SpillBuffer},
+    stays_inside,
+    transform::Transform,
+    volume::{self, VolumeReader, VolumeWriter},
 };
 
-use crate::{BUFFER_SIZE, HasherWriter, InnerFile, SIGNATURE};
+#[cfg(feature = "signing")]
+use crate::signing;
+#[cfg(feature = "zip")]
+use crate::zipfile;
+
+use crate::{
+    BUFFER_SIZE, CancelFlag, DEFAULT_BUFFER_CAPACITY, Footer, InnerFile, SIGNATURE,
+    archive_checksum,
+};
+
+/// Files smaller than this are stored uncompressed by default, gzip framing
+/// alone takes 18 bytes.
+pub const DEFAULT_COMPRESS_THRESHOLD: u64 = 32;
+
+pub struct PackOptions {
+    /// Container to write, only `Format::Slf` supports all of the options.
+    pub format: Format,
+    /// Password encrypting every entry with AES-256, ZIP archives only.
+    pub password: Option<String>,
+    /// Base name of the archive written into a target directory instead of
+    /// the one derived from the source, the extension of the `format` is
+    /// appended unless present. Ignored when the target has the extension.
+    pub name: Option<OsString>,
+    /// Directory the entry names are relative to instead of the source, so
+    /// they keep the path from it, e.g. `src/main.rs` for the source
+    /// `project/src` and the base `project`. Has to contain the source.
+    pub base: Option<PathBuf>,
+    /// Pack exactly these files in this order instead of walking the source,
+    /// which is then the directory their names are relative to. See
+    /// `read_file_list`.
+    pub files: Option<Vec<PathBuf>>,
+    /// Keep entries with the same name instead of failing with `DuplicateEntry`.
+    pub allow_duplicates: bool,
+    /// Write an archive even when no files are left to pack, which usually
+    /// means a wrong source or too strict filters.
+    pub allow_empty: bool,
+    /// Compress all files into a single stream, see `FLAG_SOLID`.
+    pub solid: bool,
+    /// Compression method of every entry, picked by the file extension
+    /// with `Method::for_path` when unset.
+    pub method: Option<Method>,
+    /// Level of gzip and deflate compression, chunks of deduplicated
+    /// archives always use the default one. See `Preset` for named levels.
+    pub level: Compression,
+    /// Maximum read throughput in bytes per second, 0 is unlimited.
+    pub rate_limit: u64,
+    /// Store uid and gid as 0, so the archive depends only on names and
+    /// contents of the files. Modification times aren't stored either.
+    pub reproducible: bool,
+    /// Files smaller than this many bytes are stored uncompressed,
+    /// ignored by solid archives.
+    pub compress_threshold: u64,
+    /// Stops packing and removes the partial archive once set.
+    pub cancel: Option<CancelFlag>,
+    /// Write the archive as volumes of at most this many bytes, at least
+    /// `volume::MIN_VOLUME_SIZE`. Volumes of an earlier split beyond the new
+    /// count are removed. Can't be resumed.
+    pub split: Option<u64>,
+    /// Continue an archive left by an interrupted pack after its last entry
+    /// passing verification, instead of starting over. The partial archive
+    /// is the temporary `<archive>.tmp` next to the target, which is also
+    /// kept when cancelled. Not supported by solid archives.
+    pub resume: bool,
+    /// Rewrite rules applied in order to every entry name.
+    pub transforms: Vec<Transform>,
+    /// Filters of the contents of files, the first one applying to the entry
+    /// name is used, see `ContentFilter`. Not supported by solid,
+    /// deduplicated or ZIP archives.
+    pub filters: Vec<FilterRule>,
+    /// Follow symlinks and pack the files they point to, see `collect_files`.
+    pub dereference: bool,
+    /// Pack the files of subdirectories too, otherwise only the ones
+    /// directly in the source directory.
+    pub recursive: bool,
+    /// Cut files into chunks of `CHUNK_SIZE` and store every distinct chunk
+    /// only once, see `FLAG_DEDUP`. Not supported by solid archives.
+    pub dedup: bool,
+    /// Capacity of the buffer in front of the archive, 0 writes straight
+    /// to the file. Source files are always read without one.
+    pub buffer_capacity: usize,
+    /// Store extended attributes of the files, needs the `xattrs` feature
+    /// and Unix.
+    pub xattrs: bool,
+    /// Store files hard-linked to one packed before them as links to its
+    /// entry instead of a second copy of the contents. Links are found by
+    /// device and inode, so only on Unix.
+    pub hard_links: bool,
+    /// Skip files smaller than this many bytes, e.g. 1 to leave out empty ones.
+    pub exclude_smaller_than: Option<u64>,
+    /// Skip files larger than this many bytes.
+    pub exclude_larger_than: Option<u64>,
+    /// Skip hidden files and don't enter hidden directories of the source,
+    /// see `is_hidden`. A hidden source itself is packed.
+    pub exclude_hidden: bool,
+    /// Sign the archive with the Ed25519 private key in this PEM file, see
+    /// `FLAG_SIGNED`. Needs the `signing` feature.
+    pub sign: Option<PathBuf>,
+    /// Contents shared by many of the files, e.g. a typical one of them.
+    /// Stored in the archive, see `FLAG_DICTIONARY`, and gzip entries are
+    /// compressed against it as `Method::Deflate` instead. Only the last
+    /// `MAX_DICTIONARY_SIZE` bytes are used. Not supported by solid or
+    /// deduplicated archives.
+    pub dictionary: Option<Vec<u8>>,
+    /// Order the contents of entries are written in.
+    pub sort_by: SortBy,
+    /// Count of threads compressing files, see `process_parallel`. Not
+    /// supported by solid or deduplicated archives.
+    pub jobs: usize,
+    /// Compressed bytes of a file a thread keeps in memory before moving
+    /// them to a temporary file next to the archive, with `jobs` above 1.
+    pub spill_threshold: usize,
+    /// Retries of a source file read failing with a transient error, see
+    /// `Retrying`.
+    pub io_retries: u32,
+    /// What gets reported besides errors, see `Verbosity`.
+    pub verbosity: Verbosity,
+    /// Called after every packed entry.
+    pub progress: Option<ProgressCallback>,
+    /// Algorithm of the checksums of original contents, see
+    /// `ChecksumAlgorithm`. Archives using CRC32C need version 1.9 to read.
+    pub checksum: ChecksumAlgorithm,
+}
+
+/// Container written by `pack`, see `PackOptions::format`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// The format of this crate, described in the crate documentation.
+    Slf,
+    /// A standard ZIP archive which any unzip tool can read, each file
+    /// stored or deflated. Needs the `zip` feature.
+    Zip,
+}
+
+impl Format {
+    pub fn from_name(name: &str) -> Result<Self> {
+        match name {
+            "slf" => Ok(Self::Slf),
+            "zip" => Ok(Self::Zip),
+            _ => Err(ArchiveError::Usage(format!(
+                "Unknown archive format '{}', expected one of: slf, zip",
+                name
+            ))),
+        }
+    }
+
+    /// Extension of the archives, appended unless the target has it.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Slf => "slf",
+            Self::Zip => "zip",
+        }
+    }
+}
+
+/// Order of the entries within an archive, see `PackOptions::sort_by`.
+/// Readers use the stored positions, so it only affects locality: e.g. small
+/// files packed next to each other are read back faster sequentially. The
+/// index array lists entries in the order they were collected either way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortBy {
+    /// The order files are collected in, sorted by path.
+    None,
+    /// Sorted by entry name, after transforms.
+    Name,
+    /// Smallest files first.
+    Size,
+}
+
+impl SortBy {
+    pub fn from_name(name: &str) -> Result<Self> {
+        match name {
+            "none" => Ok(Self::None),
+            "name" => Ok(Self::Name),
+            "size" => Ok(Self::Size),
+            _ => Err(ArchiveError::Usage(format!(
+                "Unknown sort order '{}', expected one of: size, name, none",
+                name
+            ))),
+        }
+    }
+}
+
+impl Default for PackOptions {
+    fn default() -> Self {
+        Self {
+            format: Format::Slf,
+            password: None,
+            name: None,
+            base: None,
+            allow_duplicates: false,
+            allow_empty: false,
+            solid: false,
+            method: None,
+            level: Compression::default(),
+            rate_limit: 0,
+            reproducible: false,
+            compress_threshold: DEFAULT_COMPRESS_THRESHOLD,
+            cancel: None,
+            split: None,
+            resume: false,
+            transforms: Vec::new(),
+            filters: Vec::new(),
+            dereference: false,
+            recursive: true,
+            dedup: false,
+            buffer_capacity: DEFAULT_BUFFER_CAPACITY,
+            xattrs: false,
+            hard_links: false,
+            exclude_smaller_than: None,
+            exclude_larger_than: None,
+            exclude_hidden: false,
+            sign: None,
+            files: None,
+            dictionary: None,
+            sort_by: SortBy::None,
+            jobs: 1,
+            spill_threshold: DEFAULT_SPILL_THRESHOLD,
+            io_retries: DEFAULT_IO_RETRIES,
+            verbosity: Verbosity::Normal,
+            progress: None,
+            checksum: ChecksumAlgorithm::Crc32,
+        }
+    }
+}
+
+/// Packs `source` into an archive named after it in `target`, see
+/// `PackOptions`. Returns the sizes of the packed entries grouped by file
+/// extension, `None` for ZIP archives.
+pub fn pack(
+    source: PathBuf,
+    target: Option<PathBuf>,
+    options: &PackOptions,
+) -> Result<Option<CompressionStats>> {
+    if options.solid && options.method.is_some_and(|method| method != Method::Gzip) {
+        return Err(ArchiveError::Usage(
+            "Solid archives support only gzip compression".to_string(),
+        ));
+    }
+    if options.solid && options.resume {
+        return Err(ArchiveError::Usage(
+            "Solid archives can't be resumed".to_string(),
+        ));
+    }
+    if options.solid && options.dedup {
+        return Err(ArchiveError::Usage(
+            "Solid archives can't be deduplicated".to_string(),
+        ));
+    }
+    if options.split.is_some() && options.resume {
+        return Err(ArchiveError::Usage(
+            "Split archives can't be resumed".to_string(),
+        ));
+    }
+    if options.jobs > 1 && (options.solid || options.dedup) {
+        return Err(ArchiveError::Usage(
+            "Solid and deduplicated archives are packed on a single thread".to_string(),
+        ));
+    }
+    options
+        .method
+        .unwrap_or_default()
+        .check_level(options.level)?;
+    check_xattrs_supported(options.xattrs)?;
+    if options.dictionary.is_some() && (options.solid || options.dedup) {
+        return Err(ArchiveError::Usage(
+            "Solid and deduplicated archives can't use a dictionary".to_string(),
+        ));
+    }
+    if options.format == Format::Zip
+        && (options.solid
+            || options.dedup
+            || options.resume
+            || options.split.is_some()
+            || options.dictionary.is_some()
+            || options.hard_links
+            || options.xattrs
+            || options.sign.is_some())
+    {
+        return Err(ArchiveError::Usage(
+            "ZIP archives can't be solid, deduplicated, resumed, split or signed, nor use a dictionary, hard links or extended attributes".to_string(),
+        ));
+    }
+    if !options.filters.is_empty()
+        && (options.solid || options.dedup || options.format == Format::Zip)
+    {
+        return Err(ArchiveError::Usage(
+            "Contents of solid, deduplicated and ZIP archives can't be filtered".to_string(),
+        ));
+    }
+    if options.password.is_some() && options.format != Format::Zip {
+        return Err(ArchiveError::Usage(
+            "Only ZIP archives can be protected by a password".to_string(),
+        ));
+    }
+
+    check_signing(options)?;
+
+    let source = resolve_source(source)?;
 
-pub fn pack(source: PathBuf, target: Option<PathBuf>) -> Result<()> {
     let target = if let Some(path) = target {
         path
+    } else if options.files.is_some() {
+        // next to the source would be outside of the directory the names start in
+        PathBuf::from(".")
     } else {
         PathBuf::from(source.parent().unwrap_or(Path::new(".")))
     };
 
-    let archive_path = get_archive_path(&source, &target)?;
+    let archive_path = get_archive_path(&source, &target, options.name.as_deref(), options.format)?;
+
+    // collected before the archive is created, so it's never packed into itself
+    let (source, mut files) = match &options.files {
+        Some(files) => listed_files(&source, files)?,
+        None => {
+            let files = collect_files(&source, options)?;
+            (source, files)
+        }
+    };
+    let inners = inner_files(&source, &mut files, options)?;
+    if files.is_empty() && !options.allow_empty {
+        return Err(ArchiveError::Path(format!(
+            "No files to pack in '{}', use `--allow-empty` to write an empty archive anyway",
+            source.display()
+        )));
+    }
+
+    if options.format == Format::Zip {
+        pack_zip(files, &inners, &archive_path, options)?;
+        return Ok(None);
+    }
     if let Some(parents) = archive_path.parent() {
         create_dir_all(parents)?;
     }
 
-    let file = File::create(archive_path)?;
-    let mut writer = BufWriter::new(file);
+    // written next to the archive and renamed over it once complete, so a
+    // partial archive is never seen under its name and an existing one
+    // survives a failed pack
+    let temp_path = temp_path(&archive_path);
+    let (writer, resumed) = if let Some(volume_size) = options.split {
+        (VolumeWriter::split(&temp_path, volume_size)?, Vec::new())
+    } else if options.resume && temp_path.exists() {
+        let (file, resumed) = resume_point(&temp_path, options)?;
+        (VolumeWriter::from_file(file, &temp_path)?, resumed)
+    } else {
+        (VolumeWriter::create(&temp_path)?, Vec::new())
+    };
+    let result = write_archive(
+        BufWriter::with_capacity(options.buffer_capacity, writer),
+        files,
+        inners,
+        &temp_path,
+        options,
+        resumed,
+    )
+    .and_then(|inners| {
+        let length = if options.split.is_some() {
+            persist_volumes(&temp_path, &archive_path)?
+        } else {
+            persist(&temp_path, &archive_path)?;
+            archive_path.metadata()?.len()
+        };
+        Ok((inners, length))
+    })
+    .map_err(|e| named_error(e, &archive_path));
+
+    // a truncated archive is of no use, unless it's resumable and kept to be
+    // continued later
+    if result.is_err() {
+        if options.split.is_some() {
+            volume::remove_volumes(&temp_path, 1)?;
+        } else if !options.resume && temp_path.exists() {
+            remove_file(&temp_path)?;
+        }
+    }
+    let (inners, length) = result?;
+    log!(
+        options.verbosity.entry_level(),
+        "Packed {} entries into '{}' ({} bytes)",
+        inners.len(),
+        archive_path.display(),
+        length
+    );
+    let stats = Listing {
+        entries: inners,
+        solid: options.solid,
+    }
+    .compression_stats();
+    Ok(Some(stats))
+}
 
-    let files: Vec<PathBuf> = collect_files(&source);
+/// Packs into a standard ZIP archive instead, see `Format::Zip`. A partial
+/// archive is removed on failure, there's nothing to resume.
+#[cfg(feature = "zip")]
+fn pack_zip(
+    files: Vec<PathBuf>,
+    inners: &[InnerFile],
+    archive_path: &Path,
+    options: &PackOptions,
+) -> Result<()> {
+    if let Some(parents) = archive_path.parent() {
+        create_dir_all(parents)?;
+    }
+    let temp_path = temp_path(archive_path);
+    let result = File::create(&temp_path)
+        .map_err(ArchiveError::from)
+        .and_then(|file| zipfile::write_zip(file, &files, inners, options))
+        .and_then(|entries| {
+            persist(&temp_path, archive_path)?;
+            Ok(entries)
+        })
+        .map_err(|e| named_error(e, archive_path));
+    if result.is_err() && temp_path.exists() {
+        remove_file(&temp_path)?;
+    }
 
-    writer.write_all(SIGNATURE)?;
-    writer.write_all(&VERSION)?;
-    writer.write_all(&u32::try_from(files.len())?.to_le_bytes())?; //file count
-    writer.write_all(&u64::to_le_bytes(0))?; //index offset
+    log!(
+        options.verbosity.entry_level(),
+        "Packed {} entries into '{}' ({} bytes)",
+        result?,
+        archive_path.display(),
+        archive_path.metadata()?.len()
+    );
+    Ok(())
+}
 
-    let mut inners = inner_files(&source, &files)?;
+/// Fails before anything is written when the archive can't be signed, e.g.
+/// because the key is unreadable.
+#[cfg(feature = "signing")]
+fn check_signing(options: &PackOptions) -> Result<()> {
+    if let Some(key) = &options.sign {
+        signing::read_signing_key(key)?;
+    }
+    Ok(())
+}
 
-    let (temp_offsets, compressed_sizes, checksums) =
-        process_files(&mut inners, files, &mut writer)?;
+#[cfg(not(feature = "signing"))]
+fn check_signing(options: &PackOptions) -> Result<()> {
+    if options.sign.is_some() {
+        return Err(ArchiveError::Unsupported(
+            "Signing archives isn't supported by this build".to_string(),
+        ));
+    }
+    Ok(())
+}
 
-    writer.flush()?;
+/// Writes the signature of the archive written so far, see `FLAG_SIGNED`.
+#[cfg(feature = "signing")]
+fn sign(writer: &mut BufWriter<VolumeWriter>, options: &PackOptions) -> Result<()> {
+    if let Some(key) = &options.sign {
+        let path = writer.get_ref().path().to_path_buf();
+        signing::write_signature(writer, &path, &signing::read_signing_key(key)?)?;
+        debug!("Signed '{}'", path.display());
+    }
+    Ok(())
+}
+
+/// `check_signing` already refused to pack an archive to be signed.
+#[cfg(not(feature = "signing"))]
+fn sign(_writer: &mut BufWriter<VolumeWriter>, _options: &PackOptions) -> Result<()> {
+    Ok(())
+}
+
+/// Temporary file an archive is written into before `persist` moves it to
+/// `archive_path`.
+pub(crate) fn temp_path(archive_path: &Path) -> PathBuf {
+    let mut path = OsString::from(archive_path.as_os_str());
+    path.push(".tmp");
+    PathBuf::from(path)
+}
+
+/// Replaces `archive_path` by the complete archive at `temp_path`, atomically
+/// unless they're on different devices, when it's copied and removed instead.
+pub(crate) fn persist(temp_path: &Path, archive_path: &Path) -> Result<()> {
+    match fs::rename(temp_path, archive_path) {
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+            debug!(
+                "'{}' is on another device, copying it to '{}'",
+                temp_path.display(),
+                archive_path.display()
+            );
+            fs::copy(temp_path, archive_path)?;
+            remove_file(temp_path)?;
+            Ok(())
+        }
+        result => Ok(result?),
+    }
+}
+
+/// Moves the volumes of the complete split archive at `temp_path` to
+/// `archive_path` like `persist`, then removes any volumes beyond them left
+/// by an earlier split into more. Returns the length of the archive.
+fn persist_volumes(temp_path: &Path, archive_path: &Path) -> Result<u64> {
+    let mut length = 0;
+    let mut count = 0;
+    loop {
+        let volume = volume::volume_path(temp_path, count + 1);
+        if !volume.is_file() {
+            break;
+        }
+        length += volume.metadata()?.len();
+        count += 1;
+        persist(&volume, &volume::volume_path(archive_path, count))?;
+    }
+    volume::remove_volumes(archive_path, count + 1)?;
+    Ok(length)
+}
+
+/// Names the archive in `error` when the device ran out of space, the
+/// underlying message doesn't mention which file was being written.
+fn named_error(error: ArchiveError, archive_path: &Path) -> ArchiveError {
+    match error {
+        ArchiveError::OutOfSpace(_) => ArchiveError::Io(format!(
+            "Out of space while writing '{}'",
+            archive_path.display()
+        )),
+        error => error,
+    }
+}
+
+#[cfg(not(feature = "zip"))]
+fn pack_zip(
+    _files: Vec<PathBuf>,
+    _inners: &[InnerFile],
+    _archive_path: &Path,
+    _options: &PackOptions,
+) -> Result<()> {
+    Err(ArchiveError::Unsupported(
+        "ZIP archives aren't supported by this build".to_string(),
+    ))
+}
+
+/// Writes the archive, continuing after the complete entries `resumed` when
+/// given, in which case `writer` is positioned right after the last of them.
+/// Returns the entries in index order.
+fn write_archive(
+    mut writer: BufWriter<VolumeWriter>,
+    files: Vec<PathBuf>,
+    inners: Vec<InnerFile>,
+    archive_path: &Path,
+    options: &PackOptions,
+    resumed: Vec<InnerFile>,
+) -> Result<Vec<InnerFile>> {
+    // entries are written in this order, the index array keeps the original one
+    let mut order: Vec<usize> = (0..inners.len()).collect();
+    match options.sort_by {
+        SortBy::None => {}
+        SortBy::Name => order.sort_by(|&a, &b| inners[a].name().cmp(inners[b].name())),
+        SortBy::Size => order.sort_by_key(|&i| inners[i].original_size()),
+    }
+    let mut files: Vec<PathBuf> = order.iter().map(|&i| files[i].clone()).collect();
+    let mut inners: Vec<InnerFile> = order.iter().map(|&i| inners[i].clone()).collect();
+
+    let done = resumed.len();
+    if done > 0 {
+        for (i, entry) in resumed.into_iter().enumerate() {
+            let expected = inners.get(i).map(InnerFile::name);
+            if expected != Some(entry.name()) {
+                return Err(ArchiveError::Path(format!(
+                    "Partial archive doesn't match the source: entry #{} is '{}', expected '{}'",
+                    i + 1,
+                    entry.name().to_string_lossy(),
+                    expected.unwrap_or_default().to_string_lossy()
+                )));
+            }
+            inners[i] = entry;
+        }
+
+        info!("Resuming after {} complete entries", done);
+        // the source may have changed since the archive was started
+        writer.seek(SeekFrom::Start(6))?;
+        writer.write_all(&u32::try_from(files.len())?.to_le_bytes())?;
+        writer.seek(SeekFrom::End(0))?;
+    } else {
+        write_header(&mut writer, files.len(), options)?;
+    }
+    let files = files.split_off(done);
+
+    let mut limiter = RateLimiter::new(options.rate_limit);
+    let progress = ProgressTracker::new(
+        options.progress.as_ref(),
+        Phase::Pack,
+        files.len() as u64,
+        Some(inners[done..].iter().map(InnerFile::original_size).sum()),
+        None,
+    );
+
+    if options.solid {
+        let temp_fields = process_solid(
+            &mut inners,
+            files,
+            &mut writer,
+            &mut limiter,
+            &progress,
+            options,
+        )?;
+        rewrite_temp_fields(&mut writer, temp_fields)?;
+    } else if options.jobs > 1 {
+        process_parallel(
+            &mut inners[done..],
+            files,
+            &mut writer,
+            archive_path,
+            options,
+            &progress,
+        )?;
+    } else {
+        let mut store = if options.dedup {
+            Some(ChunkStore::new(writer.get_ref().path(), options.checksum)?)
+        } else {
+            None
+        };
+        process_files(
+            &mut inners[done..],
+            files,
+            &mut writer,
+            store.as_mut(),
+            options,
+            &mut limiter,
+            &progress,
+        )?;
+    }
 
-    rewrite_temp_fields(&mut writer, temp_offsets, compressed_sizes, checksums)?;
+    write_index_offset(&mut writer)?;
 
+    let mut logical: Vec<(usize, InnerFile)> = order.into_iter().zip(inners).collect();
+    logical.sort_by_key(|&(i, _)| i);
+    let inners: Vec<InnerFile> = logical.into_iter().map(|(_, inner)| inner).collect();
     write_index_array(&mut writer, &inners)?;
 
+    write_volume_count(&mut writer, options)?;
+    sign(&mut writer, options)?;
+    let path = writer.get_ref().path().to_path_buf();
+    write_footer(&mut writer, &path)?;
+
     writer.flush()?;
+    Ok(inners)
+}
+
+fn write_header(
+    writer: &mut BufWriter<VolumeWriter>,
+    file_count: usize,
+    options: &PackOptions,
+) -> Result<()> {
+    writer.write_all(SIGNATURE)?;
+    writer.write_all(&VERSION.to_bytes())?;
+    writer.write_all(&u32::try_from(file_count)?.to_le_bytes())?; //file count
+    writer.write_all(&u64::to_le_bytes(0))?; //index offset
+
+    let mut flags = 0;
+    if options.solid {
+        flags |= FLAG_SOLID;
+    }
+    if options.dedup {
+        flags |= FLAG_DEDUP;
+    }
+    if options.dictionary.is_some() {
+        flags |= FLAG_DICTIONARY;
+    }
+    // the signature is written right before the footer, see `sign`
+    if options.sign.is_some() {
+        flags |= FLAG_SIGNED;
+    }
+    if options.split.is_some() {
+        flags |= FLAG_SPLIT;
+    }
+    writer.write_all(&flags.to_le_bytes())?;
+    writer.write_all(&[options.checksum.id()])?;
+    if options.split.is_some() {
+        writer.write_all(&1u16.to_le_bytes())?; //volume index
+        writer.write_all(&0u16.to_le_bytes())?; //volume count, see `write_volume_count`
+    }
+
+    if let Some(dictionary) = &options.dictionary {
+        write_dictionary(writer, dictionary_tail(dictionary))?;
+    }
     Ok(())
 }
 
-fn get_archive_path(source: &PathBuf, target: &PathBuf) -> Result<PathBuf> {
+/// Writes the dictionary section following the header.
+pub(crate) fn write_dictionary<W: Write>(writer: &mut W, dictionary: &[u8]) -> Result<()> {
+    writer.write_all(&u32::try_from(dictionary.len())?.to_le_bytes())?;
+    writer.write_all(dictionary)?;
+    Ok(())
+}
+
+/// The part of a dictionary within reach of the deflate window.
+pub(crate) fn dictionary_tail(dictionary: &[u8]) -> &[u8] {
+    &dictionary[dictionary.len().saturating_sub(MAX_DICTIONARY_SIZE)..]
+}
+
+/// Opens an archive left by an interrupted pack for writing, positioned
+/// right after its last entry which passes verification, and returns the
+/// complete entries. Anything after them is truncated.
+fn resume_point(archive_path: &Path, options: &PackOptions) -> Result<(File, Vec<InnerFile>)> {
+    let mut reader = BufReader::new(File::open(archive_path)?);
+    let mut buffer = vec![0u8; BUFFER_SIZE];
+
+    let header = Header::from_archive(&mut reader, &mut buffer)?;
+    if header.has_index() {
+        return Err(ArchiveError::Usage(format!(
+            "Archive '{}' is already complete, nothing to resume",
+            archive_path.display()
+        )));
+    }
+    let dictionary = options.dictionary.as_deref().map(dictionary_tail);
+    if header.version != VERSION
+        || header.is_solid()
+        || header.is_signed() != options.sign.is_some()
+        || header.is_dedup() != options.dedup
+        || header.checksum != options.checksum
+        || header
+            .has_dictionary()
+            .then_some(header.dictionary.as_slice())
+            != dictionary
+    {
+        return Err(ArchiveError::Usage(format!(
+            "Archive '{}' was written with another version or options and can't be resumed",
+            archive_path.display()
+        )));
+    }
+
+    let mut entries = Vec::new();
+    let mut end = header.size();
+    for _ in 0..header.file_count {
+        // the entry being written when interrupted has no sizes or checksums yet
+        let entry = match InnerFile::from_archive(&mut reader, &mut buffer, header.version) {
+            Ok(entry) => entry,
+            Err(_) => break,
+        };
+        if io::copy(
+            &mut EntryReader::new(&mut reader, entry.clone(), &header),
+            &mut io::sink(),
+        )
+        .is_err()
+        {
+            break;
+        }
+
+        end = reader.stream_position()?;
+        entries.push(entry);
+    }
+
+    // with no complete entries `write_archive` starts over with the header
+    if entries.is_empty() {
+        end = 0;
+    }
+
+    let mut file = OpenOptions::new().write(true).open(archive_path)?;
+    file.set_len(end)?;
+    file.seek(SeekFrom::Start(end))?;
+    Ok((file, entries))
+}
+
+/// Sources like `.` or `dir/..` have no name of their own, so they're
+/// replaced by the directory they point to.
+fn resolve_source(source: PathBuf) -> Result<PathBuf> {
+    if source.file_name().is_none() && source.exists() {
+        return Ok(source.canonicalize()?);
+    }
+    Ok(source)
+}
+
+fn get_archive_path(
+    source: &Path,
+    target: &Path,
+    name: Option<&OsStr>,
+    format: Format,
+) -> Result<PathBuf> {
+    let extension = format.extension();
     let source = normalize_path(source);
     let target = normalize_path(target);
 
-    if !source.exists() || (!source.is_file() && !source.is_dir()) {
+    if !source.exists() {
         return Err(ArchiveError::Path(format!(
             "Invalid source destination at path: {}",
             source.display()
         )));
     }
-    Ok(if target.extension().map_or(false, |ex| ex == "slf") {
+
+    if !source.is_file() && !source.is_dir() {
+        return Err(ArchiveError::Path(format!(
+            "Source isn't a regular file or directory (FIFO, socket or device), nothing to pack at path: {}",
+            source.display()
+        )));
+    }
+
+    Ok(if target.extension().is_some_and(|ex| ex == extension) {
+        if let Some(name) = name {
+            warn!(
+                "Target '{}' is an archive path, ignoring the name '{}'",
+                target.display(),
+                name.to_string_lossy()
+            );
+        }
         target
+    } else if let Some(name) = name {
+        let name = Path::new(name);
+        if name.file_name() != Some(name.as_os_str()) {
+            return Err(ArchiveError::Usage(format!(
+                "Archive name '{}' isn't a plain file name",
+                name.display()
+            )));
+        }
+        if name.extension().is_some_and(|ex| ex == extension) {
+            target.join(name)
+        } else {
+            let mut file_name = name.as_os_str().to_os_string();
+            file_name.push(".");
+            file_name.push(extension);
+            target.join(file_name)
+        }
     } else {
         let archive_name = get_archive_name(&source)?;
-        target.join(archive_name).with_extension("slf")
+        target.join(archive_name).with_extension(extension)
     })
 }
 
-fn get_archive_name(source: &PathBuf) -> Result<PathBuf> {
+fn get_archive_name(source: &Path) -> Result<PathBuf> {
     Ok(if source.is_file() {
         PathBuf::from(source.file_stem().ok_or(ArchiveError::Path(format!(
             "Failed to get file stem from path: {}",
             source.display()
         )))?)
     } else {
-        PathBuf::from(source.file_name().ok_or(ArchiveError::Path(format!(
-            "Failed to get directory name from path: {}",
-            source.display()
-        )))?)
+        // only the root directory has no name after `resolve_source`
+        PathBuf::from(source.file_name().unwrap_or(OsStr::new("root")))
     })
 }
 
-fn collect_files(root: &Path) -> Vec<PathBuf> {
+/// Returns files sorted by path, so the order doesn't depend on the file system.
+/// With `dereference` symlinks are followed, failing on links pointing back
+/// to one of their ancestors (detected by device and inode on Unix).
+pub(crate) fn collect_files(root: &Path, options: &PackOptions) -> Result<Vec<PathBuf>> {
     if root.is_file() {
-        vec![root.to_path_buf()]
+        return Ok(vec![root.to_path_buf()]);
+    }
+    if !options.recursive {
+        return collect_top_level(root, options);
+    }
+
+    let mut files = Vec::new();
+    let walker = WalkDir::new(root)
+        .follow_links(options.dereference)
+        .into_iter()
+        // a hidden directory is pruned with everything in it
+        .filter_entry(|entry| entry.depth() == 0 || !skip_hidden(entry.path(), options));
+    for entry in walker {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) if e.loop_ancestor().is_some() => {
+                return Err(ArchiveError::Path(format!(
+                    "symlink loop detected at {}",
+                    e.path().unwrap_or(root).display()
+                )));
+            }
+            Err(_) => continue,
+        };
+
+        let file_type = entry.file_type();
+        if !file_type.is_file() && !file_type.is_dir() {
+            warn!("Skipping '{}': not a regular file", entry.path().display());
+        }
+        if file_type.is_file() {
+            files.push(entry.into_path());
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Resolves the files given by `PackOptions::files` and the directory
+/// `root` their names are relative to, which has to contain all of them.
+/// The order of the list is kept.
+fn listed_files(root: &Path, files: &[PathBuf]) -> Result<(PathBuf, Vec<PathBuf>)> {
+    // resolved lexically, a listed symlink is packed under its own name
+    let resolved_root = normalize_path(&path::absolute(root)?);
+    if !resolved_root.is_dir() {
+        return Err(ArchiveError::Path(format!(
+            "Listed files are named relative to '{}', which isn't a directory",
+            root.display()
+        )));
+    }
+
+    let mut resolved = Vec::with_capacity(files.len());
+    for file in files {
+        let path = normalize_path(&path::absolute(file)?);
+        if !path.exists() {
+            return Err(ArchiveError::Path(format!(
+                "Listed file '{}' doesn't exist",
+                file.display()
+            )));
+        }
+        if !path.is_file() {
+            return Err(ArchiveError::Path(format!(
+                "Listed path '{}' isn't a regular file",
+                file.display()
+            )));
+        }
+        if !path.starts_with(&resolved_root) {
+            return Err(ArchiveError::Path(format!(
+                "Listed file '{}' isn't inside '{}', which the names are relative to",
+                file.display(),
+                root.display()
+            )));
+        }
+        resolved.push(path);
+    }
+    Ok((resolved_root, resolved))
+}
+
+/// Reads a list of paths for `PackOptions::files`, one per line or, with
+/// `separator` 0, separated by NUL bytes so names may contain newlines.
+/// Empty entries are skipped, `-` reads the list from stdin.
+pub fn read_file_list(path: &Path, separator: u8) -> Result<Vec<PathBuf>> {
+    let list = if path == Path::new("-") {
+        let mut list = Vec::new();
+        io::stdin().lock().read_to_end(&mut list)?;
+        list
     } else {
-        WalkDir::new(root)
-            .into_iter()
-            .filter_map(|e| e.ok())
-            .filter(|e| e.file_type().is_file())
-            .map(|e| e.path().to_path_buf())
-            .collect()
+        fs::read(path).map_err(|e| {
+            ArchiveError::Path(format!(
+                "Failed to read file list '{}': {}",
+                path.display(),
+                e
+            ))
+        })?
+    };
+
+    let mut files = Vec::new();
+    for entry in list.split(|&b| b == separator) {
+        let entry = if separator == b'\n' {
+            entry.strip_suffix(b"\r").unwrap_or(entry)
+        } else {
+            entry
+        };
+        if !entry.is_empty() {
+            files.push(PathBuf::from(name_from_bytes(entry)?));
+        }
+    }
+    Ok(files)
+}
+
+/// Returns the files directly in `root`, sorted by path. Subdirectories
+/// aren't entered, so symlinks can't loop.
+fn collect_top_level(root: &Path, options: &PackOptions) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in root.read_dir()? {
+        let entry = entry?;
+        let path = entry.path();
+        if skip_hidden(&path, options) {
+            continue;
+        }
+        let file_type = if options.dereference {
+            // a dangling link is skipped like by `WalkDir`
+            match path.metadata() {
+                Ok(metadata) => metadata.file_type(),
+                Err(_) => continue,
+            }
+        } else {
+            entry.file_type()?
+        };
+
+        if file_type.is_file() {
+            files.push(path);
+        } else if !file_type.is_dir() {
+            warn!("Skipping '{}': not a regular file", path.display());
+        }
     }
+    files.sort();
+    Ok(files)
 }
 
-fn inner_files(root: &Path, paths: &Vec<PathBuf>) -> Result<Vec<InnerFile>> {
+/// Whether `path` is hidden and `PackOptions::exclude_hidden` is set, which
+/// is logged.
+fn skip_hidden(path: &Path, options: &PackOptions) -> bool {
+    let skipped = options.exclude_hidden && is_hidden(path);
+    if skipped {
+        info!("Skipping hidden '{}'", path.display());
+    }
+    skipped
+}
+
+/// Whether the file or directory at `path` has the hidden attribute.
+#[cfg(windows)]
+fn is_hidden(path: &Path) -> bool {
+    use std::os::windows::fs::MetadataExt;
+
+    const FILE_ATTRIBUTE_HIDDEN: u32 = 0x2;
+    fs::symlink_metadata(path)
+        .is_ok_and(|metadata| metadata.file_attributes() & FILE_ATTRIBUTE_HIDDEN != 0)
+}
+
+/// Whether the name of the file or directory at `path` starts with a dot.
+#[cfg(not(windows))]
+fn is_hidden(path: &Path) -> bool {
+    path.file_name()
+        .is_some_and(|name| name.as_encoded_bytes().starts_with(b"."))
+}
+
+/// Creates the entries of `paths`, dropping the files excluded by their size
+/// from `paths` as well.
+fn inner_files(
+    root: &Path,
+    paths: &mut Vec<PathBuf>,
+    options: &PackOptions,
+) -> Result<Vec<InnerFile>> {
     let mut inners = Vec::new();
+    let mut kept = Vec::with_capacity(paths.len());
+    let mut seen_names = HashSet::new();
+    let mut linked = HashMap::new();
+    let mut timed_dirs = HashSet::new();
+    let prefix = match &options.base {
+        Some(base) => base_prefix(root, base)?,
+        None => PathBuf::new(),
+    };
+
+    for path in paths.drain(..) {
+        let metadata = path.metadata()?;
+        if options
+            .exclude_smaller_than
+            .is_some_and(|min| metadata.len() < min)
+            || options
+                .exclude_larger_than
+                .is_some_and(|max| metadata.len() > max)
+        {
+            info!(
+                "Skipping '{}' of {} bytes, outside of the size limits",
+                path.display(),
+                metadata.len()
+            );
+            continue;
+        }
 
-    for path in paths {
         let relative_name = if root.is_file() {
             path.file_name()
                 .ok_or(ArchiveError::Path(format!(
@@ -111,112 +1081,747 @@ fn inner_files(root: &Path, paths: &Vec<PathBuf>) -> Result<Vec<InnerFile>> {
         } else {
             path.strip_prefix(root)?.as_os_str().to_os_string()
         };
-        let file_size = path.metadata()?.len();
-
-        let inner_file = InnerFile::create(relative_name, file_size, 0, 0, 0);
+        let relative_name = if prefix.as_os_str().is_empty() {
+            relative_name
+        } else {
+            prefix.join(relative_name).into_os_string()
+        };
 
-        inners.push(inner_file);
-    }
-    Ok(inners)
-}
+        // directories above the file which are stored as well
+        let mut levels = Path::new(&relative_name)
+            .components()
+            .count()
+            .saturating_sub(1);
+        let relative_name = if options.transforms.is_empty() {
+            relative_name
+        } else {
+            transformed_name(relative_name, &options.transforms)?
+        };
 
-fn process_files(
-    inners: &mut Vec<InnerFile>,
-    paths: Vec<PathBuf>,
-    writer: &mut BufWriter<File>,
-) -> Result<(Vec<u64>, Vec<u64>, Vec<(u32, u32)>)> {
-    let mut temp_offsets = Vec::new();
-    let mut compressed_sizes = Vec::new();
-    let mut checksums = Vec::new();
+        if !options.allow_duplicates && !seen_names.insert(relative_name.clone()) {
+            return Err(ArchiveError::DuplicateEntry(
+                relative_name.to_string_lossy().into_owned(),
+            ));
+        }
 
-    for (i, path) in paths.iter().enumerate() {
-        let offset = inners[i].write_metadata(writer)?;
+        let link_target = if options.hard_links {
+            hard_link_target(&metadata, &relative_name, &mut linked)
+        } else {
+            None
+        };
 
-        let hasher = Crc::new();
-        let hasher_writer = HasherWriter::new(writer, hasher);
+        // the contents are stored once, with the entry linked to
+        let size = if link_target.is_some() {
+            0
+        } else {
+            metadata.len()
+        };
+        let mut inner_file = InnerFile::create(relative_name, size, 0, 0, 0);
+        inner_file.set_link_target(link_target);
+        inner_file.set_method(entry_method(&path, metadata.len(), options));
+        inner_file.set_chunked(options.dedup);
+        record_metadata(&mut inner_file, &metadata, options);
+        if !options.reproducible {
+            levels = levels.min(
+                Path::new(inner_file.name())
+                    .components()
+                    .count()
+                    .saturating_sub(1),
+            );
+            inner_file.set_dir_mtimes(dir_mtimes(&path, levels, &mut timed_dirs));
+        }
 
-        let (size, (original_cheksum, compressed_checksum)) =
-            process_single_file(path, hasher_writer)?;
+        #[cfg(all(unix, feature = "xattrs"))]
+        if options.xattrs {
+            inner_file.set_xattrs(read_xattrs(&path)?);
+        }
 
-        temp_offsets.push(offset);
-        compressed_sizes.push(size);
-        checksums.push((original_cheksum, compressed_checksum));
+        inners.push(inner_file);
+        kept.push(path);
     }
 
-    Ok((temp_offsets, compressed_sizes, checksums))
+    *paths = kept;
+    Ok(inners)
 }
 
-fn process_single_file(
-    path: &PathBuf,
-    mut hasher_writer: HasherWriter,
-) -> Result<(u64, (u32, u32))> {
-    let file = File::open(path)?;
-    let mut reader = BufReader::new(file);
+/// Stores the owner, permissions and modification time of the file in its
+/// entry. Reproducible archives keep only the permissions.
+pub(crate) fn record_metadata(
+    inner_file: &mut InnerFile,
+    metadata: &Metadata,
+    options: &PackOptions,
+) {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{MetadataExt, PermissionsExt};
+        inner_file.set_mode(Some(metadata.permissions().mode() & 0o7777));
+        if !options.reproducible {
+            inner_file.set_owner(metadata.uid(), metadata.gid());
+        }
+    }
+    if !options.reproducible {
+        inner_file.set_mtime(metadata.modified().ok());
+    }
+}
 
-    let mut buffer = [0u8; BUFFER_SIZE];
+/// Modification times of the directories up to `levels` above the file at
+/// `path` which no earlier entry recorded, see `InnerFile::dir_mtimes`.
+fn dir_mtimes(
+    path: &Path,
+    levels: usize,
+    timed_dirs: &mut HashSet<PathBuf>,
+) -> Vec<(u16, SystemTime)> {
+    let mut mtimes = Vec::new();
+    for (level, dir) in path.ancestors().enumerate().skip(1).take(levels) {
+        if !timed_dirs.insert(dir.to_path_buf()) {
+            continue;
+        }
+        let (Ok(level), Ok(mtime)) = (
+            u16::try_from(level),
+            dir.metadata().and_then(|m| m.modified()),
+        ) else {
+            continue;
+        };
+        mtimes.push((level, mtime));
+    }
+    mtimes
+}
 
-    let mut original_checksum = Crc::new();
+/// Path of the directory holding the entries of `root` relative to `base`,
+/// which has to contain it. Both are resolved first, so e.g. a relative
+/// source and an absolute base can be mixed.
+fn base_prefix(root: &Path, base: &Path) -> Result<PathBuf> {
+    let resolved_root = root.canonicalize()?;
+    let resolved_base = base.canonicalize().map_err(|e| {
+        ArchiveError::Path(format!(
+            "Invalid base directory '{}': {}",
+            base.display(),
+            e
+        ))
+    })?;
+    if !resolved_base.is_dir() {
+        return Err(ArchiveError::Path(format!(
+            "Base '{}' isn't a directory",
+            base.display()
+        )));
+    }
 
-    let encoder = GzEncoder::new(hasher_writer, Compression::default());
+    // a packed file is named after itself, a directory only by its contents
+    let dir = if resolved_root.is_file() {
+        resolved_root.parent().unwrap_or(&resolved_root)
+    } else {
+        &resolved_root
+    };
+    match dir.strip_prefix(&resolved_base) {
+        Ok(prefix) => Ok(prefix.to_path_buf()),
+        Err(_) => Err(ArchiveError::Path(format!(
+            "Source '{}' isn't inside the base directory '{}'",
+            root.display(),
+            base.display()
+        ))),
+    }
+}
 
-    hasher_writer =
-        compress_file_content(&mut reader, encoder, &mut original_checksum, &mut buffer)?;
+/// Picks the compression method of the file at `path` of `size` bytes.
+pub(crate) fn entry_method(path: &Path, size: u64, options: &PackOptions) -> Method {
+    let method = if options.solid {
+        Method::Gzip
+    } else if size < options.compress_threshold {
+        Method::Store
+    } else {
+        options.method.unwrap_or_else(|| Method::for_path(path))
+    };
+    if method == Method::Gzip && options.dictionary.is_some() {
+        Method::Deflate
+    } else {
+        method
+    }
+}
 
-    let size = hasher_writer.take_written_bytes();
+/// Returns the name of the entry packed first of the files sharing the inode
+/// of `metadata`, unless that's `name` itself. `linked` maps every (device,
+/// inode) seen with several links to the first of its names.
+#[cfg(unix)]
+fn hard_link_target(
+    metadata: &Metadata,
+    name: &OsStr,
+    linked: &mut HashMap<(u64, u64), OsString>,
+) -> Option<OsString> {
+    use std::os::unix::fs::MetadataExt;
+    if metadata.nlink() < 2 {
+        return None;
+    }
 
-    let original_checksum = original_checksum.sum();
-    let compressed_checksum = hasher_writer.sum();
+    let first = linked
+        .entry((metadata.dev(), metadata.ino()))
+        .or_insert_with(|| name.to_os_string());
+    (first != name).then(|| first.clone())
+}
 
-    Ok((size, (original_checksum, compressed_checksum)))
+#[cfg(not(unix))]
+fn hard_link_target(
+    _metadata: &Metadata,
+    _name: &OsStr,
+    _linked: &mut HashMap<(u64, u64), OsString>,
+) -> Option<OsString> {
+    None
 }
 
-fn compress_file_content<R: Read, W: Write>(
-    reader: &mut R,
-    mut encoder: GzEncoder<W>,
-    checksum: &mut Crc,
-    mut buffer: &mut [u8],
-) -> Result<W> {
-    loop {
-        let bytes = reader.read(&mut buffer)?;
+/// Reads the extended attributes of a file, sorted by name. File systems
+/// without them give none.
+#[cfg(all(unix, feature = "xattrs"))]
+fn read_xattrs(path: &Path) -> Result<Vec<(OsString, Vec<u8>)>> {
+    let names = match xattr::list(path) {
+        Ok(names) => names,
+        Err(e) if e.kind() == io::ErrorKind::Unsupported => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
 
-        if bytes == 0 {
-            break; //EOF
+    let mut xattrs = Vec::new();
+    for name in names {
+        // removed since being listed
+        if let Some(value) = xattr::get(path, &name)? {
+            xattrs.push((name, value));
         }
+    }
+    xattrs.sort();
+    Ok(xattrs)
+}
+
+/// Applies `transforms` to a name relative to the packed directory, failing
+/// when the result is empty or points outside of it.
+fn transformed_name(name: OsString, transforms: &[Transform]) -> Result<OsString> {
+    let transformed = Transform::apply_all(transforms, &name)?;
+    if Path::new(&transformed).file_name().is_none() || !stays_inside(Path::new(&transformed)) {
+        return Err(ArchiveError::Path(format!(
+            "Transformed name '{}' of '{}' is empty or points outside of the archive",
+            transformed.to_string_lossy(),
+            name.to_string_lossy()
+        )));
+    }
+    Ok(normalize_path(Path::new(&transformed)).into_os_string())
+}
+
+/// Offsets of the compressed size fields, the (original, compressed) sizes
+/// and the checksums to patch there. Original sizes are the bytes actually
+/// read, which may differ from the size seen while collecting files.
+type TempFields = (Vec<u64>, Vec<(Option<u64>, u64)>, Vec<(u32, u32)>);
+
+fn process_files(
+    inners: &mut [InnerFile],
+    paths: Vec<PathBuf>,
+    writer: &mut BufWriter<VolumeWriter>,
+    mut store: Option<&mut ChunkStore>,
+    options: &PackOptions,
+    limiter: &mut RateLimiter,
+    progress: &ProgressTracker,
+) -> Result<()> {
+    let cancel = options.cancel.as_deref();
+
+    for (i, path) in paths.iter().enumerate() {
+        reserve(
+            writer,
+            inners[i].metadata_size() + inners[i].original_size(),
+        )?;
+        let offset = inners[i].write_metadata(writer)?;
+
+        let method = inners[i].method;
+        let mut reader = Retrying::new(
+            open_source(path, inners[i].link_target.is_some())?,
+            options.io_retries,
+        );
+        let summary = match store.as_deref_mut() {
+            Some(store) => store.write_entry(&mut reader, method, writer, limiter, cancel)?,
+            None => process_single_file(
+                reader,
+                method,
+                content_filter(&inners[i], options),
+                &mut *writer,
+                limiter,
+                options,
+            )?,
+        };
+        log!(
+            options.verbosity.entry_level(),
+            "Packed '{}' ({} -> {} bytes, {})",
+            path.display(),
+            summary.original_size,
+            summary.compressed_size,
+            inners[i].method.name()
+        );
+
+        inners[i].set_original_size(summary.original_size);
+        inners[i].set_compressed_size(summary.compressed_size);
+        progress.advance(
+            inners[i].name(),
+            summary.original_size,
+            summary.compressed_size,
+        );
+
+        // patched right away, so an interrupted archive can be resumed
+        patch_temp_fields(
+            writer,
+            offset,
+            Some(summary.original_size),
+            summary.compressed_size,
+            (summary.original_checksum, summary.compressed_checksum),
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Filter of `PackOptions::filters` applying to `inner` along with its name,
+/// links have no contents to filter.
+fn content_filter<'a, 'b>(
+    inner: &'a InnerFile,
+    options: &'b PackOptions,
+) -> Option<(&'a OsStr, &'b dyn ContentFilter)> {
+    if inner.link_target.is_some() {
+        return None;
+    }
+    options
+        .filters
+        .iter()
+        .find(|rule| (rule.applies_to)(inner.name()))
+        .map(|rule| (inner.name(), &*rule.filter))
+}
 
-        let chunk = &buffer[..bytes];
+fn process_single_file<R: Read, W: Write>(
+    mut reader: R,
+    method: Method,
+    filter: Option<(&OsStr, &dyn ContentFilter)>,
+    writer: W,
+    limiter: &mut RateLimiter,
+    options: &PackOptions,
+) -> Result<CompressionSummary> {
+    let mut buffer = [0u8; BUFFER_SIZE];
+    let dictionary = options
+        .dictionary
+        .as_deref()
+        .map_or(&[][..], dictionary_tail);
+    let cancel = options.cancel.as_deref();
 
-        checksum.update(&chunk);
+    let mut compressor = Compressor::with_dictionary(writer, method, options.level, dictionary)?
+        .with_checksum(options.checksum);
 
-        encoder.write_all(chunk)?;
+    match filter {
+        Some((name, filter)) => {
+            let mut reader = Throttled {
+                reader,
+                limiter,
+                cancel,
+            };
+            filter.filter(name, &mut reader, &mut compressor)?;
+        }
+        None => {
+            compress_file_content(&mut reader, &mut compressor, &mut buffer, limiter, cancel)?;
+        }
     }
 
-    Ok(encoder.finish()?)
+    let (_, summary) = compressor.finish()?;
+
+    Ok(summary)
 }
 
-fn rewrite_temp_fields(
-    writer: &mut BufWriter<File>,
-    temp_offsets: Vec<u64>,
-    compressed_sizes: Vec<u64>,
-    checksums: Vec<(u32, u32)>,
+/// Compresses files on `options.jobs` threads, each into a `SpillBuffer`,
+/// while this thread writes them into the archive in order. A thread waits
+/// before taking a file more than `2 * jobs` places ahead of the last one
+/// written, so at most that many compressed files are held at once, each
+/// taking no more than `options.spill_threshold` bytes of memory.
+fn process_parallel(
+    inners: &mut [InnerFile],
+    paths: Vec<PathBuf>,
+    writer: &mut BufWriter<VolumeWriter>,
+    archive_path: &Path,
+    options: &PackOptions,
+    progress: &ProgressTracker,
+) -> Result<()> {
+    let jobs = options.jobs.min(paths.len()).max(1);
+    let window = 2 * jobs;
+    // the limit is shared by all threads
+    let rate_limit = if options.rate_limit > 0 {
+        (options.rate_limit / jobs as u64).max(1)
+    } else {
+        0
+    };
+    let methods: Vec<Method> = inners.iter().map(|inner| inner.method).collect();
+    let links: Vec<bool> = inners
+        .iter()
+        .map(|inner| inner.link_target.is_some())
+        .collect();
+    let filters: Vec<Option<(OsString, &dyn ContentFilter)>> = inners
+        .iter()
+        .map(|inner| {
+            content_filter(inner, options).map(|(name, filter)| (name.to_os_string(), filter))
+        })
+        .collect();
+
+    let next = AtomicUsize::new(0);
+    let stop = AtomicBool::new(false);
+    let written = (Mutex::new(0), Condvar::new());
+    let (sender, receiver) = mpsc::channel();
+
+    let worker = |sender: Sender<(usize, Result<(SpillBuffer, CompressionSummary)>)>| {
+        let mut limiter = RateLimiter::new(rate_limit);
+        loop {
+            let i = next.fetch_add(1, Ordering::Relaxed);
+            let Some(path) = paths.get(i) else {
+                break;
+            };
+
+            let (lock, wakeup) = &written;
+            let mut done = lock.lock().unwrap();
+            while i >= *done + window && !stop.load(Ordering::Relaxed) {
+                done = wakeup.wait(done).unwrap();
+            }
+            drop(done);
+            if stop.load(Ordering::Relaxed) {
+                break;
+            }
+
+            let _report = PanicReport {
+                sender: &sender,
+                index: i,
+            };
+            let mut spill = SpillBuffer::new(spill_path(archive_path, i), options.spill_threshold);
+            let result = open_source(path, links[i])
+                .and_then(|file| {
+                    process_single_file(
+                        Retrying::new(file, options.io_retries),
+                        methods[i],
+                        filters[i]
+                            .as_ref()
+                            .map(|(name, filter)| (name.as_os_str(), *filter)),
+                        &mut spill,
+                        &mut limiter,
+                        options,
+                    )
+                })
+                .map(|summary| (spill, summary));
+            if sender.send((i, result)).is_err() {
+                break;
+            }
+        }
+    };
+
+    thread::scope(|scope| {
+        let workers: Vec<_> = (0..jobs)
+            .map(|_| {
+                let sender = sender.clone();
+                scope.spawn(|| worker(sender))
+            })
+            .collect();
+        drop(sender);
+
+        let result = (|| -> Result<()> {
+            let mut pending = HashMap::new();
+            for (i, path) in paths.iter().enumerate() {
+                let (mut spill, summary) = loop {
+                    if let Some(result) = pending.remove(&i) {
+                        break result?;
+                    }
+                    let (j, result) = receiver.recv().map_err(|_| compression_panicked())?;
+                    pending.insert(j, result);
+                };
+
+                reserve(writer, inners[i].metadata_size() + summary.compressed_size)?;
+                let offset = inners[i].write_metadata(writer)?;
+                spill.copy_to(writer)?;
+                log!(
+                    options.verbosity.entry_level(),
+                    "Packed '{}' ({} -> {} bytes, {}{})",
+                    path.display(),
+                    summary.original_size,
+                    summary.compressed_size,
+                    inners[i].method.name(),
+                    if spill.is_spilled() { ", spilled" } else { "" }
+                );
+
+                inners[i].set_original_size(summary.original_size);
+                inners[i].set_compressed_size(summary.compressed_size);
+                progress.advance(
+                    inners[i].name(),
+                    summary.original_size,
+                    summary.compressed_size,
+                );
+
+                // patched right away, so an interrupted archive can be resumed
+                patch_temp_fields(
+                    writer,
+                    offset,
+                    Some(summary.original_size),
+                    summary.compressed_size,
+                    (summary.original_checksum, summary.compressed_checksum),
+                )?;
+
+                let (lock, wakeup) = &written;
+                *lock.lock().unwrap() = i + 1;
+                wakeup.notify_all();
+            }
+            Ok(())
+        })();
+
+        // wakes up threads waiting for a file which will never be written
+        stop.store(true, Ordering::Relaxed);
+        written.1.notify_all();
+        // a panic left unjoined would be raised again at the end of the scope
+        let joined: Vec<_> = workers.into_iter().map(|worker| worker.join()).collect();
+        if joined.iter().any(|joined| joined.is_err()) {
+            result.and(Err(compression_panicked()))
+        } else {
+            result
+        }
+    })
+}
+
+fn compression_panicked() -> ArchiveError {
+    ArchiveError::Io("Compression thread panicked while packing".to_string())
+}
+
+/// Sends an error for the file `index` of `process_parallel` when the
+/// thread compressing it panics, so the writing thread stops instead of
+/// waiting for it.
+struct PanicReport<'a, T> {
+    sender: &'a Sender<(usize, Result<T>)>,
+    index: usize,
+}
+
+impl<T> Drop for PanicReport<'_, T> {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            let _ = self.sender.send((self.index, Err(compression_panicked())));
+        }
+    }
+}
+
+/// Temporary file next to the archive holding the compressed file `index`
+/// of `process_parallel`.
+fn spill_path(archive_path: &Path, index: usize) -> PathBuf {
+    let mut path = archive_path.as_os_str().to_os_string();
+    path.push(format!(".{}.spill", index));
+    PathBuf::from(path)
+}
+
+/// Writes metadata of every file first, followed by one compressed block
+/// holding contents of all files back to back.
+fn process_solid(
+    inners: &mut [InnerFile],
+    paths: Vec<PathBuf>,
+    writer: &mut BufWriter<VolumeWriter>,
+    limiter: &mut RateLimiter,
+    progress: &ProgressTracker,
+    options: &PackOptions,
+) -> Result<TempFields> {
+    let cancel = options.cancel.as_deref();
+    let mut temp_offsets = Vec::new();
+    let mut sizes = Vec::new();
+    let mut checksums = Vec::new();
+
+    for inner in inners.iter_mut() {
+        temp_offsets.push(inner.write_metadata(writer)?);
+    }
+
+    let block_offset = writer.stream_position()?;
+    writer.write_all(&[0u8; 16])?; // solid block sizes and checksums
+
+    let mut buffer = [0u8; BUFFER_SIZE];
+    let mut compressor =
+        Compressor::with_level(&mut *writer, options.level).with_checksum(options.checksum);
+
+    for (i, path) in paths.iter().enumerate() {
+        let file = Retrying::new(
+            open_source(path, inners[i].link_target.is_some())?,
+            options.io_retries,
+        );
+        let mut reader = ChecksumReader::new(file, options.checksum);
+
+        let written =
+            compress_file_content(&mut reader, &mut compressor, &mut buffer, limiter, cancel)?;
+        inners[i].set_original_size(written);
+        progress.advance(inners[i].name(), written, 0);
+
+        log!(
+            options.verbosity.entry_level(),
+            "Packed '{}' into the solid block ({} bytes)",
+            path.display(),
+            written
+        );
+
+        sizes.push((Some(written), 0));
+        checksums.push((reader.checksum().sum(), 0));
+    }
+
+    let (_, summary) = compressor.finish()?;
+
+    temp_offsets.push(block_offset);
+    sizes.push((None, summary.compressed_size));
+    checksums.push((summary.original_checksum, summary.compressed_checksum));
+
+    Ok((temp_offsets, sizes, checksums))
+}
+
+/// Opens the file at `path`, or nothing for a hard link, whose contents are
+/// stored with the entry it links to, see `PackOptions::hard_links`.
+fn open_source(path: &Path, link: bool) -> Result<Box<dyn Read + Send>> {
+    if link {
+        return Ok(Box::new(io::empty()));
+    }
+    Ok(Box::new(File::open(path)?))
+}
+
+/// Source of a `ContentFilter`, which reads it itself, checking for
+/// cancellation and keeping the rate limit like `compress_file_content`.
+struct Throttled<'a, R: Read> {
+    reader: R,
+    limiter: &'a mut RateLimiter,
+    cancel: Option<&'a AtomicBool>,
+}
+
+impl<R: Read> Read for Throttled<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        check_cancelled(self.cancel).map_err(io::Error::other)?;
+        let bytes = self.reader.read(buf)?;
+        self.limiter.consume(bytes);
+        Ok(bytes)
+    }
+}
+
+/// Returns the count of bytes read from `reader`.
+fn compress_file_content<R: Read, W: Write>(
+    reader: &mut R,
+    compressor: &mut Compressor<W>,
+    buffer: &mut [u8],
+    limiter: &mut RateLimiter,
+    cancel: Option<&AtomicBool>,
+) -> Result<u64> {
+    let mut total = 0;
+    loop {
+        check_cancelled(cancel)?;
+
+        let bytes = match reader.read(buffer) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        };
+
+        if bytes == 0 {
+            break; //EOF
+        }
+
+        compressor.write_all(&buffer[..bytes])?;
+        total += bytes as u64;
+
+        limiter.consume(bytes);
+    }
+
+    Ok(total)
+}
+
+fn rewrite_temp_fields<W: Write + Seek>(writer: &mut W, temp_fields: TempFields) -> Result<()> {
+    let (temp_offsets, sizes, checksums) = temp_fields;
+    for (i, &position) in temp_offsets.iter().enumerate() {
+        let (original_size, compressed_size) = sizes[i];
+        patch_temp_fields(
+            writer,
+            position,
+            original_size,
+            compressed_size,
+            checksums[i],
+        )?;
+    }
+    Ok(())
+}
+
+/// Writes the sizes and checksums of an entry whose compressed size is at
+/// `position`, returning to the current position afterwards.
+pub(crate) fn patch_temp_fields<W: Write + Seek>(
+    writer: &mut W,
+    position: u64,
+    original_size: Option<u64>,
+    compressed_size: u64,
+    checksums: (u32, u32),
 ) -> Result<()> {
+    let end = writer.stream_position()?;
+    if let Some(original_size) = original_size {
+        writer.seek(SeekFrom::Start(position - 8))?;
+        writer.write_all(&original_size.to_le_bytes())?;
+    } else {
+        writer.seek(SeekFrom::Start(position))?;
+    }
+    writer.write_all(&compressed_size.to_le_bytes())?;
+    writer.write_all(&checksums.0.to_le_bytes())?;
+    writer.write_all(&checksums.1.to_le_bytes())?;
+    writer.seek(SeekFrom::Start(end))?;
+    Ok(())
+}
+
+/// Points the header at the index array, which is written next.
+pub(crate) fn write_index_offset<W: Write + Seek>(writer: &mut W) -> Result<()> {
     let end = writer.stream_position()?;
     writer.seek(SeekFrom::Start(10))?;
     writer.write_all(&end.to_le_bytes())?;
-    writer.flush()?;
-    for (i, &position) in temp_offsets.iter().enumerate() {
-        writer.seek(SeekFrom::Start(position))?;
-        let size = compressed_sizes[i];
-        let checksum = checksums[i];
-        writer.write_all(&size.to_le_bytes())?;
-        writer.write_all(&checksum.0.to_le_bytes())?;
-        writer.write_all(&checksum.1.to_le_bytes())?;
+    writer.seek(SeekFrom::Start(end))?;
+    Ok(())
+}
+
+/// Starts the next volume of a split archive unless `len` more bytes fit into
+/// the current one, see `VolumeWriter::reserve`.
+fn reserve(writer: &mut BufWriter<VolumeWriter>, len: u64) -> Result<()> {
+    if writer.get_ref().is_split() {
         writer.flush()?;
+        writer.get_mut().reserve(len)?;
+    }
+    Ok(())
+}
+
+/// Stores the count of volumes in the header of a split archive, once the
+/// signature and the footer are sure to fit into the last one.
+fn write_volume_count(writer: &mut BufWriter<VolumeWriter>, options: &PackOptions) -> Result<()> {
+    if !writer.get_ref().is_split() {
+        return Ok(());
     }
+    // see `signing::SIGNATURE_SIZE`
+    let signature_size = if options.sign.is_some() { 64 } else { 0 };
+    reserve(writer, signature_size + Footer::size(VERSION))?;
+
+    let count = u16::try_from(writer.get_ref().volume_count())?;
+    let end = writer.stream_position()?;
+    writer.seek(SeekFrom::Start(23))?;
+    writer.write_all(&count.to_le_bytes())?;
     writer.seek(SeekFrom::Start(end))?;
     Ok(())
 }
 
-fn write_index_array(writer: &mut BufWriter<File>, inners: &Vec<InnerFile>) -> Result<()> {
+/// Writes the footer, including the checksum of the whole archive read back
+/// from disk, since the temp fields were patched after being written. A split
+/// archive is read back from its first volume at `archive_path`.
+pub(crate) fn write_footer<W: Write + Seek>(writer: &mut W, archive_path: &Path) -> Result<()> {
+    let total_length = writer.stream_position()? + Footer::size(VERSION);
+    let footer = Footer {
+        total_length,
+        checksum: None,
+    };
+    footer.write(writer)?;
+    writer.flush()?;
+
+    let mut buffer = vec![0u8; BUFFER_SIZE];
+    let mut reader = VolumeReader::open(archive_path)?;
+    let checksum = archive_checksum(&mut reader, total_length - 4, &mut buffer)?;
+
+    writer.write_all(&checksum.to_le_bytes())?;
+    Ok(())
+}
+
+pub(crate) fn write_index_array<W: Write + Seek>(
+    writer: &mut W,
+    inners: &[InnerFile],
+) -> Result<()> {
+    debug!(
+        "Writing index array of {} entries at offset {}",
+        inners.len(),
+        writer.stream_position()?
+    );
     for inner in inners {
         let position = inner.position;
         writer.write_all(&position.to_le_bytes())?;
@@ -224,3 +1829,1146 @@ fn write_index_array(writer: &mut BufWriter<File>, inners: &Vec<InnerFile>) -> R
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{env, sync::Arc, time::Duration};
+
+    use flate2::{Crc, read::GzDecoder};
+
+    use super::*;
+    use crate::{
+        builder::ArchiveBuilder,
+        reader::{ArchiveReader, read_index},
+        testing::{Flaky, TempDir, noise},
+    };
+
+    /// Checks the compressed checksum and size of every entry of `archive`
+    /// against a fresh checksum of the bytes stored for it, and that these
+    /// decode to `contents`.
+    fn check_compressed(archive: &[u8], contents: &[(&str, Vec<u8>)]) {
+        let mut reader = io::Cursor::new(archive);
+        let mut buffer = vec![0u8; BUFFER_SIZE];
+        let header = Header::from_archive(&mut reader, &mut buffer).unwrap();
+        let entries = read_index(&mut reader, &header, &mut buffer).unwrap();
+        assert_eq!(entries.len(), contents.len());
+
+        for entry in entries {
+            let start = (entry.position() + entry.metadata_size()) as usize;
+            let raw = &archive[start..start + entry.compressed_size() as usize];
+            let name = entry.name().to_str().unwrap();
+
+            let mut crc = Crc::new();
+            crc.update(raw);
+            assert_eq!(crc.sum(), entry.compressed_checksum(), "{}", name);
+
+            let decoded = match entry.method() {
+                Method::Store => raw.to_vec(),
+                Method::Gzip => {
+                    let mut decoded = Vec::new();
+                    GzDecoder::new(raw).read_to_end(&mut decoded).unwrap();
+                    decoded
+                }
+                method => panic!("unexpected method {:?}", method),
+            };
+            let (_, original) = contents.iter().find(|(n, _)| *n == name).unwrap();
+            assert_eq!(&decoded, original, "{}", name);
+        }
+    }
+
+    /// Sorted names of the entries of the archive at `path`.
+    fn stored_names(path: &Path) -> Vec<String> {
+        let reader = ArchiveReader::open(path).unwrap();
+        let mut names: Vec<_> = reader
+            .entries()
+            .iter()
+            .map(|entry| entry.name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    fn contents() -> Vec<(&'static str, Vec<u8>)> {
+        vec![
+            ("empty", Vec::new()),
+            ("small.txt", b"compressed checksum".to_vec()),
+            ("text.txt", b"line of text\n".repeat(50_000)),
+            (
+                "mixed.bin",
+                (0..600_000u32).map(|i| (i * 7 % 251) as u8).collect(),
+            ),
+        ]
+    }
+
+    #[test]
+    fn duplicate_names_are_rejected() {
+        let dir = TempDir::new("duplicate-names");
+        let source = dir.write_files("src", &[("one/a.txt", b"1"), ("two/a.txt", b"2")]);
+
+        let listed = PackOptions {
+            files: Some(vec![
+                source.join("one/a.txt"),
+                source.join("two/../one/a.txt"),
+            ]),
+            ..PackOptions::default()
+        };
+        let flattened = PackOptions {
+            transforms: vec!["s|^.*/||".parse().unwrap()],
+            ..PackOptions::default()
+        };
+        for options in [listed, flattened] {
+            let result = pack(source.clone(), Some(dir.join("archives")), &options);
+            assert!(
+                matches!(result, Err(ArchiveError::DuplicateEntry(ref name)) if name == "one/a.txt" || name == "a.txt"),
+                "{:?}",
+                result
+            );
+        }
+        assert!(!dir.join("archives/src.slf").exists());
+
+        let mut builder = ArchiveBuilder::new();
+        builder.add_bytes("a.txt", b"1").unwrap();
+        assert!(matches!(
+            builder.add_bytes("a.txt", b"2"),
+            Err(ArchiveError::DuplicateEntry(_))
+        ));
+    }
+
+    #[test]
+    fn compressed_checksums_match_stored_bytes() {
+        let dir = TempDir::new("compressed-checksums");
+        let source = dir.join("src");
+        fs::create_dir_all(&source).unwrap();
+        for (name, data) in contents() {
+            fs::write(source.join(name), data).unwrap();
+        }
+
+        for jobs in [1, 2] {
+            let options = PackOptions {
+                method: Some(Method::Gzip),
+                jobs,
+                ..PackOptions::default()
+            };
+            pack(source.clone(), Some(dir.join("archives")), &options).unwrap();
+            let archive = fs::read(dir.join("archives/src.slf")).unwrap();
+            check_compressed(&archive, &contents());
+        }
+    }
+
+    #[test]
+    fn built_compressed_checksums_match_stored_bytes() {
+        for method in [Method::Store, Method::Gzip] {
+            let mut builder = ArchiveBuilder::new();
+            builder.method(method);
+            for (name, data) in contents() {
+                builder.add_bytes(name, &data).unwrap();
+            }
+            let archive = builder.finish(Vec::new()).unwrap();
+            check_compressed(&archive, &contents());
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "xz")]
+    fn xz_round_trip() {
+        use crate::unpack::{UnpackOptions, unpack};
+
+        let dir = TempDir::new("xz");
+        let source = dir.join("src");
+        fs::create_dir_all(&source).unwrap();
+        for (name, data) in contents() {
+            fs::write(source.join(name), data).unwrap();
+        }
+
+        let options = PackOptions {
+            method: Some(Method::from_name("xz").unwrap()),
+            ..PackOptions::default()
+        };
+        pack(source, Some(dir.join("archives")), &options).unwrap();
+        let archive = dir.join("archives/src.slf");
+        let mut reader = ArchiveReader::open(&archive).unwrap();
+        reader.verify().unwrap();
+        let mut text = Vec::new();
+        let mut entry = reader.open_entry("text.txt").unwrap();
+        assert_eq!(entry.entry().method(), Method::Xz);
+        entry.read_to_end(&mut text).unwrap();
+        assert!(text == b"line of text\n".repeat(50_000));
+
+        unpack(archive, Some(dir.join("out")), &UnpackOptions::default()).unwrap();
+        for (name, data) in contents() {
+            let unpacked = fs::read(dir.join("out/src").join(name)).unwrap();
+            assert!(unpacked == data, "{}", name);
+        }
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn fifos_are_skipped() {
+        let dir = TempDir::new("fifo");
+        let source = dir.write_files("src", &[("a.txt", b"regular")]);
+        let fifo = source.join("pipe");
+        let status = std::process::Command::new("mkfifo")
+            .arg(&fifo)
+            .status()
+            .unwrap();
+        assert!(status.success());
+
+        pack(source, Some(dir.join("archives")), &PackOptions::default()).unwrap();
+        assert_eq!(stored_names(&dir.join("archives/src.slf")), ["a.txt"]);
+
+        let result = pack(fifo, Some(dir.join("archives")), &PackOptions::default());
+        assert!(matches!(result, Err(ArchiveError::Path(_))), "{:?}", result);
+    }
+
+    #[test]
+    fn sort_by_size_orders_contents() {
+        use crate::unpack::{UnpackOptions, unpack};
+
+        let dir = TempDir::new("sort-by-size");
+        let (large, medium) = (noise(3000), noise(2000));
+        let files: [(&str, &[u8]); 4] = [
+            ("a-large.bin", &large),
+            ("b-small.txt", b"small"),
+            ("c-medium.bin", &medium),
+            ("d-empty", b""),
+        ];
+        let source = dir.write_files("src", &files);
+        let options = PackOptions {
+            sort_by: SortBy::Size,
+            ..PackOptions::default()
+        };
+        pack(source, Some(dir.join("archives")), &options).unwrap();
+
+        let archive = dir.join("archives/src.slf");
+        let mut entries = ArchiveReader::open(&archive).unwrap().entries().to_vec();
+        entries.sort_by_key(InnerFile::position);
+        let sizes: Vec<_> = entries.iter().map(InnerFile::original_size).collect();
+        assert_eq!(sizes, [0, 5, 2000, 3000]);
+
+        unpack(archive, Some(dir.join("out")), &UnpackOptions::default()).unwrap();
+        for (name, contents) in files {
+            assert!(
+                fs::read(dir.join("out/src").join(name)).unwrap() == contents,
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn parallel_pack_spills_large_files() {
+        use crate::unpack::{UnpackOptions, unpack};
+
+        let dir = TempDir::new("spill");
+        let large = noise(1 << 20);
+        let text = b"line of text\n".repeat(10_000);
+        let files: [(&str, &[u8]); 3] = [
+            ("large.bin", &large),
+            ("text.txt", &text),
+            ("small", b"small"),
+        ];
+        let source = dir.write_files("src", &files);
+        let options = PackOptions {
+            jobs: 2,
+            spill_threshold: 4096,
+            ..PackOptions::default()
+        };
+        pack(source, Some(dir.join("archives")), &options).unwrap();
+
+        // spilled contents are removed with their temporary files
+        let left: Vec<_> = fs::read_dir(dir.join("archives")).unwrap().collect();
+        assert_eq!(left.len(), 1);
+
+        let archive = dir.join("archives/src.slf");
+        unpack(archive, Some(dir.join("out")), &UnpackOptions::default()).unwrap();
+        for (name, contents) in files {
+            assert!(
+                fs::read(dir.join("out/src").join(name)).unwrap() == contents,
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn a_panicking_compression_thread_fails_the_pack() {
+        struct Panicking;
+
+        impl ContentFilter for Panicking {
+            fn filter(&self, _: &OsStr, _: &mut dyn Read, _: &mut dyn Write) -> Result<()> {
+                panic!("filter panicked");
+            }
+        }
+
+        let dir = TempDir::new("compression-panic");
+        let large = noise(1 << 20);
+        let files: [(&str, &[u8]); 4] = [
+            ("a.bin", &large),
+            ("b.txt", b"b"),
+            ("panic.txt", b"panic"),
+            ("c.bin", &large),
+        ];
+        let source = dir.write_files("src", &files);
+        let options = PackOptions {
+            jobs: 2,
+            spill_threshold: 4096,
+            filters: vec![FilterRule::new(|name| name == "panic.txt", Panicking)],
+            ..PackOptions::default()
+        };
+        match pack(source, Some(dir.join("archives")), &options) {
+            Err(ArchiveError::Io(message)) => {
+                assert_eq!(message, "Compression thread panicked while packing")
+            }
+            result => panic!("{:?}", result.map(|_| ())),
+        }
+
+        // neither the temporary archive nor spilled files are left behind
+        let left: Vec<_> = fs::read_dir(dir.join("archives")).unwrap().collect();
+        assert!(left.is_empty(), "{:?}", left);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn hard_links_are_stored_once() {
+        use std::os::unix::fs::MetadataExt;
+
+        use crate::unpack::{UnpackOptions, unpack};
+
+        let dir = TempDir::new("hard-links");
+        let text = b"line of text\n".repeat(1000);
+        let source = dir.write_files("src", &[("a.txt", &text)]);
+        fs::hard_link(source.join("a.txt"), source.join("b.txt")).unwrap();
+        let options = PackOptions {
+            hard_links: true,
+            ..PackOptions::default()
+        };
+        pack(source, Some(dir.join("archives")), &options).unwrap();
+
+        let archive = dir.join("archives/src.slf");
+        let entries = ArchiveReader::open(&archive).unwrap().entries().to_vec();
+        let stored: Vec<_> = entries
+            .iter()
+            .filter(|entry| entry.link_target().is_none())
+            .collect();
+        assert_eq!(stored.len(), 1);
+        let link = entries
+            .iter()
+            .find(|entry| entry.link_target().is_some())
+            .unwrap();
+        assert_eq!(link.link_target(), Some(stored[0].name()));
+        assert_eq!(link.original_size(), 0);
+
+        unpack(archive, Some(dir.join("out")), &UnpackOptions::default()).unwrap();
+        let first = fs::metadata(dir.join("out/src/a.txt")).unwrap();
+        let second = fs::metadata(dir.join("out/src/b.txt")).unwrap();
+        assert_eq!((first.dev(), first.ino()), (second.dev(), second.ino()));
+        assert!(fs::read(dir.join("out/src/b.txt")).unwrap() == text);
+    }
+
+    #[test]
+    fn name_overrides_the_archive_name() {
+        let dir = TempDir::new("archive-name");
+        let source = dir.write_files("src", &[("a.txt", b"a")]);
+        fs::create_dir(dir.join("dist")).unwrap();
+        let path = |name: Option<&str>, target: &str| {
+            get_archive_path(
+                &source,
+                &dir.join(target),
+                name.map(OsStr::new),
+                Format::Slf,
+            )
+        };
+
+        assert_eq!(path(None, "dist").unwrap(), dir.join("dist/src.slf"));
+        assert_eq!(
+            path(Some("release"), "dist").unwrap(),
+            dir.join("dist/release.slf")
+        );
+        assert_eq!(
+            path(Some("release.slf"), "dist").unwrap(),
+            dir.join("dist/release.slf")
+        );
+        assert_eq!(
+            path(Some("release"), "dist/full.slf").unwrap(),
+            dir.join("dist/full.slf")
+        );
+        assert!(matches!(
+            path(Some("nested/release"), "dist"),
+            Err(ArchiveError::Usage(_))
+        ));
+
+        let options = PackOptions {
+            name: Some("release".into()),
+            ..PackOptions::default()
+        };
+        pack(source, Some(dir.join("dist")), &options).unwrap();
+        assert_eq!(stored_names(&dir.join("dist/release.slf")), ["a.txt"]);
+    }
+
+    #[test]
+    fn no_recursion_packs_only_top_level_files() {
+        let dir = TempDir::new("no-recursion");
+        let source = dir.write_files(
+            "src",
+            &[
+                ("a.txt", b"a"),
+                ("b.txt", b"b"),
+                ("sub/c.txt", b"c"),
+                ("sub/deeper/d.txt", b"d"),
+            ],
+        );
+        let options = PackOptions {
+            recursive: false,
+            ..PackOptions::default()
+        };
+        pack(source, Some(dir.join("archives")), &options).unwrap();
+        assert_eq!(
+            stored_names(&dir.join("archives/src.slf")),
+            ["a.txt", "b.txt"]
+        );
+    }
+
+    #[test]
+    fn crc32c_archives_round_trip() {
+        use crate::unpack::{UnpackOptions, unpack};
+
+        let dir = TempDir::new("crc32c");
+        let contents = contents();
+        let files: Vec<_> = contents
+            .iter()
+            .map(|(name, contents)| (*name, contents.as_slice()))
+            .collect();
+        let source = dir.write_files("src", &files);
+        let options = PackOptions {
+            checksum: ChecksumAlgorithm::Crc32c,
+            ..PackOptions::default()
+        };
+        pack(source, Some(dir.join("archives")), &options).unwrap();
+
+        let archive = dir.join("archives/src.slf");
+        let mut reader = ArchiveReader::open(&archive).unwrap();
+        assert_eq!(reader.header().checksum, ChecksumAlgorithm::Crc32c);
+        for entry in reader.entries() {
+            let name = entry.name().to_str().unwrap();
+            let (_, original) = contents.iter().find(|(n, _)| *n == name).unwrap();
+            assert_eq!(
+                entry.original_checksum(),
+                crc32c::crc32c(original),
+                "{}",
+                name
+            );
+        }
+        reader.verify().unwrap();
+
+        unpack(archive, Some(dir.join("out")), &UnpackOptions::default()).unwrap();
+        for (name, original) in &contents {
+            assert!(
+                fs::read(dir.join("out/src").join(name)).unwrap() == *original,
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn empty_packs_need_allow_empty() {
+        let dir = TempDir::new("allow-empty");
+        let empty = dir.join("empty");
+        fs::create_dir(&empty).unwrap();
+        // there are no exclusion globs, the size and hidden filters drop everything
+        let filtered = dir.write_files("filtered", &[("a.txt", b"a"), (".hidden", b"hidden")]);
+        let excluding = PackOptions {
+            exclude_larger_than: Some(0),
+            exclude_hidden: true,
+            ..PackOptions::default()
+        };
+
+        for (source, options) in [(&empty, PackOptions::default()), (&filtered, excluding)] {
+            let archives = dir.join("archives");
+            let result = pack(source.clone(), Some(archives.clone()), &options);
+            assert!(matches!(result, Err(ArchiveError::Path(_))), "{:?}", result);
+            assert!(!archives.exists());
+
+            let options = PackOptions {
+                allow_empty: true,
+                ..options
+            };
+            pack(source.clone(), Some(archives.clone()), &options).unwrap();
+            let name = source.file_name().unwrap().to_str().unwrap();
+            let archive = archives.join(format!("{}.slf", name));
+            assert!(stored_names(&archive).is_empty());
+            fs::remove_dir_all(archives).unwrap();
+        }
+    }
+
+    #[test]
+    fn base_keeps_the_path_from_it() {
+        let dir = TempDir::new("base");
+        let source = dir.write_files(
+            "project/src",
+            &[("main.rs", b"fn main() {}"), ("lib/mod.rs", b"")],
+        );
+        fs::create_dir(dir.join("other")).unwrap();
+        let pack_with = |base: &str, archives: &str| {
+            let options = PackOptions {
+                base: Some(dir.join(base)),
+                ..PackOptions::default()
+            };
+            pack(source.clone(), Some(dir.join(archives)), &options)
+                .map(|_| stored_names(&dir.join(archives).join("src.slf")))
+        };
+
+        assert_eq!(
+            pack_with("project/src", "equal").unwrap(),
+            ["lib/mod.rs", "main.rs"]
+        );
+        assert_eq!(
+            pack_with("project", "parent").unwrap(),
+            ["src/lib/mod.rs", "src/main.rs"]
+        );
+        assert_eq!(
+            pack_with(".", "grandparent").unwrap(),
+            ["project/src/lib/mod.rs", "project/src/main.rs"]
+        );
+        let unrelated = pack_with("other", "unrelated");
+        assert!(
+            matches!(unrelated, Err(ArchiveError::Path(_))),
+            "{:?}",
+            unrelated
+        );
+        assert!(!dir.join("unrelated/src.slf").exists());
+    }
+
+    #[test]
+    fn full_devices_are_named() {
+        let mut writer = Flaky::new(Vec::new(), &[io::ErrorKind::StorageFull]);
+        let error = ArchiveError::from(writer.write_all(b"contents").unwrap_err());
+        assert!(matches!(error, ArchiveError::OutOfSpace(_)), "{:?}", error);
+        match named_error(error, Path::new("dist/src.slf")) {
+            ArchiveError::Io(message) => {
+                assert_eq!(message, "Out of space while writing 'dist/src.slf'")
+            }
+            error => panic!("{:?}", error),
+        }
+    }
+
+    /// Writes into `/dev/full` through a link where the partial archive
+    /// goes, so every write fails like on a full device.
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn failed_writes_remove_the_partial_archive() {
+        let dir = TempDir::new("out-of-space");
+        let source = dir.write_files("src", &[("text.txt", &b"line of text\n".repeat(10_000))]);
+        let archives = dir.join("archives");
+        fs::create_dir(&archives).unwrap();
+        let partial = temp_path(&archives.join("src.slf"));
+        std::os::unix::fs::symlink("/dev/full", &partial).unwrap();
+
+        let result = pack(source, Some(archives.clone()), &PackOptions::default());
+        match result {
+            Err(ArchiveError::Io(message)) => assert!(
+                message.starts_with("Out of space while writing") && message.contains("src.slf"),
+                "{}",
+                message
+            ),
+            result => panic!("{:?}", result),
+        }
+        assert!(fs::symlink_metadata(&partial).is_err());
+        assert!(!archives.join("src.slf").exists());
+    }
+
+    #[test]
+    fn compression_stats_group_by_extension() {
+        let dir = TempDir::new("compression-stats");
+        let (text, image) = (b"line of text\n".repeat(1000), noise(20_000));
+        let source = dir.write_files(
+            "src",
+            &[
+                ("a.txt", &text),
+                ("sub/B.TXT", &text),
+                ("image.png", &image),
+                ("README", b"read me"),
+            ],
+        );
+        let stats = pack(source, Some(dir.join("archives")), &PackOptions::default())
+            .unwrap()
+            .unwrap();
+
+        let groups: Vec<_> = stats
+            .groups
+            .iter()
+            .map(|group| {
+                (
+                    group.extension.to_str().unwrap(),
+                    group.entries,
+                    group.original_size,
+                )
+            })
+            .collect();
+        // the incompressible image takes up the most
+        assert_eq!(
+            groups,
+            [
+                ("png", 1, 20_000),
+                ("txt", 2, 2 * text.len() as u64),
+                ("", 1, 7)
+            ]
+        );
+        assert!(stats.groups[1].ratio().unwrap() < 0.1);
+        assert!(stats.groups[0].ratio().unwrap() >= 1.0);
+
+        let report = stats.to_string();
+        let extensions: Vec<_> = report
+            .lines()
+            .map(|line| line.split_whitespace().next().unwrap())
+            .collect();
+        assert_eq!(extensions, ["Extension", ".png", ".txt", "(none)"]);
+    }
+
+    #[test]
+    fn failed_pack_keeps_the_previous_archive() {
+        let dir = TempDir::new("atomic-pack");
+        let text = b"line of text\n".repeat(10_000);
+        let source = dir.write_files("src", &[("a.txt", &text), ("b.txt", &text)]);
+        let archives = dir.join("archives");
+        pack(
+            source.clone(),
+            Some(archives.clone()),
+            &PackOptions::default(),
+        )
+        .unwrap();
+        let archive = archives.join("src.slf");
+        let previous = fs::read(&archive).unwrap();
+
+        // fails after the first entry is written, before the rename
+        fs::write(source.join("c.txt"), b"added").unwrap();
+        let cancel = CancelFlag::default();
+        let flag = cancel.clone();
+        let options = PackOptions {
+            cancel: Some(cancel),
+            progress: Some(Arc::new(move |_| flag.store(true, Ordering::Relaxed))),
+            ..PackOptions::default()
+        };
+        let result = pack(source.clone(), Some(archives.clone()), &options);
+        assert!(
+            matches!(result, Err(ArchiveError::Cancelled)),
+            "{:?}",
+            result
+        );
+        assert!(fs::read(&archive).unwrap() == previous);
+        assert!(!temp_path(&archive).exists());
+
+        pack(source, Some(archives.clone()), &PackOptions::default()).unwrap();
+        assert_eq!(stored_names(&archive), ["a.txt", "b.txt", "c.txt"]);
+        assert_eq!(fs::read_dir(&archives).unwrap().count(), 1);
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn hidden_files_are_skipped() {
+        let dir = TempDir::new("exclude-hidden");
+        let files: [(&str, &[u8]); 5] = [
+            ("visible.txt", b"visible"),
+            (".env", b"secret"),
+            (".git/config", b"config"),
+            ("sub/.cache/data", b"data"),
+            ("sub/kept.txt", b"kept"),
+        ];
+        let source = dir.write_files("src", &files);
+        pack(
+            source.clone(),
+            Some(dir.join("all")),
+            &PackOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(stored_names(&dir.join("all/src.slf")).len(), files.len());
+
+        let options = PackOptions {
+            exclude_hidden: true,
+            ..PackOptions::default()
+        };
+        pack(source, Some(dir.join("visible")), &options).unwrap();
+        assert_eq!(
+            stored_names(&dir.join("visible/src.slf")),
+            ["sub/kept.txt", "visible.txt"]
+        );
+
+        // a hidden source itself is packed, only what's hidden inside it isn't
+        let hidden = dir.write_files(".hidden", &[("a.txt", b"a"), (".b", b"b")]);
+        pack(hidden, Some(dir.join("hidden")), &options).unwrap();
+        assert_eq!(stored_names(&dir.join("hidden/.hidden.slf")), ["a.txt"]);
+    }
+
+    #[test]
+    fn reproducible_output_is_identical() {
+        let dir = TempDir::new("reproducible");
+        let files: [(&str, &[u8]); 3] = [
+            ("a.txt", b"first"),
+            ("sub/b.txt", b"second"),
+            ("sub/deeper/c.txt", b"third"),
+        ];
+        let mut reversed = files;
+        reversed.reverse();
+        let one = dir.write_files("one", &files);
+        let two = dir.write_files("two", &reversed);
+        let mtime = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+        File::options()
+            .write(true)
+            .open(two.join("a.txt"))
+            .unwrap()
+            .set_modified(mtime)
+            .unwrap();
+
+        let options = PackOptions {
+            reproducible: true,
+            ..PackOptions::default()
+        };
+        for source in [one, two] {
+            pack(source, Some(dir.join("archives")), &options).unwrap();
+        }
+        let one = fs::read(dir.join("archives/one.slf")).unwrap();
+        let two = fs::read(dir.join("archives/two.slf")).unwrap();
+        assert!(one == two);
+    }
+
+    #[test]
+    fn tiny_files_are_stored() {
+        let dir = TempDir::new("compress-threshold");
+        let text = b"line of text\n".repeat(100);
+        let source = dir.write_files("src", &[("tiny.txt", b"hello"), ("text.txt", &text)]);
+        pack(source, Some(dir.join("archives")), &PackOptions::default()).unwrap();
+
+        let reader = ArchiveReader::open(dir.join("archives/src.slf")).unwrap();
+        for entry in reader.entries() {
+            let expected = if entry.original_size() < DEFAULT_COMPRESS_THRESHOLD {
+                Method::Store
+            } else {
+                Method::Gzip
+            };
+            assert_eq!(entry.method(), expected, "{:?}", entry.name());
+            assert!(entry.compressed_size() <= entry.original_size());
+        }
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn non_utf8_names_round_trip() {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+        use crate::unpack::{UnpackOptions, unpack};
+
+        let name = OsStr::from_bytes(b"invalid \xff\xfe utf-8");
+        let dir = TempDir::new("non-utf8");
+        let source = dir.write_files("src", &[("valid.txt", b"valid")]);
+        fs::write(source.join(name), b"invalid").unwrap();
+
+        pack(source, Some(dir.join("archives")), &PackOptions::default()).unwrap();
+        let archive = dir.join("archives/src.slf");
+        let reader = ArchiveReader::open(&archive).unwrap();
+        assert!(reader.contains(name));
+
+        unpack(archive, Some(dir.join("out")), &UnpackOptions::default()).unwrap();
+        assert_eq!(
+            fs::read(dir.join("out/src").join(name)).unwrap(),
+            b"invalid"
+        );
+        assert_eq!(fs::read(dir.join("out/src/valid.txt")).unwrap(), b"valid");
+    }
+
+    #[test]
+    fn dot_sources_are_named_after_their_directory() {
+        let name = |source: &str| {
+            let source = resolve_source(PathBuf::from(source)).unwrap();
+            get_archive_name(&source).unwrap()
+        };
+        let cwd = env::current_dir().unwrap();
+        let parent = cwd.parent().unwrap();
+        assert_eq!(name("."), cwd.file_name().unwrap());
+        assert_eq!(name("./"), cwd.file_name().unwrap());
+        assert_eq!(name(".."), parent.file_name().unwrap());
+        assert_eq!(name("../sibling"), Path::new("sibling"));
+        assert_eq!(name("sibling/"), Path::new("sibling"));
+
+        let dir = TempDir::new("dot-sources");
+        dir.write_files("project", &[("a.txt", b"a")]);
+        fs::create_dir(dir.join("other")).unwrap();
+        for (i, source) in ["project/.", "project/", "other/../project"]
+            .iter()
+            .enumerate()
+        {
+            let target = dir.join(format!("archives-{}", i));
+            pack(
+                dir.join(source),
+                Some(target.clone()),
+                &PackOptions::default(),
+            )
+            .unwrap();
+            assert_eq!(
+                stored_names(&target.join("project.slf")),
+                ["a.txt"],
+                "{}",
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn cancelling_removes_partial_archive() {
+        let dir = TempDir::new("cancel-pack");
+        let text = b"line of text\n".repeat(10_000);
+        let source = dir.write_files(
+            "src",
+            &[("a.txt", &text), ("b.txt", &text), ("c.txt", &text)],
+        );
+        fs::create_dir(dir.join("archives")).unwrap();
+
+        let cancel = CancelFlag::default();
+        let flag = cancel.clone();
+        let options = PackOptions {
+            cancel: Some(cancel),
+            progress: Some(Arc::new(move |_| flag.store(true, Ordering::Relaxed))),
+            ..PackOptions::default()
+        };
+        let result = pack(source, Some(dir.join("archives")), &options);
+        assert!(
+            matches!(result, Err(ArchiveError::Cancelled)),
+            "{:?}",
+            result
+        );
+        assert_eq!(fs::read_dir(dir.join("archives")).unwrap().count(), 0);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn size_read_is_stored() {
+        use crate::unpack::{UnpackOptions, unpack};
+
+        // reported as empty by its metadata
+        let source = PathBuf::from("/proc/version");
+        let contents = fs::read(&source).unwrap();
+        assert_eq!(fs::metadata(&source).unwrap().len(), 0);
+
+        let dir = TempDir::new("size-read");
+        for solid in [false, true] {
+            let options = PackOptions {
+                solid,
+                ..PackOptions::default()
+            };
+            pack(source.clone(), Some(dir.join("archives")), &options).unwrap();
+            let archive = dir.join("archives/version.slf");
+            let reader = ArchiveReader::open(&archive).unwrap();
+            assert_eq!(reader.entries()[0].original_size(), contents.len() as u64);
+
+            let target = dir.join(format!("out-{}", solid));
+            unpack(archive, Some(target.clone()), &UnpackOptions::default()).unwrap();
+            assert_eq!(fs::read(target.join("version")).unwrap(), contents);
+        }
+    }
+
+    #[test]
+    fn split_volumes_round_trip() {
+        use crate::unpack::{UnpackOptions, unpack};
+
+        let dir = TempDir::new("split");
+        // barely compressible, so the archive spans several volumes
+        let noise = noise(20_000);
+        let source = dir.write_files("src", &[("noise.bin", &noise), ("small.txt", b"small")]);
+        let options = PackOptions {
+            split: Some(4096),
+            ..PackOptions::default()
+        };
+        pack(source, Some(dir.join("archives")), &options).unwrap();
+
+        assert!(!dir.join("archives/src.slf").exists());
+        let volumes: Vec<_> = (1..)
+            .map(|i| dir.join(format!("archives/src.slf.{:03}", i)))
+            .take_while(|path| path.exists())
+            .collect();
+        assert!(volumes.len() >= 5, "{} volumes", volumes.len());
+        for volume in &volumes {
+            assert!(fs::metadata(volume).unwrap().len() <= 4096);
+        }
+        let header = ArchiveReader::open(&volumes[0]).unwrap().header().clone();
+        assert_eq!(header.volume_index, 1);
+        assert_eq!(header.volume_count as usize, volumes.len());
+
+        let first = volumes[0].clone();
+        ArchiveReader::open(&first).unwrap().verify().unwrap();
+        unpack(first, Some(dir.join("out")), &UnpackOptions::default()).unwrap();
+        assert!(fs::read(dir.join("out/src/noise.bin")).unwrap() == noise);
+        assert_eq!(fs::read(dir.join("out/src/small.txt")).unwrap(), b"small");
+    }
+
+    #[test]
+    fn resplitting_into_fewer_volumes_removes_stale_ones() {
+        use crate::unpack::{UnpackOptions, unpack};
+
+        let dir = TempDir::new("resplit");
+        // the copy is stored as references to chunks of earlier volumes
+        let noise = noise(20_000);
+        let source = dir.write_files("src", &[("a.bin", &noise), ("b.bin", &noise)]);
+        let volume_count = || {
+            (1..)
+                .take_while(|i| dir.join(format!("archives/src.slf.{:03}", i)).exists())
+                .count()
+        };
+
+        let options = PackOptions {
+            split: Some(2048),
+            ..PackOptions::default()
+        };
+        pack(source.clone(), Some(dir.join("archives")), &options).unwrap();
+        let before = volume_count();
+
+        let options = PackOptions {
+            split: Some(8192),
+            dedup: true,
+            ..PackOptions::default()
+        };
+        pack(source, Some(dir.join("archives")), &options).unwrap();
+        let after = volume_count();
+        assert!(after < before, "{} volumes after {}", after, before);
+        assert!(!dir.join("archives/src.slf.tmp.001").exists());
+
+        let first = dir.join("archives/src.slf.001");
+        let header = ArchiveReader::open(&first).unwrap().header().clone();
+        assert_eq!(header.volume_count as usize, after);
+        unpack(first, Some(dir.join("out")), &UnpackOptions::default()).unwrap();
+        assert!(fs::read(dir.join("out/src/a.bin")).unwrap() == noise);
+        assert!(fs::read(dir.join("out/src/b.bin")).unwrap() == noise);
+    }
+
+    #[test]
+    fn methods_follow_extensions() {
+        let dir = TempDir::new("extension-methods");
+        let text = b"line of text\n".repeat(1000);
+        let image = noise(5000);
+        let source = dir.write_files("src", &[("notes.txt", &text), ("image.png", &image)]);
+        pack(
+            source.clone(),
+            Some(dir.join("auto")),
+            &PackOptions::default(),
+        )
+        .unwrap();
+        let options = PackOptions {
+            method: Some(Method::Gzip),
+            ..PackOptions::default()
+        };
+        pack(source, Some(dir.join("forced")), &options).unwrap();
+
+        for (archive, png) in [("auto", Method::Store), ("forced", Method::Gzip)] {
+            let reader = ArchiveReader::open(dir.join(archive).join("src.slf")).unwrap();
+            let method = |name: &str| {
+                let entry = reader.entries().iter().find(|e| e.name() == name);
+                entry.unwrap().method()
+            };
+            assert_eq!(method("image.png"), png, "{}", archive);
+            assert_eq!(method("notes.txt"), Method::Gzip, "{}", archive);
+        }
+    }
+
+    #[test]
+    fn dedup_stores_shared_chunks_once() {
+        use crate::{
+            dedup::CHUNK_SIZE,
+            unpack::{UnpackOptions, unpack},
+        };
+
+        let dir = TempDir::new("dedup");
+        let prefix = noise(8 * CHUNK_SIZE);
+        let first = [prefix.as_slice(), b"first tail"].concat();
+        let second = [prefix.as_slice(), b"second, longer tail"].concat();
+        let source = dir.write_files("src", &[("first.bin", &first), ("second.bin", &second)]);
+        let options = PackOptions {
+            dedup: true,
+            ..PackOptions::default()
+        };
+        pack(source, Some(dir.join("archives")), &options).unwrap();
+
+        let archive = dir.join("archives/src.slf");
+        let size = fs::metadata(&archive).unwrap().len();
+        assert!(
+            size < (first.len() + second.len()) as u64 * 3 / 4,
+            "{} bytes",
+            size
+        );
+
+        unpack(archive, Some(dir.join("out")), &UnpackOptions::default()).unwrap();
+        assert!(fs::read(dir.join("out/src/first.bin")).unwrap() == first);
+        assert!(fs::read(dir.join("out/src/second.bin")).unwrap() == second);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn symlink_loops_are_reported() {
+        let dir = TempDir::new("symlink-loop");
+        let source = dir.write_files("src", &[("sub/a.txt", b"a")]);
+        std::os::unix::fs::symlink("..", source.join("sub/up")).unwrap();
+
+        let options = PackOptions {
+            dereference: true,
+            ..PackOptions::default()
+        };
+        let result = pack(source.clone(), Some(dir.join("archives")), &options);
+        assert!(
+            matches!(&result, Err(ArchiveError::Path(message)) if message.contains("loop")),
+            "{:?}",
+            result
+        );
+
+        // without following it the link isn't a problem
+        pack(source, Some(dir.join("archives")), &PackOptions::default()).unwrap();
+    }
+
+    #[test]
+    fn resumes_after_two_of_three_entries() {
+        let dir = TempDir::new("resume");
+        let text = b"line of text\n".repeat(1000);
+        let source = dir.write_files(
+            "src",
+            &[("a.txt", &text), ("b.txt", &text), ("c.txt", &text)],
+        );
+        pack(
+            source.clone(),
+            Some(dir.join("archives")),
+            &PackOptions::default(),
+        )
+        .unwrap();
+
+        // an interrupted pack leaves the index offset unset and the last
+        // entry cut short
+        let archive = dir.join("archives/src.slf");
+        let last = ArchiveReader::open(&archive).unwrap().entries()[2].position() as usize;
+        let mut partial = fs::read(&archive).unwrap();
+        partial.truncate(last + 20);
+        partial[10..18].fill(0);
+        fs::write(temp_path(&archive), partial).unwrap();
+        fs::remove_file(&archive).unwrap();
+
+        // packed entries are kept, so this change doesn't make it in
+        fs::write(source.join("a.txt"), b"changed").unwrap();
+        let options = PackOptions {
+            resume: true,
+            ..PackOptions::default()
+        };
+        pack(source, Some(dir.join("archives")), &options).unwrap();
+        assert!(!temp_path(&archive).exists());
+
+        let mut reader = ArchiveReader::open(&archive).unwrap();
+        reader.verify().unwrap();
+        assert_eq!(reader.entries().len(), 3);
+        for name in ["a.txt", "b.txt", "c.txt"] {
+            let mut contents = Vec::new();
+            reader
+                .open_entry(name)
+                .unwrap()
+                .read_to_end(&mut contents)
+                .unwrap();
+            assert!(contents == text, "{}", name);
+        }
+    }
+
+    #[test]
+    fn dictionary_packs_small_files_smaller() {
+        use crate::unpack::{UnpackOptions, unpack};
+
+        let dir = TempDir::new("dictionary");
+        let configs: Vec<_> = (0..50)
+            .map(|i| {
+                let json = format!(
+                    "{{\"name\": \"service-{}\", \"enabled\": true, \"replicas\": {}, \"image\": \"registry.example.com/service:latest\"}}",
+                    i,
+                    i % 5
+                );
+                (format!("config-{}.json", i), json)
+            })
+            .collect();
+        let files: Vec<_> = configs
+            .iter()
+            .map(|(name, json)| (name.as_str(), json.as_bytes()))
+            .collect();
+        let source = dir.write_files("src", &files);
+
+        let dictionary = configs[0].1.repeat(2).into_bytes();
+        let mut sizes = Vec::new();
+        for (archives, dictionary) in [("plain", None), ("dictionary", Some(dictionary))] {
+            let options = PackOptions {
+                dictionary,
+                ..PackOptions::default()
+            };
+            pack(source.clone(), Some(dir.join(archives)), &options).unwrap();
+            let archive = dir.join(archives).join("src.slf");
+            sizes.push(fs::metadata(&archive).unwrap().len());
+
+            let target = dir.join(archives).join("out");
+            unpack(archive, Some(target.clone()), &UnpackOptions::default()).unwrap();
+            for (name, json) in &files {
+                assert_eq!(&fs::read(target.join("src").join(name)).unwrap(), json);
+            }
+        }
+        assert!(sizes[1] < sizes[0], "{:?}", sizes);
+    }
+
+    #[test]
+    fn size_filters_exclude_files() {
+        let dir = TempDir::new("size-filters");
+        let large = vec![b'x'; 10_000];
+        let source = dir.write_files(
+            "src",
+            &[
+                ("empty", b""),
+                ("medium.txt", b"medium"),
+                ("large.bin", &large),
+            ],
+        );
+
+        let cases = [
+            (Some(1), None, vec!["large.bin", "medium.txt"]),
+            (None, Some(1000), vec!["empty", "medium.txt"]),
+            (Some(1), Some(1000), vec!["medium.txt"]),
+        ];
+        for (i, (smaller, larger, expected)) in cases.into_iter().enumerate() {
+            let options = PackOptions {
+                exclude_smaller_than: smaller,
+                exclude_larger_than: larger,
+                ..PackOptions::default()
+            };
+            let target = dir.join(format!("archives-{}", i));
+            pack(source.clone(), Some(target.clone()), &options).unwrap();
+            assert_eq!(stored_names(&target.join("src.slf")), expected);
+        }
+    }
+
+    #[test]
+    #[cfg(all(unix, feature = "xattrs"))]
+    fn xattrs_round_trip() {
+        use crate::unpack::{UnpackOptions, unpack};
+
+        let dir = TempDir::new("xattrs");
+        let source = dir.write_files("src", &[("a.txt", b"a"), ("b.txt", b"b")]);
+        if let Err(e) = xattr::set(source.join("a.txt"), "user.test", b"value") {
+            // e.g. tmpfs without user attributes
+            assert_eq!(e.kind(), io::ErrorKind::Unsupported);
+            return;
+        }
+
+        let options = PackOptions {
+            xattrs: true,
+            ..PackOptions::default()
+        };
+        pack(source, Some(dir.join("archives")), &options).unwrap();
+        let options = UnpackOptions {
+            xattrs: true,
+            ..UnpackOptions::default()
+        };
+        let archive = dir.join("archives/src.slf");
+        unpack(archive, Some(dir.join("out")), &options).unwrap();
+
+        let value = xattr::get(dir.join("out/src/a.txt"), "user.test").unwrap();
+        assert_eq!(value.as_deref(), Some(b"value".as_slice()));
+        assert_eq!(
+            xattr::get(dir.join("out/src/b.txt"), "user.test").unwrap(),
+            None
+        );
+    }
+}
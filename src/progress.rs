@@ -0,0 +1,196 @@
+use std::{
+    ffi::{OsStr, OsString},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// Called by `pack` and `unpack` after every entry, possibly from several
+/// threads, but one call at a time, so the counters never go backwards.
+pub type ProgressCallback = Arc<dyn Fn(Progress) + Send + Sync>;
+
+/// Operation a `Progress` is reported by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Pack,
+    Unpack,
+}
+
+impl Phase {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Pack => "pack",
+            Self::Unpack => "unpack",
+        }
+    }
+}
+
+/// Entries, original and compressed bytes processed so far.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Progress {
+    pub phase: Phase,
+    /// Name of the entry just processed.
+    pub name: OsString,
+    pub entries: u64,
+    pub total_entries: u64,
+    pub bytes: u64,
+    /// Unknown when the archive is read without its index, e.g. as a stream.
+    pub total_bytes: Option<u64>,
+    /// Bytes of the archive written or read for the entries, not counting
+    /// entries of solid archives, which share a single compressed block.
+    pub compressed_bytes: u64,
+    /// Known when unpacking an archive with an index which isn't solid, the
+    /// archive size isn't known before packing.
+    pub total_compressed_bytes: Option<u64>,
+}
+
+impl Progress {
+    /// Share of the work done: by the compressed bytes when unpacking, since
+    /// reading the archive is what takes the time, by the original ones
+    /// otherwise. `None` when the total is unknown or 0.
+    pub fn fraction(&self) -> Option<f64> {
+        let (done, total) = match (self.phase, self.total_compressed_bytes) {
+            (Phase::Unpack, Some(total)) => (self.compressed_bytes, total),
+            _ => (self.bytes, self.total_bytes?),
+        };
+        (total > 0).then(|| (done as f64 / total as f64).min(1.0))
+    }
+
+    /// Time left at the average speed so far, `elapsed` being the time since
+    /// the operation started.
+    pub fn eta(&self, elapsed: Duration) -> Option<Duration> {
+        let fraction = self.fraction().filter(|&fraction| fraction > 0.0)?;
+        Some(elapsed.mul_f64((1.0 - fraction) / fraction))
+    }
+}
+
+/// Counts processed entries and reports them to the callback, if any.
+pub(crate) struct ProgressTracker {
+    callback: Option<ProgressCallback>,
+    phase: Phase,
+    total_entries: u64,
+    total_bytes: Option<u64>,
+    total_compressed_bytes: Option<u64>,
+    /// Entries, original and compressed bytes so far.
+    counters: Mutex<(u64, u64, u64)>,
+}
+
+impl ProgressTracker {
+    pub(crate) fn new(
+        callback: Option<&ProgressCallback>,
+        phase: Phase,
+        total_entries: u64,
+        total_bytes: Option<u64>,
+        total_compressed_bytes: Option<u64>,
+    ) -> Self {
+        Self {
+            callback: callback.cloned(),
+            phase,
+            total_entries,
+            total_bytes,
+            total_compressed_bytes,
+            counters: Mutex::new((0, 0, 0)),
+        }
+    }
+
+    /// Accounts the entry `name` of `bytes` original and `compressed` bytes.
+    pub(crate) fn advance(&self, name: &OsStr, bytes: u64, compressed: u64) {
+        let Some(callback) = &self.callback else {
+            return;
+        };
+
+        // held during the call, so a thread can't report older counters
+        // after another reported newer ones
+        let mut counters = self.counters.lock().unwrap();
+        counters.0 += 1;
+        counters.1 += bytes;
+        counters.2 += compressed;
+        let (entries, bytes, compressed_bytes) = *counters;
+        callback(Progress {
+            phase: self.phase,
+            name: name.to_os_string(),
+            entries,
+            total_entries: self.total_entries,
+            bytes,
+            total_bytes: self.total_bytes,
+            compressed_bytes,
+            total_compressed_bytes: self.total_compressed_bytes,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        pack::{PackOptions, pack},
+        testing::{TempDir, noise},
+        unpack::{UnpackOptions, unpack},
+    };
+
+    /// Callback collecting every report.
+    fn recorder() -> (ProgressCallback, Arc<Mutex<Vec<Progress>>>) {
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let recorded = reports.clone();
+        let callback: ProgressCallback =
+            Arc::new(move |progress| recorded.lock().unwrap().push(progress));
+        (callback, reports)
+    }
+
+    /// Checks that the counters never go backwards and end at the totals.
+    fn check_monotonic(reports: &[Progress], phase: Phase) {
+        assert_eq!(reports.len(), 4);
+        for pair in reports.windows(2) {
+            assert!(pair[1].entries > pair[0].entries, "{:?}", pair);
+            assert!(pair[1].bytes >= pair[0].bytes, "{:?}", pair);
+            assert!(
+                pair[1].compressed_bytes >= pair[0].compressed_bytes,
+                "{:?}",
+                pair
+            );
+        }
+        let last = reports.last().unwrap();
+        assert!(reports.iter().all(|report| report.phase == phase));
+        assert_eq!(last.entries, last.total_entries);
+        assert_eq!(Some(last.bytes), last.total_bytes);
+        assert_eq!(last.fraction(), Some(1.0));
+    }
+
+    #[test]
+    fn counters_advance_monotonically() {
+        let dir = TempDir::new("progress");
+        let (text, data) = (b"line of text\n".repeat(5000), noise(30_000));
+        let source = dir.write_files(
+            "src",
+            &[
+                ("a.txt", &text),
+                ("b.bin", &data),
+                ("c.txt", b"small"),
+                ("d", b""),
+            ],
+        );
+
+        let (callback, reports) = recorder();
+        let options = PackOptions {
+            progress: Some(callback),
+            ..PackOptions::default()
+        };
+        pack(source, Some(dir.join("archives")), &options).unwrap();
+        check_monotonic(&reports.lock().unwrap(), Phase::Pack);
+
+        let (callback, reports) = recorder();
+        let options = UnpackOptions {
+            progress: Some(callback),
+            ..UnpackOptions::default()
+        };
+        unpack(
+            dir.join("archives/src.slf"),
+            Some(dir.join("out")),
+            &options,
+        )
+        .unwrap();
+        let reports = reports.lock().unwrap();
+        check_monotonic(&reports, Phase::Unpack);
+        let last = reports.last().unwrap();
+        assert_eq!(Some(last.compressed_bytes), last.total_compressed_bytes);
+    }
+}
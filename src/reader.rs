@@ -0,0 +1,812 @@
+use std::{
+    collections::HashMap,
+    ffi::{OsStr, OsString},
+    fmt,
+    io::{self, Read, Seek, SeekFrom, Take, Write},
+    ops::Range,
+    path::Path,
+};
+
+use flate2::{CrcReader, read::MultiGzDecoder};
+#[cfg(feature = "xz")]
+use xz2::read::XzDecoder;
+
+use crate::{
+    BUFFER_SIZE, DEFAULT_BUFFER_CAPACITY, Footer, Header, InnerFile,
+    checksum::{Checksum, ChecksumAlgorithm, ChecksumWriter},
+    compressor::{DeflateReader, Method},
+    dedup::ChunkDecoder,
+    entry_capacity,
+    error::{ArchiveError, Result},
+    normalize_path,
+    source::ArchiveSource,
+};
+
+/// Size of the windows damaged contents are re-read in, see
+/// `ArchiveReader::verify_deep`.
+pub const DEEP_WINDOW: u64 = 64 * 1024;
+
+/// Entry failing verification, see `ArchiveReader::verify_deep`.
+#[derive(Debug, Clone)]
+pub struct DamagedEntry {
+    /// Name of the entry, or of every entry sharing a solid block.
+    pub names: Vec<OsString>,
+    /// Offsets of the compressed contents within the archive. For chunked
+    /// entries these are their chunk records, the chunks may lie elsewhere.
+    pub contents: Range<u64>,
+    pub error: ArchiveError,
+    /// Windows of `contents` which read differently on two passes, pointing
+    /// at failing storage rather than at damage written to it.
+    pub unstable: Vec<Range<u64>>,
+}
+
+impl fmt::Display for DamagedEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: Vec<_> = self.names.iter().map(|n| n.to_string_lossy()).collect();
+        write!(
+            f,
+            "'{}' at archive bytes {}..{}: {}",
+            names.join("', '"),
+            self.contents.start,
+            self.contents.end,
+            self.error
+        )?;
+        for window in &self.unstable {
+            write!(
+                f,
+                "\n  bytes {}..{} read differently on two passes",
+                window.start, window.end
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Random access reader over an archive, driven by its index array.
+pub struct ArchiveReader<R: Read + Seek> {
+    reader: R,
+    header: Header,
+    footer: Option<Footer>,
+    entries: Vec<InnerFile>,
+    /// Entry name to its index in `entries`, the first one wins for duplicates.
+    names: HashMap<OsString, usize>,
+    buffer: Vec<u8>,
+}
+
+impl ArchiveReader<ArchiveSource> {
+    /// Opens an archive file, or all volumes of a split archive when given
+    /// the first one. The file is mapped into memory with the `mmap` feature.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::new(ArchiveSource::open(path, DEFAULT_BUFFER_CAPACITY)?)
+    }
+}
+
+impl<R: Read + Seek> ArchiveReader<R> {
+    pub fn new(mut reader: R) -> Result<Self> {
+        let mut buffer = vec![0u8; BUFFER_SIZE];
+
+        let header = Header::from_archive(&mut reader, &mut buffer)?;
+        let footer = if header.has_footer() {
+            Some(Footer::from_archive(
+                &mut reader,
+                &mut buffer,
+                header.version,
+            )?)
+        } else {
+            None
+        };
+        let entries = read_index(&mut reader, &header, &mut buffer)?;
+
+        let mut names = HashMap::with_capacity(entries.len());
+        for (i, entry) in entries.iter().enumerate() {
+            names.entry(entry.name().to_os_string()).or_insert(i);
+        }
+
+        Ok(Self {
+            reader,
+            header,
+            footer,
+            entries,
+            names,
+            buffer,
+        })
+    }
+
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    pub fn footer(&self) -> Option<&Footer> {
+        self.footer.as_ref()
+    }
+
+    /// Checks the checksum covering the whole archive, if it has one.
+    pub fn verify_archive_checksum(&mut self) -> Result<()> {
+        match &self.footer {
+            Some(footer) => footer.verify_checksum(&mut self.reader, &mut self.buffer),
+            None => Ok(()),
+        }
+    }
+
+    pub fn entries(&self) -> &[InnerFile] {
+        &self.entries
+    }
+
+    /// Whether an entry named `name` exists, looked up in the index only.
+    /// `.` components and repeated separators in `name` are ignored.
+    pub fn contains<N: AsRef<OsStr>>(&self, name: N) -> bool {
+        let name = name.as_ref();
+        self.names.contains_key(name)
+            || self
+                .names
+                .contains_key(normalize_path(Path::new(name)).as_os_str())
+    }
+
+    /// Returns a reader decompressing the entry on demand. Checksums and size
+    /// are verified once the reader reaches EOF. `name` is looked up like
+    /// with `contains`.
+    pub fn open_entry<N: AsRef<OsStr>>(&mut self, name: N) -> Result<EntryReader<'_, R>> {
+        let index = self.index_of(name.as_ref())?;
+        self.open_entry_at(index)
+    }
+
+    /// Same as `open_entry`, but looks the entry up by its index.
+    pub fn open_entry_at(&mut self, index: usize) -> Result<EntryReader<'_, R>> {
+        let entry = self.seek_entry(index)?;
+        Ok(EntryReader::new(&mut self.reader, entry, &self.header))
+    }
+
+    /// Returns a reader of the still compressed contents of a gzip entry, so
+    /// they can be passed on as is, e.g. with `Content-Encoding: gzip`. The
+    /// compressed checksum is verified before the reader is returned.
+    pub fn entry_raw_reader<N: AsRef<OsStr>>(&mut self, name: N) -> Result<RawEntryReader<'_, R>> {
+        let name = name.as_ref();
+        let index = self.index_of(name)?;
+        let entry = self.seek_entry(index)?;
+
+        if entry.method() != Method::Gzip || entry.is_chunked() {
+            return Err(ArchiveError::Unsupported(format!(
+                "Entry '{}' isn't compressed as a single gzip stream",
+                name.to_string_lossy()
+            )));
+        }
+
+        let start = self.reader.stream_position()?;
+        let mut compressed = CrcReader::new((&mut self.reader).take(entry.compressed_size()));
+        io::copy(&mut compressed, &mut io::sink())?;
+
+        let compressed_checksum = compressed.crc().sum();
+        if compressed_checksum != entry.compressed_checksum() {
+            return Err(ArchiveError::CorruptedArchive(format!(
+                "Archive corrupted! Unpacked checksums isn't equal to! {} isn't equal to {}",
+                compressed_checksum,
+                entry.compressed_checksum()
+            )));
+        }
+
+        self.reader.seek(SeekFrom::Start(start))?;
+        let reader = (&mut self.reader).take(entry.compressed_size());
+        Ok(RawEntryReader { reader, entry })
+    }
+
+    fn index_of(&self, name: &OsStr) -> Result<usize> {
+        self.names
+            .get(name)
+            .or_else(|| self.names.get(normalize_path(Path::new(name)).as_os_str()))
+            .copied()
+            .ok_or_else(|| ArchiveError::EntryNotFound(name.to_string_lossy().into_owned()))
+    }
+
+    /// Parses the metadata of the entry, leaving the reader at its contents.
+    fn seek_entry(&mut self, index: usize) -> Result<InnerFile> {
+        if self.header.is_solid() {
+            return Err(ArchiveError::Unsupported(
+                "Random access isn't supported for solid archives".to_string(),
+            ));
+        }
+
+        let position = self
+            .entries
+            .get(index)
+            .map(InnerFile::position)
+            .ok_or_else(|| ArchiveError::EntryNotFound(format!("#{}", index + 1)))?;
+
+        self.reader.seek(SeekFrom::Start(position))?;
+        InnerFile::from_archive(&mut self.reader, &mut self.buffer, self.header.version)
+    }
+
+    /// Walks the entries sequentially and checks that the index array points
+    /// exactly at their metadata. The index may list them in another order
+    /// than they're stored in, see `PackOptions::sort_by`.
+    pub fn check_index(&mut self) -> Result<()> {
+        self.reader.seek(SeekFrom::Start(self.header.size()))?;
+
+        let mut indexed: Vec<u64> = self.entries.iter().map(InnerFile::position).collect();
+        indexed.sort_unstable();
+
+        for (i, &expected) in indexed.iter().enumerate() {
+            let position = self.reader.stream_position()?;
+            if position != expected {
+                return Err(ArchiveError::CorruptedArchive(format!(
+                    "Index points to offset {}, but metadata of entry #{} is at {}",
+                    expected,
+                    i + 1,
+                    position
+                )));
+            }
+
+            let inner =
+                InnerFile::from_archive(&mut self.reader, &mut self.buffer, self.header.version)?;
+            if !self.header.is_solid() {
+                self.reader
+                    .seek(SeekFrom::Current(i64::try_from(inner.compressed_size())?))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Decompresses every entry, verifying its size and checksums.
+    pub fn verify(&mut self) -> Result<()> {
+        if self.header.is_solid() {
+            // the block holds the contents in the stored order
+            let mut entries = self.entries.clone();
+            entries.sort_by_key(InnerFile::position);
+            let Some(last) = entries.last() else {
+                return Ok(());
+            };
+
+            self.reader.seek(SeekFrom::Start(last.position()))?;
+            InnerFile::from_archive(&mut self.reader, &mut self.buffer, self.header.version)?;
+
+            return read_solid_block(
+                &mut self.reader,
+                &entries,
+                self.header.checksum,
+                &mut self.buffer,
+                |_| Ok(io::sink()),
+                |_, _| Ok(()),
+            );
+        }
+
+        for index in 0..self.entries.len() {
+            let mut entry = self.open_entry_at(index)?;
+            io::copy(&mut entry, &mut io::sink())?;
+        }
+        Ok(())
+    }
+
+    /// Same as `verify`, but goes on past damaged entries and returns them
+    /// with the range of the archive holding their contents. A solid block
+    /// is damaged as a whole. The range of every damaged entry is read twice
+    /// more in windows of `DEEP_WINDOW` bytes to find unstable regions.
+    pub fn verify_deep(&mut self) -> Result<Vec<DamagedEntry>> {
+        let mut damaged = Vec::new();
+        if self.header.is_solid() {
+            let Err(error) = self.verify() else {
+                return Ok(damaged);
+            };
+            let contents = self.solid_contents()?;
+            let unstable = self.unstable_windows(contents.clone())?;
+            let mut entries = self.entries.clone();
+            entries.sort_by_key(InnerFile::position);
+            damaged.push(DamagedEntry {
+                names: entries.iter().map(|e| e.name().to_os_string()).collect(),
+                contents,
+                error,
+                unstable,
+            });
+            return Ok(damaged);
+        }
+
+        for index in 0..self.entries.len() {
+            let entry = self.seek_entry(index)?;
+            let start = self.reader.stream_position()?;
+            let contents = start..start + entry.compressed_size();
+
+            let mut reader = EntryReader::new(&mut self.reader, entry, &self.header);
+            let Err(error) = io::copy(&mut reader, &mut io::sink()) else {
+                continue;
+            };
+            damaged.push(DamagedEntry {
+                names: vec![reader.entry().name().to_os_string()],
+                unstable: self.unstable_windows(contents.clone())?,
+                contents,
+                error: error.into(),
+            });
+        }
+        Ok(damaged)
+    }
+
+    /// Range of the compressed contents of the solid block, which follows
+    /// the metadata of the last entry and its own sizes and checksums.
+    fn solid_contents(&mut self) -> Result<Range<u64>> {
+        let Some(last) = self.entries.iter().map(InnerFile::position).max() else {
+            return Ok(0..0);
+        };
+        self.reader.seek(SeekFrom::Start(last))?;
+        InnerFile::from_archive(&mut self.reader, &mut self.buffer, self.header.version)?;
+        self.reader.read_exact(&mut self.buffer[..16])?;
+        let compressed_size = u64::from_le_bytes(self.buffer[..8].try_into()?);
+        let start = self.reader.stream_position()?;
+        let remaining = self.reader.seek(SeekFrom::End(0))?.saturating_sub(start);
+        if compressed_size > remaining {
+            return Err(ArchiveError::CorruptedArchive(format!(
+                "Solid block claims {} compressed bytes, but only {} remain in the archive",
+                compressed_size, remaining
+            )));
+        }
+        Ok(start..start + compressed_size)
+    }
+
+    /// Reads `range` twice, returning the windows whose checksums differ
+    /// between the passes.
+    fn unstable_windows(&mut self, range: Range<u64>) -> Result<Vec<Range<u64>>> {
+        let first = self.window_checksums(range.clone())?;
+        let second = self.window_checksums(range.clone())?;
+
+        let mut unstable = Vec::new();
+        for (i, (a, b)) in first.iter().zip(&second).enumerate() {
+            if a != b {
+                let start = range.start + i as u64 * DEEP_WINDOW;
+                unstable.push(start..(start + DEEP_WINDOW).min(range.end));
+            }
+        }
+        Ok(unstable)
+    }
+
+    fn window_checksums(&mut self, range: Range<u64>) -> Result<Vec<u32>> {
+        self.reader.seek(SeekFrom::Start(range.start))?;
+        let mut checksums = Vec::new();
+        let mut remaining = range.end - range.start;
+        while remaining > 0 {
+            let len = remaining.min(DEEP_WINDOW);
+            let mut window = CrcReader::new((&mut self.reader).take(len));
+            // a window cut short by the end of the archive is compared as read
+            let read = io::copy(&mut window, &mut io::sink())?;
+            checksums.push(window.crc().sum());
+            if read < len {
+                break;
+            }
+            remaining -= len;
+        }
+        Ok(checksums)
+    }
+}
+
+/// Reads the metadata of every entry pointed by the index array. Archives
+/// without one (index offset 0) are walked sequentially instead.
+pub fn read_index<R: Read + Seek>(
+    reader: &mut R,
+    header: &Header,
+    buffer: &mut [u8],
+) -> Result<Vec<InnerFile>> {
+    if !header.has_index() {
+        return scan_entries(reader, header, buffer);
+    }
+
+    let positions = read_index_positions(reader, buffer, header.index_offset, header.file_count)?;
+
+    let mut entries = Vec::with_capacity(positions.len());
+    for position in positions {
+        reader.seek(SeekFrom::Start(position))?;
+        entries.push(InnerFile::from_archive(reader, buffer, header.version)?);
+    }
+    Ok(entries)
+}
+
+fn scan_entries<R: Read + Seek>(
+    reader: &mut R,
+    header: &Header,
+    buffer: &mut [u8],
+) -> Result<Vec<InnerFile>> {
+    reader.seek(SeekFrom::Start(header.size()))?;
+
+    let mut entries = Vec::with_capacity(entry_capacity(header.file_count));
+    for _ in 0..header.file_count {
+        let entry = InnerFile::from_archive(reader, buffer, header.version)?;
+        if !header.is_solid() {
+            reader.seek(SeekFrom::Current(i64::try_from(entry.compressed_size())?))?;
+        }
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+/// Decompresses the solid block at the current position of `reader`, streaming
+/// contents of every entry into the writer made by `create` and verifying
+/// sizes and checksums. `finish` receives the writer of every verified entry.
+pub(crate) fn read_solid_block<R: Read, W: Write>(
+    reader: &mut R,
+    entries: &[InnerFile],
+    checksum: ChecksumAlgorithm,
+    buffer: &mut [u8],
+    mut create: impl FnMut(&InnerFile) -> Result<W>,
+    mut finish: impl FnMut(&InnerFile, W) -> Result<()>,
+) -> Result<()> {
+    reader.read_exact(&mut buffer[..16])?;
+    let compressed_size = u64::from_le_bytes(buffer[..8].try_into()?);
+    let block_original_checksum = u32::from_le_bytes(buffer[8..12].try_into()?);
+    let block_compressed_checksum = u32::from_le_bytes(buffer[12..16].try_into()?);
+
+    let mut decoder = MultiGzDecoder::new(CrcReader::new(reader.take(compressed_size)));
+    let mut block_checksum = Checksum::new(checksum);
+
+    for entry in entries {
+        let mut writer = ChecksumWriter::new(create(entry)?, checksum);
+
+        let size = io::copy(&mut (&mut decoder).take(entry.original_size()), &mut writer)?;
+        let original_checksum = writer.checksum().sum();
+        block_checksum.combine(writer.checksum());
+
+        if entry.original_size() != size {
+            return Err(ArchiveError::CorruptedArchive(format!(
+                "Archive corrupted! Unpacked file has another size! {} isn't equal to {}",
+                entry.original_size(),
+                size
+            )));
+        }
+
+        if original_checksum != entry.original_checksum() {
+            return Err(ArchiveError::CorruptedArchive(format!(
+                "Archive corrupted! Unpacked checksums isn't equal to! {} isn't equal to {}",
+                original_checksum,
+                entry.original_checksum()
+            )));
+        }
+
+        finish(entry, writer.into_inner())?;
+    }
+
+    if io::copy(&mut decoder, &mut io::sink())? != 0 {
+        return Err(ArchiveError::CorruptedArchive(
+            "Archive corrupted! Solid block has more data than its entries".to_string(),
+        ));
+    }
+
+    let mut compressed = decoder.into_inner();
+    io::copy(&mut compressed, &mut io::sink())?;
+
+    if block_checksum.sum() != block_original_checksum {
+        return Err(ArchiveError::CorruptedArchive(format!(
+            "Archive corrupted! Unpacked checksums isn't equal to! {} isn't equal to {}",
+            block_checksum.sum(),
+            block_original_checksum
+        )));
+    }
+
+    let compressed_checksum = compressed.crc().sum();
+    if compressed_checksum != block_compressed_checksum {
+        return Err(ArchiveError::CorruptedArchive(format!(
+            "Archive corrupted! Unpacked checksums isn't equal to! {} isn't equal to {}",
+            compressed_checksum, block_compressed_checksum
+        )));
+    }
+    Ok(())
+}
+
+/// Reads the index array, keeping the current position of `reader`. An index
+/// which doesn't fit within the archive is rejected before anything is read.
+pub fn read_index_positions<R: Read + Seek>(
+    reader: &mut R,
+    buffer: &mut [u8],
+    index_offset: u64,
+    file_count: u32,
+) -> Result<Vec<u64>> {
+    let current = reader.stream_position()?;
+    let length = reader.seek(SeekFrom::End(0))?;
+
+    let index_end = index_offset.checked_add(8 * file_count as u64);
+    if index_end.is_none_or(|end| end > length) {
+        return Err(ArchiveError::CorruptedArchive(format!(
+            "Index array of {} entries at offset {} exceeds the archive length of {}",
+            file_count, index_offset, length
+        )));
+    }
+    reader.seek(SeekFrom::Start(index_offset))?;
+
+    let mut positions = Vec::with_capacity(file_count as usize);
+    for _ in 0..file_count {
+        reader.read_exact(&mut buffer[..8])?;
+        positions.push(u64::from_le_bytes(buffer[..8].try_into()?));
+    }
+
+    reader.seek(SeekFrom::Start(current))?;
+    Ok(positions)
+}
+
+enum EntryDecoder<'a, R: Read + Seek> {
+    Store(CrcReader<Take<&'a mut R>>),
+    Gzip(MultiGzDecoder<CrcReader<Take<&'a mut R>>>),
+    Deflate(DeflateReader<CrcReader<Take<&'a mut R>>>),
+    #[cfg(feature = "xz")]
+    Xz(XzDecoder<CrcReader<Take<&'a mut R>>>),
+    Chunked(ChunkDecoder<'a, R>),
+}
+
+impl<R: Read + Seek> EntryDecoder<'_, R> {
+    /// Reads the rest of the compressed contents, returning their checksum.
+    fn compressed_checksum(&mut self) -> Result<u32> {
+        let compressed = match self {
+            Self::Store(reader) => reader,
+            Self::Gzip(decoder) => decoder.get_mut(),
+            Self::Deflate(decoder) => decoder.get_mut(),
+            #[cfg(feature = "xz")]
+            Self::Xz(decoder) => decoder.get_mut(),
+            Self::Chunked(decoder) => return decoder.compressed_checksum(),
+        };
+        io::copy(compressed, &mut io::sink())?;
+        Ok(compressed.crc().sum())
+    }
+}
+
+impl<R: Read + Seek> Read for EntryDecoder<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Self::Store(reader) => reader.read(buf),
+            Self::Gzip(decoder) => decoder.read(buf),
+            Self::Deflate(decoder) => decoder.read(buf),
+            #[cfg(feature = "xz")]
+            Self::Xz(decoder) => decoder.read(buf),
+            Self::Chunked(decoder) => decoder.read(buf),
+        }
+    }
+}
+
+pub struct EntryReader<'a, R: Read + Seek> {
+    decoder: EntryDecoder<'a, R>,
+    entry: InnerFile,
+    checksum: Checksum,
+    size: u64,
+    verified: bool,
+}
+
+impl<'a, R: Read + Seek> EntryReader<'a, R> {
+    /// `header` is the one of the archive, giving the dictionary used by
+    /// `Method::Deflate` and the checksum algorithm.
+    pub(crate) fn new(reader: &'a mut R, entry: InnerFile, header: &Header) -> Self {
+        let decoder = if entry.is_chunked() {
+            EntryDecoder::Chunked(ChunkDecoder::new(reader, entry.compressed_size()))
+        } else {
+            let compressed = CrcReader::new(reader.take(entry.compressed_size()));
+            match entry.method() {
+                Method::Store => EntryDecoder::Store(compressed),
+                Method::Gzip => EntryDecoder::Gzip(MultiGzDecoder::new(compressed)),
+                Method::Deflate => {
+                    EntryDecoder::Deflate(DeflateReader::new(compressed, &header.dictionary))
+                }
+                #[cfg(feature = "xz")]
+                Method::Xz => EntryDecoder::Xz(XzDecoder::new_multi_decoder(compressed)),
+            }
+        };
+        Self {
+            decoder,
+            entry,
+            checksum: Checksum::new(header.checksum),
+            size: 0,
+            verified: false,
+        }
+    }
+
+    pub fn entry(&self) -> &InnerFile {
+        &self.entry
+    }
+
+    /// Whether the entry was read to the end and passed verification.
+    pub fn verified(&self) -> bool {
+        self.verified
+    }
+
+    fn verify(&mut self) -> Result<()> {
+        let compressed_checksum = self.decoder.compressed_checksum()?;
+        if compressed_checksum != self.entry.compressed_checksum() {
+            return Err(ArchiveError::CorruptedArchive(format!(
+                "Archive corrupted! Unpacked checksums isn't equal to! {} isn't equal to {}",
+                compressed_checksum,
+                self.entry.compressed_checksum()
+            )));
+        }
+
+        if self.checksum.sum() != self.entry.original_checksum() {
+            return Err(ArchiveError::CorruptedArchive(format!(
+                "Archive corrupted! Unpacked checksums isn't equal to! {} isn't equal to {}",
+                self.checksum.sum(),
+                self.entry.original_checksum()
+            )));
+        }
+
+        if self.size != self.entry.original_size() {
+            return Err(ArchiveError::CorruptedArchive(format!(
+                "Archive corrupted! Unpacked file has another size! {} isn't equal to {}",
+                self.entry.original_size(),
+                self.size
+            )));
+        }
+
+        self.verified = true;
+        Ok(())
+    }
+}
+
+impl<R: Read + Seek> Read for EntryReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let bytes = self.decoder.read(buf)?;
+
+        if bytes == 0 && !buf.is_empty() && !self.verified {
+            self.verify()
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        }
+
+        self.checksum.update(&buf[..bytes]);
+        self.size += bytes as u64;
+        Ok(bytes)
+    }
+}
+
+/// Compressed contents of a single entry, see `ArchiveReader::entry_raw_reader`.
+pub struct RawEntryReader<'a, R: Read> {
+    reader: Take<&'a mut R>,
+    entry: InnerFile,
+}
+
+impl<R: Read> RawEntryReader<'_, R> {
+    pub fn entry(&self) -> &InnerFile {
+        &self.entry
+    }
+
+    /// Size of the contents once decoded.
+    pub fn original_size(&self) -> u64 {
+        self.entry.original_size()
+    }
+
+    /// Size of the gzip stream yielded by this reader.
+    pub fn compressed_size(&self) -> u64 {
+        self.entry.compressed_size()
+    }
+}
+
+impl<R: Read> Read for RawEntryReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.reader.read(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io::Cursor, path::PathBuf, sync::Arc};
+
+    use super::*;
+    use crate::{
+        builder::ArchiveBuilder,
+        filesystem::MemFs,
+        pack::{PackOptions, pack},
+        testing::{TempDir, update_checksum},
+        unpack::{UnpackOptions, unpack_stream},
+    };
+
+    fn two_entries() -> Vec<u8> {
+        let mut builder = ArchiveBuilder::new();
+        builder.add_bytes("a.txt", b"first").unwrap();
+        builder.add_bytes("b.txt", b"second").unwrap();
+        builder.finish(Vec::new()).unwrap()
+    }
+
+    /// Reads and extracts `archive` as far as it goes, which must not panic.
+    fn read_all(archive: &[u8]) -> Result<()> {
+        let options = UnpackOptions {
+            file_system: Arc::new(MemFs::new()),
+            ..UnpackOptions::default()
+        };
+        let streamed = unpack_stream(archive, PathBuf::from("out"), &options).map(|_| ());
+        let mut reader = ArchiveReader::new(io::Cursor::new(archive))?;
+        for index in 0..reader.entries().len() {
+            io::copy(&mut reader.open_entry_at(index)?, &mut io::sink())?;
+        }
+        reader.verify()?;
+        reader.verify_deep()?;
+        streamed
+    }
+
+    #[test]
+    fn damaged_range_contains_the_corruption() {
+        let mut builder = ArchiveBuilder::new();
+        for (name, line) in [("a.txt", "first"), ("b.txt", "second"), ("c.txt", "third")] {
+            let contents = format!("{} line of text\n", line).repeat(500);
+            builder.add_bytes(name, contents.as_bytes()).unwrap();
+        }
+        let mut archive = builder.finish(Vec::new()).unwrap();
+
+        let entries = ArchiveReader::new(Cursor::new(&archive))
+            .unwrap()
+            .entries()
+            .to_vec();
+        let b = &entries[1];
+        let offset = (b.position() + b.metadata_size() + b.compressed_size() / 2) as usize;
+        archive[offset] ^= 0xff;
+
+        let mut reader = ArchiveReader::new(Cursor::new(&archive)).unwrap();
+        let damaged = reader.verify_deep().unwrap();
+        assert_eq!(damaged.len(), 1, "{:?}", damaged);
+        assert_eq!(damaged[0].names, ["b.txt"]);
+        assert!(
+            damaged[0].contents.contains(&(offset as u64)),
+            "{}",
+            damaged[0]
+        );
+        // the same bytes are read on both passes
+        assert!(damaged[0].unstable.is_empty());
+    }
+
+    #[test]
+    fn entries_are_opened_by_normalized_names() {
+        let mut builder = ArchiveBuilder::new();
+        builder.add_bytes("sub/b.txt", b"second").unwrap();
+        let archive = builder.finish(Vec::new()).unwrap();
+        let mut reader = ArchiveReader::new(Cursor::new(archive)).unwrap();
+
+        for name in ["sub/b.txt", "./sub//b.txt", "sub/./b.txt"] {
+            let mut contents = Vec::new();
+            reader
+                .open_entry(name)
+                .unwrap()
+                .read_to_end(&mut contents)
+                .unwrap();
+            assert_eq!(contents, b"second", "{}", name);
+        }
+        assert!(matches!(
+            reader.open_entry("b.txt"),
+            Err(ArchiveError::EntryNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn huge_file_counts_are_rejected() {
+        let mut archive = two_entries();
+        archive[6..10].copy_from_slice(&u32::MAX.to_le_bytes());
+        update_checksum(&mut archive);
+        assert!(read_all(&archive).is_err());
+    }
+
+    #[test]
+    fn oversized_solid_blocks_are_rejected() {
+        let dir = TempDir::new("solid-size");
+        let source = dir.write_files("src", &[("a.txt", b"first"), ("b.txt", b"second")]);
+        let options = PackOptions {
+            solid: true,
+            ..PackOptions::default()
+        };
+        pack(source, Some(dir.join("archives")), &options).unwrap();
+        let mut archive = fs::read(dir.join("archives/src.slf")).unwrap();
+
+        // the compressed size of the block follows the metadata of the last entry
+        let mut reader = ArchiveReader::new(io::Cursor::new(&archive)).unwrap();
+        let last = reader
+            .entries()
+            .iter()
+            .map(InnerFile::position)
+            .max()
+            .unwrap();
+        let version = reader.header().version;
+        reader.reader.seek(SeekFrom::Start(last)).unwrap();
+        InnerFile::from_archive(&mut reader.reader, &mut vec![0; BUFFER_SIZE], version).unwrap();
+        let at = reader.reader.stream_position().unwrap() as usize;
+        archive[at..at + 8].copy_from_slice(&(u64::MAX / 2).to_le_bytes());
+        update_checksum(&mut archive);
+
+        let mut reader = ArchiveReader::new(io::Cursor::new(&archive)).unwrap();
+        match reader.verify_deep() {
+            Err(ArchiveError::CorruptedArchive(message)) => {
+                assert!(message.starts_with("Solid block claims"), "{}", message)
+            }
+            result => panic!("{:?}", result),
+        }
+    }
+
+    #[test]
+    fn truncated_archives_fail_without_panicking() {
+        let archive = two_entries();
+        for len in 0..archive.len() {
+            assert!(read_all(&archive[..len]).is_err(), "{} bytes", len);
+        }
+        read_all(&archive).unwrap();
+    }
+}
@@ -0,0 +1,289 @@
+use std::io::{self, Read, Seek, SeekFrom};
+
+use log::debug;
+use ureq::{Agent, http::StatusCode};
+
+use crate::{
+    error::{ArchiveError, Result},
+    reader::ArchiveReader,
+};
+
+/// Bytes fetched by a request which doesn't continue the previous one, enough
+/// for the header, the index or a small entry.
+const MIN_BLOCK_SIZE: u64 = 64 * 1024;
+
+/// Largest request made while reading sequentially, e.g. a large entry.
+const MAX_BLOCK_SIZE: u64 = 8 << 20;
+
+/// Reader of an archive served over HTTP, fetching only the parts read with
+/// range requests: e.g. the header, the index and one entry when extracting
+/// it through `ArchiveReader`. Bytes are fetched in blocks, which double in
+/// size while reads continue where the last block ended, so decompressing a
+/// large entry takes few requests. The server has to answer range requests
+/// with `206 Partial Content`, and the archive must not change meanwhile.
+pub struct RangeReader {
+    agent: Agent,
+    url: String,
+    len: u64,
+    position: u64,
+    /// Bytes of the last request, starting at `block_start`.
+    block: Vec<u8>,
+    block_start: u64,
+    block_size: u64,
+    fetched: u64,
+}
+
+impl RangeReader {
+    /// Fetches the first block of the archive at `url`, which also tells the
+    /// length of the archive.
+    pub fn open(url: &str) -> Result<Self> {
+        let mut reader = Self {
+            agent: Agent::new_with_defaults(),
+            url: url.to_string(),
+            len: 0,
+            position: 0,
+            block: Vec::new(),
+            block_start: 0,
+            block_size: MIN_BLOCK_SIZE,
+            fetched: 0,
+        };
+        reader.len = reader.fetch(0, MIN_BLOCK_SIZE)?;
+        Ok(reader)
+    }
+
+    /// Length of the whole archive on the server.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Bytes downloaded so far.
+    pub fn fetched(&self) -> u64 {
+        self.fetched
+    }
+
+    /// Replaces the block with `size` bytes from `start`, fewer at the end of
+    /// the archive. Returns the length of the archive given by the server.
+    fn fetch(&mut self, start: u64, size: u64) -> Result<u64> {
+        let mut response = self
+            .agent
+            .get(&self.url)
+            .header("Range", format!("bytes={}-{}", start, start + size - 1))
+            // a compressed response would have no byte ranges of the archive
+            .header("Accept-Encoding", "identity")
+            .call()
+            .map_err(|e| self.request_error(e))?;
+        if response.status() != StatusCode::PARTIAL_CONTENT {
+            return Err(ArchiveError::Unsupported(format!(
+                "Server of '{}' doesn't support range requests",
+                self.url
+            )));
+        }
+
+        let range = response
+            .headers()
+            .get("Content-Range")
+            .and_then(|value| value.to_str().ok())
+            .and_then(content_range);
+        let Some((range_start, len)) = range.filter(|&(range_start, _)| range_start == start)
+        else {
+            return Err(ArchiveError::Io(format!(
+                "Server of '{}' answered a range request without the requested range",
+                self.url
+            )));
+        };
+
+        self.block = response
+            .body_mut()
+            .with_config()
+            // reaching the limit exactly already counts as exceeding it
+            .limit(size + 1)
+            .read_to_vec()
+            .map_err(|e| self.request_error(e))?;
+        self.block_start = range_start;
+        self.fetched += self.block.len() as u64;
+        debug!(
+            "Fetched {} bytes at {} of '{}'",
+            self.block.len(),
+            start,
+            self.url
+        );
+        Ok(len)
+    }
+
+    fn request_error(&self, error: ureq::Error) -> ArchiveError {
+        ArchiveError::Io(format!("Failed to fetch '{}': {}", self.url, error))
+    }
+}
+
+/// Parses the start and the complete length of `bytes <start>-<end>/<length>`.
+fn content_range(value: &str) -> Option<(u64, u64)> {
+    let (range, len) = value.strip_prefix("bytes ")?.split_once('/')?;
+    let (start, _) = range.split_once('-')?;
+    Some((start.parse().ok()?, len.parse().ok()?))
+}
+
+impl Read for RangeReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() || self.position >= self.len {
+            return Ok(0);
+        }
+
+        let block_end = self.block_start + self.block.len() as u64;
+        if !(self.block_start..block_end).contains(&self.position) {
+            self.block_size = if self.position == block_end {
+                (self.block_size * 2).min(MAX_BLOCK_SIZE)
+            } else {
+                MIN_BLOCK_SIZE
+            };
+            let size = self.block_size.min(self.len - self.position);
+            let len = self.fetch(self.position, size).map_err(io::Error::other)?;
+            if len != self.len {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("archive at '{}' changed while it was read", self.url),
+                ));
+            }
+            if self.block.is_empty() {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+        }
+
+        let offset = (self.position - self.block_start) as usize;
+        let bytes = buf.len().min(self.block.len() - offset);
+        buf[..bytes].copy_from_slice(&self.block[offset..offset + bytes]);
+        self.position += bytes as u64;
+        Ok(bytes)
+    }
+}
+
+impl Seek for RangeReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.len.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+        self.position = position.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "seek before the start of the archive",
+            )
+        })?;
+        Ok(self.position)
+    }
+
+    fn stream_position(&mut self) -> io::Result<u64> {
+        Ok(self.position)
+    }
+}
+
+impl ArchiveReader<RangeReader> {
+    /// Opens the archive at `url` for random access, downloading the header
+    /// and the index only, see `RangeReader`.
+    pub fn open_url(url: &str) -> Result<Self> {
+        Self::new(RangeReader::open(url)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+        path::{Path, PathBuf},
+        sync::{
+            Arc,
+            atomic::{AtomicU64, Ordering},
+        },
+        thread,
+    };
+
+    use super::*;
+    use crate::{
+        builder::ArchiveBuilder,
+        compressor::Method,
+        filesystem::MemFs,
+        testing::noise,
+        unpack::{UnpackOptions, glob_set, unpack_url},
+    };
+
+    /// Serves `archive` over HTTP, answering each range request on its own
+    /// connection. Returns the URL and the count of body bytes sent.
+    fn serve(archive: Vec<u8>) -> (String, Arc<AtomicU64>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/archive.slf", listener.local_addr().unwrap());
+        let sent = Arc::new(AtomicU64::new(0));
+        let counter = sent.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut range = None;
+                for line in BufReader::new(&stream).lines() {
+                    let line = line.unwrap();
+                    if line.is_empty() {
+                        break;
+                    }
+                    if let Some(value) = line.to_ascii_lowercase().strip_prefix("range: bytes=") {
+                        let (start, end) = value.split_once('-').unwrap();
+                        range = Some((
+                            start.parse::<usize>().unwrap(),
+                            end.parse::<usize>().unwrap(),
+                        ));
+                    }
+                }
+                let (start, end) = range.expect("only range requests are made");
+                let end = end.min(archive.len() - 1);
+                let body = &archive[start..=end];
+                write!(
+                    stream,
+                    "HTTP/1.1 206 Partial Content\r\nContent-Range: bytes {}-{}/{}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    start,
+                    end,
+                    archive.len(),
+                    body.len()
+                )
+                .unwrap();
+                stream.write_all(body).unwrap();
+                counter.fetch_add(body.len() as u64, Ordering::Relaxed);
+            }
+        });
+        (url, sent)
+    }
+
+    #[test]
+    fn extracts_one_entry_with_range_requests() {
+        let large = noise(2 << 20);
+        let mut builder = ArchiveBuilder::new();
+        builder.method(Method::Store);
+        builder.add_bytes("first.bin", &large).unwrap();
+        builder.add_bytes("wanted.txt", b"wanted contents").unwrap();
+        builder.add_bytes("last.bin", &large).unwrap();
+        let archive = builder.finish(Vec::new()).unwrap();
+        let len = archive.len() as u64;
+        let (url, sent) = serve(archive);
+
+        let fs = Arc::new(MemFs::new());
+        let options = UnpackOptions {
+            file_system: fs.clone(),
+            matching: Some(glob_set(&["wanted.txt"]).unwrap()),
+            ..UnpackOptions::default()
+        };
+        let extracted = unpack_url(&url, PathBuf::from("out"), &options).unwrap();
+        assert_eq!(extracted.len(), 1);
+        assert_eq!(
+            fs.read(Path::new("out/archive/wanted.txt")).as_deref(),
+            Some(&b"wanted contents"[..])
+        );
+
+        // a few blocks around the header, the index and the entry
+        let sent = sent.load(Ordering::Relaxed);
+        assert!(sent <= 4 * MIN_BLOCK_SIZE, "{} of {} bytes", sent, len);
+
+        let reader = ArchiveReader::open_url(&url).unwrap();
+        assert_eq!(reader.entries().len(), 3);
+    }
+}
@@ -0,0 +1,192 @@
+use std::{
+    fs::{self, File, remove_file},
+    io::{self, BufWriter, Read, Seek, Write},
+    path::Path,
+};
+
+use flate2::Compression;
+use log::debug;
+
+use crate::{
+    FLAG_DEDUP, FLAG_SIGNED, FLAG_SPLIT, SIGNATURE, VERSION,
+    compressor::{Compressor, Method},
+    edit::{check_rewritable, warn_signature_dropped},
+    error::{ArchiveError, Result},
+    pack::{
+        patch_temp_fields, persist, temp_path, write_dictionary, write_footer, write_index_array,
+        write_index_offset,
+    },
+    reader::ArchiveReader,
+};
+
+/// Writes a copy of the archive at `source` into `target` with every entry
+/// recompressed by `method` at `level`, without the source files. Entries
+/// are decompressed and their checksums verified on the way, so damaged
+/// data is never carried over. Gzip entries of an archive with a dictionary
+/// become `Method::Deflate`, like when packing. Deduplicated archives are
+/// expanded into plain ones, solid archives aren't supported. The copy is
+/// written next to `target` and replaces it once complete, so a failed
+/// repack leaves an existing target as it was.
+pub fn repack<P: AsRef<Path>, Q: AsRef<Path>>(
+    source: P,
+    target: Q,
+    method: Method,
+    level: Compression,
+) -> Result<()> {
+    let (source, target) = (source.as_ref(), target.as_ref());
+    method.check_level(level)?;
+    if target.exists() && fs::canonicalize(source)? == fs::canonicalize(target)? {
+        return Err(ArchiveError::Usage(
+            "Archive can't be repacked into itself".to_string(),
+        ));
+    }
+
+    let mut archive = ArchiveReader::open(source)?;
+    archive.verify_archive_checksum()?;
+    if archive.header().is_solid() {
+        return Err(ArchiveError::Unsupported(
+            "Solid archives can't be repacked, their entries can't be read one by one".to_string(),
+        ));
+    }
+    check_rewritable(archive.header())?;
+
+    warn_signature_dropped(archive.header(), source);
+
+    // an existing target is only replaced by a complete archive
+    let temp_path = temp_path(target);
+    let result = File::create(&temp_path)
+        .map_err(ArchiveError::from)
+        .and_then(|file| {
+            write_repacked(
+                &mut archive,
+                BufWriter::new(file),
+                &temp_path,
+                method,
+                level,
+            )
+        });
+
+    if let Err(e) = result {
+        if temp_path.exists() {
+            remove_file(&temp_path)?;
+        }
+        return Err(e);
+    }
+
+    persist(&temp_path, target)
+}
+
+fn write_repacked<R: Read + Seek>(
+    archive: &mut ArchiveReader<R>,
+    mut writer: BufWriter<File>,
+    temp_path: &Path,
+    method: Method,
+    level: Compression,
+) -> Result<()> {
+    let header = archive.header().clone();
+    let method = if method == Method::Gzip && header.has_dictionary() {
+        Method::Deflate
+    } else {
+        method
+    };
+
+    let mut entries = archive.entries().to_vec();
+    writer.write_all(SIGNATURE)?;
+    writer.write_all(&VERSION.to_bytes())?;
+    writer.write_all(&u32::try_from(entries.len())?.to_le_bytes())?; //file count
+    writer.write_all(&u64::to_le_bytes(0))?; //index offset
+    // written as a single file, even from the volumes of a split archive
+    writer.write_all(&(header.flags & !(FLAG_DEDUP | FLAG_SIGNED | FLAG_SPLIT)).to_le_bytes())?;
+    writer.write_all(&[header.checksum.id()])?;
+    if header.has_dictionary() {
+        write_dictionary(&mut writer, &header.dictionary)?;
+    }
+
+    for (i, entry) in entries.iter_mut().enumerate() {
+        entry.set_method(method);
+        entry.set_chunked(false);
+        let offset = entry.write_metadata(&mut writer)?;
+
+        // checksums and size of the old contents are verified at EOF
+        let mut reader = archive.open_entry_at(i)?;
+        let mut compressor =
+            Compressor::with_dictionary(&mut writer, method, level, &header.dictionary)?
+                .with_checksum(header.checksum);
+        io::copy(&mut reader, &mut compressor)?;
+        let (_, summary) = compressor.finish()?;
+
+        debug!(
+            "Repacked '{}' ({} -> {} bytes, {})",
+            entry.name().to_string_lossy(),
+            summary.original_size,
+            summary.compressed_size,
+            method.name()
+        );
+
+        patch_temp_fields(
+            &mut writer,
+            offset,
+            None,
+            summary.compressed_size,
+            (summary.original_checksum, summary.compressed_checksum),
+        )?;
+    }
+
+    write_index_offset(&mut writer)?;
+    write_index_array(&mut writer, &entries)?;
+    write_footer(&mut writer, temp_path)?;
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::{
+        pack::{PackOptions, pack},
+        testing::TempDir,
+        unpack::{UnpackOptions, unpack},
+    };
+
+    #[test]
+    fn repacks_gzip_to_store() {
+        let dir = TempDir::new("repack");
+        let text = b"line of text\n".repeat(1000);
+        let files: [(&str, &[u8]); 2] = [("a.txt", &text), ("sub/b.txt", b"second")];
+        let source = dir.write_files("src", &files);
+        let options = PackOptions {
+            method: Some(Method::Gzip),
+            ..PackOptions::default()
+        };
+        pack(source, Some(dir.join("archives")), &options).unwrap();
+
+        // an existing target is replaced
+        let stored = dir.join("archives/stored.slf");
+        fs::write(&stored, b"old").unwrap();
+        repack(
+            dir.join("archives/src.slf"),
+            &stored,
+            Method::Store,
+            Compression::default(),
+        )
+        .unwrap();
+        assert!(!temp_path(&stored).exists());
+        let mut reader = ArchiveReader::open(&stored).unwrap();
+        reader.verify().unwrap();
+        for entry in reader.entries() {
+            assert_eq!(entry.method(), Method::Store);
+            assert_eq!(entry.compressed_size(), entry.original_size());
+        }
+
+        unpack(stored, Some(dir.join("out")), &UnpackOptions::default()).unwrap();
+        for (name, contents) in files {
+            assert!(
+                fs::read(dir.join("out/stored").join(name)).unwrap() == contents,
+                "{}",
+                name
+            );
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use std::{
+    io::{self, Read, Seek, SeekFrom, Write},
+    thread,
+    time::Duration,
+};
+
+use log::debug;
+
+/// Retries of a failing read or write used by `pack` and `unpack` unless
+/// configured otherwise.
+pub const DEFAULT_IO_RETRIES: u32 = 3;
+
+/// Delay before the first retry, doubled after every further one up to
+/// `MAX_BACKOFF`.
+const INITIAL_BACKOFF: Duration = Duration::from_millis(10);
+const MAX_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Wraps a reader or writer, retrying operations which fail with a
+/// transient error, e.g. on a flaky network file system. `Interrupted` is
+/// always retried right away, `WouldBlock` and `TimedOut` up to `retries`
+/// times with a growing delay before the error is returned.
+pub struct Retrying<T> {
+    inner: T,
+    retries: u32,
+}
+
+impl<T> Retrying<T> {
+    pub fn new(inner: T, retries: u32) -> Self {
+        Self { inner, retries }
+    }
+
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    fn retry<U>(&mut self, mut op: impl FnMut(&mut T) -> io::Result<U>) -> io::Result<U> {
+        let mut attempt = 0;
+        loop {
+            match op(&mut self.inner) {
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) if is_transient(&e) && attempt < self.retries => {
+                    let backoff = INITIAL_BACKOFF
+                        .saturating_mul(2u32.saturating_pow(attempt))
+                        .min(MAX_BACKOFF);
+                    debug!("Retrying after a transient error in {:?}: {}", backoff, e);
+                    thread::sleep(backoff);
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+fn is_transient(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+    )
+}
+
+impl<R: Read> Read for Retrying<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.retry(|inner| inner.read(buf))
+    }
+}
+
+impl<W: Write> Write for Retrying<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.retry(|inner| inner.write(buf))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.retry(|inner| inner.flush())
+    }
+}
+
+impl<S: Seek> Seek for Retrying<S> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.retry(|inner| inner.seek(pos))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use io::ErrorKind::{Interrupted, NotFound, TimedOut, WouldBlock};
+
+    use super::*;
+    use crate::testing::Flaky;
+
+    fn read_all<R: Read>(mut reader: R) -> io::Result<Vec<u8>> {
+        let mut contents = Vec::new();
+        let mut buffer = [0u8; 4];
+        loop {
+            match reader.read(&mut buffer)? {
+                0 => return Ok(contents),
+                n => contents.extend_from_slice(&buffer[..n]),
+            }
+        }
+    }
+
+    #[test]
+    fn interrupted_reads_are_always_retried() {
+        let flaky = Flaky::new(b"contents".as_slice(), &[Interrupted; 10]);
+        assert_eq!(read_all(Retrying::new(flaky, 0)).unwrap(), b"contents");
+    }
+
+    #[test]
+    fn transient_errors_are_retried_up_to_the_limit() {
+        let flaky = Flaky::new(b"contents".as_slice(), &[WouldBlock, TimedOut]);
+        assert_eq!(read_all(Retrying::new(flaky, 2)).unwrap(), b"contents");
+
+        let flaky = Flaky::new(b"contents".as_slice(), &[WouldBlock, TimedOut]);
+        let error = read_all(Retrying::new(flaky, 1)).unwrap_err();
+        assert_eq!(error.kind(), TimedOut);
+
+        let flaky = Flaky::new(b"contents".as_slice(), &[NotFound]);
+        let error = read_all(Retrying::new(flaky, 3)).unwrap_err();
+        assert_eq!(error.kind(), NotFound);
+    }
+
+    #[test]
+    fn interrupted_writes_are_retried() {
+        let mut writer = Retrying::new(Flaky::new(Vec::new(), &[Interrupted, WouldBlock]), 1);
+        writer.write_all(b"contents").unwrap();
+        assert_eq!(writer.get_ref().inner, b"contents");
+    }
+}
@@ -0,0 +1,198 @@
+use std::{
+    fs,
+    io::{Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use ed25519_dalek::{
+    Digest, Sha512, Signature, SigningKey, VerifyingKey,
+    pkcs8::{DecodePrivateKey, DecodePublicKey},
+};
+
+use crate::{
+    BUFFER_SIZE, DEFAULT_BUFFER_CAPACITY, Footer, Header,
+    error::{ArchiveError, Result},
+    source::ArchiveSource,
+    volume::VolumeReader,
+};
+
+/// Size of the signature stored in front of the footer, see `FLAG_SIGNED`.
+pub const SIGNATURE_SIZE: u64 = 64;
+
+/// Context of the Ed25519ph signatures, so a signature of an archive can't
+/// be passed off as one of anything else made with the same key.
+const CONTEXT: &[u8] = b"sulfur archive";
+
+/// Reads a private key in PKCS#8 PEM, e.g. made by
+/// `openssl genpkey -algorithm ed25519`.
+pub fn read_signing_key(path: &Path) -> Result<SigningKey> {
+    let pem = read_key(path)?;
+    SigningKey::from_pkcs8_pem(&pem).map_err(|e| invalid_key(path, e))
+}
+
+/// Reads a public key in SPKI PEM, e.g. made by `openssl pkey -pubout`.
+pub fn read_verifying_key(path: &Path) -> Result<VerifyingKey> {
+    let pem = read_key(path)?;
+    VerifyingKey::from_public_key_pem(&pem).map_err(|e| invalid_key(path, e))
+}
+
+fn read_key(path: &Path) -> Result<String> {
+    fs::read_to_string(path)
+        .map_err(|e| ArchiveError::Path(format!("Failed to read key '{}': {}", path.display(), e)))
+}
+
+fn invalid_key(path: &Path, error: impl std::fmt::Display) -> ArchiveError {
+    ArchiveError::Usage(format!(
+        "'{}' isn't an Ed25519 key in PEM: {}",
+        path.display(),
+        error
+    ))
+}
+
+/// Writes the signature of the archive written so far through `writer`,
+/// read back from `archive_path`, or from all volumes when it's the first of
+/// a split one. Its header has to have `FLAG_SIGNED` set already, so the
+/// signature covers it. The footer follows.
+pub(crate) fn write_signature<W: Write + Seek>(
+    writer: &mut W,
+    archive_path: &Path,
+    key: &SigningKey,
+) -> Result<()> {
+    writer.flush()?;
+    let end = writer.stream_position()?;
+
+    let mut buffer = vec![0u8; BUFFER_SIZE];
+    let digest = digest(&mut VolumeReader::open(archive_path)?, end, &mut buffer)?;
+    let signature = key
+        .sign_prehashed(digest, Some(CONTEXT))
+        .map_err(|e| ArchiveError::Unsupported(format!("Failed to sign the archive: {}", e)))?;
+    writer.write_all(&signature.to_bytes())?;
+    Ok(())
+}
+
+/// Checks the signature of the archive at `path`, or of all volumes of a
+/// split one when given the first, against `key`.
+pub fn verify_signature(path: &Path, key: &VerifyingKey) -> Result<()> {
+    let mut reader = ArchiveSource::open(path, DEFAULT_BUFFER_CAPACITY)?;
+    let mut buffer = vec![0u8; BUFFER_SIZE];
+    let header = Header::from_archive(&mut reader, &mut buffer)?;
+    if !header.is_signed() || !header.has_footer() {
+        return Err(ArchiveError::SignatureInvalid(
+            "Archive isn't signed".to_string(),
+        ));
+    }
+
+    let footer = Footer::from_archive(&mut reader, &mut buffer, header.version)?;
+    let end = footer
+        .total_length
+        .checked_sub(Footer::size(header.version) + SIGNATURE_SIZE)
+        .filter(|&end| end >= header.size())
+        .ok_or_else(|| {
+            ArchiveError::CorruptedArchive("Archive is too short for its signature".to_string())
+        })?;
+
+    reader.seek(SeekFrom::Start(end))?;
+    let mut signature = [0u8; SIGNATURE_SIZE as usize];
+    reader.read_exact(&mut signature)?;
+    let signature = Signature::from_bytes(&signature);
+
+    let digest = digest(&mut reader, end, &mut buffer)?;
+    key.verify_prehashed_strict(digest, Some(CONTEXT), &signature)
+        .map_err(|_| {
+            ArchiveError::SignatureInvalid(
+                "Signature of the archive doesn't match the public key".to_string(),
+            )
+        })
+}
+
+/// Hashes the first `length` bytes of the archive.
+fn digest<R: Read + Seek>(reader: &mut R, length: u64, buffer: &mut [u8]) -> Result<Sha512> {
+    reader.seek(SeekFrom::Start(0))?;
+
+    let mut digest = Sha512::new();
+    let mut remaining = length;
+    while remaining > 0 {
+        let to_read = remaining.min(buffer.len() as u64) as usize;
+        reader.read_exact(&mut buffer[..to_read])?;
+        digest.update(&buffer[..to_read]);
+        remaining -= to_read as u64;
+    }
+    Ok(digest)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use ed25519_dalek::pkcs8::{EncodePrivateKey, spki::der::pem::LineEnding};
+
+    use super::*;
+    use crate::{
+        pack::{PackOptions, pack},
+        testing::{TempDir, noise},
+    };
+
+    /// Packs `contents` signed with the key of `seed`, into volumes of
+    /// `split` bytes if given, and returns the path of the archive to read.
+    fn signed_archive(dir: &TempDir, seed: u8, contents: &[u8], split: Option<u64>) -> PathBuf {
+        let key = dir.join("key.pem");
+        let pem = SigningKey::from_bytes(&[seed; 32])
+            .to_pkcs8_pem(LineEnding::LF)
+            .unwrap();
+        fs::write(&key, pem.as_bytes()).unwrap();
+
+        let source = dir.write_files("src", &[("a.txt", contents)]);
+        let options = PackOptions {
+            sign: Some(key),
+            split,
+            ..PackOptions::default()
+        };
+        pack(source, Some(dir.join("signed")), &options).unwrap();
+        match split {
+            Some(_) => dir.join("signed/src.slf.001"),
+            None => dir.join("signed/src.slf"),
+        }
+    }
+
+    #[test]
+    fn signatures_match_only_their_key_and_contents() {
+        let dir = TempDir::new("signing");
+        let path = signed_archive(&dir, 1, &b"line of text\n".repeat(100), None);
+        let key = SigningKey::from_bytes(&[1; 32]).verifying_key();
+        verify_signature(&path, &key).unwrap();
+
+        let other = SigningKey::from_bytes(&[2; 32]).verifying_key();
+        assert!(matches!(
+            verify_signature(&path, &other),
+            Err(ArchiveError::SignatureInvalid(_))
+        ));
+
+        // the last byte of the contents, right before the index
+        let mut archive = fs::read(&path).unwrap();
+        let header = Header::from_archive(&mut archive.as_slice(), &mut [0; BUFFER_SIZE]).unwrap();
+        archive[header.index_offset as usize - 1] ^= 0xff;
+        fs::write(&path, archive).unwrap();
+        assert!(matches!(
+            verify_signature(&path, &key),
+            Err(ArchiveError::SignatureInvalid(_))
+        ));
+    }
+
+    #[test]
+    fn split_archives_are_signed_across_volumes() {
+        let dir = TempDir::new("signing-split");
+        let path = signed_archive(&dir, 1, &noise(5000), Some(1024));
+        assert!(dir.join("signed/src.slf.005").exists());
+        let key = SigningKey::from_bytes(&[1; 32]).verifying_key();
+        verify_signature(&path, &key).unwrap();
+
+        let middle = dir.join("signed/src.slf.003");
+        let mut volume = fs::read(&middle).unwrap();
+        volume[0] ^= 0xff;
+        fs::write(&middle, volume).unwrap();
+        assert!(matches!(
+            verify_signature(&path, &key),
+            Err(ArchiveError::SignatureInvalid(_))
+        ));
+    }
+}
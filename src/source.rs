@@ -0,0 +1,104 @@
+use std::{
+    io::{self, BufReader, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+#[cfg(feature = "mmap")]
+use std::{fs::File, io::Cursor};
+
+#[cfg(feature = "mmap")]
+use log::debug;
+#[cfg(feature = "mmap")]
+use memmap2::Mmap;
+
+use crate::{error::Result, volume::VolumeReader};
+
+/// Contents of an archive on disk. With the `mmap` feature the file is
+/// mapped into memory, so seeking between entries costs nothing and the
+/// fields are read straight from the mapped pages. Split archives, and
+/// files which fail to map, are read through a buffer instead.
+///
+/// A mapped archive must not be modified while it's read, e.g. by another
+/// process packing into the same path.
+pub enum ArchiveSource {
+    Buffered(BufReader<VolumeReader>),
+    #[cfg(feature = "mmap")]
+    Mapped(Cursor<Mmap>),
+}
+
+impl ArchiveSource {
+    /// Opens the archive at `path`, `capacity` being the size of the buffer
+    /// used when it isn't mapped.
+    pub fn open<P: AsRef<Path>>(path: P, capacity: usize) -> Result<Self> {
+        let path = path.as_ref();
+
+        #[cfg(feature = "mmap")]
+        if !crate::volume::is_first_volume(path) {
+            match map(path) {
+                Ok(map) => return Ok(Self::Mapped(Cursor::new(map))),
+                Err(e) => debug!(
+                    "Failed to map '{}', reading it buffered: {}",
+                    path.display(),
+                    e
+                ),
+            }
+        }
+
+        let volumes = VolumeReader::open(path)?;
+        Ok(Self::Buffered(BufReader::with_capacity(capacity, volumes)))
+    }
+
+    /// Whether the archive is mapped into memory.
+    pub fn is_mapped(&self) -> bool {
+        !matches!(self, Self::Buffered(_))
+    }
+
+    /// Length of the whole archive, all volumes included.
+    pub fn len(&self) -> u64 {
+        match self {
+            Self::Buffered(reader) => reader.get_ref().len(),
+            #[cfg(feature = "mmap")]
+            Self::Mapped(cursor) => cursor.get_ref().len() as u64,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(feature = "mmap")]
+fn map(path: &Path) -> io::Result<Mmap> {
+    let file = File::open(path)?;
+    // SAFETY: the archive is only read, changing it meanwhile is documented
+    // on `ArchiveSource` as not allowed
+    unsafe { Mmap::map(&file) }
+}
+
+impl Read for ArchiveSource {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Self::Buffered(reader) => reader.read(buf),
+            #[cfg(feature = "mmap")]
+            Self::Mapped(cursor) => cursor.read(buf),
+        }
+    }
+}
+
+impl Seek for ArchiveSource {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match self {
+            Self::Buffered(reader) => reader.seek(pos),
+            #[cfg(feature = "mmap")]
+            Self::Mapped(cursor) => cursor.seek(pos),
+        }
+    }
+
+    fn stream_position(&mut self) -> io::Result<u64> {
+        match self {
+            Self::Buffered(reader) => reader.stream_position(),
+            #[cfg(feature = "mmap")]
+            Self::Mapped(cursor) => cursor.stream_position(),
+        }
+    }
+}
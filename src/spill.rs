@@ -0,0 +1,110 @@
+use std::{
+    fs::{File, OpenOptions, remove_file},
+    io::{self, Seek, SeekFrom, Write},
+    path::PathBuf,
+};
+
+/// Compressed bytes of a file kept in memory by a packing thread before
+/// they're moved to a temporary file, see `PackOptions::spill_threshold`.
+pub const DEFAULT_SPILL_THRESHOLD: usize = 8 << 20;
+
+/// Writer collecting bytes in memory until they exceed `threshold`, then
+/// moving them to a temporary file at `path`, which is removed on drop.
+pub struct SpillBuffer {
+    memory: Vec<u8>,
+    file: Option<File>,
+    path: PathBuf,
+    threshold: usize,
+}
+
+impl SpillBuffer {
+    pub fn new(path: PathBuf, threshold: usize) -> Self {
+        Self {
+            memory: Vec::new(),
+            file: None,
+            path,
+            threshold,
+        }
+    }
+
+    /// Whether the bytes were moved to the temporary file.
+    pub fn is_spilled(&self) -> bool {
+        self.file.is_some()
+    }
+
+    /// Writes everything collected into `writer`.
+    pub fn copy_to<W: Write>(&mut self, writer: &mut W) -> io::Result<u64> {
+        match &mut self.file {
+            Some(file) => {
+                file.seek(SeekFrom::Start(0))?;
+                io::copy(file, writer)
+            }
+            None => {
+                writer.write_all(&self.memory)?;
+                Ok(self.memory.len() as u64)
+            }
+        }
+    }
+}
+
+impl Write for SpillBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Some(file) = &mut self.file {
+            return file.write(buf);
+        }
+
+        if self.memory.len() + buf.len() <= self.threshold {
+            self.memory.extend_from_slice(buf);
+            return Ok(buf.len());
+        }
+
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&self.path)?;
+        file.write_all(&self.memory)?;
+        self.memory = Vec::new();
+        self.file.insert(file).write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.file {
+            Some(file) => file.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for SpillBuffer {
+    fn drop(&mut self) {
+        if self.file.take().is_some() {
+            let _ = remove_file(&self.path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    #[test]
+    fn spills_past_the_threshold() {
+        let dir = TempDir::new("spill-buffer");
+        let path = dir.join("spilled");
+        let mut buffer = SpillBuffer::new(path.clone(), 8);
+        buffer.write_all(b"12345678").unwrap();
+        assert!(!buffer.is_spilled());
+        buffer.write_all(b"9").unwrap();
+        assert!(buffer.is_spilled() && path.exists());
+        buffer.write_all(b" and more").unwrap();
+
+        let mut copied = Vec::new();
+        assert_eq!(buffer.copy_to(&mut copied).unwrap(), 18);
+        assert_eq!(copied, b"123456789 and more");
+        drop(buffer);
+        assert!(!path.exists());
+    }
+}
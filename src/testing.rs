@@ -0,0 +1,111 @@
+use std::{
+    env, fs,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    process,
+};
+
+use flate2::Crc;
+
+use crate::Version;
+
+/// Directory of a test below the temporary one, removed when dropped.
+pub(crate) struct TempDir(PathBuf);
+
+impl TempDir {
+    pub(crate) fn new(name: &str) -> Self {
+        let path = env::temp_dir().join(format!("sulfur-test-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+
+    pub(crate) fn join<P: AsRef<Path>>(&self, path: P) -> PathBuf {
+        self.0.join(path)
+    }
+
+    /// Writes `files` below `dir`, creating their parents.
+    pub(crate) fn write_files(&self, dir: &str, files: &[(&str, &[u8])]) -> PathBuf {
+        let dir = self.join(dir);
+        for (name, contents) in files {
+            let path = dir.join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+        dir
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Replaces the version of an archive with a footer checksum, updating the
+/// checksum.
+pub(crate) fn set_version(archive: &mut [u8], version: Version) {
+    archive[4..6].copy_from_slice(&version.to_bytes());
+    update_checksum(archive);
+}
+
+/// Recomputes the checksum at the end of an archive after it was edited.
+pub(crate) fn update_checksum(archive: &mut [u8]) {
+    let end = archive.len() - 4;
+    let mut crc = Crc::new();
+    crc.update(&archive[..end]);
+    archive[end..].copy_from_slice(&crc.sum().to_le_bytes());
+}
+
+/// Pseudorandom bytes that don't compress, the same for every call.
+pub(crate) fn noise(len: usize) -> Vec<u8> {
+    let mut state = 0x2545_f491_u32;
+    (0..len)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as u8
+        })
+        .collect()
+}
+
+/// Fails with the errors of `kinds` in turn, then passes reads and writes on
+/// to the wrapped reader or writer.
+pub(crate) struct Flaky<T> {
+    pub(crate) inner: T,
+    kinds: Vec<io::ErrorKind>,
+}
+
+impl<T> Flaky<T> {
+    pub(crate) fn new(inner: T, kinds: &[io::ErrorKind]) -> Self {
+        let mut kinds = kinds.to_vec();
+        kinds.reverse();
+        Self { inner, kinds }
+    }
+
+    fn fail(&mut self) -> io::Result<()> {
+        match self.kinds.pop() {
+            Some(kind) => Err(io::Error::new(kind, "flaky")),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<R: Read> Read for Flaky<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.fail()?;
+        self.inner.read(buf)
+    }
+}
+
+impl<W: Write> Write for Flaky<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.fail()?;
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
@@ -0,0 +1,246 @@
+use std::{
+    ffi::{OsStr, OsString},
+    mem,
+    str::FromStr,
+};
+
+use regex_automata::meta::Regex;
+
+use crate::{
+    error::{ArchiveError, Result},
+    name_from_bytes,
+};
+
+/// Rewrite rule of entry names in the form of sed's `s|pattern|replacement|`,
+/// with any delimiter following the `s`. The pattern is a regular expression,
+/// in the replacement `&` stands for the whole match and `\1` to `\9` for its
+/// groups, `\&` and `\\` for themselves. The `g` flag replaces every match
+/// instead of the first one, a delimiter inside the rule is escaped with a
+/// backslash.
+#[derive(Debug, Clone)]
+pub struct Transform {
+    regex: Regex,
+    replacement: Vec<Piece>,
+    global: bool,
+}
+
+/// Part of a replacement.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Piece {
+    Literal(Vec<u8>),
+    /// Group of the match, `0` for the whole match.
+    Group(usize),
+}
+
+impl Transform {
+    /// Applies the rule to `name`, leaving it as is when nothing matches.
+    pub fn apply(&self, name: &OsStr) -> Result<OsString> {
+        let name = name.as_encoded_bytes();
+
+        let mut result = Vec::with_capacity(name.len());
+        let mut last = 0;
+        for captures in self.regex.captures_iter(name) {
+            let Some(matched) = captures.get_match() else {
+                break;
+            };
+            result.extend_from_slice(&name[last..matched.start()]);
+            for piece in &self.replacement {
+                match piece {
+                    Piece::Literal(bytes) => result.extend_from_slice(bytes),
+                    // a group outside of the matching alternative is empty
+                    Piece::Group(group) => {
+                        if let Some(span) = captures.get_group(*group) {
+                            result.extend_from_slice(&name[span.range()]);
+                        }
+                    }
+                }
+            }
+            last = matched.end();
+            if !self.global {
+                break;
+            }
+        }
+        result.extend_from_slice(&name[last..]);
+        name_from_bytes(&result)
+    }
+
+    /// Applies every rule in order, each to the result of the previous one.
+    pub fn apply_all(transforms: &[Transform], name: &OsStr) -> Result<OsString> {
+        let mut name = name.to_os_string();
+        for transform in transforms {
+            name = transform.apply(&name)?;
+        }
+        Ok(name)
+    }
+}
+
+impl FromStr for Transform {
+    type Err = ArchiveError;
+
+    fn from_str(rule: &str) -> Result<Self> {
+        let invalid =
+            |reason: &str| ArchiveError::Usage(format!("Invalid transform '{}': {}", rule, reason));
+
+        let mut chars = rule.chars();
+        if chars.next() != Some('s') {
+            return Err(invalid("expected 's|pattern|replacement|'"));
+        }
+        let delimiter = chars.next().ok_or_else(|| invalid("missing delimiter"))?;
+
+        let mut parts = vec![String::new()];
+        let mut escaped = false;
+        for c in chars {
+            match c {
+                _ if escaped => {
+                    // the regex and the replacement read the other escapes
+                    if c != delimiter {
+                        parts.last_mut().unwrap().push('\\');
+                    }
+                    parts.last_mut().unwrap().push(c);
+                    escaped = false;
+                }
+                '\\' => escaped = true,
+                _ if c == delimiter && parts.len() < 3 => parts.push(String::new()),
+                _ => parts.last_mut().unwrap().push(c),
+            }
+        }
+
+        let [pattern, replacement, flags]: [String; 3] = parts
+            .try_into()
+            .map_err(|_| invalid("expected 's|pattern|replacement|'"))?;
+
+        let global = match flags.as_str() {
+            "" => false,
+            "g" => true,
+            _ => return Err(invalid("only the 'g' flag is supported")),
+        };
+
+        if pattern.is_empty() {
+            return Err(invalid("empty pattern"));
+        }
+        let regex = Regex::new(&pattern).map_err(|e| invalid(&e.to_string()))?;
+        let replacement = parse_replacement(&replacement);
+        let groups = regex.captures_len();
+        if let Some(group) = replacement.iter().find_map(|piece| match piece {
+            Piece::Group(group) if *group >= groups => Some(group),
+            _ => None,
+        }) {
+            return Err(invalid(&format!(
+                "the pattern has no group {} for '\\{}'",
+                group, group
+            )));
+        }
+
+        Ok(Self {
+            regex,
+            replacement,
+            global,
+        })
+    }
+}
+
+/// Splits a replacement into literal bytes and references to groups.
+fn parse_replacement(replacement: &str) -> Vec<Piece> {
+    let mut pieces = Vec::new();
+    let mut literal = String::new();
+    let mut chars = replacement.chars();
+    while let Some(c) = chars.next() {
+        let group = match c {
+            '&' => 0,
+            '\\' => match chars.next() {
+                Some(digit @ '0'..='9') => digit as usize - '0' as usize,
+                // `\&`, `\\` and any other escaped character stand for themselves
+                Some(c) => {
+                    literal.push(c);
+                    continue;
+                }
+                None => {
+                    literal.push('\\');
+                    continue;
+                }
+            },
+            c => {
+                literal.push(c);
+                continue;
+            }
+        };
+        if !literal.is_empty() {
+            pieces.push(Piece::Literal(mem::take(&mut literal).into_bytes()));
+        }
+        pieces.push(Piece::Group(group));
+    }
+    if !literal.is_empty() {
+        pieces.push(Piece::Literal(literal.into_bytes()));
+    }
+    pieces
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::{
+        InnerFile,
+        unpack::{UnpackOptions, entry_path},
+    };
+
+    fn apply(rule: &str, name: &str) -> String {
+        let transform: Transform = rule.parse().unwrap();
+        transform
+            .apply(OsStr::new(name))
+            .unwrap()
+            .into_string()
+            .unwrap()
+    }
+
+    #[test]
+    fn adds_and_removes_prefixes() {
+        assert_eq!(apply("s|^|lib/|", "a/b.rs"), "lib/a/b.rs");
+        assert_eq!(apply("s|^src/||", "src/main.rs"), "main.rs");
+        assert_eq!(apply("s|^src/||", "test/src/main.rs"), "test/src/main.rs");
+    }
+
+    #[test]
+    fn replaces_with_groups() {
+        assert_eq!(
+            apply(r"s|^src/(.*)\.rs$|lib/\1.txt|", "src/a/b.rs"),
+            "lib/a/b.txt"
+        );
+        assert_eq!(apply("s|[0-9]+|<&>|", "v12/v3"), "v<12>/v3");
+        assert_eq!(apply("s|[0-9]+|<&>|g", "v12/v3"), "v<12>/v<3>");
+        assert_eq!(apply(r"s|a|\&\\|", "a"), r"&\");
+        assert_eq!(apply(r"s,/,\,,g", "a/b/c"), "a,b,c");
+    }
+
+    #[test]
+    fn rejects_invalid_rules() {
+        for rule in [
+            "x|a|b|",
+            "s|a|b",
+            "s||b|",
+            "s|a|b|x",
+            "s|(a|b|",
+            r"s|(a)|\2|",
+        ] {
+            assert!(
+                matches!(rule.parse::<Transform>(), Err(ArchiveError::Usage(_))),
+                "{}",
+                rule
+            );
+        }
+    }
+
+    #[test]
+    fn traversal_is_rejected() {
+        let options = UnpackOptions {
+            transforms: vec!["s|^|../|".parse().unwrap()],
+            ..UnpackOptions::default()
+        };
+        let entry = InnerFile::create("a.txt".into(), 0, 0, 0, 0);
+        assert!(matches!(
+            entry_path(&entry, Path::new("out"), &options),
+            Err(ArchiveError::Path(_))
+        ));
+    }
+}
@@ -1,185 +1,2702 @@
 use std::{
-    fs::{File, create_dir_all},
-    io::{BufReader, BufWriter, Read, Write},
-    path::{Path, PathBuf},
+    collections::{BTreeMap, BTreeSet, HashMap},
+    ffi::{OsStr, OsString},
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    mem,
+    path::{Component, Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc::{self, Receiver, SyncSender},
+    },
+    thread,
+    time::SystemTime,
 };
 
-use flate2::{Crc, write::GzDecoder};
+use flate2::CrcReader;
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use log::{debug, error, info, log, warn};
 
 use crate::{
-    BUFFER_SIZE, HasherWriter, InnerFile, SIGNATURE, VERSION,
+    BUFFER_SIZE, CancelFlag, DEFAULT_BUFFER_CAPACITY, Footer, HasherWriter, Header, InnerFile,
+    MAX_ORIGINAL_SIZE, check_cancelled, check_xattrs_supported,
+    checksum::{Checksum, ChecksumAlgorithm},
+    compressor::Decompressor,
+    dedup::ChunkDecoder,
+    entry_capacity,
     error::{ArchiveError, Result},
+    filesystem::{FileSystem, OutputFile, RealFs},
+    limiter::RateLimiter,
+    log::Verbosity,
     normalize_path,
+    progress::{Phase, ProgressCallback, ProgressTracker},
+    reader::{read_index, read_index_positions, read_solid_block},
+    retry::{DEFAULT_IO_RETRIES, Retrying},
+    source::ArchiveSource,
+    stays_inside,
+    transform::Transform,
+    volume::logical_path,
 };
 
-pub fn unpack(source: PathBuf, target: Option<PathBuf>) -> Result<()> {
+#[cfg(feature = "remote")]
+use crate::remote::RangeReader;
+
+#[cfg(feature = "zip")]
+use crate::zipfile;
+
+pub struct UnpackOptions {
+    /// Restore the stored uid/gid of every file, requires root privileges.
+    pub preserve_owner: bool,
+    /// Skip damaged entries using the index array and report them at the end.
+    pub best_effort: bool,
+    /// Leave holes instead of writing long runs of zeros.
+    pub sparse: bool,
+    /// Extract single-file archives into the working directory, ignoring the target.
+    pub single_in_cwd: bool,
+    /// Maximum read throughput in bytes per second, 0 is unlimited.
+    pub rate_limit: u64,
+    /// Check the checksum covering the whole archive before extracting.
+    pub check_archive: bool,
+    /// Keep files on disk modified after the stored modification time of
+    /// their entry, which is skipped instead of overwriting them. Entries
+    /// without a stored time are always extracted.
+    pub skip_newer: bool,
+    /// Extract multi-file archives next to an existing non-empty directory of
+    /// the same name, as `name (1)`, instead of merging into it.
+    pub fresh: bool,
+    /// Fail on entry names differing only by case, always done on platforms
+    /// with case-insensitive file systems.
+    pub check_case: bool,
+    /// Count of leading path components removed from every entry name.
+    pub strip_components: usize,
+    /// Rewrite rules applied in order to every entry name, before
+    /// `strip_components`.
+    pub transforms: Vec<Transform>,
+    /// Extract only the entries whose stored names match any of the globs,
+    /// the others are skipped. See `glob_set` for the syntax.
+    pub matching: Option<GlobSet>,
+    /// Largest original size of an entry accepted, `MAX_ORIGINAL_SIZE` if unset.
+    pub max_entry_size: Option<u64>,
+    /// Permissions forced on every extracted file instead of the stored ones,
+    /// Unix only.
+    pub file_mode: Option<u32>,
+    /// Permissions forced on every created directory, Unix only.
+    pub dir_mode: Option<u32>,
+    /// Check checksums of extracted entries, sizes are checked regardless.
+    /// Solid archives are always verified.
+    pub verify: bool,
+    /// Count of threads extracting entries, each with its own handle of the
+    /// archive. Solid archives and archives without an index use one.
+    pub jobs: usize,
+    /// Decompress entries on one thread while another one writes the files
+    /// of the previous ones, see `write_outputs`. Applies to a single job,
+    /// solid archives and streams are extracted without. A failing write
+    /// stops extraction even with `best_effort`.
+    pub pipeline: bool,
+    /// Stops extraction once set, the file being written is left incomplete.
+    pub cancel: Option<CancelFlag>,
+    /// Capacity of the buffers in front of the archive and of every
+    /// extracted file, 0 reads and writes them directly.
+    pub buffer_capacity: usize,
+    /// Restore extended attributes stored with `PackOptions::xattrs`, needs
+    /// the `xattrs` feature and Unix.
+    pub xattrs: bool,
+    /// Retries of a write to an extracted file failing with a transient
+    /// error, see `Retrying`.
+    pub io_retries: u32,
+    /// Password of encrypted entries of ZIP archives.
+    pub password: Option<String>,
+    /// What gets reported besides errors, see `Verbosity`.
+    pub verbosity: Verbosity,
+    /// Called after every extracted or skipped entry.
+    pub progress: Option<ProgressCallback>,
+    /// Where the entries are written, the disk unless replaced, e.g. by a
+    /// `MemFs`.
+    pub file_system: Arc<dyn FileSystem>,
+}
+
+impl Default for UnpackOptions {
+    fn default() -> Self {
+        Self {
+            preserve_owner: false,
+            best_effort: false,
+            sparse: false,
+            single_in_cwd: false,
+            rate_limit: 0,
+            check_archive: false,
+            skip_newer: false,
+            fresh: false,
+            check_case: false,
+            strip_components: 0,
+            transforms: Vec::new(),
+            max_entry_size: None,
+            file_mode: None,
+            dir_mode: None,
+            verify: true,
+            jobs: 1,
+            pipeline: false,
+            cancel: None,
+            buffer_capacity: DEFAULT_BUFFER_CAPACITY,
+            xattrs: false,
+            io_retries: DEFAULT_IO_RETRIES,
+            matching: None,
+            password: None,
+            verbosity: Verbosity::Normal,
+            progress: None,
+            file_system: Arc::new(RealFs),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryStatus {
+    Created,
+    Overwritten,
+    Skipped,
+}
+
+/// Describes a single entry written by `unpack`.
+#[derive(Debug, Clone)]
+pub struct ExtractedEntry {
+    pub name: OsString,
+    pub original_size: u64,
+    pub path: PathBuf,
+    pub status: EntryStatus,
+    /// Entry this one is a hard link to, see `InnerFile::link_target`.
+    pub link_target: Option<OsString>,
+    /// Times of the directories above, see `InnerFile::dir_mtimes`.
+    pub dir_mtimes: Vec<(u16, SystemTime)>,
+}
+
+impl ExtractedEntry {
+    pub(crate) fn new(inner_file: &InnerFile, path: PathBuf, fs: &dyn FileSystem) -> Self {
+        let status = if fs.exists(&path) {
+            EntryStatus::Overwritten
+        } else {
+            EntryStatus::Created
+        };
+
+        Self {
+            name: inner_file.name.clone(),
+            original_size: inner_file.original_size,
+            path,
+            status,
+            link_target: inner_file.link_target.clone(),
+            dir_mtimes: inner_file.dir_mtimes.clone(),
+        }
+    }
+
+    pub(crate) fn skipped(inner_file: &InnerFile, dir_path: &Path) -> Self {
+        Self {
+            name: inner_file.name.clone(),
+            original_size: inner_file.original_size,
+            path: normalize_path(&dir_path.join(&inner_file.name)),
+            status: EntryStatus::Skipped,
+            link_target: inner_file.link_target.clone(),
+            dir_mtimes: inner_file.dir_mtimes.clone(),
+        }
+    }
+}
+
+/// Builds the set of `UnpackOptions::matching` from shell globs. `*` and
+/// `?` don't match a path separator, `**` matches any count of components.
+pub fn glob_set<S: AsRef<str>>(patterns: &[S]) -> Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let pattern = pattern.as_ref();
+        let glob = GlobBuilder::new(pattern)
+            .literal_separator(true)
+            .build()
+            .map_err(|e| ArchiveError::Usage(format!("Invalid glob '{}': {}", pattern, e)))?;
+        builder.add(glob);
+    }
+    builder
+        .build()
+        .map_err(|e| ArchiveError::Usage(format!("Invalid globs: {}", e)))
+}
+
+/// Blocks of zeros of this size are skipped in sparse mode.
+const SPARSE_BLOCK_SIZE: usize = 4096;
+
+pub fn unpack(
+    source: PathBuf,
+    target: Option<PathBuf>,
+    options: &UnpackOptions,
+) -> Result<Vec<ExtractedEntry>> {
+    check_xattrs_supported(options.xattrs)?;
+
     let target = if let Some(path) = target {
         path
     } else {
         PathBuf::from(source.parent().unwrap_or(Path::new(".")))
     };
 
+    if is_zip(&source) {
+        return unpack_zip(&source, &target, options);
+    }
+
     let extraction_path = get_extraction_path(&source, &target)?;
 
-    let file = File::open(&source)?;
-    let mut reader = BufReader::new(file);
+    let mut reader = ArchiveSource::open(&source, options.buffer_capacity)?;
     let mut buffer = [0u8; BUFFER_SIZE];
 
-    validate_archive(&mut reader, &mut buffer, &source)?;
+    let header = Header::from_archive(&mut reader, &mut buffer)?;
+    if header.has_footer() {
+        let footer = Footer::from_archive(&mut reader, &mut buffer, header.version)?;
+        if options.check_archive {
+            footer.verify_checksum(&mut reader, &mut buffer)?;
+        }
+    }
+    let file_count = header.file_count;
+
+    if options.check_case || cfg!(any(windows, target_os = "macos")) {
+        let entries = read_index(&mut reader, &header, &mut buffer)?;
+        check_case_collisions(&entries)?;
+        reader.seek(SeekFrom::Start(header.size()))?;
+    }
+
+    if !header.has_index() {
+        info!("Archive has no index, random access is unavailable");
+    }
+
+    let (total_bytes, total_compressed_bytes) = if options.progress.is_some() && header.has_index()
+    {
+        let entries = read_index(&mut reader, &header, &mut buffer)?;
+        reader.seek(SeekFrom::Start(header.size()))?;
+        (
+            Some(entries.iter().map(InnerFile::original_size).sum()),
+            (!header.is_solid()).then(|| entries.iter().map(InnerFile::compressed_size).sum()),
+        )
+    } else {
+        (None, None)
+    };
+    let progress = ProgressTracker::new(
+        options.progress.as_ref(),
+        Phase::Unpack,
+        file_count.into(),
+        total_bytes,
+        total_compressed_bytes,
+    );
+
+    let positions = if options.best_effort && header.has_index() {
+        let mut positions =
+            read_index_positions(&mut reader, &mut buffer, header.index_offset, file_count)?;
+        // entries are walked in the stored order, see `PackOptions::sort_by`
+        positions.sort_unstable();
+        Some(positions)
+    } else {
+        None
+    };
+
+    let dir_path = archive_dir(&source, extraction_path, file_count as usize, options)?;
+
+    if cfg!(not(unix)) && (options.file_mode.is_some() || options.dir_mode.is_some()) {
+        warn!("Changing permissions isn't supported on this platform, ignoring");
+    }
+
+    let extracted = if header.is_solid() {
+        unpack_solid(
+            &mut reader,
+            &header,
+            &dir_path,
+            &mut buffer,
+            options,
+            &progress,
+        )?
+    } else if options.jobs > 1 && header.has_index() {
+        let entries = read_index(&mut reader, &header, &mut buffer)?;
+        unpack_parallel(&source, &entries, &header, &dir_path, options, &progress)?
+    } else {
+        unpack_files(
+            &mut reader,
+            &header,
+            positions.as_deref(),
+            &dir_path,
+            &mut buffer,
+            options,
+            &progress,
+        )?
+    };
+    restore_hard_links(&extracted, &*options.file_system)?;
+    restore_dir_mtimes(&extracted, &dir_path, &*options.file_system)?;
 
-    reader.read_exact(&mut buffer[..4])?;
-    let file_count = u32::from_le_bytes(buffer[..4].try_into()?);
+    if let Some(mode) = options.dir_mode {
+        // the directory named after the archive is created by us as well
+        let root = if file_count > 1 {
+            dir_path.parent().unwrap_or(&dir_path)
+        } else {
+            &dir_path
+        };
+        apply_dir_mode(&extracted, root, mode, &*options.file_system)?;
+    }
 
-    reader.read_exact(&mut buffer[..8])?; // skip index offset
+    log_summary(&extracted, &dir_path, options);
+    Ok(extracted)
+}
 
+/// Directory the entries of `source` are extracted into, a new one named
+/// after the archive when it holds several files. Its parents are created.
+pub(crate) fn archive_dir(
+    source: &Path,
+    extraction_path: PathBuf,
+    file_count: usize,
+    options: &UnpackOptions,
+) -> Result<PathBuf> {
     let dir_path = if file_count > 1 {
-        let source_stem = source.file_stem().ok_or(ArchiveError::Path(format!(
+        let archive_path = logical_path(source);
+        let source_stem = archive_path.file_stem().ok_or(ArchiveError::Path(format!(
             "Failed to get file stem from path: {}",
             source.display()
         )))?;
-        extraction_path.join(source_stem)
+        let dir_path = extraction_path.join(source_stem);
+        if options.fresh {
+            fresh_dir(dir_path, &*options.file_system)?
+        } else {
+            dir_path
+        }
+    } else if options.single_in_cwd {
+        PathBuf::new()
     } else {
         extraction_path
     };
 
     if let Some(parents) = dir_path.parent() {
-        create_dir_all(parents)?;
+        options.file_system.create_dir_all(parents)?;
     }
+    Ok(dir_path)
+}
+
+/// Whether `source` starts with the signature of a ZIP archive, an empty
+/// one included. Errors are left for the regular checks to report.
+fn is_zip(source: &Path) -> bool {
+    let mut signature = [0u8; 4];
+    File::open(source)
+        .and_then(|mut file| file.read_exact(&mut signature))
+        .is_ok()
+        && (signature == *b"PK\x03\x04" || signature == *b"PK\x05\x06")
+}
 
-    unpack_files(&mut reader, file_count, &dir_path, &mut buffer)?;
+#[cfg(feature = "zip")]
+fn unpack_zip(
+    source: &Path,
+    target: &Path,
+    options: &UnpackOptions,
+) -> Result<Vec<ExtractedEntry>> {
+    zipfile::read_zip(source, target, options)
+}
 
-    Ok(())
+#[cfg(not(feature = "zip"))]
+fn unpack_zip(
+    _source: &Path,
+    _target: &Path,
+    _options: &UnpackOptions,
+) -> Result<Vec<ExtractedEntry>> {
+    Err(ArchiveError::Unsupported(
+        "ZIP archives aren't supported by this build".to_string(),
+    ))
 }
 
-fn validate_archive(reader: &mut BufReader<File>, buffer: &mut [u8], path: &PathBuf) -> Result<()> {
-    reader.read_exact(&mut buffer[..4])?;
-    if &buffer[..4] != SIGNATURE {
-        return Err(ArchiveError::Path(format!(
-            "File is corrupted or has incorrect type. File at path: {}",
-            path.display()
-        )));
+/// Whether `source` is a URL `unpack_url` reads from.
+pub fn is_url(source: &str) -> bool {
+    source.starts_with("http://") || source.starts_with("https://")
+}
+
+/// Extracts the entries selected by `UnpackOptions::matching` from the
+/// archive served at `url`. Only the header, the index and the selected
+/// entries are downloaded, see `RangeReader`, so the archive needs an index
+/// and can't be solid. The directory of several entries is named after the
+/// last segment of the URL.
+#[cfg(feature = "remote")]
+pub fn unpack_url(
+    url: &str,
+    target: PathBuf,
+    options: &UnpackOptions,
+) -> Result<Vec<ExtractedEntry>> {
+    check_xattrs_supported(options.xattrs)?;
+
+    let mut reader = RangeReader::open(url)?;
+    let mut buffer = [0u8; BUFFER_SIZE];
+
+    let header = Header::from_archive(&mut reader, &mut buffer)?;
+    if !header.has_index() || header.is_solid() {
+        return Err(ArchiveError::Unsupported(
+            "Only archives with an index which aren't solid can be read remotely".to_string(),
+        ));
+    }
+    let entries: Vec<InnerFile> = read_index(&mut reader, &header, &mut buffer)?
+        .into_iter()
+        .filter(|entry| is_selected(entry, options))
+        .collect();
+    if options.check_case || cfg!(any(windows, target_os = "macos")) {
+        check_case_collisions(&entries)?;
     }
 
-    reader.read_exact(&mut buffer[..2])?;
-    if buffer[0] != VERSION[0] {
+    let progress = ProgressTracker::new(
+        options.progress.as_ref(),
+        Phase::Unpack,
+        entries.len() as u64,
+        Some(entries.iter().map(InnerFile::original_size).sum()),
+        Some(entries.iter().map(InnerFile::compressed_size).sum()),
+    );
+    // the query and the fragment aren't part of the name
+    let source = Path::new(url.split(['?', '#']).next().unwrap_or(url));
+    let dir_path = archive_dir(source, target, header.file_count as usize, options)?;
+
+    let mut extraction = Extraction::new(options, &header, options.rate_limit, &progress);
+    let mut extracted = Vec::with_capacity(entries.len());
+    for entry in &entries {
+        check_cancelled(options.cancel.as_deref())?;
+        reader.seek(SeekFrom::Start(entry.position()))?;
+        let inner_file = InnerFile::from_archive(&mut reader, &mut buffer, header.version)?;
+        extracted.push(unpack_entry(
+            &inner_file,
+            &mut reader,
+            &dir_path,
+            &mut buffer,
+            &mut extraction,
+        )?);
+    }
+    restore_hard_links(&extracted, &*options.file_system)?;
+    restore_dir_mtimes(&extracted, &dir_path, &*options.file_system)?;
+
+    debug!(
+        "Downloaded {} of the {} bytes of '{}'",
+        reader.fetched(),
+        reader.len(),
+        url
+    );
+    log_summary(&extracted, &dir_path, options);
+    Ok(extracted)
+}
+
+#[cfg(not(feature = "remote"))]
+pub fn unpack_url(
+    _url: &str,
+    _target: PathBuf,
+    _options: &UnpackOptions,
+) -> Result<Vec<ExtractedEntry>> {
+    Err(ArchiveError::Unsupported(
+        "Remote archives aren't supported by this build".to_string(),
+    ))
+}
+
+/// Extracts an archive read sequentially from a reader which can't seek,
+/// such as standard input. Only the metadata preceding every entry is used,
+/// the index array and the footer are never read. Having no file name to
+/// derive a directory from, entries are extracted straight into `target`.
+pub fn unpack_stream<R: Read>(
+    reader: R,
+    target: PathBuf,
+    options: &UnpackOptions,
+) -> Result<Vec<ExtractedEntry>> {
+    check_xattrs_supported(options.xattrs)?;
+
+    if target.is_file() {
         return Err(ArchiveError::Path(format!(
-            "File is corrupted or has incorrect type. File at path: {}",
-            path.display()
+            "Archive can't be unpacked into file at path: {}",
+            target.display(),
         )));
     }
+
+    let mut reader = StreamReader::new(BufReader::with_capacity(options.buffer_capacity, reader));
+    let mut buffer = vec![0u8; BUFFER_SIZE];
+
+    let header = Header::from_archive(&mut reader, &mut buffer)?;
+    if header.is_dedup() {
+        return Err(ArchiveError::Unsupported(
+            "Deduplicated archives reference chunks by offset and can't be read as a stream"
+                .to_string(),
+        ));
+    }
+    if options.check_archive {
+        warn!("The archive checksum is stored at its end and can't be checked in a stream");
+    }
+
+    options.file_system.create_dir_all(&target)?;
+    let max_entry_size = options.max_entry_size.unwrap_or(MAX_ORIGINAL_SIZE);
+    let progress = ProgressTracker::new(
+        options.progress.as_ref(),
+        Phase::Unpack,
+        header.file_count.into(),
+        None,
+        None,
+    );
+
+    let extracted = if header.is_solid() {
+        unpack_solid(
+            &mut reader,
+            &header,
+            &target,
+            &mut buffer,
+            options,
+            &progress,
+        )?
+    } else {
+        let mut extraction = Extraction::new(options, &header, options.rate_limit, &progress);
+        let mut seen = HashMap::new();
+        let mut extracted = Vec::with_capacity(entry_capacity(header.file_count));
+
+        for _ in 0..header.file_count {
+            let inner_file =
+                InnerFile::read_metadata(&mut reader, &mut buffer, header.version, max_entry_size)?;
+            if options.check_case || cfg!(any(windows, target_os = "macos")) {
+                check_case_collision(&mut seen, &inner_file)?;
+            }
+
+            extracted.push(unpack_entry(
+                &inner_file,
+                &mut reader,
+                &target,
+                &mut buffer,
+                &mut extraction,
+            )?);
+        }
+        extracted
+    };
+    restore_hard_links(&extracted, &*options.file_system)?;
+    restore_dir_mtimes(&extracted, &target, &*options.file_system)?;
+
+    if let Some(mode) = options.dir_mode {
+        apply_dir_mode(&extracted, &target, mode, &*options.file_system)?;
+    }
+
+    log_summary(&extracted, &target, options);
+    Ok(extracted)
+}
+
+/// Replaces the empty files extracted for hard links by links to the files
+/// of their targets. A link whose target wasn't extracted, e.g. for not
+/// matching `UnpackOptions::matching`, is left empty.
+fn restore_hard_links(extracted: &[ExtractedEntry], fs: &dyn FileSystem) -> Result<()> {
+    let paths: HashMap<&OsStr, &Path> = extracted
+        .iter()
+        .filter(|e| e.status != EntryStatus::Skipped)
+        .map(|e| (e.name.as_os_str(), e.path.as_path()))
+        .collect();
+
+    for entry in extracted {
+        let Some(target) = &entry.link_target else {
+            continue;
+        };
+        if entry.status == EntryStatus::Skipped {
+            continue;
+        }
+
+        let Some(&target_path) = paths.get(target.as_os_str()) else {
+            warn!(
+                "'{}' is a hard link to '{}', which wasn't extracted, leaving it empty",
+                entry.path.display(),
+                target.to_string_lossy()
+            );
+            continue;
+        };
+        fs.remove_file(&entry.path)?;
+        fs.hard_link(target_path, &entry.path)?;
+        debug!(
+            "Linked '{}' to '{}'",
+            entry.path.display(),
+            target_path.display()
+        );
+    }
+    Ok(())
+}
+
+/// Restores the stored times of the directories below `root` last, as
+/// writing into a directory changes its time. Deepest first, the way tar
+/// does, so no parent is touched after its time is set. Directories of
+/// entries which weren't extracted are set if they exist anyway.
+fn restore_dir_mtimes(
+    extracted: &[ExtractedEntry],
+    root: &Path,
+    fs: &dyn FileSystem,
+) -> Result<()> {
+    let mut dirs = BTreeMap::new();
+    for entry in extracted {
+        for &(levels, mtime) in &entry.dir_mtimes {
+            // `strip_components` may leave fewer levels than were stored
+            if let Some(dir) = entry.path.ancestors().nth(levels.into())
+                && dir != root
+                && dir.starts_with(root)
+                && fs.exists(dir)
+            {
+                dirs.insert(dir, mtime);
+            }
+        }
+    }
+
+    for (dir, mtime) in dirs.into_iter().rev() {
+        match fs.set_modified(dir, mtime) {
+            Ok(()) => {}
+            Err(e) if is_unavailable(&e) => {
+                debug!(
+                    "Modification time of '{}' isn't restored: {}",
+                    dir.display(),
+                    e
+                )
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn log_summary(extracted: &[ExtractedEntry], dir_path: &Path, options: &UnpackOptions) {
+    let skipped = extracted
+        .iter()
+        .filter(|e| e.status == EntryStatus::Skipped)
+        .count();
+    log!(
+        options.verbosity.entry_level(),
+        "Extracted {} entries into '{}', {} skipped",
+        extracted.len() - skipped,
+        dir_path.display(),
+        skipped
+    );
+}
+
+/// Fails when two entries would end up as the same file on a
+/// case-insensitive file system.
+fn check_case_collisions(entries: &[InnerFile]) -> Result<()> {
+    let mut seen = HashMap::with_capacity(entries.len());
+    for entry in entries {
+        check_case_collision(&mut seen, entry)?;
+    }
+    Ok(())
+}
+
+/// Checks a single entry against the lowercased names `seen` before it.
+fn check_case_collision(seen: &mut HashMap<String, String>, entry: &InnerFile) -> Result<()> {
+    let name = entry.name.to_string_lossy().into_owned();
+    if let Some(other) = seen.insert(name.to_lowercase(), name.clone())
+        && other != name
+    {
+        return Err(ArchiveError::CaseCollision(other, name));
+    }
     Ok(())
 }
 
 fn unpack_files(
-    reader: &mut BufReader<File>,
-    file_count: u32,
+    reader: &mut ArchiveSource,
+    header: &Header,
+    positions: Option<&[u64]>,
     dir_path: &Path,
     buffer: &mut [u8],
-) -> Result<()> {
-    for _ in 0..file_count {
-        let inner_file = InnerFile::from_archive(reader, buffer)?;
+    options: &UnpackOptions,
+    progress: &ProgressTracker,
+) -> Result<Vec<ExtractedEntry>> {
+    let mut extraction = Extraction::new(options, header, options.rate_limit, progress);
+    let max_entry_size = options.max_entry_size.unwrap_or(MAX_ORIGINAL_SIZE);
+    if !options.pipeline {
+        return extract_in_order(
+            reader,
+            header,
+            positions,
+            buffer,
+            max_entry_size,
+            |inner_file, reader, buffer| {
+                unpack_entry(inner_file, reader, dir_path, buffer, &mut extraction)
+            },
+        );
+    }
 
-        let mut file_path = if file_count > 1 {
-            dir_path.join(&inner_file.name)
-        } else {
-            PathBuf::from(&inner_file.name)
-        };
+    thread::scope(|scope| {
+        let (sender, receiver) = mpsc::sync_channel(PIPELINE_BLOCKS);
+        let writer =
+            scope.spawn(|| write_outputs(receiver, Extraction::new(options, header, 0, progress)));
+
+        let extracted = extract_in_order(
+            reader,
+            header,
+            positions,
+            buffer,
+            max_entry_size,
+            |inner_file, reader, buffer| {
+                unpack_entry_pipelined(
+                    inner_file,
+                    reader,
+                    dir_path,
+                    buffer,
+                    &mut extraction,
+                    &sender,
+                )
+            },
+        );
+        drop(sender);
+        let written = writer.join().unwrap_or_else(|_| {
+            Err(ArchiveError::Io(
+                "Writer thread panicked while extracting".to_string(),
+            ))
+        });
+        match (extracted, written) {
+            // the reader fails to send once the writer stopped on an error,
+            // which is the one explaining why
+            (Err(ArchiveError::Cancelled), Err(e)) => Err(e),
+            (Err(e), _) => Err(e),
+            (Ok(extracted), written) => written.map(|()| extracted),
+        }
+    })
+}
+
+/// Reads the entries one after the other, extracting each with `extract`.
+/// With `positions`, which `UnpackOptions::best_effort` reads, a failed entry
+/// is logged and extraction continues with the next one.
+fn extract_in_order(
+    reader: &mut ArchiveSource,
+    header: &Header,
+    positions: Option<&[u64]>,
+    buffer: &mut [u8],
+    max_entry_size: u64,
+    mut extract: impl FnMut(&InnerFile, &mut ArchiveSource, &mut [u8]) -> Result<ExtractedEntry>,
+) -> Result<Vec<ExtractedEntry>> {
+    let (version, file_count) = (header.version, header.file_count);
+    let mut extracted = Vec::with_capacity(entry_capacity(file_count));
+    let mut failed = Vec::new();
 
-        file_path = normalize_path(&file_path);
+    for i in 0..file_count as usize {
+        let mut name = format!("#{}", i + 1);
 
-        if let Some(parents) = file_path.parent() {
-            create_dir_all(parents)?;
+        let result = InnerFile::from_archive_with_limit(reader, buffer, version, max_entry_size)
+            .and_then(|inner_file| {
+                name = inner_file.name.to_string_lossy().into_owned();
+                extract(&inner_file, reader, buffer)
+            });
+
+        match result {
+            Ok(entry) => extracted.push(entry),
+            // the following entries would fail the same way, see `send`
+            Err(ArchiveError::Cancelled) => return Err(ArchiveError::Cancelled),
+            Err(e) => {
+                let Some(positions) = positions else {
+                    return Err(e);
+                };
+
+                error!("Failed to extract '{}': {}", name, e);
+                failed.push(name);
+
+                if let Some(&next) = positions.get(i + 1) {
+                    reader.seek(SeekFrom::Start(next))?;
+                }
+            }
         }
+    }
 
-        let file = File::create(file_path)?;
-        let mut writer = BufWriter::new(file);
+    if !failed.is_empty() {
+        return Err(ArchiveError::CorruptedArchive(format!(
+            "Failed to extract {} of {} entries: {}",
+            failed.len(),
+            file_count,
+            failed.join(", ")
+        )));
+    }
+    Ok(extracted)
+}
+
+/// Extracts entries on `options.jobs` threads. Every thread opens its own
+/// handle of the archive and takes the next entry from the index until none
+/// are left. `create_dir_all` tolerates directories created concurrently.
+fn unpack_parallel(
+    source: &Path,
+    entries: &[InnerFile],
+    header: &Header,
+    dir_path: &Path,
+    options: &UnpackOptions,
+    progress: &ProgressTracker,
+) -> Result<Vec<ExtractedEntry>> {
+    let jobs = options.jobs.min(entries.len()).max(1);
+    let max_entry_size = options.max_entry_size.unwrap_or(MAX_ORIGINAL_SIZE);
+    // the limit is shared by all threads
+    let rate_limit = if options.rate_limit > 0 {
+        (options.rate_limit / jobs as u64).max(1)
+    } else {
+        0
+    };
 
-        let hasher = Crc::new();
-        let mut hasher_writer = HasherWriter::new(&mut writer, hasher);
+    let next = AtomicUsize::new(0);
+    let stop = AtomicBool::new(false);
 
-        let (original_checksum, compressed_checksum) =
-            unpack_single_file(&inner_file, reader, &mut hasher_writer, buffer)?;
+    let worker = || -> Result<Vec<(usize, Result<ExtractedEntry>)>> {
+        let mut reader = ArchiveSource::open(source, options.buffer_capacity)?;
+        let mut buffer = vec![0u8; BUFFER_SIZE];
+        let mut extraction = Extraction::new(options, header, rate_limit, progress);
+        let mut done = Vec::new();
 
-        if original_checksum != inner_file.original_checksum {
-            return Err(ArchiveError::CorruptedArchive(format!(
-                "Archive corrupted! Unpacked checksums isn't equal to! {} isn't equal to {}",
-                original_checksum, inner_file.original_checksum
-            )));
+        while !stop.load(Ordering::Relaxed) {
+            let i = next.fetch_add(1, Ordering::Relaxed);
+            let Some(entry) = entries.get(i) else {
+                break;
+            };
+
+            let result = reader
+                .seek(SeekFrom::Start(entry.position()))
+                .map_err(ArchiveError::from)
+                .and_then(|_| {
+                    InnerFile::from_archive_with_limit(
+                        &mut reader,
+                        &mut buffer,
+                        header.version,
+                        max_entry_size,
+                    )
+                })
+                .and_then(|inner_file| {
+                    unpack_entry(
+                        &inner_file,
+                        &mut reader,
+                        dir_path,
+                        &mut buffer,
+                        &mut extraction,
+                    )
+                });
+
+            if result.is_err() && !options.best_effort {
+                stop.store(true, Ordering::Relaxed);
+            }
+            done.push((i, result));
         }
+        Ok(done)
+    };
 
-        if compressed_checksum != inner_file.compressed_checksum {
-            return Err(ArchiveError::CorruptedArchive(format!(
-                "Archive corrupted! Unpacked checksums isn't equal to! {} isn't equal to {}",
-                compressed_checksum, inner_file.compressed_checksum
-            )));
+    let mut results = Vec::with_capacity(entries.len());
+    thread::scope(|scope| -> Result<()> {
+        let workers: Vec<_> = (0..jobs).map(|_| scope.spawn(worker)).collect();
+        // every thread is joined before returning, a panic left unjoined
+        // would be raised again at the end of the scope
+        let joined: Vec<_> = workers
+            .into_iter()
+            .map(|worker| {
+                worker.join().unwrap_or_else(|_| {
+                    Err(ArchiveError::Io(
+                        "Extraction thread panicked while extracting".to_string(),
+                    ))
+                })
+            })
+            .collect();
+        for done in joined {
+            results.extend(done?);
         }
+        Ok(())
+    })?;
+    results.sort_by_key(|(i, _)| *i);
 
-        let size = hasher_writer.take_written_bytes();
-        if inner_file.original_size != size {
-            return Err(ArchiveError::CorruptedArchive(format!(
-                "Archive corrupted! Unpacked file has another size! {} isn't equal to {}",
-                inner_file.original_size, size
-            )));
+    let mut extracted = Vec::with_capacity(results.len());
+    let mut failed = Vec::new();
+    for (i, result) in results {
+        match result {
+            Ok(entry) => extracted.push(entry),
+            Err(e) if options.best_effort => {
+                let name = entries[i].name.to_string_lossy().into_owned();
+                error!("Failed to extract '{}': {}", name, e);
+                failed.push(name);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    if !failed.is_empty() {
+        return Err(ArchiveError::CorruptedArchive(format!(
+            "Failed to extract {} of {} entries: {}",
+            failed.len(),
+            entries.len(),
+            failed.join(", ")
+        )));
+    }
+    Ok(extracted)
+}
+
+/// State shared by the entries extracted on one thread.
+struct Extraction<'a> {
+    options: &'a UnpackOptions,
+    /// Dictionary of the archive, see `Method::Deflate`.
+    dictionary: &'a [u8],
+    /// Algorithm of the original checksums of the archive.
+    checksum: ChecksumAlgorithm,
+    /// Cleared once restoring an owner fails for lack of privileges.
+    preserve_owner: bool,
+    /// Cleared once the target doesn't accept extended attributes.
+    xattrs: bool,
+    limiter: RateLimiter,
+    progress: &'a ProgressTracker,
+}
+
+impl<'a> Extraction<'a> {
+    fn new(
+        options: &'a UnpackOptions,
+        header: &'a Header,
+        rate_limit: u64,
+        progress: &'a ProgressTracker,
+    ) -> Self {
+        Self {
+            options,
+            dictionary: &header.dictionary,
+            checksum: header.checksum,
+            preserve_owner: options.preserve_owner,
+            xattrs: options.xattrs,
+            limiter: RateLimiter::new(rate_limit),
+            progress,
+        }
+    }
+}
+
+fn unpack_entry<R: Read + Seek>(
+    inner_file: &InnerFile,
+    reader: &mut R,
+    dir_path: &Path,
+    buffer: &mut [u8],
+    extraction: &mut Extraction,
+) -> Result<ExtractedEntry> {
+    let file_path = match extraction_target(inner_file, dir_path, extraction.options)? {
+        Target::Extract(path) => path,
+        Target::Skip(entry) => return skip_entry(inner_file, reader, entry, extraction),
+    };
+
+    let (entry, mut writer) = create_output(inner_file, file_path, extraction.options)?;
+    let size = decode_entry(inner_file, reader, &mut writer, buffer, extraction)?;
+    finish_entry(writer, inner_file, &entry.path, size, extraction)?;
+    Ok(entry)
+}
+
+/// Same as `unpack_entry`, but the decompressed contents are sent to the
+/// thread running `write_outputs` in blocks, which writes the file and
+/// restores its metadata once the contents are verified.
+fn unpack_entry_pipelined<R: Read + Seek>(
+    inner_file: &InnerFile,
+    reader: &mut R,
+    dir_path: &Path,
+    buffer: &mut [u8],
+    extraction: &mut Extraction,
+    sender: &SyncSender<Output>,
+) -> Result<ExtractedEntry> {
+    let file_path = match extraction_target(inner_file, dir_path, extraction.options)? {
+        Target::Extract(path) => path,
+        Target::Skip(entry) => return skip_entry(inner_file, reader, entry, extraction),
+    };
+
+    let (entry, writer) = create_output(inner_file, file_path, extraction.options)?;
+    send(sender, Output::Create(writer))?;
+    let mut writer = BlockSender {
+        sender,
+        block: Vec::with_capacity(PIPELINE_BLOCK_SIZE),
+    };
+    let size = decode_entry(inner_file, reader, &mut writer, buffer, extraction)?;
+    writer.flush()?;
+    send(
+        sender,
+        Output::Finish(Box::new(inner_file.clone()), entry.path.clone(), size),
+    )?;
+    Ok(entry)
+}
+
+/// Size of the blocks of decompressed contents sent to the writer thread.
+const PIPELINE_BLOCK_SIZE: usize = 256 * 1024;
+
+/// Blocks on the way to the writer thread, so decompression stays at most
+/// this far ahead of the disk.
+const PIPELINE_BLOCKS: usize = 16;
+
+/// What the writer thread of `UnpackOptions::pipeline` is told to do.
+enum Output {
+    /// Write the following blocks into this file.
+    Create(OutputWriter),
+    Write(Vec<u8>),
+    /// The contents of the entry, of this path and size, are written and
+    /// verified, so the file is finished.
+    Finish(Box<InnerFile>, PathBuf, u64),
+}
+
+/// Fails with `ArchiveError::Cancelled` once the writer thread stopped on an
+/// error, which it returns itself.
+fn send(sender: &SyncSender<Output>, output: Output) -> Result<()> {
+    sender.send(output).map_err(|_| ArchiveError::Cancelled)
+}
+
+/// Collects decompressed contents into blocks for the writer thread.
+struct BlockSender<'a> {
+    sender: &'a SyncSender<Output>,
+    block: Vec<u8>,
+}
+
+impl Write for BlockSender<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let bytes = buf.len().min(PIPELINE_BLOCK_SIZE - self.block.len());
+        self.block.extend_from_slice(&buf[..bytes]);
+        if self.block.len() == PIPELINE_BLOCK_SIZE {
+            self.flush()?;
+        }
+        Ok(bytes)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.block.is_empty() {
+            return Ok(());
+        }
+        let block = mem::replace(&mut self.block, Vec::with_capacity(PIPELINE_BLOCK_SIZE));
+        send(self.sender, Output::Write(block)).map_err(io::Error::other)
+    }
+}
+
+/// Runs on the writer thread of `UnpackOptions::pipeline` until the reader
+/// hangs up. Stops at the first error, so the reader fails to send next.
+fn write_outputs(receiver: Receiver<Output>, mut extraction: Extraction) -> Result<()> {
+    let mut writer = None;
+    for output in receiver {
+        match output {
+            Output::Create(output) => writer = Some(output),
+            Output::Write(block) => {
+                if let Some(writer) = &mut writer {
+                    writer.write_all(&block)?;
+                }
+            }
+            Output::Finish(inner_file, file_path, size) => {
+                if let Some(writer) = writer.take() {
+                    finish_entry(writer, &inner_file, &file_path, size, &mut extraction)?;
+                }
+            }
         }
     }
     Ok(())
 }
 
-fn get_extraction_path(source: &PathBuf, target: &PathBuf) -> Result<PathBuf> {
-    let source = normalize_path(source);
-    let target = normalize_path(target);
+/// Where an entry goes, see `extraction_target`.
+enum Target {
+    Extract(PathBuf),
+    Skip(ExtractedEntry),
+}
 
-    if !source.exists() || !source.is_file() || !source.extension().map_or(false, |ex| ex == "slf")
-    {
-        return Err(ArchiveError::Path(format!(
-            "Invalid source destination at path: {}",
-            source.display()
+/// Path the entry is extracted to, unless it isn't selected, has too few
+/// components or is older than the file on disk, which is logged.
+fn extraction_target(
+    inner_file: &InnerFile,
+    dir_path: &Path,
+    options: &UnpackOptions,
+) -> Result<Target> {
+    let selected = is_selected(inner_file, options);
+    let file_path = if selected {
+        entry_path(inner_file, dir_path, options)?
+    } else {
+        None
+    };
+    let Some(file_path) = file_path else {
+        if selected {
+            warn_too_short(inner_file, options.strip_components);
+        }
+        return Ok(Target::Skip(ExtractedEntry::skipped(inner_file, dir_path)));
+    };
+    if is_newer_on_disk(&file_path, inner_file, options) {
+        return Ok(Target::Skip(ExtractedEntry {
+            path: file_path,
+            ..ExtractedEntry::skipped(inner_file, dir_path)
+        }));
+    }
+    Ok(Target::Extract(file_path))
+}
+
+/// Creates the file of an entry and its parents.
+fn create_output(
+    inner_file: &InnerFile,
+    file_path: PathBuf,
+    options: &UnpackOptions,
+) -> Result<(ExtractedEntry, OutputWriter)> {
+    if let Some(parents) = file_path.parent() {
+        options.file_system.create_dir_all(parents)?;
+    }
+
+    let entry = ExtractedEntry::new(inner_file, file_path, &*options.file_system);
+    let writer = OutputWriter::create(&entry.path, options)?;
+    Ok((entry, writer))
+}
+
+/// Decompresses the contents of the entry into `writer`, checking their
+/// checksum and size. Returns the size.
+fn decode_entry<R: Read + Seek, W: Write>(
+    inner_file: &InnerFile,
+    reader: &mut R,
+    writer: W,
+    buffer: &mut [u8],
+    extraction: &mut Extraction,
+) -> Result<u64> {
+    let options = extraction.options;
+    let mut hasher_writer = if options.verify {
+        HasherWriter::new(writer, Checksum::new(extraction.checksum))
+    } else {
+        HasherWriter::counting(writer)
+    };
+
+    let original_checksum = unpack_single_file(
+        inner_file,
+        reader,
+        &mut hasher_writer,
+        buffer,
+        extraction.dictionary,
+        options,
+        &mut extraction.limiter,
+    )?;
+
+    if options.verify && original_checksum != inner_file.original_checksum {
+        return Err(ArchiveError::CorruptedArchive(format!(
+            "Archive corrupted! Unpacked checksums isn't equal to! {} isn't equal to {}",
+            original_checksum, inner_file.original_checksum
         )));
     }
 
-    Ok(if target.is_file() {
-        return Err(ArchiveError::Path(format!(
-            "Archive can't be unpacked into file at path: {}",
-            target.display(),
+    let size = hasher_writer.take_written_bytes();
+    if inner_file.original_size != size {
+        return Err(ArchiveError::CorruptedArchive(format!(
+            "Archive corrupted! Unpacked file has another size! {} isn't equal to {}",
+            inner_file.original_size, size
         )));
-    } else {
-        target
-    })
+    }
+    Ok(size)
+}
+
+/// Flushes the file of a decoded entry and restores its metadata.
+fn finish_entry(
+    writer: OutputWriter,
+    inner_file: &InnerFile,
+    file_path: &Path,
+    size: u64,
+    extraction: &mut Extraction,
+) -> Result<()> {
+    let options = extraction.options;
+    writer.finish()?;
+    log!(
+        options.verbosity.entry_level(),
+        "Extracted '{}' ({} bytes{})",
+        file_path.display(),
+        size,
+        if options.verify {
+            ", checksums match"
+        } else {
+            ""
+        }
+    );
+
+    let fs = &*options.file_system;
+    apply_owner(fs, file_path, inner_file, &mut extraction.preserve_owner)?;
+    apply_xattrs(fs, file_path, inner_file, &mut extraction.xattrs)?;
+    apply_times_and_mode(fs, file_path, inner_file, options)?;
+    extraction
+        .progress
+        .advance(&inner_file.name, size, inner_file.compressed_size);
+    Ok(())
 }
 
-fn unpack_single_file(
+/// Seeks over the contents of an entry which isn't extracted.
+fn skip_entry<R: Read + Seek>(
     inner_file: &InnerFile,
-    reader: &mut BufReader<File>,
-    mut hasher_writer: &mut HasherWriter,
+    reader: &mut R,
+    entry: ExtractedEntry,
+    extraction: &mut Extraction,
+) -> Result<ExtractedEntry> {
+    reader.seek(SeekFrom::Current(i64::try_from(
+        inner_file.compressed_size,
+    )?))?;
+    extraction.progress.advance(
+        &inner_file.name,
+        inner_file.original_size,
+        inner_file.compressed_size,
+    );
+    Ok(entry)
+}
+
+/// Whether the file at `path` was modified after the entry, so it's kept,
+/// see `UnpackOptions::skip_newer`.
+fn is_newer_on_disk(path: &Path, inner_file: &InnerFile, options: &UnpackOptions) -> bool {
+    let newer = options.skip_newer
+        && inner_file.mtime.is_some_and(|mtime| {
+            options
+                .file_system
+                .modified(path)
+                .is_ok_and(|modified| modified > mtime)
+        });
+    if newer {
+        log!(
+            options.verbosity.entry_level(),
+            "Skipped '{}', the file on disk is newer",
+            path.display()
+        );
+    }
+    newer
+}
+
+/// Extracts an archive written with `FLAG_SOLID` by decompressing the single
+/// block once and slicing it into files by their original sizes.
+fn unpack_solid<R: Read + Seek>(
+    reader: &mut R,
+    header: &Header,
+    dir_path: &Path,
     buffer: &mut [u8],
-) -> Result<(u32, u32)> {
-    let mut compressed_checksum = Crc::new();
+    options: &UnpackOptions,
+    progress: &ProgressTracker,
+) -> Result<Vec<ExtractedEntry>> {
+    let file_count = header.file_count;
+    let mut preserve_owner = options.preserve_owner;
+    let mut xattrs = options.xattrs;
+    let mut extracted = Vec::with_capacity(entry_capacity(file_count));
 
-    let mut decoder = GzDecoder::new(&mut hasher_writer);
+    let mut inners = Vec::with_capacity(entry_capacity(file_count));
+    for _ in 0..file_count {
+        // solid entries have no compressed size of their own to check
+        inners.push(InnerFile::read_metadata(
+            reader,
+            buffer,
+            header.version,
+            options.max_entry_size.unwrap_or(MAX_ORIGINAL_SIZE),
+        )?);
+    }
 
-    let mut remaining_bytes = inner_file.compressed_size;
+    read_solid_block(
+        reader,
+        &inners,
+        header.checksum,
+        buffer,
+        |inner_file| {
+            check_cancelled(options.cancel.as_deref())?;
 
-    loop {
-        let to_read = remaining_bytes.min(BUFFER_SIZE as u64) as usize;
+            if !is_selected(inner_file, options) {
+                extracted.push(ExtractedEntry::skipped(inner_file, dir_path));
+                return Ok(OutputWriter::discard());
+            }
+            let Some(file_path) = entry_path(inner_file, dir_path, options)? else {
+                warn_too_short(inner_file, options.strip_components);
+                extracted.push(ExtractedEntry::skipped(inner_file, dir_path));
+                return Ok(OutputWriter::discard());
+            };
+            if is_newer_on_disk(&file_path, inner_file, options) {
+                extracted.push(ExtractedEntry {
+                    path: file_path,
+                    ..ExtractedEntry::skipped(inner_file, dir_path)
+                });
+                return Ok(OutputWriter::discard());
+            }
 
-        let bytes = reader.read(&mut buffer[..to_read])?;
+            if let Some(parents) = file_path.parent() {
+                options.file_system.create_dir_all(parents)?;
+            }
 
-        if bytes == 0 {
-            break;
-        }
+            let entry = ExtractedEntry::new(inner_file, file_path, &*options.file_system);
+            let writer = OutputWriter::create(&entry.path, options);
+            extracted.push(entry);
+            writer
+        },
+        |inner_file, writer| {
+            let skipped = writer.is_discarded();
+            writer.finish()?;
+            progress.advance(&inner_file.name, inner_file.original_size, 0);
+            if skipped {
+                return Ok(());
+            }
+            log!(
+                options.verbosity.entry_level(),
+                "Extracted '{}' from the solid block ({} bytes, checksum matches)",
+                inner_file.name.to_string_lossy(),
+                inner_file.original_size
+            );
 
-        let chunk = &buffer[..bytes];
+            let Some(file_path) = entry_path(inner_file, dir_path, options)? else {
+                return Ok(());
+            };
+            let fs = &*options.file_system;
+            apply_owner(fs, &file_path, inner_file, &mut preserve_owner)?;
+            apply_xattrs(fs, &file_path, inner_file, &mut xattrs)?;
+            apply_times_and_mode(fs, &file_path, inner_file, options)?;
+            Ok(())
+        },
+    )?;
 
-        compressed_checksum.update(chunk);
+    Ok(extracted)
+}
 
-        decoder.write_all(chunk)?;
+/// Reader of an archive which can't seek, counting the bytes read so the
+/// position is known. Seeking forward reads and discards the bytes in between,
+/// seeking backwards or from the end fails.
+struct StreamReader<R: Read> {
+    reader: R,
+    position: u64,
+}
 
-        remaining_bytes -= bytes as u64;
+impl<R: Read> StreamReader<R> {
+    fn new(reader: R) -> Self {
+        Self {
+            reader,
+            position: 0,
+        }
     }
+}
 
-    decoder.finish()?;
+impl<R: Read> Read for StreamReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let bytes = self.reader.read(buf)?;
+        self.position += bytes as u64;
+        Ok(bytes)
+    }
+}
 
-    let original_checksum = hasher_writer.sum();
-    let compressed_checksum = compressed_checksum.sum();
-    Ok((original_checksum, compressed_checksum))
+impl<R: Read> Seek for StreamReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+            SeekFrom::End(_) => None,
+        }
+        .filter(|&position| position >= self.position)
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::Unsupported,
+                "a stream can only be skipped forward",
+            )
+        })?;
+
+        let skipped = io::copy(&mut self.take(position - self.position), &mut io::sink())?;
+        if self.position != position {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("stream ended after {} of the skipped bytes", skipped),
+            ));
+        }
+        Ok(position)
+    }
+}
+
+/// Writer of an extracted file which, in sparse mode, seeks over blocks of
+/// zeros instead of writing them, leaving holes in the file. Without a file
+/// everything written is discarded, which is used for skipped entries.
+pub(crate) struct OutputWriter {
+    writer: Option<BufWriter<Retrying<Box<dyn OutputFile>>>>,
+    sparse: bool,
+}
+
+impl OutputWriter {
+    pub(crate) fn create(path: &Path, options: &UnpackOptions) -> Result<Self> {
+        let file = Retrying::new(options.file_system.create_file(path)?, options.io_retries);
+        Ok(Self {
+            writer: Some(BufWriter::with_capacity(options.buffer_capacity, file)),
+            sparse: options.sparse,
+        })
+    }
+
+    fn discard() -> Self {
+        Self {
+            writer: None,
+            sparse: false,
+        }
+    }
+
+    fn is_discarded(&self) -> bool {
+        self.writer.is_none()
+    }
+
+    pub(crate) fn finish(self) -> Result<()> {
+        let Some(mut writer) = self.writer else {
+            return Ok(());
+        };
+
+        writer.flush()?;
+        if self.sparse {
+            // trailing holes aren't materialized by seeking alone
+            let len = writer.stream_position()?;
+            writer.get_ref().get_ref().set_len(len)?;
+        }
+        Ok(())
+    }
+}
+
+impl Write for OutputWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let Some(writer) = &mut self.writer else {
+            return Ok(buf.len());
+        };
+
+        if !self.sparse {
+            return writer.write(buf);
+        }
+
+        for block in buf.chunks(SPARSE_BLOCK_SIZE) {
+            if block.len() == SPARSE_BLOCK_SIZE && block.iter().all(|&byte| byte == 0) {
+                writer.seek(SeekFrom::Current(block.len() as i64))?;
+            } else {
+                writer.write_all(block)?;
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.writer {
+            Some(writer) => writer.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Whether the stored name of the entry matches `UnpackOptions::matching`.
+pub(crate) fn is_selected(inner_file: &InnerFile, options: &UnpackOptions) -> bool {
+    options
+        .matching
+        .as_ref()
+        .is_none_or(|globs| globs.is_match(Path::new(&inner_file.name)))
+}
+
+/// Returns `None` when the name has no components left after applying the
+/// transforms and stripping `strip_components` leading ones. Names pointing
+/// outside of `dir_path` are rejected.
+pub(crate) fn entry_path(
+    inner_file: &InnerFile,
+    dir_path: &Path,
+    options: &UnpackOptions,
+) -> Result<Option<PathBuf>> {
+    let name = Transform::apply_all(&options.transforms, &inner_file.name)?;
+
+    let mut stripped = PathBuf::new();
+    let mut skipped = 0;
+
+    for component in Path::new(&name).components() {
+        if skipped < options.strip_components && matches!(component, Component::Normal(_)) {
+            skipped += 1;
+            continue;
+        }
+        stripped.push(component);
+    }
+
+    if stripped.as_os_str().is_empty() {
+        return Ok(None);
+    }
+    if !stays_inside(&stripped) {
+        return Err(ArchiveError::Path(format!(
+            "Entry '{}' points outside of the target directory",
+            name.to_string_lossy()
+        )));
+    }
+    let path = normalize_path(&dir_path.join(stripped));
+    check_path_length(&path, &name)?;
+    Ok(Some(long_path(path)?))
+}
+
+/// Longest path component the file systems of the platform accept, in bytes
+/// on Unix and UTF-16 units on Windows.
+const MAX_COMPONENT_LEN: usize = 255;
+
+/// Longest path accepted by the system calls, `PATH_MAX` including the NUL.
+#[cfg(all(unix, not(target_os = "macos")))]
+const MAX_PATH_LEN: usize = 4096;
+#[cfg(target_os = "macos")]
+const MAX_PATH_LEN: usize = 1024;
+
+/// Fails with a clear error before extraction gets to an entry whose path
+/// the system would refuse, leaving a partial extraction behind.
+#[cfg(unix)]
+fn check_path_length(path: &Path, name: &OsStr) -> Result<()> {
+    check_components(path, name, |component| component.len())?;
+    let len = path.as_os_str().len() + 1;
+    if len > MAX_PATH_LEN {
+        return Err(ArchiveError::Path(format!(
+            "Entry '{}' would be extracted to a path of {} bytes, longer than the limit of {}",
+            name.to_string_lossy(),
+            len,
+            MAX_PATH_LEN
+        )));
+    }
+    Ok(())
+}
+
+/// Only components are limited, `long_path` lifts the limit of the path.
+#[cfg(windows)]
+fn check_path_length(path: &Path, name: &OsStr) -> Result<()> {
+    use std::os::windows::ffi::OsStrExt;
+    check_components(path, name, |component| component.encode_wide().count())
+}
+
+fn check_components(path: &Path, name: &OsStr, len: impl Fn(&OsStr) -> usize) -> Result<()> {
+    for component in path.components() {
+        if let Component::Normal(component) = component
+            && len(component) > MAX_COMPONENT_LEN
+        {
+            return Err(ArchiveError::Path(format!(
+                "Entry '{}' has a path component of {} characters, longer than the limit of {}",
+                name.to_string_lossy(),
+                len(component),
+                MAX_COMPONENT_LEN
+            )));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn long_path(path: PathBuf) -> Result<PathBuf> {
+    Ok(path)
+}
+
+/// Paths beyond `MAX_PATH` get the `\\?\` prefix, which lifts the limit
+/// but needs an absolute path.
+#[cfg(windows)]
+fn long_path(path: PathBuf) -> Result<PathBuf> {
+    const MAX_PATH: usize = 260;
+    if path.as_os_str().len() < MAX_PATH {
+        return Ok(path);
+    }
+
+    let absolute = std::path::absolute(&path)?;
+    let absolute = absolute.as_os_str().to_string_lossy();
+    let prefixed = if absolute.starts_with(r"\\?\") {
+        return Ok(path);
+    } else if let Some(share) = absolute.strip_prefix(r"\\") {
+        format!(r"\\?\UNC\{}", share)
+    } else {
+        format!(r"\\?\{}", absolute)
+    };
+    Ok(PathBuf::from(prefixed))
+}
+
+pub(crate) fn warn_too_short(inner_file: &InnerFile, strip_components: usize) {
+    warn!(
+        "Skipping '{}': fewer than {} path components to strip",
+        inner_file.name.to_string_lossy(),
+        strip_components + 1
+    );
+}
+
+fn apply_owner(
+    fs: &dyn FileSystem,
+    path: &Path,
+    inner_file: &InnerFile,
+    preserve_owner: &mut bool,
+) -> Result<()> {
+    if *preserve_owner && !restore_owner(fs, path, inner_file)? {
+        warn!("Insufficient permissions to restore file owners, skipping ownership restoration");
+        *preserve_owner = false;
+    }
+    Ok(())
+}
+
+fn apply_xattrs(
+    fs: &dyn FileSystem,
+    path: &Path,
+    inner_file: &InnerFile,
+    xattrs: &mut bool,
+) -> Result<()> {
+    if *xattrs && !restore_xattrs(fs, path, inner_file)? {
+        warn!("The target doesn't accept extended attributes, skipping their restoration");
+        *xattrs = false;
+    }
+    Ok(())
+}
+
+/// Restores the stored modification time and then the permissions, which
+/// `UnpackOptions::file_mode` overrides. Times the file system can't set
+/// are left as they are.
+fn apply_times_and_mode(
+    fs: &dyn FileSystem,
+    path: &Path,
+    inner_file: &InnerFile,
+    options: &UnpackOptions,
+) -> Result<()> {
+    if let Some(mtime) = inner_file.mtime() {
+        match fs.set_modified(path, mtime) {
+            Ok(()) => {}
+            Err(e) if is_unavailable(&e) => {
+                debug!(
+                    "Modification time of '{}' isn't restored: {}",
+                    path.display(),
+                    e
+                )
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+    if let Some(mode) = options.file_mode.or(inner_file.mode()) {
+        fs.set_permissions(path, mode)?;
+    }
+    Ok(())
+}
+
+/// Returns `false` when the file system or the privileges of the process
+/// don't allow setting the attributes.
+fn restore_xattrs(fs: &dyn FileSystem, path: &Path, inner_file: &InnerFile) -> Result<bool> {
+    for (name, value) in inner_file.xattrs() {
+        match fs.set_xattr(path, name, value) {
+            Ok(()) => {}
+            Err(e) if is_unavailable(&e) => return Ok(false),
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(true)
+}
+
+/// Whether the error only means the operation isn't available, see `FileSystem`.
+fn is_unavailable(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        io::ErrorKind::PermissionDenied | io::ErrorKind::Unsupported
+    )
+}
+
+/// Sets `mode` on every directory between `root` (exclusive) and the
+/// extracted files. Done after extraction, so read-only modes don't get in the way.
+pub(crate) fn apply_dir_mode(
+    extracted: &[ExtractedEntry],
+    root: &Path,
+    mode: u32,
+    fs: &dyn FileSystem,
+) -> Result<()> {
+    let mut dirs = BTreeSet::new();
+    for entry in extracted {
+        if entry.status == EntryStatus::Skipped {
+            continue;
+        }
+
+        for dir in entry.path.ancestors().skip(1) {
+            if dir == root || !dir.starts_with(root) {
+                break;
+            }
+            dirs.insert(dir);
+        }
+    }
+
+    // deepest first, so the parents stay writable until their children are done
+    for dir in dirs.iter().rev() {
+        fs.set_permissions(dir, mode)?;
+    }
+    Ok(())
+}
+
+/// Returns `false` when the process isn't permitted to change the owner.
+fn restore_owner(fs: &dyn FileSystem, path: &Path, inner_file: &InnerFile) -> Result<bool> {
+    match fs.set_owner(path, inner_file.uid, inner_file.gid) {
+        Ok(()) => Ok(true),
+        Err(e) if is_unavailable(&e) => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Returns `path` if it's missing or empty, otherwise the first sibling
+/// `name (n)` which is.
+fn fresh_dir(path: PathBuf, fs: &dyn FileSystem) -> Result<PathBuf> {
+    let is_free = |path: &Path| -> Result<bool> { Ok(!fs.exists(path) || fs.is_empty_dir(path)?) };
+
+    if is_free(&path)? {
+        return Ok(path);
+    }
+
+    let name = path
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .into_owned();
+    for n in 1.. {
+        let candidate = path.with_file_name(format!("{} ({})", name, n));
+        if is_free(&candidate)? {
+            return Ok(candidate);
+        }
+    }
+    unreachable!()
+}
+
+fn get_extraction_path(source: &Path, target: &Path) -> Result<PathBuf> {
+    let source = normalize_path(source);
+    let target = normalize_path(target);
+
+    if !source.exists() || !source.is_file() {
+        return Err(ArchiveError::Path(format!(
+            "Invalid source destination at path: {}",
+            source.display()
+        )));
+    }
+
+    // the signature decides whether it's an archive, the extension is only a hint
+    if logical_path(&source)
+        .extension()
+        .is_none_or(|ex| ex != "slf")
+    {
+        warn!(
+            "'{}' doesn't have the '.slf' extension, trying to unpack it anyway",
+            source.display()
+        );
+    }
+
+    Ok(if target.is_file() {
+        return Err(ArchiveError::Path(format!(
+            "Archive can't be unpacked into file at path: {}",
+            target.display(),
+        )));
+    } else {
+        target
+    })
+}
+
+/// Decompresses the entry at the current position of `reader` into
+/// `hasher_writer`, returning the original checksum. The compressed bytes are
+/// hashed as they are read, and the rest of them once the decoder fails, so
+/// a damaged entry is reported as a compressed checksum mismatch first.
+/// Without `options.verify` the entry is only decompressed.
+fn unpack_single_file<R: Read + Seek, W: Write>(
+    inner_file: &InnerFile,
+    reader: &mut R,
+    mut hasher_writer: &mut HasherWriter<W>,
+    buffer: &mut [u8],
+    dictionary: &[u8],
+    options: &UnpackOptions,
+    limiter: &mut RateLimiter,
+) -> Result<u32> {
+    let (verify, cancel) = (options.verify, options.cancel.as_deref());
+    if inner_file.chunked {
+        return unpack_chunked(inner_file, reader, hasher_writer, verify, limiter, cancel);
+    }
+
+    if !verify {
+        let decoder =
+            Decompressor::with_dictionary(&mut hasher_writer, inner_file.method, dictionary)?;
+        decompress(
+            &mut reader.take(inner_file.compressed_size),
+            decoder,
+            buffer,
+            limiter,
+            cancel,
+        )?;
+        return Ok(hasher_writer.sum());
+    }
+
+    let mut compressed = CrcReader::new(reader.take(inner_file.compressed_size));
+
+    let decoder = Decompressor::with_dictionary(&mut hasher_writer, inner_file.method, dictionary)?;
+    let decoded = decompress(&mut compressed, decoder, buffer, limiter, cancel);
+    if let Err(ArchiveError::Cancelled) = decoded {
+        return Err(ArchiveError::Cancelled);
+    }
+
+    io::copy(&mut compressed, &mut io::sink())?;
+
+    let compressed_checksum = compressed.crc().sum();
+    if compressed_checksum != inner_file.compressed_checksum {
+        return Err(ArchiveError::CorruptedArchive(format!(
+            "Archive corrupted! Unpacked checksums isn't equal to! {} isn't equal to {}",
+            compressed_checksum, inner_file.compressed_checksum
+        )));
+    }
+    decoded?;
+
+    Ok(hasher_writer.sum())
+}
+
+/// Same as `unpack_single_file` for entries stored as chunk records.
+fn unpack_chunked<R: Read + Seek, W: Write>(
+    inner_file: &InnerFile,
+    reader: &mut R,
+    hasher_writer: &mut HasherWriter<W>,
+    verify: bool,
+    limiter: &mut RateLimiter,
+    cancel: Option<&AtomicBool>,
+) -> Result<u32> {
+    let mut decoder = ChunkDecoder::new(reader, inner_file.compressed_size);
+
+    let mut decode = || -> Result<()> {
+        while let Some(chunk) = decoder.next_chunk()? {
+            check_cancelled(cancel)?;
+            hasher_writer.write_all(chunk)?;
+            limiter.consume(chunk.len());
+        }
+        Ok(())
+    };
+    let decoded = decode();
+    if let Err(ArchiveError::Cancelled) = decoded {
+        return Err(ArchiveError::Cancelled);
+    }
+
+    let compressed_checksum = decoder.compressed_checksum()?;
+    if verify && compressed_checksum != inner_file.compressed_checksum {
+        return Err(ArchiveError::CorruptedArchive(format!(
+            "Archive corrupted! Unpacked checksums isn't equal to! {} isn't equal to {}",
+            compressed_checksum, inner_file.compressed_checksum
+        )));
+    }
+    decoded?;
+
+    Ok(hasher_writer.sum())
+}
+
+fn decompress<R: Read, W: Write>(
+    reader: &mut R,
+    mut decoder: Decompressor<W>,
+    buffer: &mut [u8],
+    limiter: &mut RateLimiter,
+    cancel: Option<&AtomicBool>,
+) -> Result<()> {
+    loop {
+        check_cancelled(cancel)?;
+
+        let bytes = match reader.read(buffer) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        };
+
+        if bytes == 0 {
+            break;
+        }
+
+        // a reader claiming more bytes than the buffer holds is a bug, which
+        // is reported instead of panicking on the slice
+        debug_assert!(bytes <= buffer.len());
+        let chunk = buffer.get(..bytes).ok_or_else(|| {
+            ArchiveError::CorruptedArchive(format!(
+                "Read of {} bytes exceeds the buffer of {}",
+                bytes,
+                buffer.len()
+            ))
+        })?;
+        decoder.write_all(chunk)?;
+
+        limiter.consume(bytes);
+    }
+
+    decoder.finish()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        time::{Duration, Instant},
+    };
+
+    use super::*;
+    use crate::{
+        builder::ArchiveBuilder,
+        compressor::Method,
+        filesystem::MemFs,
+        pack::{PackOptions, pack},
+        reader::ArchiveReader,
+        testing::{Flaky, TempDir, noise, update_checksum},
+    };
+
+    /// Claims to have read more bytes than the buffer it was given holds.
+    struct Overreporting;
+
+    impl Read for Overreporting {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            Ok(buf.len() + 1)
+        }
+    }
+
+    /// Hands out at most 7 bytes per read and can't seek, like a pipe.
+    struct Trickle<'a>(&'a [u8]);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let len = buf.len().min(self.0.len()).min(7);
+            buf[..len].copy_from_slice(&self.0[..len]);
+            self.0 = &self.0[len..];
+            Ok(len)
+        }
+    }
+
+    fn decompress_overreported() -> Result<()> {
+        let mut buffer = vec![0u8; 16];
+        decompress(
+            &mut Overreporting,
+            Decompressor::new(io::sink(), Method::Store),
+            &mut buffer,
+            &mut RateLimiter::new(0),
+            None,
+        )
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "bytes <= buffer.len()")]
+    fn overreported_read_is_asserted() {
+        let _ = decompress_overreported();
+    }
+
+    #[test]
+    #[cfg(not(debug_assertions))]
+    fn overreported_read_is_corruption() {
+        assert!(matches!(
+            decompress_overreported(),
+            Err(ArchiveError::CorruptedArchive(_))
+        ));
+    }
+
+    #[test]
+    fn interrupted_reads_are_retried() {
+        let mut reader = Flaky::new(b"contents".as_slice(), &[io::ErrorKind::Interrupted; 3]);
+        let mut output = Vec::new();
+        decompress(
+            &mut reader,
+            Decompressor::new(&mut output, Method::Store),
+            &mut [0u8; 16],
+            &mut RateLimiter::new(0),
+            None,
+        )
+        .unwrap();
+        assert_eq!(output, b"contents");
+    }
+
+    /// Builds an archive of `files` stored with `method`.
+    fn archive_of(files: &[(&str, &[u8])], method: Method) -> Vec<u8> {
+        let mut builder = ArchiveBuilder::new();
+        builder.method(method);
+        for (name, contents) in files {
+            builder.add_bytes(*name, contents).unwrap();
+        }
+        builder.finish(Vec::new()).unwrap()
+    }
+
+    /// Builds an archive of `files` stored with `method` and extracts it into
+    /// a `MemFs` under `out`.
+    fn round_trip(files: &[(&str, &[u8])], method: Method) -> (Arc<MemFs>, Vec<ExtractedEntry>) {
+        let archive = archive_of(files, method);
+        ArchiveReader::new(io::Cursor::new(&archive))
+            .unwrap()
+            .verify()
+            .unwrap();
+
+        let fs = Arc::new(MemFs::new());
+        let options = UnpackOptions {
+            file_system: fs.clone(),
+            ..UnpackOptions::default()
+        };
+        let extracted = unpack_stream(archive.as_slice(), PathBuf::from("out"), &options).unwrap();
+        (fs, extracted)
+    }
+
+    #[test]
+    fn empty_file_alone() {
+        for method in [Method::Store, Method::Gzip] {
+            let (fs, extracted) = round_trip(&[("empty", b"")], method);
+            assert_eq!(extracted.len(), 1);
+            assert_eq!(extracted[0].original_size, 0);
+            assert_eq!(extracted[0].status, EntryStatus::Created);
+            assert_eq!(fs.read(Path::new("out/empty")), Some(Vec::new()));
+        }
+    }
+
+    #[test]
+    fn empty_files_among_others() {
+        let large = vec![7u8; 3 * BUFFER_SIZE + 1];
+        let files: [(&str, &[u8]); 5] = [
+            ("empty-first", b""),
+            ("a.txt", b"abc"),
+            ("dir/empty", b""),
+            ("large.bin", &large),
+            ("empty-last", b""),
+        ];
+        for method in [Method::Store, Method::Gzip] {
+            let (fs, extracted) = round_trip(&files, method);
+            assert_eq!(extracted.len(), files.len());
+            for (name, contents) in files {
+                let path = Path::new("out").join(name);
+                assert_eq!(fs.read(&path).as_deref(), Some(contents), "{}", name);
+            }
+        }
+    }
+
+    #[test]
+    fn packs_empty_files() {
+        let dir = TempDir::new("empty-files");
+        fs::create_dir_all(dir.join("alone")).unwrap();
+        fs::write(dir.join("alone/empty"), b"").unwrap();
+        fs::create_dir_all(dir.join("mixed/sub")).unwrap();
+        fs::write(dir.join("mixed/a.txt"), b"abc").unwrap();
+        fs::write(dir.join("mixed/sub/empty"), b"").unwrap();
+
+        for (source, files) in [
+            ("alone", &["empty"][..]),
+            ("mixed", &["a.txt", "sub/empty"]),
+        ] {
+            pack(
+                dir.join(source),
+                Some(dir.join("archives")),
+                &PackOptions::default(),
+            )
+            .unwrap();
+            let archive = dir.join("archives").join(source).with_extension("slf");
+            ArchiveReader::open(&archive).unwrap().verify().unwrap();
+
+            let mem = Arc::new(MemFs::new());
+            let options = UnpackOptions {
+                file_system: mem.clone(),
+                ..UnpackOptions::default()
+            };
+            let extracted = unpack(archive, Some(PathBuf::from("out")), &options).unwrap();
+            let mut names: Vec<_> = extracted.iter().map(|e| e.name.clone()).collect();
+            names.sort();
+            assert_eq!(names, files);
+            for entry in &extracted {
+                let contents = fs::read(dir.join(source).join(&entry.name)).unwrap();
+                assert_eq!(mem.read(&entry.path), Some(contents));
+            }
+        }
+    }
+
+    #[test]
+    fn restores_dir_mtimes_last() {
+        let dir = TempDir::new("dir-mtimes");
+        let source = dir.join("src");
+        fs::create_dir_all(source.join("a/b")).unwrap();
+        fs::write(source.join("a/b/x"), b"x").unwrap();
+        fs::write(source.join("a/y"), b"y").unwrap();
+        let mtime = SystemTime::UNIX_EPOCH + Duration::new(981_173_106, 700_000_000);
+        for path in ["a", "a/b"] {
+            File::open(source.join(path))
+                .unwrap()
+                .set_modified(mtime)
+                .unwrap();
+        }
+
+        pack(source, Some(dir.join("archives")), &PackOptions::default()).unwrap();
+        let target = dir.join("out");
+        let archive = dir.join("archives/src.slf");
+        unpack(archive, Some(target.clone()), &UnpackOptions::default()).unwrap();
+
+        for path in ["src/a", "src/a/b"] {
+            let modified = fs::metadata(target.join(path)).unwrap().modified().unwrap();
+            assert_eq!(modified, mtime, "{}", path);
+        }
+    }
+
+    #[test]
+    fn best_effort_skips_damaged_entry() {
+        let mut builder = ArchiveBuilder::new();
+        builder.method(Method::Store);
+        for (name, contents) in [("a.txt", "first"), ("b.txt", "second"), ("c.txt", "third")] {
+            builder.add_bytes(name, contents.as_bytes()).unwrap();
+        }
+        let mut archive = builder.finish(Vec::new()).unwrap();
+        let at = archive.windows(6).position(|w| w == b"second").unwrap();
+        archive[at] ^= 0xff;
+        update_checksum(&mut archive);
+
+        let dir = TempDir::new("best-effort");
+        let source = dir.join("damaged.slf");
+        fs::write(&source, &archive).unwrap();
+
+        for jobs in [1, 2] {
+            let memory = Arc::new(MemFs::new());
+            let options = UnpackOptions {
+                best_effort: true,
+                jobs,
+                file_system: memory.clone(),
+                ..UnpackOptions::default()
+            };
+            match unpack(source.clone(), Some(PathBuf::from("out")), &options) {
+                Err(ArchiveError::CorruptedArchive(message)) => {
+                    assert!(message.contains("1 of 3 entries: b.txt"), "{}", message)
+                }
+                result => panic!("{:?}", result.map(|_| ())),
+            }
+            let read = |name: &str| memory.read(&Path::new("out/damaged").join(name));
+            assert_eq!(read("a.txt"), Some(b"first".to_vec()));
+            assert_eq!(read("c.txt"), Some(b"third".to_vec()));
+
+            let options = UnpackOptions {
+                jobs,
+                ..UnpackOptions::default()
+            };
+            assert!(unpack(source.clone(), Some(dir.join("strict")), &options).is_err());
+        }
+    }
+
+    #[test]
+    fn sparse_extraction_leaves_holes() {
+        let hole = vec![0u8; 8 << 20];
+        let inner = [b"head".as_slice(), &hole, b"tail"].concat();
+        let trailing = [b"head".as_slice(), &hole].concat();
+        let mut builder = ArchiveBuilder::new();
+        builder.add_bytes("inner", &inner).unwrap();
+        builder.add_bytes("trailing", &trailing).unwrap();
+        let archive = builder.finish(Vec::new()).unwrap();
+
+        let dir = TempDir::new("sparse");
+        let source = dir.join("holes.slf");
+        fs::write(&source, archive).unwrap();
+        let options = UnpackOptions {
+            sparse: true,
+            ..UnpackOptions::default()
+        };
+        unpack(source, Some(dir.join("out")), &options).unwrap();
+
+        for (name, contents) in [("inner", inner), ("trailing", trailing)] {
+            let path = dir.join("out/holes").join(name);
+            assert!(fs::read(&path).unwrap() == contents, "{}", name);
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::MetadataExt;
+                let allocated = fs::metadata(&path).unwrap().blocks() * 512;
+                assert!(
+                    allocated < 1 << 20,
+                    "{} has {} bytes allocated",
+                    name,
+                    allocated
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn single_file_goes_to_target() {
+        let mut builder = ArchiveBuilder::new();
+        builder.add_bytes("a.txt", b"single").unwrap();
+        let dir = TempDir::new("single-file");
+        let source = dir.join("archives/single.slf");
+        fs::create_dir_all(dir.join("archives")).unwrap();
+        fs::write(&source, builder.finish(Vec::new()).unwrap()).unwrap();
+
+        let options = UnpackOptions::default();
+        let extracted = unpack(source.clone(), Some(dir.join("out")), &options).unwrap();
+        assert_eq!(extracted[0].path, dir.join("out/a.txt"));
+        assert_eq!(fs::read(dir.join("out/a.txt")).unwrap(), b"single");
+
+        unpack(source.clone(), None, &options).unwrap();
+        assert_eq!(fs::read(dir.join("archives/a.txt")).unwrap(), b"single");
+
+        let memory = Arc::new(MemFs::new());
+        let options = UnpackOptions {
+            single_in_cwd: true,
+            file_system: memory.clone(),
+            ..UnpackOptions::default()
+        };
+        unpack(source, Some(dir.join("ignored")), &options).unwrap();
+        assert_eq!(memory.read(Path::new("a.txt")), Some(b"single".to_vec()));
+    }
+
+    #[test]
+    fn rate_limit_slows_extraction() {
+        let mut builder = ArchiveBuilder::new();
+        builder.method(Method::Store);
+        builder.add_bytes("data", &vec![7u8; 128 * 1024]).unwrap();
+        let archive = builder.finish(Vec::new()).unwrap();
+
+        let options = UnpackOptions {
+            rate_limit: 512 * 1024,
+            file_system: Arc::new(MemFs::new()),
+            ..UnpackOptions::default()
+        };
+        let start = Instant::now();
+        unpack_stream(archive.as_slice(), PathBuf::from("out"), &options).unwrap();
+        // 128 KiB at 512 KiB per second
+        assert!(start.elapsed() >= Duration::from_millis(250));
+    }
+
+    #[test]
+    fn extracts_only_matching_entries() {
+        let archive = archive_of(
+            &[
+                ("a.txt", b"a"),
+                ("b.rs", b"b"),
+                ("sub/c.txt", b"c"),
+                ("sub/d.bin", b"d"),
+            ],
+            Method::Gzip,
+        );
+        // `*` stays within a component
+        for (pattern, expected) in [
+            ("*.txt", &["a.txt"][..]),
+            ("**/*.txt", &["a.txt", "sub/c.txt"]),
+        ] {
+            let memory = Arc::new(MemFs::new());
+            let options = UnpackOptions {
+                matching: Some(glob_set(&[pattern]).unwrap()),
+                file_system: memory.clone(),
+                ..UnpackOptions::default()
+            };
+            let extracted =
+                unpack_stream(archive.as_slice(), PathBuf::from("out"), &options).unwrap();
+
+            let created: Vec<_> = extracted
+                .iter()
+                .filter(|entry| entry.status != EntryStatus::Skipped)
+                .map(|entry| entry.name.to_str().unwrap())
+                .collect();
+            assert_eq!(created, expected);
+            for entry in &extracted {
+                let written = memory.exists(&entry.path);
+                assert_eq!(
+                    written,
+                    entry.status != EntryStatus::Skipped,
+                    "{:?}",
+                    entry.path
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn mem_fs_receives_the_whole_tree() {
+        let files: [(&str, &[u8]); 3] = [
+            ("top.txt", b"top"),
+            ("dir/nested.txt", b"nested"),
+            ("dir/deeper/empty", b""),
+        ];
+        let (fs, extracted) = round_trip(&files, Method::Gzip);
+        assert_eq!(extracted.len(), 3);
+        assert_eq!(
+            fs.paths(),
+            [
+                "out",
+                "out/dir",
+                "out/dir/deeper",
+                "out/dir/deeper/empty",
+                "out/dir/nested.txt",
+                "out/top.txt",
+            ]
+            .map(PathBuf::from)
+        );
+        assert!(fs.metadata(Path::new("out/dir")).unwrap().is_dir);
+        for (name, contents) in files {
+            let path = Path::new("out").join(name);
+            assert_eq!(fs.read(&path).as_deref(), Some(contents));
+            // nothing reached the real disk
+            assert!(!path.exists());
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn overlong_paths_are_rejected_up_front() {
+        let dir = TempDir::new("long-paths");
+        let deep = vec!["d".repeat(250); 20].join("/") + "/deep.txt";
+        let long_component = format!("dir/{}.txt", "n".repeat(300));
+        for (name, limit) in [(deep.as_str(), "4096"), (long_component.as_str(), "255")] {
+            let archive = dir.join("long.slf");
+            fs::write(&archive, archive_of(&[(name, b"contents")], Method::Gzip)).unwrap();
+            let target = dir.join("out");
+
+            match unpack(
+                archive.clone(),
+                Some(target.clone()),
+                &UnpackOptions::default(),
+            ) {
+                Err(ArchiveError::Path(message)) => {
+                    assert!(
+                        message.starts_with(&format!("Entry '{}'", name)),
+                        "{}",
+                        message
+                    );
+                    assert!(message.ends_with(&format!("longer than the limit of {}", limit)));
+                }
+                result => panic!("{:?}", result),
+            }
+            assert!(!target.join("dir").exists() && !target.join("d").exists());
+        }
+    }
+
+    #[test]
+    fn skip_newer_keeps_local_changes() {
+        let dir = TempDir::new("skip-newer");
+        let source = dir.write_files(
+            "src",
+            &[
+                ("a.txt", b"archived a"),
+                ("b.txt", b"archived b"),
+                ("c.txt", b"archived c"),
+            ],
+        );
+        pack(source, Some(dir.join("archives")), &PackOptions::default()).unwrap();
+        let archive = dir.join("archives/src.slf");
+        unpack(
+            archive.clone(),
+            Some(dir.join("out")),
+            &UnpackOptions::default(),
+        )
+        .unwrap();
+
+        // the middle entry is skipped, so the last one is read past its bytes
+        let out = dir.join("out/src");
+        let now = SystemTime::now();
+        for (name, modified) in [
+            ("a.txt", now - Duration::from_secs(3600)),
+            ("b.txt", now + Duration::from_secs(3600)),
+        ] {
+            let file = fs::File::create(out.join(name)).unwrap();
+            (&file).write_all(b"local").unwrap();
+            file.set_modified(modified).unwrap();
+        }
+        fs::remove_file(out.join("c.txt")).unwrap();
+
+        let options = UnpackOptions {
+            skip_newer: true,
+            ..UnpackOptions::default()
+        };
+        let extracted = unpack(archive, Some(dir.join("out")), &options).unwrap();
+        let statuses: Vec<_> = extracted.iter().map(|entry| entry.status).collect();
+        assert_eq!(
+            statuses,
+            [
+                EntryStatus::Overwritten,
+                EntryStatus::Skipped,
+                EntryStatus::Created
+            ]
+        );
+        assert_eq!(fs::read(out.join("a.txt")).unwrap(), b"archived a");
+        assert_eq!(fs::read(out.join("b.txt")).unwrap(), b"local");
+        assert_eq!(fs::read(out.join("c.txt")).unwrap(), b"archived c");
+    }
+
+    #[test]
+    fn pipelined_round_trip() {
+        let dir = TempDir::new("pipeline");
+        let (large, text) = (
+            noise(3 * PIPELINE_BLOCK_SIZE + 5),
+            b"line of text\n".repeat(50_000),
+        );
+        let files: [(&str, &[u8]); 4] = [
+            ("empty", b""),
+            ("large.bin", &large),
+            ("dir/text.txt", &text),
+            ("dir/small.txt", b"small"),
+        ];
+        for method in [Method::Store, Method::Gzip] {
+            let archive = dir.join("pipelined.slf");
+            fs::write(&archive, archive_of(&files, method)).unwrap();
+            let target = dir.join(format!("out-{}", method.name()));
+            let options = UnpackOptions {
+                pipeline: true,
+                ..UnpackOptions::default()
+            };
+            let extracted = unpack(archive, Some(target.clone()), &options).unwrap();
+            assert_eq!(extracted.len(), files.len());
+            for (name, contents) in files {
+                let path = target.join("pipelined").join(name);
+                assert!(
+                    fs::read(&path).unwrap() == contents,
+                    "{} with {}",
+                    name,
+                    method.name()
+                );
+            }
+        }
+
+        // damage is still found on the reading side
+        let mut archive = archive_of(&files[2..], Method::Store);
+        // the contents, after the name holding the same word
+        let at = archive.windows(5).rposition(|w| w == b"small").unwrap();
+        archive[at] ^= 0xff;
+        update_checksum(&mut archive);
+        let damaged = dir.join("damaged.slf");
+        fs::write(&damaged, archive).unwrap();
+        let options = UnpackOptions {
+            pipeline: true,
+            best_effort: true,
+            ..UnpackOptions::default()
+        };
+        match unpack(damaged.clone(), Some(dir.join("damaged")), &options) {
+            Err(ArchiveError::CorruptedArchive(message)) => {
+                assert!(
+                    message.contains("1 of 2 entries: dir/small.txt"),
+                    "{}",
+                    message
+                )
+            }
+            result => panic!("{:?}", result.map(|_| ())),
+        }
+        assert!(fs::read(dir.join("damaged/damaged/dir/text.txt")).unwrap() == text);
+
+        // without best effort the decoding error is returned, not the writer's
+        let options = UnpackOptions {
+            pipeline: true,
+            ..UnpackOptions::default()
+        };
+        match unpack(damaged, Some(dir.join("strict")), &options) {
+            Err(ArchiveError::CorruptedArchive(_)) => {}
+            result => panic!("{:?}", result.map(|_| ())),
+        }
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn chmod_overrides_modes() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let mut builder = ArchiveBuilder::new();
+        builder.add_bytes("a.txt", b"a").unwrap();
+        builder.add_bytes("sub/b.txt", b"b").unwrap();
+        let dir = TempDir::new("chmod");
+        let source = dir.join("modes.slf");
+        fs::write(&source, builder.finish(Vec::new()).unwrap()).unwrap();
+
+        let options = UnpackOptions {
+            file_mode: Some(0o640),
+            dir_mode: Some(0o750),
+            ..UnpackOptions::default()
+        };
+        unpack(source, Some(dir.join("out")), &options).unwrap();
+
+        let mode = |path: &str| {
+            let metadata = fs::metadata(dir.join("out").join(path)).unwrap();
+            metadata.permissions().mode() & 0o7777
+        };
+        assert_eq!(mode("modes/a.txt"), 0o640);
+        assert_eq!(mode("modes/sub/b.txt"), 0o640);
+        assert_eq!(mode("modes"), 0o750);
+        assert_eq!(mode("modes/sub"), 0o750);
+    }
+
+    #[test]
+    fn strips_leading_components() {
+        let archive = archive_of(
+            &[
+                ("top/a.txt", b"a"),
+                ("top/sub/b.txt", b"b"),
+                ("short", b"c"),
+            ],
+            Method::Gzip,
+        );
+        let memory = Arc::new(MemFs::new());
+        let options = UnpackOptions {
+            strip_components: 1,
+            file_system: memory.clone(),
+            ..UnpackOptions::default()
+        };
+        let extracted = unpack_stream(archive.as_slice(), PathBuf::from("out"), &options).unwrap();
+
+        let statuses: Vec<_> = extracted.iter().map(|entry| entry.status).collect();
+        assert_eq!(
+            statuses,
+            [
+                EntryStatus::Created,
+                EntryStatus::Created,
+                EntryStatus::Skipped
+            ]
+        );
+        assert_eq!(memory.read(Path::new("out/a.txt")), Some(b"a".to_vec()));
+        assert_eq!(memory.read(Path::new("out/sub/b.txt")), Some(b"b".to_vec()));
+        assert!(!memory.exists(Path::new("out/short")));
+    }
+
+    #[test]
+    fn index_offset_zero_is_read_sequentially() {
+        let mut archive = archive_of(&[("a.txt", b"a"), ("sub/b.txt", b"b")], Method::Gzip);
+        archive[10..18].fill(0);
+        update_checksum(&mut archive);
+        let dir = TempDir::new("index-offset-zero");
+        let source = dir.join("streamed.slf");
+        fs::write(&source, &archive).unwrap();
+
+        // both would use the index if there was one
+        let options = UnpackOptions {
+            best_effort: true,
+            jobs: 2,
+            check_archive: true,
+            ..UnpackOptions::default()
+        };
+        let extracted = unpack(source, Some(dir.join("out")), &options).unwrap();
+        assert_eq!(extracted.len(), 2);
+        assert_eq!(fs::read(dir.join("out/streamed/a.txt")).unwrap(), b"a");
+        assert_eq!(fs::read(dir.join("out/streamed/sub/b.txt")).unwrap(), b"b");
+    }
+
+    #[test]
+    fn case_collisions_are_rejected() {
+        let archive = archive_of(&[("A.txt", b"upper"), ("a.txt", b"lower")], Method::Gzip);
+        let dir = TempDir::new("case-collision");
+        let source = dir.join("cases.slf");
+        fs::write(&source, &archive).unwrap();
+
+        let memory = Arc::new(MemFs::new());
+        let options = UnpackOptions {
+            check_case: true,
+            file_system: memory.clone(),
+            ..UnpackOptions::default()
+        };
+        let result = unpack(source, Some(PathBuf::from("out")), &options);
+        assert!(
+            matches!(&result, Err(ArchiveError::CaseCollision(first, second)) if first == "A.txt" && second == "a.txt"),
+            "{:?}",
+            result.map(|_| ())
+        );
+        assert!(!memory.exists(Path::new("out/cases/A.txt")));
+    }
+
+    #[test]
+    fn reports_each_corruption_location() {
+        let contents = b"compressible text\n".repeat(1000);
+        let archive = archive_of(&[("a.txt", &contents)], Method::Gzip);
+        let reader = ArchiveReader::new(io::Cursor::new(&archive)).unwrap();
+        let entry = reader.entries()[0].clone();
+        // name length and name, then original and compressed sizes
+        let checksums = entry.position() as usize + 4 + entry.name().len() + 16;
+        let data = (entry.position() + entry.metadata_size()) as usize;
+
+        // position of the flipped byte and the stored checksum reported
+        let locations = [
+            (
+                data + entry.compressed_size() as usize / 2,
+                entry.compressed_checksum(),
+            ),
+            (data, entry.compressed_checksum()),
+            (checksums + 4, entry.compressed_checksum() ^ 0x01),
+            (checksums, entry.original_checksum() ^ 0x01),
+        ];
+        for (at, expected) in locations {
+            let mut damaged = archive.clone();
+            damaged[at] ^= 0x01;
+            let options = UnpackOptions {
+                file_system: Arc::new(MemFs::new()),
+                ..UnpackOptions::default()
+            };
+            match unpack_stream(damaged.as_slice(), PathBuf::from("out"), &options) {
+                Err(ArchiveError::CorruptedArchive(message)) => assert!(
+                    message.ends_with(&format!("isn't equal to {}", expected)),
+                    "byte {}: {}",
+                    at,
+                    message
+                ),
+                result => panic!("byte {}: {:?}", at, result.map(|_| ())),
+            }
+        }
+    }
+
+    #[test]
+    fn fresh_extracts_next_to_existing() {
+        let archive = archive_of(&[("a.txt", b"a"), ("b.txt", b"b")], Method::Gzip);
+        let dir = TempDir::new("fresh");
+        let source = dir.join("two.slf");
+        fs::write(&source, &archive).unwrap();
+
+        let memory = Arc::new(MemFs::new());
+        let options = UnpackOptions {
+            fresh: true,
+            file_system: memory.clone(),
+            ..UnpackOptions::default()
+        };
+        for expected in ["out/two", "out/two (1)", "out/two (2)"] {
+            let extracted = unpack(source.clone(), Some(PathBuf::from("out")), &options).unwrap();
+            assert_eq!(extracted[0].path, Path::new(expected).join("a.txt"));
+            assert_eq!(
+                memory.read(&Path::new(expected).join("b.txt")),
+                Some(b"b".to_vec())
+            );
+        }
+    }
+
+    #[test]
+    fn no_verify_extracts_identical_bytes() {
+        let contents = b"trusted contents\n".repeat(1000);
+        let mut archive = archive_of(&[("a.txt", &contents)], Method::Gzip);
+        let extract = |archive: &[u8], verify: bool| {
+            let memory = Arc::new(MemFs::new());
+            let options = UnpackOptions {
+                verify,
+                file_system: memory.clone(),
+                ..UnpackOptions::default()
+            };
+            unpack_stream(archive, PathBuf::from("out"), &options)
+                .map(|_| memory.read(Path::new("out/a.txt")).unwrap())
+        };
+        assert!(extract(&archive, false).unwrap() == contents);
+
+        // a wrong original checksum goes unnoticed, the contents are intact
+        let entry = ArchiveReader::new(io::Cursor::new(&archive))
+            .unwrap()
+            .entries()[0]
+            .clone();
+        archive[entry.position() as usize + 4 + entry.name().len() + 16] ^= 0x01;
+        assert!(extract(&archive, false).unwrap() == contents);
+        assert!(extract(&archive, true).is_err());
+    }
+
+    #[test]
+    fn parallel_round_trip() {
+        let files: Vec<_> = (0..40)
+            .map(|i| {
+                let name = format!("dir{}/sub/file{}.txt", i % 4, i);
+                (name, format!("contents of {}\n", i).repeat(i * 100))
+            })
+            .collect();
+        let files: Vec<_> = files
+            .iter()
+            .map(|(name, contents)| (name.as_str(), contents.as_bytes()))
+            .collect();
+        let dir = TempDir::new("parallel");
+        let source = dir.join("many.slf");
+        fs::write(&source, archive_of(&files, Method::Gzip)).unwrap();
+
+        let options = UnpackOptions {
+            jobs: 4,
+            ..UnpackOptions::default()
+        };
+        let extracted = unpack(source, Some(dir.join("out")), &options).unwrap();
+        let names: Vec<_> = extracted
+            .iter()
+            .map(|entry| entry.name.to_str().unwrap())
+            .collect();
+        let expected: Vec<_> = files.iter().map(|(name, _)| *name).collect();
+        assert_eq!(names, expected);
+        for (name, contents) in files {
+            assert_eq!(
+                fs::read(dir.join("out/many").join(name)).unwrap(),
+                contents,
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn other_extensions_are_unpacked() {
+        let archive = archive_of(&[("a.txt", b"a"), ("b.txt", b"b")], Method::Gzip);
+        let dir = TempDir::new("other-extension");
+        let source = dir.join("backup.dat");
+        fs::write(&source, &archive).unwrap();
+
+        let options = UnpackOptions::default();
+        unpack(source.clone(), Some(dir.join("out")), &options).unwrap();
+        assert_eq!(fs::read(dir.join("out/backup/b.txt")).unwrap(), b"b");
+
+        // the target still can't be a file
+        let result = unpack(source.clone(), Some(source), &options);
+        assert!(matches!(result, Err(ArchiveError::Path(_))));
+    }
+
+    #[test]
+    fn streams_without_seeking() {
+        let text = b"streamed text\n".repeat(500);
+        let mut archive = archive_of(&[("a.txt", &text), ("sub/b.txt", b"b")], Method::Gzip);
+        // never looked at by a sequential read
+        archive[10..18].copy_from_slice(&u64::MAX.to_le_bytes());
+
+        let memory = Arc::new(MemFs::new());
+        let options = UnpackOptions {
+            file_system: memory.clone(),
+            ..UnpackOptions::default()
+        };
+        let extracted = unpack_stream(Trickle(&archive), PathBuf::from("out"), &options).unwrap();
+        assert_eq!(extracted.len(), 2);
+        assert_eq!(memory.read(Path::new("out/a.txt")), Some(text));
+        assert_eq!(memory.read(Path::new("out/sub/b.txt")), Some(b"b".to_vec()));
+    }
+
+    #[test]
+    fn index_offset_past_the_end_is_rejected() {
+        let archive = archive_of(&[("a.txt", b"a"), ("b.txt", b"b")], Method::Gzip);
+        let dir = TempDir::new("index-offset-range");
+        let source = dir.join("range.slf");
+
+        // the index would run past the end, start there or overflow
+        let len = archive.len() as u64;
+        for offset in [len - 8, len + 100, u64::MAX - 4] {
+            let mut damaged = archive.clone();
+            damaged[10..18].copy_from_slice(&offset.to_le_bytes());
+            update_checksum(&mut damaged);
+            fs::write(&source, &damaged).unwrap();
+
+            assert!(matches!(
+                ArchiveReader::new(io::Cursor::new(&damaged)),
+                Err(ArchiveError::CorruptedArchive(_))
+            ));
+            let options = UnpackOptions {
+                jobs: 2,
+                file_system: Arc::new(MemFs::new()),
+                ..UnpackOptions::default()
+            };
+            let result = unpack(source.clone(), Some(PathBuf::from("out")), &options);
+            assert!(
+                matches!(result, Err(ArchiveError::CorruptedArchive(_))),
+                "offset {}: {:?}",
+                offset,
+                result.map(|_| ())
+            );
+        }
+    }
 }
@@ -0,0 +1,56 @@
+use std::path::{Path, PathBuf};
+
+use crate::{
+    error::{ArchiveError, Result},
+    reader::ArchiveReader,
+};
+
+#[cfg(feature = "signing")]
+use crate::signing;
+
+/// Checks that the archive is complete, its index agrees with the entries and
+/// every entry decompresses to the stored size and checksums, without writing anything.
+pub fn verify(source: PathBuf) -> Result<()> {
+    let mut archive = ArchiveReader::open(&source)?;
+    archive.verify_archive_checksum()?;
+    archive.check_index()?;
+    archive.verify()
+}
+
+/// Same as `verify`, but checks every entry before failing and names the
+/// range of the archive holding each damaged one, see
+/// `ArchiveReader::verify_deep`.
+pub fn verify_deep(source: PathBuf) -> Result<()> {
+    let mut archive = ArchiveReader::open(&source)?;
+    archive.check_index()?;
+    let damaged = archive.verify_deep()?;
+    if damaged.is_empty() {
+        // the entries are fine, so damage is in the metadata or the index
+        return archive.verify_archive_checksum();
+    }
+
+    let mut message = format!(
+        "Archive corrupted! {} of {} entries are damaged:",
+        damaged.iter().map(|d| d.names.len()).sum::<usize>(),
+        archive.entries().len()
+    );
+    for entry in &damaged {
+        message.push_str(&format!("\n{}", entry));
+    }
+    Err(ArchiveError::CorruptedArchive(message))
+}
+
+/// Checks that the archive is signed by the Ed25519 public key in the PEM
+/// file at `pubkey`, see `signing`.
+#[cfg(feature = "signing")]
+pub fn verify_signature(source: &Path, pubkey: &Path) -> Result<()> {
+    let key = signing::read_verifying_key(pubkey)?;
+    signing::verify_signature(source, &key)
+}
+
+#[cfg(not(feature = "signing"))]
+pub fn verify_signature(_source: &Path, _pubkey: &Path) -> Result<()> {
+    Err(ArchiveError::Unsupported(
+        "Signed archives aren't supported by this build".to_string(),
+    ))
+}
@@ -0,0 +1,439 @@
+use std::{
+    ffi::OsString,
+    fs::{File, remove_file},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use log::debug;
+
+use crate::{
+    FLAG_SPLIT, SIGNATURE, Version,
+    error::{ArchiveError, Result},
+    supports_version,
+};
+
+/// Extension of the first volume of a split archive, e.g. `out.slf.001`.
+pub const FIRST_VOLUME: &str = "001";
+/// Smallest volume size accepted, which keeps the fixed part of the header
+/// in the first volume and the signature and footer in the last one.
+pub const MIN_VOLUME_SIZE: u64 = 1024;
+
+/// Whether `path` is the first volume of a split archive.
+pub fn is_first_volume(path: &Path) -> bool {
+    path.extension().is_some_and(|ex| ex == FIRST_VOLUME)
+}
+
+/// Path of the archive the volumes were split from, `out.slf` for
+/// `out.slf.001`. Other paths are returned as they are.
+pub fn logical_path(path: &Path) -> PathBuf {
+    if is_first_volume(path) {
+        path.with_extension("")
+    } else {
+        path.to_path_buf()
+    }
+}
+
+/// Path of volume `number` of the archive at `archive_path`, counting from 1.
+pub fn volume_path(archive_path: &Path, number: usize) -> PathBuf {
+    let mut path = OsString::from(archive_path.as_os_str());
+    path.push(format!(".{:03}", number));
+    PathBuf::from(path)
+}
+
+/// Removes volumes `from` on of the archive at `archive_path`, up to the
+/// first missing one, e.g. those left by an earlier split into more volumes.
+pub fn remove_volumes(archive_path: &Path, from: usize) -> Result<()> {
+    for number in from.. {
+        let volume = volume_path(archive_path, number);
+        if !volume.is_file() {
+            break;
+        }
+        remove_file(&volume)?;
+        debug!("Removed stale volume '{}'", volume.display());
+    }
+    Ok(())
+}
+
+/// Count of volumes stored in the header of the first volume at `path`.
+/// `None` if it has none, which is the case while the archive is still being
+/// written and for archives before 1.10, whose volumes are counted on disk.
+/// Headers which can't be read are left to the archive reader to report.
+fn stored_volume_count(path: &Path) -> Result<Option<usize>> {
+    let mut header = [0u8; 25];
+    if File::open(path)?.read_exact(&mut header).is_err() || &header[..4] != SIGNATURE {
+        return Ok(None);
+    }
+    let version = Version::from_bytes([header[4], header[5]]);
+    let flags = u16::from_le_bytes([header[18], header[19]]);
+    if !supports_version(version) || version < Version::new(1, 10) || flags & FLAG_SPLIT == 0 {
+        return Ok(None);
+    }
+    let count = u16::from_le_bytes([header[23], header[24]]);
+    Ok((count > 0).then_some(count as usize))
+}
+
+struct Volume {
+    file: File,
+    start: u64,
+    len: u64,
+}
+
+/// Reads volumes of a split archive as one continuous archive. A path which
+/// isn't a first volume is read as a single file.
+pub struct VolumeReader {
+    volumes: Vec<Volume>,
+    /// Path of the archive whose volumes are counted on disk, see `refresh`.
+    counted: Option<PathBuf>,
+    length: u64,
+    position: u64,
+    /// Whether the file of the current volume has to be sought to `position`.
+    dirty: bool,
+}
+
+impl VolumeReader {
+    /// Opens the volumes counted in the header of the first one, or every
+    /// consecutive one on disk if the header has no count.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let mut reader = Self {
+            volumes: Vec::new(),
+            counted: None,
+            length: 0,
+            position: 0,
+            dirty: true,
+        };
+
+        if !is_first_volume(path) {
+            reader.push(path)?;
+            return Ok(reader);
+        }
+
+        let archive_path = logical_path(path);
+        match stored_volume_count(path)? {
+            Some(count) => {
+                // volumes beyond the count, e.g. of an earlier split, aren't read
+                for number in 1..=count {
+                    let volume = volume_path(&archive_path, number);
+                    if !volume.is_file() {
+                        return Err(ArchiveError::Path(format!(
+                            "Volume '{}' of {} is missing",
+                            volume.display(),
+                            count
+                        )));
+                    }
+                    reader.push(&volume)?;
+                }
+            }
+            None => {
+                reader.counted = Some(archive_path);
+                reader.refresh()?;
+            }
+        }
+        Ok(reader)
+    }
+
+    /// Picks up bytes and volumes written since the reader was opened, for
+    /// reading back an archive while it's being written.
+    pub fn refresh(&mut self) -> Result<()> {
+        if let Some(last) = self.volumes.last_mut() {
+            last.len = last.file.metadata()?.len();
+            self.length = last.start + last.len;
+        }
+        if let Some(archive_path) = self.counted.clone() {
+            for number in self.volumes.len() + 1.. {
+                let volume = volume_path(&archive_path, number);
+                if !volume.is_file() {
+                    break;
+                }
+                self.push(&volume)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn push(&mut self, path: &Path) -> Result<()> {
+        let file = File::open(path)?;
+        let len = file.metadata()?.len();
+        self.volumes.push(Volume {
+            file,
+            start: self.length,
+            len,
+        });
+        self.length += len;
+        Ok(())
+    }
+
+    /// Count of volumes the archive consists of.
+    pub fn volume_count(&self) -> usize {
+        self.volumes.len()
+    }
+
+    pub fn len(&self) -> u64 {
+        self.length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    fn current(&self) -> Option<usize> {
+        self.volumes
+            .iter()
+            .position(|v| self.position >= v.start && self.position < v.start + v.len)
+    }
+}
+
+impl Read for VolumeReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let Some(index) = self.current() else {
+            return Ok(0); // EOF
+        };
+        let volume = &mut self.volumes[index];
+
+        if self.dirty {
+            volume
+                .file
+                .seek(SeekFrom::Start(self.position - volume.start))?;
+        }
+
+        let remaining = volume.start + volume.len - self.position;
+        // a volume left larger than the address space mustn't wrap to a short read
+        let to_read = usize::try_from(remaining).map_or(buf.len(), |r| buf.len().min(r));
+        let bytes = volume.file.read(&mut buf[..to_read])?;
+
+        self.position += bytes as u64;
+        // the next volume starts from its beginning, which needs a seek as well
+        self.dirty = bytes as u64 == remaining;
+        Ok(bytes)
+    }
+}
+
+impl Seek for VolumeReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.length.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        }
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )
+        })?;
+
+        if position != self.position {
+            self.position = position;
+            self.dirty = true;
+        }
+        Ok(position)
+    }
+}
+
+/// Writes an archive into volumes of at most `volume_size` bytes, named as
+/// `volume_path` gives them and started once the current one is full, or
+/// into a single file. Bytes written before can be overwritten after seeking
+/// back, e.g. to patch the header in the first volume.
+pub struct VolumeWriter {
+    /// The single file, or the first volume, to read the archive back from.
+    path: PathBuf,
+    archive_path: PathBuf,
+    volume_size: Option<u64>,
+    volumes: Vec<Volume>,
+    position: u64,
+    /// Whether the file of the current volume has to be sought to `position`.
+    dirty: bool,
+}
+
+impl VolumeWriter {
+    /// Creates the single file `path`, truncating it.
+    pub fn create(path: &Path) -> Result<Self> {
+        Self::from_file(File::create(path)?, path)
+    }
+
+    /// Continues the single file `path` from the current position of `file`.
+    pub fn from_file(mut file: File, path: &Path) -> Result<Self> {
+        let len = file.metadata()?.len();
+        let position = file.stream_position()?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            archive_path: path.to_path_buf(),
+            volume_size: None,
+            volumes: vec![Volume {
+                file,
+                start: 0,
+                len,
+            }],
+            position,
+            dirty: false,
+        })
+    }
+
+    /// Creates the first volume of the archive at `archive_path`, removing
+    /// any volumes left there before.
+    pub fn split(archive_path: &Path, volume_size: u64) -> Result<Self> {
+        if volume_size < MIN_VOLUME_SIZE {
+            return Err(ArchiveError::Usage(format!(
+                "Volume size must be at least {} bytes",
+                MIN_VOLUME_SIZE
+            )));
+        }
+        remove_volumes(archive_path, 1)?;
+
+        let mut writer = Self {
+            path: volume_path(archive_path, 1),
+            archive_path: archive_path.to_path_buf(),
+            volume_size: Some(volume_size),
+            volumes: Vec::new(),
+            position: 0,
+            dirty: false,
+        };
+        writer.start_volume()?;
+        Ok(writer)
+    }
+
+    /// The single file, or the first volume, which `VolumeReader::open`
+    /// reads the archive from.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn is_split(&self) -> bool {
+        self.volume_size.is_some()
+    }
+
+    /// Count of volumes started so far.
+    pub fn volume_count(&self) -> usize {
+        self.volumes.len()
+    }
+
+    /// Starts the next volume unless `len` more bytes fit into the rest of
+    /// the current one, so an entry spans volumes only when it's larger than
+    /// a whole one. Does nothing unless writing at the end of a split archive.
+    pub fn reserve(&mut self, len: u64) -> io::Result<()> {
+        let (Some(volume_size), Some(last)) = (self.volume_size, self.volumes.last()) else {
+            return Ok(());
+        };
+        let used = last.len;
+        if self.position == last.start + used
+            && used > 0
+            && volume_size - used.min(volume_size) < len
+            && len <= volume_size
+        {
+            self.start_volume()?;
+        }
+        Ok(())
+    }
+
+    fn start_volume(&mut self) -> io::Result<()> {
+        let number = self.volumes.len() + 1;
+        if number > u16::MAX as usize {
+            return Err(io::Error::other(format!(
+                "Archive needs more than {} volumes",
+                u16::MAX
+            )));
+        }
+        let path = volume_path(&self.archive_path, number);
+        let file = File::create(&path)?;
+        debug!("Started volume '{}'", path.display());
+
+        let start = self.volumes.last().map_or(0, |last| last.start + last.len);
+        self.volumes.push(Volume {
+            file,
+            start,
+            len: 0,
+        });
+        self.dirty = true;
+        Ok(())
+    }
+
+    /// Bytes volume `index` can hold: all it has for a finished one, up to
+    /// the volume size for the last.
+    fn capacity(&self, index: usize) -> u64 {
+        if index + 1 < self.volumes.len() {
+            self.volumes[index].len
+        } else {
+            self.volume_size.unwrap_or(u64::MAX)
+        }
+    }
+
+    fn length(&self) -> u64 {
+        self.volumes.last().map_or(0, |last| last.start + last.len)
+    }
+
+    /// Index of the volume `position` falls into, starting the next one
+    /// when writing past the end of a full last volume.
+    fn current(&mut self) -> io::Result<usize> {
+        let found = (0..self.volumes.len()).find(|&i| {
+            let start = self.volumes[i].start;
+            self.position >= start && self.position - start < self.capacity(i)
+        });
+        match found {
+            Some(index) => Ok(index),
+            None if self.position == self.length() => {
+                self.start_volume()?;
+                Ok(self.volumes.len() - 1)
+            }
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "write past the end of the last volume",
+            )),
+        }
+    }
+}
+
+impl Write for VolumeWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let index = self.current()?;
+        let remaining = self.capacity(index) - (self.position - self.volumes[index].start);
+        let volume = &mut self.volumes[index];
+
+        if self.dirty {
+            volume
+                .file
+                .seek(SeekFrom::Start(self.position - volume.start))?;
+        }
+
+        let to_write = usize::try_from(remaining).map_or(buf.len(), |r| buf.len().min(r));
+        let bytes = volume.file.write(&buf[..to_write])?;
+
+        self.position += bytes as u64;
+        volume.len = volume.len.max(self.position - volume.start);
+        // the next volume is written from its beginning, which needs a seek as well
+        self.dirty = bytes as u64 == remaining;
+        Ok(bytes)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        for volume in &mut self.volumes {
+            volume.file.flush()?;
+        }
+        Ok(())
+    }
+}
+
+impl Seek for VolumeWriter {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.length().checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        }
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )
+        })?;
+
+        if position != self.position {
+            self.position = position;
+            self.dirty = true;
+        }
+        Ok(position)
+    }
+}
@@ -0,0 +1,332 @@
+use std::{
+    fs::{File, Metadata},
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::{Component, Path, PathBuf},
+    sync::atomic::AtomicBool,
+};
+
+use log::log;
+use zip::{
+    AesMode, CompressionMethod, ZIP64_BYTES_THR, ZipArchive, ZipWriter, write::SimpleFileOptions,
+};
+
+use crate::{
+    BUFFER_SIZE, InnerFile, check_cancelled,
+    compressor::Method,
+    error::{ArchiveError, Result},
+    limiter::RateLimiter,
+    normalize_path,
+    pack::PackOptions,
+    progress::{Phase, ProgressTracker},
+    retry::Retrying,
+    unpack::{
+        ExtractedEntry, OutputWriter, UnpackOptions, apply_dir_mode, archive_dir, entry_path,
+        is_selected, log_summary, warn_too_short,
+    },
+};
+
+/// Writes `files` as a ZIP archive under the names of their `inners`, see
+/// `Format::Zip`. Stored files stay stored and the rest is deflated.
+/// Modification times aren't kept, every entry gets the earliest one ZIP
+/// can hold. Returns the count of entries.
+pub(crate) fn write_zip(
+    file: File,
+    files: &[PathBuf],
+    inners: &[InnerFile],
+    options: &PackOptions,
+) -> Result<usize> {
+    let mut writer = ZipWriter::new(BufWriter::with_capacity(options.buffer_capacity, file));
+    let mut limiter = RateLimiter::new(options.rate_limit);
+    let progress = ProgressTracker::new(
+        options.progress.as_ref(),
+        Phase::Pack,
+        files.len() as u64,
+        Some(inners.iter().map(InnerFile::original_size).sum()),
+        None,
+    );
+    let mut buffer = vec![0u8; BUFFER_SIZE];
+
+    for (path, inner) in files.iter().zip(inners) {
+        // gzip framing has no place in ZIP, its raw deflate stream does, and
+        // few ZIP readers know xz, so it's deflated as well
+        let (method, compression) = match inner.method() {
+            Method::Store => (Method::Store, CompressionMethod::Stored),
+            Method::Gzip | Method::Deflate => (Method::Deflate, CompressionMethod::Deflated),
+            #[cfg(feature = "xz")]
+            Method::Xz => (Method::Deflate, CompressionMethod::Deflated),
+        };
+        let mut file_options = SimpleFileOptions::default()
+            .compression_method(compression)
+            .compression_level(
+                (compression == CompressionMethod::Deflated)
+                    .then_some(options.level.level().into()),
+            )
+            .large_file(inner.original_size() >= ZIP64_BYTES_THR);
+        let metadata = path.metadata()?;
+        if let Some(mode) = permissions(&metadata) {
+            file_options = file_options.unix_permissions(mode);
+        }
+        if let Some(password) = &options.password {
+            file_options = file_options.with_aes_encryption(AesMode::Aes256, password);
+        }
+
+        writer.start_file(zip_name(inner)?, file_options)?;
+        let mut reader = Retrying::new(File::open(path)?, options.io_retries);
+        let size = copy_contents(
+            &mut reader,
+            &mut writer,
+            &mut buffer,
+            &mut limiter,
+            options.cancel.as_deref(),
+        )?;
+
+        log!(
+            options.verbosity.entry_level(),
+            "Packed '{}' ({} bytes, {})",
+            path.display(),
+            size,
+            method.name()
+        );
+        // the compressed size is only known to the writer of the central directory
+        progress.advance(inner.name(), size, 0);
+    }
+
+    writer.finish()?.flush()?;
+    Ok(inners.len())
+}
+
+/// Name of the entry within a ZIP archive, which separates components by
+/// `/` and expects UTF-8.
+fn zip_name(inner: &InnerFile) -> Result<String> {
+    let mut components = Vec::new();
+    for component in Path::new(inner.name()).components() {
+        if let Component::Normal(component) = component {
+            components.push(component.to_str().ok_or_else(|| {
+                ArchiveError::Path(format!(
+                    "Entry '{}' isn't valid UTF-8, which ZIP archives require",
+                    inner.name().to_string_lossy()
+                ))
+            })?);
+        }
+    }
+    Ok(components.join("/"))
+}
+
+#[cfg(unix)]
+fn permissions(metadata: &Metadata) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    Some(metadata.permissions().mode() & 0o7777)
+}
+
+#[cfg(not(unix))]
+fn permissions(_metadata: &Metadata) -> Option<u32> {
+    None
+}
+
+/// Extracts a ZIP archive the way `unpack` extracts `.slf` ones, into a
+/// directory named after it when it holds several entries. Stored
+/// permissions are restored unless `options.file_mode` overrides them, and
+/// checksums are always verified by the ZIP reader.
+pub(crate) fn read_zip(
+    source: &Path,
+    target: &Path,
+    options: &UnpackOptions,
+) -> Result<Vec<ExtractedEntry>> {
+    let target = normalize_path(target);
+    if target.is_file() {
+        return Err(ArchiveError::Path(format!(
+            "Archive can't be unpacked into file at path: {}",
+            target.display(),
+        )));
+    }
+
+    let file = File::open(source)?;
+    let mut archive = ZipArchive::new(BufReader::with_capacity(options.buffer_capacity, file))?;
+    let file_count = archive.len();
+    let dir_path = archive_dir(source, target, file_count, options)?;
+
+    let fs = &*options.file_system;
+    let total_bytes = archive
+        .decompressed_size()
+        .and_then(|size| u64::try_from(size).ok());
+    let progress = ProgressTracker::new(
+        options.progress.as_ref(),
+        Phase::Unpack,
+        file_count as u64,
+        total_bytes,
+        None,
+    );
+    let mut limiter = RateLimiter::new(options.rate_limit);
+    let mut buffer = vec![0u8; BUFFER_SIZE];
+    let mut extracted = Vec::with_capacity(file_count);
+
+    for i in 0..file_count {
+        check_cancelled(options.cancel.as_deref())?;
+
+        let mut file = match &options.password {
+            Some(password) => archive.by_index_decrypt(i, password.as_bytes())?,
+            None => archive.by_index(i)?,
+        };
+        let name = file.enclosed_name().ok_or_else(|| {
+            ArchiveError::Path(format!(
+                "Entry '{}' points outside of the target directory",
+                String::from_utf8_lossy(file.name_raw())
+            ))
+        })?;
+        let inner = InnerFile::create(
+            name.into_os_string(),
+            file.size(),
+            file.compressed_size(),
+            file.crc32(),
+            0,
+        );
+
+        let file_path = if is_selected(&inner, options) {
+            entry_path(&inner, &dir_path, options)?
+        } else {
+            None
+        };
+        if file.is_dir() {
+            if let Some(path) = file_path {
+                fs.create_dir_all(&path)?;
+            }
+            continue;
+        }
+        let Some(file_path) = file_path else {
+            if is_selected(&inner, options) {
+                warn_too_short(&inner, options.strip_components);
+            }
+            progress.advance(inner.name(), inner.original_size(), inner.compressed_size());
+            extracted.push(ExtractedEntry::skipped(&inner, &dir_path));
+            continue;
+        };
+
+        if let Some(parents) = file_path.parent() {
+            fs.create_dir_all(parents)?;
+        }
+        let entry = ExtractedEntry::new(&inner, file_path, fs);
+        let mut writer = OutputWriter::create(&entry.path, options)?;
+        let size = copy_contents(
+            &mut file,
+            &mut writer,
+            &mut buffer,
+            &mut limiter,
+            options.cancel.as_deref(),
+        )?;
+        writer.finish()?;
+
+        if let Some(mode) = options
+            .file_mode
+            .or(file.unix_mode().map(|mode| mode & 0o7777))
+        {
+            fs.set_permissions(&entry.path, mode)?;
+        }
+        log!(
+            options.verbosity.entry_level(),
+            "Extracted '{}' ({} bytes)",
+            entry.path.display(),
+            size
+        );
+        progress.advance(inner.name(), size, inner.compressed_size());
+        extracted.push(entry);
+    }
+
+    if let Some(mode) = options.dir_mode {
+        // the directory named after the archive is created by us as well
+        let root = if file_count > 1 {
+            dir_path.parent().unwrap_or(&dir_path)
+        } else {
+            &dir_path
+        };
+        apply_dir_mode(&extracted, root, mode, fs)?;
+    }
+
+    log_summary(&extracted, &dir_path, options);
+    Ok(extracted)
+}
+
+/// Copies `reader` into `writer`, returning the count of bytes.
+fn copy_contents<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    buffer: &mut [u8],
+    limiter: &mut RateLimiter,
+    cancel: Option<&AtomicBool>,
+) -> Result<u64> {
+    let mut total = 0;
+    loop {
+        check_cancelled(cancel)?;
+
+        let bytes = match reader.read(buffer) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        };
+        if bytes == 0 {
+            return Ok(total);
+        }
+
+        writer.write_all(&buffer[..bytes])?;
+        limiter.consume(bytes);
+        total += bytes as u64;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::{
+        pack::{Format, pack},
+        testing::TempDir,
+        unpack::unpack,
+    };
+
+    const FILES: [(&str, &[u8]); 3] = [
+        ("empty", b""),
+        ("notes.txt", b"line of text\n"),
+        ("sub/more.txt", b"more text"),
+    ];
+
+    fn read_all(archive: &mut ZipArchive<File>, name: &str, password: Option<&str>) -> Vec<u8> {
+        let mut file = match password {
+            Some(password) => archive.by_name_decrypt(name, password.as_bytes()),
+            None => archive.by_name(name),
+        }
+        .unwrap();
+        let mut contents = Vec::new();
+        file.read_to_end(&mut contents).unwrap();
+        contents
+    }
+
+    #[test]
+    fn zip_round_trips() {
+        for password in [None, Some("secret")] {
+            let dir = TempDir::new("zip");
+            let source = dir.write_files("src", &FILES);
+            let options = PackOptions {
+                format: Format::Zip,
+                password: password.map(str::to_string),
+                ..PackOptions::default()
+            };
+            pack(source, Some(dir.join("archives")), &options).unwrap();
+
+            let path = dir.join("archives/src.zip");
+            let mut archive = ZipArchive::new(File::open(&path).unwrap()).unwrap();
+            assert_eq!(archive.len(), FILES.len());
+            for (name, contents) in FILES {
+                assert_eq!(read_all(&mut archive, name, password), contents, "{}", name);
+            }
+
+            let options = UnpackOptions {
+                password: password.map(str::to_string),
+                ..UnpackOptions::default()
+            };
+            unpack(path, Some(dir.join("out")), &options).unwrap();
+            for (name, contents) in FILES {
+                assert_eq!(fs::read(dir.join("out/src").join(name)).unwrap(), contents);
+            }
+        }
+    }
+}
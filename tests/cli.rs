@@ -0,0 +1,282 @@
+//! Runs the `sulfur` binary, for behavior only visible from the command line.
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::{self, Command, Output},
+};
+
+/// Directory of a test below the temporary one, removed when dropped.
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new(name: &str) -> Self {
+        let path = env::temp_dir().join(format!("sulfur-cli-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+
+    fn join<P: AsRef<Path>>(&self, path: P) -> PathBuf {
+        self.0.join(path)
+    }
+
+    /// Writes `files` below `dir`, creating their parents.
+    fn write_files(&self, dir: &str, files: &[(&str, &[u8])]) -> PathBuf {
+        let dir = self.join(dir);
+        for (name, contents) in files {
+            let path = dir.join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+        dir
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+fn sulfur(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_sulfur"))
+        .args(args)
+        .env_remove("RUST_LOG")
+        .output()
+        .unwrap()
+}
+
+/// Temporary paths are valid UTF-8.
+fn arg(path: &Path) -> &str {
+    path.to_str().unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8(output.stderr.clone()).unwrap()
+}
+
+#[test]
+fn machine_output_ends_with_a_summary() {
+    let dir = TempDir::new("machine");
+    let source = dir.write_files("src", &[("a.txt", b"first"), ("sub/b.txt", b"second")]);
+    let archives = dir.join("archives");
+
+    let output = sulfur(&["pack", arg(&source), arg(&archives), "--machine"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let progress: Vec<_> = stderr(&output)
+        .lines()
+        .filter(|line| line.starts_with("progress "))
+        .map(str::to_string)
+        .collect();
+    assert_eq!(progress.len(), 2, "{:?}", progress);
+    assert!(progress[1].contains(" entries=2 total_entries=2 bytes=11 "));
+
+    let summary: serde_json::Value = serde_json::from_str(stdout(&output).trim()).unwrap();
+    assert_eq!(summary["command"], "pack");
+    assert_eq!(summary["entries"], 2);
+    assert_eq!(summary["bytes"], 11);
+    assert!(summary["elapsed_ms"].is_u64());
+
+    let archive = archives.join("src.slf");
+    let output = sulfur(&["unpack", arg(&archive), arg(&dir.join("out")), "--machine"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let summary: serde_json::Value = serde_json::from_str(stdout(&output).trim()).unwrap();
+    assert_eq!(summary["command"], "unpack");
+    assert_eq!(summary["entries"], 2);
+    assert_eq!(summary["skipped"], 0);
+    assert_eq!(summary["bytes"], 11);
+}
+
+#[test]
+fn contains_exits_with_presence() {
+    let dir = TempDir::new("contains");
+    let source = dir.write_files("src", &[("a.txt", b"a"), ("sub/b.txt", b"b")]);
+    let archives = dir.join("archives");
+    assert!(
+        sulfur(&["pack", arg(&source), arg(&archives)])
+            .status
+            .success()
+    );
+
+    let archive = archives.join("src.slf");
+    for (name, code) in [
+        ("sub/b.txt", 0),
+        ("./sub//b.txt", 0),
+        ("b.txt", 1),
+        ("sub", 1),
+    ] {
+        let output = sulfur(&["contains", arg(&archive), name]);
+        assert_eq!(output.status.code(), Some(code), "{}", name);
+        assert!(output.stdout.is_empty());
+    }
+
+    // usage errors are told apart from absence
+    let output = sulfur(&["contains", arg(&archive)]);
+    assert_eq!(output.status.code(), Some(4));
+}
+
+#[test]
+fn verbosity_levels_filter_messages() {
+    let dir = TempDir::new("verbosity");
+    let source = dir.write_files("src", &[("a.txt", b"a"), ("sub/b.txt", b"b")]);
+    let archives = dir.join("archives");
+    let output = sulfur(&["pack", arg(&source), arg(&archives), "-vv"]);
+    assert!(
+        stderr(&output)
+            .lines()
+            .any(|line| line.starts_with("[DEBUG] "))
+    );
+
+    // the extension warns, which only -q hides
+    let renamed = dir.join("backup.dat");
+    fs::rename(archives.join("src.slf"), &renamed).unwrap();
+    let levels: [(&str, &[&str]); 3] = [
+        ("-q", &[]),
+        ("", &["[WARNING]"]),
+        ("-v", &["[WARNING]", "[INFO]", "[INFO]", "[INFO]"]),
+    ];
+    for (i, (flag, prefixes)) in levels.into_iter().enumerate() {
+        let target = dir.join(format!("out-{}", i));
+        let mut args = vec!["unpack", arg(&renamed), arg(&target)];
+        args.extend(Some(flag).filter(|flag| !flag.is_empty()));
+        let output = sulfur(&args);
+        assert!(output.status.success());
+        assert!(output.stdout.is_empty());
+
+        let stderr = stderr(&output);
+        let lines: Vec<_> = stderr
+            .lines()
+            .map(|line| line.split(' ').next().unwrap())
+            .collect();
+        assert_eq!(lines, prefixes, "{:?}: {}", flag, stderr);
+    }
+}
+
+#[test]
+fn usage_lists_every_command() {
+    const COMMANDS: [&str; 14] = [
+        "pack", "unpack", "verify", "list", "info", "manifest", "check", "bench", "estimate",
+        "diff", "contains", "rename", "remove", "repack",
+    ];
+    let listed = |text: &str| {
+        for command in COMMANDS {
+            let prefix = format!("  {} ", command);
+            assert!(
+                text.lines().any(|line| line.starts_with(&prefix)),
+                "{}: {}",
+                command,
+                text
+            );
+        }
+        assert!(text.contains("-o <target>"), "{}", text);
+    };
+
+    let output = sulfur(&[]);
+    assert_eq!(output.status.code(), Some(4));
+    assert!(output.stdout.is_empty());
+    listed(&stderr(&output));
+
+    let output = sulfur(&["frobnicate", "archive.slf"]);
+    assert_eq!(output.status.code(), Some(4));
+    let stderr = stderr(&output);
+    assert!(
+        stderr.contains("Unknown command 'frobnicate'"),
+        "{}",
+        stderr
+    );
+    listed(&stderr);
+
+    let output = sulfur(&["--help"]);
+    assert!(output.status.success());
+    listed(&stdout(&output));
+}
+
+#[test]
+fn unknown_options_are_usage_errors() {
+    let dir = TempDir::new("unknown-option");
+    let source = dir.write_files("src", &[("a.txt", b"a")]);
+    let archives = dir.join("archives");
+    let output = sulfur(&["pack", arg(&source), arg(&archives), "--preserv-owner"]);
+    assert_eq!(output.status.code(), Some(4));
+    let message = stderr(&output);
+    assert!(
+        message.contains("Unknown option '--preserv-owner'"),
+        "{}",
+        message
+    );
+    assert!(!archives.exists());
+
+    // `-` alone still reads the archive from stdin
+    assert!(
+        sulfur(&["pack", arg(&source), arg(&archives)])
+            .status
+            .success()
+    );
+    let out = dir.join("out");
+    let output = Command::new(env!("CARGO_BIN_EXE_sulfur"))
+        .args(["unpack", "-", arg(&out)])
+        .stdin(fs::File::open(archives.join("src.slf")).unwrap())
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(fs::read(out.join("a.txt")).unwrap(), b"a");
+}
+
+#[test]
+fn listed_files_are_named_from_the_base() {
+    let dir = TempDir::new("files-from");
+    let project = dir.write_files(
+        "project",
+        &[
+            ("src/main.rs", b"fn main() {}"),
+            ("docs/guide.md", b"# Guide"),
+            ("docs/line\nbreak.txt", b"newline"),
+            ("unlisted.txt", b"unlisted"),
+        ],
+    );
+    let listed = ["src/main.rs", "docs/guide.md", "docs/line\nbreak.txt"];
+    let paths: Vec<_> = listed.iter().map(|name| project.join(name)).collect();
+
+    for (flag, separator) in [("--files-from", "\n"), ("--files-from0", "\0")] {
+        // a name with a newline can only be listed NUL-separated
+        let count = if separator == "\n" { 2 } else { 3 };
+        let list: Vec<_> = paths[..count].iter().map(|path| arg(path)).collect();
+        let list_path = dir.join("list.txt");
+        fs::write(&list_path, list.join(separator)).unwrap();
+
+        let archive = dir.join(format!("{}.slf", flag.trim_start_matches('-')));
+        let output = sulfur(&[
+            "pack",
+            flag,
+            arg(&list_path),
+            "--base",
+            arg(&project),
+            "-o",
+            arg(&archive),
+        ]);
+        assert!(output.status.success(), "{}", stderr(&output));
+
+        for (i, name) in listed.iter().enumerate() {
+            let output = sulfur(&["contains", arg(&archive), name]);
+            let code = if i < count { 0 } else { 1 };
+            assert_eq!(output.status.code(), Some(code), "{:?} with {}", name, flag);
+        }
+        let output = sulfur(&["contains", arg(&archive), "unlisted.txt"]);
+        assert_eq!(output.status.code(), Some(1));
+    }
+
+    // without a base the names start at the current directory, outside of which the files are
+    let output = sulfur(&[
+        "pack",
+        "--files-from",
+        arg(&dir.join("list.txt")),
+        "-o",
+        arg(&dir.join("a.slf")),
+    ]);
+    assert_eq!(output.status.code(), Some(3), "{}", stderr(&output));
+}